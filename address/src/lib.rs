@@ -0,0 +1,316 @@
+//! # Factom Addresses
+//!
+//! Human-readable addresses as used by legacy Factom clients. An address is a two
+//! byte prefix, a 32 byte key and a four byte checksum, base58 encoded. The
+//! checksum is the start of the double SHA-256 hash of the prefix and key.
+//!
+//! | Prefix | Key                                   |
+//! |--------|---------------------------------------|
+//! | FA     | account holding factoids              |
+//! | Fs     | factoid private key                   |
+//! | EC     | account holding entry credits         |
+//! | Es     | entry credit private key              |
+//!
+//! Accounts on this chain are sr25519 public keys, shown as the FA address of
+//! their factoids and the EC address of their entry credits, see
+//! `Address::from_account` and `Address::to_account`. Every FA and EC address is
+//! an account: the FA address of a legacy key holds its RCD hash and the EC
+//! address its ed25519 public key, see `Address::factoid`, accounts that no
+//! sr25519 key signs for. Balances of legacy keys are claimed with the legacy key
+//! instead.
+//!
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+/// Length of a decoded address: prefix, key and checksum
+const ADDRESS_LENGTH: usize = 38;
+
+/// Kinds of Factom addresses
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddressKind {
+    /// Public factoid address, "FA..."
+    FactoidPublic,
+    /// Private factoid address, "Fs..."
+    FactoidSecret,
+    /// Public entry credit address, "EC..."
+    EntryCreditPublic,
+    /// Private entry credit address, "Es..."
+    EntryCreditSecret,
+}
+
+impl AddressKind {
+    /// The bytes that make the address start with its human-readable prefix
+    pub fn prefix(self) -> [u8; 2] {
+        match self {
+            AddressKind::FactoidPublic => [0x5f, 0xb1],
+            AddressKind::FactoidSecret => [0x64, 0x78],
+            AddressKind::EntryCreditPublic => [0x59, 0x2a],
+            AddressKind::EntryCreditSecret => [0x5d, 0xb6],
+        }
+    }
+
+    fn from_prefix(prefix: [u8; 2]) -> Option<Self> {
+        [
+            AddressKind::FactoidPublic,
+            AddressKind::FactoidSecret,
+            AddressKind::EntryCreditPublic,
+            AddressKind::EntryCreditSecret,
+        ]
+        .iter()
+        .cloned()
+        .find(|kind| kind.prefix() == prefix)
+    }
+
+    /// Whether addresses of this kind hold a private key
+    pub fn is_secret(self) -> bool {
+        match self {
+            AddressKind::FactoidSecret | AddressKind::EntryCreditSecret => true,
+            AddressKind::FactoidPublic | AddressKind::EntryCreditPublic => false,
+        }
+    }
+}
+
+/// Reasons an address fails to decode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Not base58
+    InvalidBase58,
+    /// Not 38 bytes once decoded
+    InvalidLength,
+    /// The checksum doesn't match
+    InvalidChecksum,
+    /// Not one of the FA, Fs, EC or Es prefixes
+    UnknownPrefix,
+    /// Decoded fine but isn't the kind of address that was asked for
+    UnexpectedKind,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            Error::InvalidBase58 => "not base58",
+            Error::InvalidLength => "wrong length",
+            Error::InvalidChecksum => "checksum mismatch",
+            Error::UnknownPrefix => "unknown prefix",
+            Error::UnexpectedKind => "unexpected address kind",
+        };
+        write!(f, "Invalid address: {}", reason)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A Factom address
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Address {
+    kind: AddressKind,
+    key: [u8; 32],
+}
+
+impl Address {
+    /// Address of a given kind for a 32 byte key
+    pub fn new(kind: AddressKind, key: [u8; 32]) -> Self {
+        Address { kind, key }
+    }
+
+    /// FA address of a legacy ed25519 factoid public key, holding its RCD hash
+    pub fn factoid(public_key: &[u8; 32]) -> Self {
+        Address::new(AddressKind::FactoidPublic, rcd_hash(public_key))
+    }
+
+    /// EC address of a legacy ed25519 entry credit public key
+    pub fn entry_credit(public_key: &[u8; 32]) -> Self {
+        Address::new(AddressKind::EntryCreditPublic, *public_key)
+    }
+
+    /// FA or EC address of an account on this chain
+    pub fn from_account(kind: AddressKind, account: [u8; 32]) -> Result<Self, Error> {
+        if kind.is_secret() {
+            return Err(Error::UnexpectedKind);
+        }
+        Ok(Address::new(kind, account))
+    }
+
+    /// Account of an FA or EC address
+    pub fn to_account(&self) -> Result<[u8; 32], Error> {
+        if self.kind.is_secret() {
+            return Err(Error::UnexpectedKind);
+        }
+        Ok(self.key)
+    }
+
+    /// Decode an address and check it is of the expected kind
+    pub fn parse_kind(s: &str, kind: AddressKind) -> Result<Self, Error> {
+        let address: Address = s.parse()?;
+        if address.kind != kind {
+            return Err(Error::UnexpectedKind);
+        }
+        Ok(address)
+    }
+
+    pub fn kind(&self) -> AddressKind {
+        self.kind
+    }
+
+    /// The 32 byte key, an RCD hash, public key, private key or account key
+    pub fn key(&self) -> &[u8; 32] {
+        &self.key
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut bytes = Vec::with_capacity(ADDRESS_LENGTH);
+        bytes.extend_from_slice(&self.kind.prefix());
+        bytes.extend_from_slice(&self.key);
+        let checksum = checksum(&bytes);
+        bytes.extend_from_slice(&checksum);
+        write!(f, "{}", bs58::encode(bytes).into_string())
+    }
+}
+
+impl FromStr for Address {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = bs58::decode(s)
+            .into_vec()
+            .map_err(|_| Error::InvalidBase58)?;
+        if bytes.len() != ADDRESS_LENGTH {
+            return Err(Error::InvalidLength);
+        }
+        if checksum(&bytes[..34]) != bytes[34..] {
+            return Err(Error::InvalidChecksum);
+        }
+
+        let kind = AddressKind::from_prefix([bytes[0], bytes[1]]).ok_or(Error::UnknownPrefix)?;
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&bytes[2..34]);
+        Ok(Address::new(kind, key))
+    }
+}
+
+/// Double SHA-256 hash
+pub fn sha256d(data: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Sha256::digest(&Sha256::digest(data)));
+    hash
+}
+
+/// RCD hash of an ed25519 public key
+///
+/// A type 1 RCD is the byte `0x01` followed by the public key.
+pub fn rcd_hash(public_key: &[u8; 32]) -> [u8; 32] {
+    let mut rcd = [0u8; 33];
+    rcd[0] = 0x01;
+    rcd[1..].copy_from_slice(public_key);
+    sha256d(&rcd)
+}
+
+fn checksum(data: &[u8]) -> [u8; 4] {
+    let mut checksum = [0u8; 4];
+    checksum.copy_from_slice(&sha256d(data)[..4]);
+    checksum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(s: &str) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    // Private key of all zeros and its public key
+    const ZERO_PUBLIC: &str = "3b6a27bcceb6a42d62a3a8d02a6f0d73653215771de243a63ac048a18b59da29";
+    const ZERO_FS: &str = "Fs1KWJrpLdfucvmYwN2nWrwepLn8ercpMbzXshd1g8zyhKXLVLWj";
+    const ZERO_FA: &str = "FA1zT4aFpEvcnPqPCigB3fvGu4Q4mTXY22iiuV69DqE1pNhdF2MC";
+    const ZERO_ES: &str = "Es2Rf7iM6PdsqfYCo3D1tnAR65SkLENyWJG1deUzpRMQmbh9F3eG";
+    const ZERO_EC: &str = "EC2DKSYyRcNWf7RS963VFYgMExoHRYLHVeCfQ9PGPmNzwrcmgm2r";
+
+    // Well known funded address of legacy local networks
+    const LOCAL_PUBLIC: &str = "718b5edd2914acc2e4677f336c1a32736e5e9bde13663e6413894f57ec272e28";
+    const LOCAL_FA: &str = "FA2jK2HcLnRdS94dEcU27rF3meoJfpUcZPSinpb7AwQvPRY6RL1Q";
+
+    #[test]
+    fn test_round_trip() {
+        for s in &[ZERO_FS, ZERO_FA, ZERO_ES, ZERO_EC, LOCAL_FA] {
+            let address: Address = s.parse().unwrap();
+            assert_eq!(&address.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_legacy_vectors() {
+        let fs: Address = ZERO_FS.parse().unwrap();
+        assert_eq!(fs.kind(), AddressKind::FactoidSecret);
+        assert_eq!(fs.key(), &[0u8; 32]);
+
+        let es: Address = ZERO_ES.parse().unwrap();
+        assert_eq!(es.kind(), AddressKind::EntryCreditSecret);
+        assert_eq!(es.key(), &[0u8; 32]);
+
+        let zero_public = from_hex(ZERO_PUBLIC);
+        assert_eq!(Address::factoid(&zero_public).to_string(), ZERO_FA);
+        assert_eq!(Address::entry_credit(&zero_public).to_string(), ZERO_EC);
+
+        let local_public = from_hex(LOCAL_PUBLIC);
+        assert_eq!(Address::factoid(&local_public).to_string(), LOCAL_FA);
+    }
+
+    #[test]
+    fn test_invalid() {
+        // Last character changed
+        let bad_checksum = "FA1zT4aFpEvcnPqPCigB3fvGu4Q4mTXY22iiuV69DqE1pNhdF2MD";
+        assert_eq!(bad_checksum.parse::<Address>(), Err(Error::InvalidChecksum));
+        assert_eq!("FA1zT4aF0".parse::<Address>(), Err(Error::InvalidBase58));
+        assert_eq!("FA1zT4aF".parse::<Address>(), Err(Error::InvalidLength));
+        assert_eq!(
+            Address::parse_kind(ZERO_FA, AddressKind::EntryCreditPublic),
+            Err(Error::UnexpectedKind)
+        );
+    }
+
+    #[test]
+    fn test_accounts() {
+        let account = from_hex(LOCAL_PUBLIC);
+        for &kind in &[AddressKind::FactoidPublic, AddressKind::EntryCreditPublic] {
+            let address = Address::from_account(kind, account).unwrap();
+            let decoded: Address = address.to_string().parse().unwrap();
+            assert_eq!(decoded.kind(), kind);
+            assert_eq!(decoded.to_account(), Ok(account));
+        }
+
+        let fa = Address::from_account(AddressKind::FactoidPublic, account).unwrap();
+        assert!(fa.to_string().starts_with("FA"));
+        let ec = Address::from_account(AddressKind::EntryCreditPublic, [0xff; 32]).unwrap();
+        assert!(ec.to_string().starts_with("EC"));
+
+        assert_eq!(
+            Address::from_account(AddressKind::FactoidSecret, account),
+            Err(Error::UnexpectedKind)
+        );
+        let fs: Address = ZERO_FS.parse().unwrap();
+        assert_eq!(fs.to_account(), Err(Error::UnexpectedKind));
+    }
+
+    #[test]
+    fn test_legacy_accounts() {
+        // The account of a legacy FA address is the RCD hash of its key, that of
+        // a legacy EC address the key itself
+        let zero_public = from_hex(ZERO_PUBLIC);
+        let fa: Address = ZERO_FA.parse().unwrap();
+        assert_eq!(fa.to_account(), Ok(rcd_hash(&zero_public)));
+        let ec: Address = ZERO_EC.parse().unwrap();
+        assert_eq!(ec.to_account(), Ok(zero_public));
+        assert_eq!(
+            Address::from_account(AddressKind::EntryCreditPublic, zero_public).unwrap().to_string(),
+            ZERO_EC
+        );
+    }
+}
@@ -0,0 +1,510 @@
+//! # Commands
+//!
+//! Each subcommand, printing what the legacy `factom-cli` printed for it.
+//!
+use crate::{Command, Get, Opt, Output};
+use factomd_address::{Address, AddressKind};
+use factomd_client::{blocking, Endpoint, EntryBuilder, LegacyEntry};
+use factomd_runtime::legacy_entry::HEADER_SIZE;
+use factomd_runtime::{write_cost, Hash};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fmt;
+use std::io::{self, Read};
+use structopt::clap::ArgMatches;
+
+/// Factoshis in a factoid
+const FACTOSHIS: u128 = 100_000_000;
+
+/// Command failures
+#[derive(Debug)]
+pub enum Error {
+    Client(factomd_client::Error),
+    /// Hex that external ids or chain ids were expected in
+    InvalidHex(String),
+    InvalidAddress(String),
+    ChainExists(String),
+    ChainNotFound(String),
+    NotEnoughCredits { address: String, cost: u64 },
+    /// Neither a chain id nor a chain name was given to `addentry`
+    MissingChain,
+    Stdin(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Client(e) => write!(f, "{}", e),
+            Error::InvalidHex(s) => write!(f, "Invalid hex {}", s),
+            Error::InvalidAddress(s) => write!(f, "Invalid address {}", s),
+            Error::ChainExists(id) => write!(f, "Chain {} already exists", id),
+            Error::ChainNotFound(id) => write!(f, "Chain {} was not found", id),
+            Error::NotEnoughCredits { address, cost } => {
+                write!(f, "Not enough Entry Credits: {} needs {}", address, cost)
+            }
+            Error::MissingChain => write!(f, "A chain id or chain name is required"),
+            Error::Stdin(e) => write!(f, "Could not read stdin: {}", e),
+        }
+    }
+}
+
+impl From<factomd_client::Error> for Error {
+    fn from(e: factomd_client::Error) -> Self {
+        Error::Client(e)
+    }
+}
+
+/// A wallet address, as `all-addresses` lists it
+#[derive(Deserialize)]
+struct AddressPair {
+    public: String,
+}
+
+#[derive(Deserialize)]
+struct Addresses {
+    addresses: Vec<AddressPair>,
+}
+
+/// A request walletd composed for the legacy API of factomd
+#[derive(Deserialize)]
+struct FactomdRequest {
+    method: String,
+    params: Value,
+}
+
+/// The requests writing an entry or chain, and what they write
+#[derive(Deserialize)]
+struct Composed {
+    commit: FactomdRequest,
+    reveal: FactomdRequest,
+    chainid: String,
+    entryhash: String,
+}
+
+/// What factomd answers a commit
+#[derive(Deserialize)]
+struct Committed {
+    txid: String,
+}
+
+/// What a written entry or chain is known by
+struct Written {
+    txid: String,
+    chain_id: String,
+    entry_hash: String,
+}
+
+/// Clients of factomd and walletd
+struct Cli {
+    factomd: blocking::Client,
+    /// The legacy API composed commits and reveals are sent to
+    legacy: Endpoint,
+    walletd: Endpoint,
+}
+
+pub fn run(opt: Opt, matches: &ArgMatches) -> Result<(), Error> {
+    let cli = Cli::new(&opt)?;
+    // External ids keep the order they were given in across flags
+    let sub = matches.subcommand().1;
+
+    match opt.command {
+        Command::Get(Get::ChainHead { hash_only, chain_id }) => print!("{}", cli.chain_head(&chain_id, hash_only)?),
+        Command::AddChain {
+            force,
+            output,
+            ec_address,
+            ..
+        } => {
+            let external_ids = ordered_ids(sub, "names", "hex_names")?;
+            let chain = external_ids
+                .into_iter()
+                .fold(EntryBuilder::new(), EntryBuilder::external_id)
+                .content(read_stdin()?)
+                .chain()?;
+            let written = cli.add_chain(&chain, &ec_address, force)?;
+            print_written(&output, &written);
+        }
+        Command::AddEntry {
+            force,
+            chain_id,
+            output,
+            ec_address,
+            ..
+        } => {
+            let names = ordered_ids(sub, "names", "hex_names")?;
+            let chain_id = if !names.is_empty() {
+                LegacyEntry::chain_id_of(&names)
+            } else if !chain_id.is_empty() {
+                decode_chain_id(&chain_id)?
+            } else {
+                return Err(Error::MissingChain);
+            };
+            let entry = ordered_ids(sub, "external_ids", "hex_external_ids")?
+                .into_iter()
+                .fold(EntryBuilder::new(), EntryBuilder::external_id)
+                .content(read_stdin()?)
+                .entry(chain_id)?;
+            let written = cli.add_entry(&entry, &ec_address, force)?;
+            print_written(&output, &written);
+        }
+        Command::ListAddresses => {
+            let addresses: Addresses = cli.factomd.call(&cli.walletd, "all-addresses", json!({}))?;
+            // Factoid addresses first, as legacy factom-cli listed them
+            let mut addresses: Vec<_> = addresses.addresses.into_iter().map(|pair| pair.public).collect();
+            addresses.sort_by_key(|address| !address.starts_with("FA"));
+            for address in addresses {
+                println!("{} {}", address, cli.balance(&address, false)?);
+            }
+        }
+        Command::Balance { raw, address } => println!("{}", cli.balance(&address, raw)?),
+        Command::EcRate => println!("{}", cli.factomd.ec_rate()?),
+    }
+    Ok(())
+}
+
+impl Cli {
+    fn new(opt: &Opt) -> Result<Self, Error> {
+        let legacy_url = format!("http://{}/v2", opt.factomd);
+        let mut walletd = Endpoint::new(&format!("http://{}/v2", opt.walletd))?;
+        if !opt.wallet_user.is_empty() {
+            walletd = walletd.with_credentials(&opt.wallet_user, &opt.wallet_password);
+        }
+        Ok(Cli {
+            factomd: blocking::Client::new(&legacy_url, &format!("http://{}", opt.node))?,
+            legacy: Endpoint::new(&legacy_url)?,
+            walletd,
+        })
+    }
+
+    /// The last entry of a chain and how many it has, or only its hash
+    fn chain_head(&self, chain_id: &str, hash_only: bool) -> Result<String, Error> {
+        let id = Hash::from(decode_chain_id(chain_id)?);
+        let latest = self
+            .factomd
+            .latest_entry(id)?
+            .ok_or_else(|| Error::ChainNotFound(chain_id.to_string()))?;
+        let entry_hash = hex::encode(latest.entry_hash);
+        if hash_only {
+            return Ok(format!("{}\n", entry_hash));
+        }
+        Ok(format!(
+            "ChainID: {}\nEntryHash: {}\nEntries: {}\n",
+            chain_id, entry_hash, latest.entries
+        ))
+    }
+
+    fn add_chain(&self, chain: &LegacyEntry, ec_address: &str, force: bool) -> Result<Written, Error> {
+        let chain_id = hex::encode(chain.chain_id);
+        if !force {
+            if self.chain_exists(chain.chain_id)? {
+                return Err(Error::ChainExists(chain_id));
+            }
+            self.check_credits(ec_address, self.cost(chain, true)?)?;
+        }
+        let composed: Composed = self.factomd.call(
+            &self.walletd,
+            "compose-chain",
+            json!({
+                "chain": { "firstentry": {
+                    "extids": chain.external_ids.iter().map(hex::encode).collect::<Vec<_>>(),
+                    "content": hex::encode(&chain.content),
+                }},
+                "ecpub": ec_address,
+            }),
+        )?;
+        self.submit(composed)
+    }
+
+    fn add_entry(&self, entry: &LegacyEntry, ec_address: &str, force: bool) -> Result<Written, Error> {
+        let chain_id = hex::encode(entry.chain_id);
+        if !force {
+            if !self.chain_exists(entry.chain_id)? {
+                return Err(Error::ChainNotFound(chain_id));
+            }
+            self.check_credits(ec_address, self.cost(entry, false)?)?;
+        }
+        let composed: Composed = self.factomd.call(
+            &self.walletd,
+            "compose-entry",
+            json!({
+                "entry": {
+                    "chainid": chain_id,
+                    "extids": entry.external_ids.iter().map(hex::encode).collect::<Vec<_>>(),
+                    "content": hex::encode(&entry.content),
+                },
+                "ecpub": ec_address,
+            }),
+        )?;
+        self.submit(composed)
+    }
+
+    /// Commit and reveal a composed entry, known by the txid of its commit
+    fn submit(&self, composed: Composed) -> Result<Written, Error> {
+        let (commit, reveal) = (composed.commit, composed.reveal);
+        let committed: Committed = self.factomd.call(&self.legacy, &commit.method, commit.params)?;
+        let _: Value = self.factomd.call(&self.legacy, &reveal.method, reveal.params)?;
+        Ok(Written {
+            txid: committed.txid,
+            chain_id: composed.chainid,
+            entry_hash: composed.entryhash,
+        })
+    }
+
+    fn chain_exists(&self, chain_id: [u8; 32]) -> Result<bool, Error> {
+        Ok(self.factomd.latest_entry(Hash::from(chain_id))?.is_some())
+    }
+
+    /// Entry credits the runtime charges for an entry, see `write_cost`
+    fn cost(&self, entry: &LegacyEntry, starts_chain: bool) -> Result<u64, Error> {
+        let costs = self.factomd.write_costs()?;
+        let chain_cost = if starts_chain { Some(costs.chain_cost) } else { None };
+        Ok(write_cost(entry_size(entry)?, costs.entry_cost, chain_cost))
+    }
+
+    fn check_credits(&self, ec_address: &str, cost: u64) -> Result<(), Error> {
+        let balance = self.factomd.balance(ec_address)?;
+        if balance < u128::from(cost) {
+            return Err(Error::NotEnoughCredits {
+                address: ec_address.to_string(),
+                cost,
+            });
+        }
+        Ok(())
+    }
+
+    /// Factoid balances in factoids unless raw, entry credit balances in credits
+    fn balance(&self, address: &str, raw: bool) -> Result<String, Error> {
+        let parsed: Address = address
+            .parse()
+            .map_err(|_| Error::InvalidAddress(address.to_string()))?;
+        match parsed.kind() {
+            AddressKind::FactoidPublic => {
+                let balance = self.factomd.balance(address)?;
+                Ok(if raw { balance.to_string() } else { factoshis_to_factoids(balance) })
+            }
+            AddressKind::EntryCreditPublic => Ok(self.factomd.balance(address)?.to_string()),
+            _ => Err(Error::InvalidAddress(address.to_string())),
+        }
+    }
+}
+
+/// Values of a text and a hex flag, in the order they were given in
+fn ordered_ids(matches: Option<&ArgMatches>, text: &str, hex: &str) -> Result<Vec<Vec<u8>>, Error> {
+    let matches = match matches {
+        Some(matches) => matches,
+        None => return Ok(Vec::new()),
+    };
+    let mut ids = Vec::new();
+    if let (Some(values), Some(indices)) = (matches.values_of(text), matches.indices_of(text)) {
+        ids.extend(indices.zip(values.map(|value| value.as_bytes().to_vec())));
+    }
+    if let (Some(values), Some(indices)) = (matches.values_of(hex), matches.indices_of(hex)) {
+        for (index, value) in indices.zip(values) {
+            let bytes = hex::decode(value).map_err(|_| Error::InvalidHex(value.to_string()))?;
+            ids.push((index, bytes));
+        }
+    }
+    ids.sort_by_key(|(index, _)| *index);
+    Ok(ids.into_iter().map(|(_, id)| id).collect())
+}
+
+fn decode_chain_id(chain_id: &str) -> Result<[u8; 32], Error> {
+    let bytes = hex::decode(chain_id).map_err(|_| Error::InvalidHex(chain_id.to_string()))?;
+    if bytes.len() != 32 {
+        return Err(Error::InvalidHex(chain_id.to_string()));
+    }
+    let mut id = [0u8; 32];
+    id.copy_from_slice(&bytes);
+    Ok(id)
+}
+
+fn read_stdin() -> Result<Vec<u8>, Error> {
+    let mut content = Vec::new();
+    io::stdin().read_to_end(&mut content).map_err(Error::Stdin)?;
+    Ok(content)
+}
+
+/// Bytes of external ids and content an entry is charged for
+fn entry_size(entry: &LegacyEntry) -> Result<usize, Error> {
+    let data = entry
+        .encode()
+        .map_err(|e| Error::Client(factomd_client::Error::InvalidEntry(e)))?;
+    Ok(data.len() - HEADER_SIZE)
+}
+
+fn print_written(output: &Output, written: &Written) {
+    if output.quiet {
+        return;
+    }
+    if output.print_txid || output.print_chain_id || output.print_entry_hash {
+        if output.print_txid {
+            println!("{}", written.txid);
+        }
+        if output.print_chain_id {
+            println!("{}", written.chain_id);
+        }
+        if output.print_entry_hash {
+            println!("{}", written.entry_hash);
+        }
+        return;
+    }
+    println!("CommitTxID: {}", written.txid);
+    println!("ChainID: {}", written.chain_id);
+    println!("Entryhash: {}", written.entry_hash);
+}
+
+/// Factoids with as many decimals as they need, as `FactoshiToFactoid` printed them
+fn factoshis_to_factoids(factoshis: u128) -> String {
+    let fraction = format!("{:08}", factoshis % FACTOSHIS);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{}", factoshis / FACTOSHIS)
+    } else {
+        format!("{}.{}", factoshis / FACTOSHIS, fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use factomd_client::state::{map_key, value_key};
+    use jsonrpc_core::{IoHandler, Params};
+    use jsonrpc_http_server::{Server, ServerBuilder};
+    use parity_codec::Encode;
+    use std::collections::HashMap;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_factoids() {
+        assert_eq!(factoshis_to_factoids(0), "0");
+        assert_eq!(factoshis_to_factoids(1_250_000_000), "12.5");
+        assert_eq!(factoshis_to_factoids(1), "0.00000001");
+        assert_eq!(factoshis_to_factoids(300_000_000), "3");
+    }
+
+    #[test]
+    fn test_ordered_ids() {
+        let matches = Opt::clap().get_matches_from(vec![
+            "factom-cli", "addchain", "-n", "a", "-h", "0102", "-n", "b", "EC2KnJQN86MYq4pQyeSGTHSiVdkhRCPXS3udzD4im6BXRBjZFMmR",
+        ]);
+        let ids = ordered_ids(matches.subcommand().1, "names", "hex_names").unwrap();
+        assert_eq!(ids, vec![b"a".to_vec(), vec![1, 2], b"b".to_vec()]);
+
+        let matches = Opt::clap().get_matches_from(vec!["factom-cli", "addchain", "-h", "zz", "EC"]);
+        assert!(ordered_ids(matches.subcommand().1, "names", "hex_names").is_err());
+    }
+
+    #[test]
+    fn test_entry_size() {
+        let entry = EntryBuilder::new().entry([0; 32]).unwrap();
+        assert_eq!(entry_size(&entry).unwrap(), 0);
+        let entry = EntryBuilder::new()
+            .external_id("id")
+            .content(vec![0u8; 1025])
+            .entry([0; 32])
+            .unwrap();
+        assert_eq!(entry_size(&entry).unwrap(), 1029);
+    }
+
+    /// A node answering only the methods a node has, factomd taking writes and walletd composing entries
+    fn mock_servers(storage: HashMap<Vec<u8>, Vec<u8>>) -> (Server, Server, Server, Cli) {
+        let mut node = IoHandler::new();
+        node.add_method("state_getStorage", move |params: Params| {
+            let (key,): (String,) = params.parse()?;
+            let key = hex::decode(key.trim_start_matches("0x")).unwrap();
+            Ok(storage
+                .get(&key)
+                .map(|value| json!(format!("0x{}", hex::encode(value))))
+                .unwrap_or(Value::Null))
+        });
+        node.add_method("chain_getBlockHash", |_: Params| Ok(json!(format!("0x{}", hex::encode([1u8; 32])))));
+
+        let mut legacy = IoHandler::new();
+        legacy.add_method("commit-entry", |_: Params| {
+            Ok(json!({ "message": "Entry Commit Success", "txid": hex::encode([9u8; 32]) }))
+        });
+        legacy.add_method("reveal-entry", |params: Params| {
+            let params: Value = params.parse()?;
+            assert_eq!(params["entry"], "0102");
+            Ok(json!({ "message": "Entry Reveal Success" }))
+        });
+
+        let mut walletd = IoHandler::new();
+        walletd.add_method("compose-entry", |params: Params| {
+            let params: Value = params.parse()?;
+            Ok(json!({
+                "commit": { "jsonrpc": "2.0", "id": 0, "method": "commit-entry", "params": { "message": "00" } },
+                "reveal": { "jsonrpc": "2.0", "id": 0, "method": "reveal-entry", "params": { "entry": "0102" } },
+                "chainid": params["entry"]["chainid"],
+                "entryhash": hex::encode([8u8; 32]),
+            }))
+        });
+
+        let start = |io| {
+            ServerBuilder::new(io)
+                .start_http(&"127.0.0.1:0".parse().unwrap())
+                .unwrap()
+        };
+        let (node, legacy, walletd) = (start(node), start(legacy), start(walletd));
+        let opt = Opt::from_iter(vec![
+            "factom-cli".to_string(),
+            "--node".to_string(),
+            node.address().to_string(),
+            "-s".to_string(),
+            legacy.address().to_string(),
+            "-w".to_string(),
+            walletd.address().to_string(),
+            "ecrate".to_string(),
+        ]);
+        let cli = Cli::new(&opt).unwrap();
+        (node, legacy, walletd, cli)
+    }
+
+    #[test]
+    fn test_node_storage() {
+        let fa = Address::new(AddressKind::FactoidPublic, [1u8; 32]).to_string();
+        let ec = Address::new(AddressKind::EntryCreditPublic, [2u8; 32]).to_string();
+        let chain_id = [3u8; 32];
+        let entry_hash = Hash::from([4u8; 32]);
+        let mut storage = HashMap::new();
+        storage.insert(map_key(b"Balances FreeBalance", &[1u8; 32]), 1_250_000_000u128.encode());
+        storage.insert(map_key(b"Instance0Balances FreeBalance", &[2u8; 32]), 3u128.encode());
+        storage.insert(value_key(b"EntryCredit EcRate"), 1000u64.encode());
+        storage.insert(value_key(b"Entry ChainCost"), 10u64.encode());
+        storage.insert(map_key(b"Entry TotalEntries", &Hash::from(chain_id)), 2u64.encode());
+        storage.insert(map_key(b"Entry ChainEntries", &(Hash::from(chain_id), 2u64)), entry_hash.encode());
+        let (_node, _legacy, _walletd, cli) = mock_servers(storage);
+
+        assert_eq!(cli.balance(&fa, false).unwrap(), "12.5");
+        assert_eq!(cli.balance(&fa, true).unwrap(), "1250000000");
+        assert_eq!(cli.balance(&ec, false).unwrap(), "3");
+        assert_eq!(cli.factomd.ec_rate().unwrap(), 1000);
+        assert_eq!(
+            cli.chain_head(&hex::encode(chain_id), false).unwrap(),
+            format!("ChainID: {}\nEntryHash: {}\nEntries: 2\n", hex::encode(chain_id), hex::encode(entry_hash))
+        );
+        assert_eq!(cli.chain_head(&hex::encode(chain_id), true).unwrap(), format!("{}\n", hex::encode(entry_hash)));
+        match cli.chain_head(&hex::encode([5u8; 32]), true) {
+            Err(Error::ChainNotFound(_)) => {}
+            Err(e) => panic!("Expected a missing chain, got {}", e),
+            Ok(head) => panic!("Expected a missing chain, got {}", head),
+        }
+
+        // Three entry credits pay for an entry of up to 3 KiB, not for a new chain
+        let entry = EntryBuilder::new().content(vec![0u8; 3000]).entry(chain_id).unwrap();
+        let written = cli.add_entry(&entry, &ec, false).unwrap();
+        assert_eq!(written.txid, hex::encode([9u8; 32]));
+        assert_eq!(written.chain_id, hex::encode(chain_id));
+        let chain = EntryBuilder::new().external_id("new").chain().unwrap();
+        match cli.add_chain(&chain, &ec, false) {
+            Err(Error::NotEnoughCredits { cost: 10, .. }) => {}
+            Err(e) => panic!("Expected too few credits, got {}", e),
+            Ok(_) => panic!("Expected too few credits"),
+        }
+        match cli.add_entry(&EntryBuilder::new().entry([5u8; 32]).unwrap(), &ec, false) {
+            Err(Error::ChainNotFound(_)) => {}
+            Err(e) => panic!("Expected a missing chain, got {}", e),
+            Ok(_) => panic!("Expected a missing chain"),
+        }
+    }
+}
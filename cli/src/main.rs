@@ -0,0 +1,180 @@
+//! # Factom CLI
+//!
+//! A command line for factomd and walletd that takes the subcommands and flags
+//! of the legacy `factom-cli` and prints what it printed, so runbooks written
+//! for it keep working:
+//!
+//! | Subcommand           | Prints                                              |
+//! |----------------------|-----------------------------------------------------|
+//! | `get chainhead`      | the latest entry of a chain, or only its hash       |
+//! | `addchain`           | commit txid, chain id and entry hash of a chain     |
+//! | `addentry`           | commit txid, chain id and entry hash of an entry    |
+//! | `listaddresses`      | every wallet address with its balance               |
+//! | `balance`            | the balance of an FA or EC address                  |
+//! | `ecrate`             | factoshis per entry credit                          |
+//!
+//! Balances, chains, costs and the entry credit rate are read from runtime
+//! storage through the node's JSON-RPC API, `--node`. The commits and reveals
+//! walletd composes, `-w`, are sent to the legacy API of factomd, `-s`. Entry
+//! content is read from stdin.
+//!
+//! There are no entry blocks on this chain. `get chainhead` shows the last entry
+//! written to the chain instead, and `-K` prints only its entry hash.
+//!
+#![warn(unused_extern_crates)]
+
+use std::process;
+use structopt::StructOpt;
+
+mod commands;
+
+/// Command line for factomd and walletd, compatible with the legacy factom-cli
+#[derive(StructOpt, Debug)]
+#[structopt(name = "factom-cli")]
+pub struct Opt {
+    /// IP address and port of the legacy API of factomd
+    #[structopt(short = "s", default_value = "localhost:8088")]
+    pub factomd: String,
+
+    /// IP address and port of the JSON-RPC API of the node
+    #[structopt(long = "node", default_value = "localhost:9933")]
+    pub node: String,
+
+    /// IP address and port of walletd
+    #[structopt(short = "w", default_value = "localhost:8089")]
+    pub walletd: String,
+
+    /// Username for walletd
+    #[structopt(long = "walletuser", default_value = "")]
+    pub wallet_user: String,
+
+    /// Password for walletd
+    #[structopt(long = "walletpassword", default_value = "")]
+    pub wallet_password: String,
+
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+/// Flags selecting what `addchain` and `addentry` print
+#[derive(StructOpt, Debug)]
+pub struct Output {
+    /// Print nothing
+    #[structopt(short = "q")]
+    pub quiet: bool,
+
+    /// Print only the chain id
+    #[structopt(short = "C")]
+    pub print_chain_id: bool,
+
+    /// Print only the entry hash
+    #[structopt(short = "E")]
+    pub print_entry_hash: bool,
+
+    /// Print only the hash of the submitted extrinsic
+    #[structopt(short = "T")]
+    pub print_txid: bool,
+}
+
+/// Chain data to look up
+#[derive(StructOpt, Debug)]
+pub enum Get {
+    /// Print the latest entry of a chain
+    #[structopt(name = "chainhead")]
+    ChainHead {
+        /// Print only the entry hash
+        #[structopt(short = "K")]
+        hash_only: bool,
+
+        chain_id: String,
+    },
+}
+
+/// Factom CLI subcommands
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Look up chain data
+    #[structopt(name = "get")]
+    Get(Get),
+
+    /// Create a chain whose first entry is read from stdin
+    #[structopt(name = "addchain")]
+    AddChain {
+        /// Don't check that the chain is new and the EC address can pay for it
+        #[structopt(short = "f")]
+        force: bool,
+
+        /// External id, which are hashed into the chain id
+        #[structopt(short = "n", number_of_values = 1)]
+        names: Vec<String>,
+
+        /// Hex encoded external id
+        #[structopt(short = "h", number_of_values = 1)]
+        hex_names: Vec<String>,
+
+        #[structopt(flatten)]
+        output: Output,
+
+        ec_address: String,
+    },
+
+    /// Add an entry read from stdin to a chain
+    #[structopt(name = "addentry")]
+    AddEntry {
+        /// Don't check that the chain exists and the EC address can pay for the entry
+        #[structopt(short = "f")]
+        force: bool,
+
+        /// Chain id of the chain
+        #[structopt(short = "c", default_value = "")]
+        chain_id: String,
+
+        /// Chain name, instead of the chain id
+        #[structopt(short = "n", number_of_values = 1)]
+        names: Vec<String>,
+
+        /// Hex encoded chain name
+        #[structopt(short = "h", number_of_values = 1)]
+        hex_names: Vec<String>,
+
+        /// External id of the entry
+        #[structopt(short = "e", number_of_values = 1)]
+        external_ids: Vec<String>,
+
+        /// Hex encoded external id of the entry
+        #[structopt(short = "x", number_of_values = 1)]
+        hex_external_ids: Vec<String>,
+
+        #[structopt(flatten)]
+        output: Output,
+
+        ec_address: String,
+    },
+
+    /// List the addresses in the wallet with their balances
+    #[structopt(name = "listaddresses")]
+    ListAddresses,
+
+    /// Print the balance of an FA or EC address
+    #[structopt(name = "balance")]
+    Balance {
+        /// Print factoid balances in factoshis
+        #[structopt(short = "r")]
+        raw: bool,
+
+        address: String,
+    },
+
+    /// Print the number of factoshis an entry credit costs
+    #[structopt(name = "ecrate")]
+    EcRate,
+}
+
+fn main() {
+    let matches = Opt::clap().get_matches();
+    let opt = Opt::from_clap(&matches);
+    if let Err(e) = commands::run(opt, &matches) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,114 @@
+//! # Blocking Client
+//!
+//! The calls of the async client, each waited for on a runtime the blocking
+//! client owns. Calls from several threads take turns on it.
+//!
+use crate::legacy::{EntryAck, FactoidAck, IndexedChain, IndexedEntry, PendingEntry, PendingTransaction};
+use crate::{
+    Endpoint, Error, LatestEntry, LegacyEntry, LegacyImport, RpcFuture, Signer, SubmittedEntry, WriteCosts,
+};
+use factomd_runtime::{Call, Hash, Nonce, UncheckedExtrinsic};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::sync::Mutex;
+use tokio::runtime::Runtime;
+
+/// Blocking client of the legacy and node APIs of a factomd node
+pub struct Client {
+    inner: crate::Client,
+    runtime: Mutex<Runtime>,
+}
+
+macro_rules! blocking {
+    ($($(#[$doc:meta])* fn $name:ident($($arg:ident: $ty:ty),*) -> $out:ty;)*) => {
+        impl Client {
+            $(
+                $(#[$doc])*
+                pub fn $name(&self, $($arg: $ty),*) -> Result<$out, Error> {
+                    self.wait(self.inner.$name($($arg),*))
+                }
+            )*
+        }
+    };
+}
+
+impl Client {
+    pub fn new(legacy_url: &str, node_url: &str) -> Result<Self, Error> {
+        Self::from_async(crate::Client::new(legacy_url, node_url)?)
+    }
+
+    /// Wait for the calls of an async client
+    pub fn from_async(inner: crate::Client) -> Result<Self, Error> {
+        Ok(Client {
+            inner,
+            runtime: Mutex::new(Runtime::new().map_err(Error::Io)?),
+        })
+    }
+
+    /// The async client behind this one
+    pub fn inner(&self) -> &crate::Client {
+        &self.inner
+    }
+
+    /// Call a method of any endpoint, such as walletd
+    pub fn call<T>(&self, endpoint: &Endpoint, method: &str, params: Value) -> Result<T, Error>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        self.wait(endpoint.call(method, params))
+    }
+
+    fn wait<T: Send + 'static>(&self, future: RpcFuture<T>) -> Result<T, Error> {
+        self.runtime
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .block_on(future)
+    }
+}
+
+blocking! {
+    /// Status of an entry, by its entry hash or the txid of the call writing it
+    fn entry_ack(hash: &str) -> EntryAck;
+    /// Status of a factoid transaction, by its txid
+    fn factoid_ack(txid: &str) -> FactoidAck;
+    /// Entries waiting in the transaction pool, of one address or of all
+    fn pending_entries(address: Option<&str>) -> Vec<PendingEntry>;
+    /// Transactions waiting in the transaction pool, of one address or of all
+    fn pending_transactions(address: Option<&str>) -> Vec<PendingTransaction>;
+    /// Entries with an external id starting with `prefix`, of one chain or of all
+    fn search_ext_id(prefix: &str, chain_id: Option<&str>, limit: Option<u32>) -> Vec<IndexedEntry>;
+    /// Chains whose first entry starts with the external ids `ext_ids`
+    fn search_chains(ext_ids: &[String], limit: Option<u32>) -> Vec<IndexedChain>;
+    /// Entries written by the blocks from `from` to `to`, of one chain or of all
+    fn entries_by_height(from: u64, to: u64, chain_id: Option<&str>, limit: Option<u32>) -> Vec<IndexedEntry>;
+
+    /// Write an entry in the legacy format, starting its chain if it is new
+    fn submit_entry(signer: &Signer, entry: &LegacyEntry) -> SubmittedEntry;
+    /// Add an entry to a chain started with `put_chain`
+    fn put_entry(signer: &Signer, chain_id: Hash, external_ids: &[Vec<u8>], content: Vec<u8>) -> SubmittedEntry;
+    /// Start a chain whose id is the Blake2 hash of its encoded external ids
+    fn put_chain(signer: &Signer, external_ids: &[Vec<u8>], content: Vec<u8>) -> SubmittedEntry;
+    /// Send factoids to an FA address
+    fn transfer_factoids(signer: &Signer, to: &str, amount: u128) -> Hash;
+    /// Buy entry credits for an EC address, at the entry credit rate
+    fn buy_entry_credits(signer: &Signer, to: &str, amount: u128) -> Hash;
+    /// Sign a call with the next nonce of the signer and submit it
+    fn submit(signer: &Signer, call: Call) -> Hash;
+    /// Sign calls with the next nonces of the signer, without submitting them
+    fn compose(signer: &Signer, calls: Vec<Call>) -> Vec<UncheckedExtrinsic>;
+    /// Nonce of the next call of an account
+    fn next_nonce(account: [u8; 32]) -> Nonce;
+    /// Hash of the genesis block
+    fn genesis_hash() -> Hash;
+
+    /// Factoshis of an FA address, or entry credits of an EC address
+    fn balance(address: &str) -> u128;
+    /// Factoshis one entry credit costs
+    fn ec_rate() -> u64;
+    /// What entries and new chains cost
+    fn write_costs() -> WriteCosts;
+    /// The last entry of a chain, or none for a chain that doesn't exist
+    fn latest_entry(chain_id: Hash) -> Option<LatestEntry>;
+    /// How far the import of legacy entries got
+    fn legacy_import() -> LegacyImport;
+}
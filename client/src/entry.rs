@@ -0,0 +1,72 @@
+//! # Entry Building
+//!
+//! New entries and chains in the legacy format, with their chain id and entry
+//! hash worked out before they are submitted.
+//!
+use crate::Error;
+use factomd_runtime::legacy_entry::LegacyEntry;
+
+/// External ids and content of an entry being built
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EntryBuilder {
+    external_ids: Vec<Vec<u8>>,
+    content: Vec<u8>,
+}
+
+impl EntryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an external id after the ones added so far
+    pub fn external_id<T: Into<Vec<u8>>>(mut self, external_id: T) -> Self {
+        self.external_ids.push(external_id.into());
+        self
+    }
+
+    pub fn content<T: Into<Vec<u8>>>(mut self, content: T) -> Self {
+        self.content = content.into();
+        self
+    }
+
+    /// The entry, for an existing chain
+    pub fn entry(self, chain_id: [u8; 32]) -> Result<LegacyEntry, Error> {
+        let entry = LegacyEntry {
+            chain_id,
+            external_ids: self.external_ids,
+            content: self.content,
+        };
+        entry.encode().map_err(Error::InvalidEntry)?;
+        Ok(entry)
+    }
+
+    /// The first entry of a new chain, whose id is derived from the external ids
+    pub fn chain(self) -> Result<LegacyEntry, Error> {
+        let chain_id = LegacyEntry::chain_id_of(&self.external_ids);
+        self.entry(chain_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let chain = EntryBuilder::new()
+            .external_id("factomd")
+            .external_id(vec![0u8, 1])
+            .content("hello")
+            .chain()
+            .unwrap();
+        assert_eq!(chain.chain_id, LegacyEntry::chain_id_of(&[b"factomd".to_vec(), vec![0, 1]]));
+        assert_eq!(chain.content, b"hello".to_vec());
+
+        let entry = EntryBuilder::new().content("again").entry(chain.chain_id).unwrap();
+        assert_eq!(entry.chain_id, chain.chain_id);
+        assert!(entry.external_ids.is_empty());
+
+        let oversized = EntryBuilder::new().external_id(vec![0u8; 10_241]).chain();
+        assert!(oversized.is_err());
+    }
+}
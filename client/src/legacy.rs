@@ -0,0 +1,270 @@
+//! # Legacy API
+//!
+//! Typed calls of the legacy methods factomd still serves next to the node's own
+//! JSON-RPC API, see `factomd_rpc`:
+//!
+//! | Call                   | Method                 | Result                                     |
+//! |------------------------|------------------------|--------------------------------------------|
+//! | `entry_ack`            | `entry-ack`            | status of an entry, by entry hash or txid  |
+//! | `factoid_ack`          | `factoid-ack`          | status of a factoid transaction, by txid   |
+//! | `pending_entries`      | `pending-entries`      | entries waiting in the transaction pool    |
+//! | `pending_transactions` | `pending-transactions` | transactions waiting in the pool           |
+//! | `search_ext_id`        | `search-extid`         | entries by a prefix of an external id      |
+//! | `search_chains`        | `search-chains`        | chains by the external ids of their start  |
+//! | `entries_by_height`    | `entries-by-height`    | entries written by a range of blocks       |
+//!
+//! The ack and pending methods are served by full nodes, the search methods by
+//! nodes keeping an entry index. Hashes, chain ids and external ids are hex
+//! strings, as on the wire. Reads of chain state and writes go through the node
+//! API instead, see `state` and `node`.
+//!
+use crate::rpc::RpcFuture;
+use crate::Client;
+use futures::Future;
+use serde::Deserialize;
+use serde_json::json;
+
+/// Status of a submission, as the ack methods give it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum AckStatus {
+    /// Not known to the node
+    Unknown,
+    /// Waiting in the transaction pool
+    NotConfirmed,
+    /// Included in a block
+    #[serde(rename = "TransactionACK")]
+    TransactionAck,
+    /// Included in a confirmed block
+    DBlockConfirmed,
+}
+
+/// Dates and status of a submission
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct AckData {
+    /// When the node first saw it, milliseconds
+    pub transactiondate: Option<u64>,
+    pub transactiondatestring: Option<String>,
+    /// Timestamp of the block including it, milliseconds
+    pub blockdate: Option<u64>,
+    pub blockdatestring: Option<String>,
+    pub status: AckStatus,
+}
+
+/// Ack of an entry
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct EntryAck {
+    /// Txid of the call writing the entry, empty if the node doesn't know it
+    pub committxid: String,
+    pub entryhash: String,
+    pub commitdata: AckData,
+    pub entrydata: AckData,
+}
+
+/// Ack of a factoid transaction
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct FactoidAck {
+    pub txid: String,
+    #[serde(flatten)]
+    pub data: AckData,
+}
+
+/// An entry call waiting in the transaction pool
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct PendingEntry {
+    pub entryhash: String,
+    pub chainid: String,
+    /// Address paying for the entry
+    pub sender: String,
+    pub txid: String,
+    pub status: AckStatus,
+}
+
+/// A factoid transfer or entry credit purchase waiting in the transaction pool
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct PendingTransaction {
+    pub txid: String,
+    pub from: String,
+    pub to: String,
+    /// Factoshis
+    pub amount: u64,
+    pub status: AckStatus,
+}
+
+/// An entry found in the entry index
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct IndexedEntry {
+    pub entryhash: String,
+    pub chainid: String,
+    pub extids: Vec<String>,
+    /// Height of the block that wrote the entry
+    pub height: u64,
+}
+
+/// A chain found in the entry index
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct IndexedChain {
+    pub chainid: String,
+    /// Hash of the entry that started the chain
+    pub firstentry: String,
+    pub extids: Vec<String>,
+    pub height: u64,
+}
+
+#[derive(Deserialize)]
+struct Entries {
+    entries: Vec<IndexedEntry>,
+}
+
+#[derive(Deserialize)]
+struct Chains {
+    chains: Vec<IndexedChain>,
+}
+
+impl Client {
+    /// Status of an entry, by its entry hash or the txid of the call writing it
+    pub fn entry_ack(&self, hash: &str) -> RpcFuture<EntryAck> {
+        self.legacy.call("entry-ack", json!({ "txid": hash }))
+    }
+
+    /// Status of a factoid transaction, by its txid
+    pub fn factoid_ack(&self, txid: &str) -> RpcFuture<FactoidAck> {
+        self.legacy.call("factoid-ack", json!({ "txid": txid }))
+    }
+
+    /// Entries waiting in the transaction pool, of one address or of all
+    pub fn pending_entries(&self, address: Option<&str>) -> RpcFuture<Vec<PendingEntry>> {
+        self.legacy.call("pending-entries", json!({ "address": address }))
+    }
+
+    /// Transactions waiting in the transaction pool, of one address or of all
+    pub fn pending_transactions(&self, address: Option<&str>) -> RpcFuture<Vec<PendingTransaction>> {
+        self.legacy.call("pending-transactions", json!({ "address": address }))
+    }
+
+    /// Entries with an external id starting with `prefix`, of one chain or of all
+    pub fn search_ext_id(&self, prefix: &str, chain_id: Option<&str>, limit: Option<u32>) -> RpcFuture<Vec<IndexedEntry>> {
+        let params = json!({ "prefix": prefix, "chainid": chain_id, "limit": limit });
+        Box::new(self.legacy.call("search-extid", params).map(|found: Entries| found.entries))
+    }
+
+    /// Chains whose first entry starts with the external ids `ext_ids`
+    pub fn search_chains(&self, ext_ids: &[String], limit: Option<u32>) -> RpcFuture<Vec<IndexedChain>> {
+        let params = json!({ "extids": ext_ids, "limit": limit });
+        Box::new(self.legacy.call("search-chains", params).map(|found: Chains| found.chains))
+    }
+
+    /// Entries written by the blocks from `from` to `to`, of one chain or of all
+    pub fn entries_by_height(
+        &self,
+        from: u64,
+        to: u64,
+        chain_id: Option<&str>,
+        limit: Option<u32>,
+    ) -> RpcFuture<Vec<IndexedEntry>> {
+        let params = json!({ "from": from, "to": to, "chainid": chain_id, "limit": limit });
+        Box::new(self.legacy.call("entries-by-height", params).map(|found: Entries| found.entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocking;
+    use factomd_rpc::{search, AckTracker, EntryIndex, NodeHealth, PendingPool};
+    use std::sync::{Arc, RwLock};
+
+    struct Index;
+
+    impl EntryIndex for Index {
+        fn entries_by_ext_id(&self, prefix: &[u8], _: Option<&[u8]>, _: u32) -> Result<Vec<search::IndexedEntry>, String> {
+            Ok(vec![search::IndexedEntry {
+                entryhash: "03".repeat(32),
+                chainid: "02".repeat(32),
+                extids: vec![hex::encode(prefix)],
+                height: 4,
+            }])
+        }
+
+        fn chains_by_ext_ids(&self, ext_ids: &[Vec<u8>], _: u32) -> Result<Vec<search::IndexedChain>, String> {
+            Ok(vec![search::IndexedChain {
+                chainid: "02".repeat(32),
+                firstentry: "03".repeat(32),
+                extids: ext_ids.iter().map(hex::encode).collect(),
+                height: 4,
+            }])
+        }
+
+        fn entries_by_height(&self, from: u64, _: u64, _: Option<&[u8]>, _: u32)
+            -> Result<Vec<search::IndexedEntry>, String> {
+            self.entries_by_ext_id(b"id", None, 1).map(|mut entries| {
+                entries[0].height = from;
+                entries
+            })
+        }
+    }
+
+    #[test]
+    fn test_served_methods() {
+        let acks = AckTracker::new();
+        acks.pending([1u8; 32], Some([3u8; 32]), 1_000);
+        acks.included([5u8; 32], None, 2_000);
+        let pending = PendingPool::new();
+        pending.update(
+            vec![factomd_rpc::PendingEntry {
+                entryhash: "03".repeat(32),
+                chainid: "02".repeat(32),
+                sender: "EC2KnJQN86MYq4pQyeSGTHSiVdkhRCPXS3udzD4im6BXRBjZFMmR".to_string(),
+                txid: "01".repeat(32),
+                status: factomd_rpc::AckStatus::NotConfirmed,
+            }],
+            vec![factomd_rpc::PendingTransaction {
+                txid: "05".repeat(32),
+                from: "FA22de5NSG2FA2HmMaD4h8qSAZAJyztmmnwgLPghCQKoSekwYYct".to_string(),
+                to: "EC2KnJQN86MYq4pQyeSGTHSiVdkhRCPXS3udzD4im6BXRBjZFMmR".to_string(),
+                amount: 100,
+                status: factomd_rpc::AckStatus::NotConfirmed,
+            }],
+        );
+        let health = Arc::new(RwLock::new(NodeHealth::starting(false, false)));
+        let server = factomd_rpc::start_rpc_server(
+            "127.0.0.1",
+            0,
+            health,
+            Some(acks),
+            Some(pending),
+            Some(Arc::new(Index)),
+            None,
+        )
+        .unwrap();
+        let url = format!("http://{}", server.address());
+        let client = blocking::Client::new(&url, &url).unwrap();
+
+        let ack = client.entry_ack(&"03".repeat(32)).unwrap();
+        assert_eq!(ack.committxid, "01".repeat(32));
+        assert_eq!(ack.entryhash, "03".repeat(32));
+        assert_eq!(ack.commitdata.status, AckStatus::NotConfirmed);
+        assert_eq!(ack.commitdata.transactiondate, Some(1_000));
+        assert_eq!(ack.entrydata.blockdate, None);
+        let ack = client.factoid_ack(&"05".repeat(32)).unwrap();
+        assert_eq!(ack.txid, "05".repeat(32));
+        assert_eq!(ack.data.status, AckStatus::TransactionAck);
+        assert_eq!(ack.data.blockdate, Some(2_000));
+        assert_eq!(client.factoid_ack(&"06".repeat(32)).unwrap().data.status, AckStatus::Unknown);
+
+        let entries = client.pending_entries(None).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].txid, "01".repeat(32));
+        assert_eq!(entries[0].status, AckStatus::NotConfirmed);
+        let transactions = client.pending_transactions(Some("FA22de5NSG2FA2HmMaD4h8qSAZAJyztmmnwgLPghCQKoSekwYYct")).unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].amount, 100);
+
+        let found = client.search_ext_id(&hex::encode("id"), None, Some(10)).unwrap();
+        assert_eq!(found[0].extids, vec![hex::encode("id")]);
+        let found = client.search_chains(&[hex::encode("a"), hex::encode("b")], None).unwrap();
+        assert_eq!(found[0].firstentry, "03".repeat(32));
+        assert_eq!(found[0].extids.len(), 2);
+        let found = client.entries_by_height(7, 9, Some(&"02".repeat(32)), None).unwrap();
+        assert_eq!(found[0].height, 7);
+    }
+}
@@ -0,0 +1,115 @@
+//! # Factomd Client
+//!
+//! A typed Rust client for factomd, in an async flavor built on futures and a
+//! blocking one in `blocking`. It speaks two APIs:
+//!
+//! | API    | Endpoint               | Calls                                        |
+//! |--------|------------------------|----------------------------------------------|
+//! | legacy | `http://<host>:8088/v2` | acks, pending submissions and entry search, see `legacy` |
+//! | node   | `http://<host>:9933`    | entries, chains and factoids as extrinsics, see `node` |
+//! |        |                         | balances, chains and costs in storage, see `state` |
+//!
+//! Entries and chains are built with `EntryBuilder` and written with
+//! `submit_entry`, signed by the account key of an EC address:
+//!
+//! ```no_run
+//! use factomd_client::{blocking, EntryBuilder, Signer};
+//!
+//! let client = blocking::Client::new(factomd_client::DEFAULT_LEGACY_URL, factomd_client::DEFAULT_NODE_URL)?;
+//! let signer = Signer::from_secret("Es37BZSs7jUpyn3HosZa79kENWfvj1AVUdZioWykTTqqvA2MRi9h")?;
+//! let chain = EntryBuilder::new().external_id("my chain").content("hello").chain()?;
+//! let submitted = client.submit_entry(&signer, &chain)?;
+//! println!("Chain {:?} pending in {:?}", submitted.chain_id, submitted.extrinsic);
+//! # Ok::<(), factomd_client::Error>(())
+//! ```
+//!
+use factomd_runtime::legacy_entry;
+use factomd_runtime::{Hash, Nonce};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+pub use entry::EntryBuilder;
+pub use factomd_runtime::legacy_entry::LegacyEntry;
+pub use node::{Signer, SubmittedEntry};
+pub use rpc::{Endpoint, RpcFuture};
+pub use state::{LatestEntry, LegacyImport, WriteCosts};
+
+pub mod blocking;
+mod entry;
+pub mod legacy;
+pub mod node;
+mod rpc;
+pub mod state;
+
+/// Legacy v2 API of a local node
+pub const DEFAULT_LEGACY_URL: &str = "http://localhost:8088/v2";
+/// JSON-RPC API of a local node
+pub const DEFAULT_NODE_URL: &str = "http://localhost:9933";
+
+/// Client failures
+#[derive(Debug)]
+pub enum Error {
+    InvalidUrl(String),
+    Http(reqwest::Error),
+    /// An error response, as the server gave it
+    Rpc {
+        code: i64,
+        message: String,
+        data: Option<Value>,
+    },
+    /// A result that doesn't have the expected form
+    InvalidResponse(String),
+    InvalidAddress(String),
+    InvalidEntry(legacy_entry::Error),
+    /// The blocking client failed to start its runtime
+    Io(std::io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidUrl(url) => write!(f, "Invalid URL {}", url),
+            Error::Http(e) => write!(f, "HTTP error: {}", e),
+            Error::Rpc { code, message, data } => match data {
+                Some(data) => write!(f, "{} ({}): {}", message, code, data),
+                None => write!(f, "{} ({})", message, code),
+            },
+            Error::InvalidResponse(reason) => write!(f, "Invalid response: {}", reason),
+            Error::InvalidAddress(address) => write!(f, "Invalid address {}", address),
+            Error::InvalidEntry(e) => write!(f, "Invalid entry: {}", e),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Async client of the legacy and node APIs of a factomd node
+///
+/// Clones share their connections, genesis hash and nonces.
+#[derive(Clone)]
+pub struct Client {
+    legacy: Endpoint,
+    node: Endpoint,
+    genesis: Arc<Mutex<Option<Hash>>>,
+    /// Nonce following the last call submitted, by account
+    nonces: Arc<Mutex<HashMap<[u8; 32], Nonce>>>,
+}
+
+impl Client {
+    pub fn new(legacy_url: &str, node_url: &str) -> Result<Self, Error> {
+        Ok(Client {
+            legacy: Endpoint::new(legacy_url)?,
+            node: Endpoint::new(node_url)?,
+            genesis: Arc::new(Mutex::new(None)),
+            nonces: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Client of a node on this machine, on the default ports
+    pub fn localhost() -> Self {
+        Self::new(DEFAULT_LEGACY_URL, DEFAULT_NODE_URL).expect("default URLs are valid; qed")
+    }
+}
@@ -0,0 +1,243 @@
+//! # Chain Calls
+//!
+//! Entries, chains and factoid transfers submitted as signed extrinsics of the
+//! runtime, through `author_submitExtrinsic` of the node's own JSON-RPC API. A
+//! call is signed with the sr25519 account key an FA or EC address holds, and
+//! paid for by that account.
+//!
+//! Calls are immortal. The nonce of a call is read from the node, or follows the
+//! last call this client submitted or composed for the account when that is
+//! later, so calls may be submitted one after another without waiting for blocks.
+//!
+use crate::rpc::RpcFuture;
+use crate::{Client, Error};
+use factomd_address::{Address, AddressKind};
+use factomd_runtime::legacy_entry::{self, LegacyEntry};
+use factomd_runtime::{Call, EntryCall, EntryStruct, FactoidCall, Hash, Nonce, UncheckedExtrinsic};
+use futures::{future, Future};
+use parity_codec::{Compact, Encode};
+use primitives::{blake2_256, sr25519, Pair};
+use runtime_primitives::generic::Era;
+use runtime_primitives::traits::{BlakeTwo256, Hash as HashT};
+use serde_json::json;
+use std::sync::{Mutex, MutexGuard};
+
+/// Payloads longer than this are signed by their Blake2 hash
+const MAX_SIGNED_PAYLOAD: usize = 256;
+
+/// An account key that signs and pays for calls
+#[derive(Clone)]
+pub struct Signer {
+    seed: [u8; 32],
+}
+
+impl Signer {
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Signer { seed }
+    }
+
+    /// The key of an Fs or Es secret address
+    pub fn from_secret(secret: &str) -> Result<Self, Error> {
+        let address: Address = secret
+            .parse()
+            .map_err(|_| Error::InvalidAddress(secret.to_string()))?;
+        match address.kind() {
+            AddressKind::FactoidSecret | AddressKind::EntryCreditSecret => Ok(Self::from_seed(*address.key())),
+            _ => Err(Error::InvalidAddress(secret.to_string())),
+        }
+    }
+
+    pub fn account(&self) -> [u8; 32] {
+        sr25519::Pair::from_seed(&self.seed).public().0
+    }
+
+    fn sign(&self, call: Call, nonce: Nonce, genesis: Hash) -> UncheckedExtrinsic {
+        let pair = sr25519::Pair::from_seed(&self.seed);
+        let era = Era::Immortal;
+        let payload = (Compact(nonce), call, era, genesis);
+        let signature = payload.using_encoded(|data| {
+            if data.len() > MAX_SIGNED_PAYLOAD {
+                pair.sign(&blake2_256(data)[..])
+            } else {
+                pair.sign(data)
+            }
+        });
+        UncheckedExtrinsic::new_signed(nonce, payload.1, pair.public().into(), signature, era)
+    }
+}
+
+/// An entry or chain in the extrinsic pool
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubmittedEntry {
+    /// Hash of the extrinsic that writes it
+    pub extrinsic: Hash,
+    pub entry_hash: Hash,
+    pub chain_id: Hash,
+}
+
+impl Client {
+    /// Write an entry in the legacy format, starting its chain if it is new
+    ///
+    /// The entry keeps its legacy entry hash and chain id, see `EntryBuilder`.
+    pub fn submit_entry(&self, signer: &Signer, entry: &LegacyEntry) -> RpcFuture<SubmittedEntry> {
+        let data = match entry.encode() {
+            Ok(data) => data,
+            Err(e) => return Box::new(future::err(Error::InvalidEntry(e))),
+        };
+        let entry_hash = Hash::from(LegacyEntry::hash(&data));
+        let chain_id = Hash::from(entry.chain_id);
+        Box::new(
+            self.submit(signer, Call::Entries(EntryCall::reveal_entry(data)))
+                .map(move |extrinsic| SubmittedEntry {
+                    extrinsic,
+                    entry_hash,
+                    chain_id,
+                }),
+        )
+    }
+
+    /// Add an entry to a chain started with `put_chain`
+    pub fn put_entry(
+        &self,
+        signer: &Signer,
+        chain_id: Hash,
+        external_ids: &[Vec<u8>],
+        content: Vec<u8>,
+    ) -> RpcFuture<SubmittedEntry> {
+        let external_ids = match legacy_entry::encode_external_ids(external_ids) {
+            Ok(external_ids) => external_ids,
+            Err(e) => return Box::new(future::err(Error::InvalidEntry(e))),
+        };
+        let entry_hash = BlakeTwo256::hash_of(&EntryStruct::new(content.clone(), external_ids.clone(), chain_id));
+        let call = Call::Entries(EntryCall::put_entry(content, external_ids, chain_id));
+        Box::new(self.submit(signer, call).map(move |extrinsic| SubmittedEntry {
+            extrinsic,
+            entry_hash,
+            chain_id,
+        }))
+    }
+
+    /// Start a chain whose id is the Blake2 hash of its encoded external ids
+    pub fn put_chain(&self, signer: &Signer, external_ids: &[Vec<u8>], content: Vec<u8>) -> RpcFuture<SubmittedEntry> {
+        let external_ids = match legacy_entry::encode_external_ids(external_ids) {
+            Ok(external_ids) => external_ids,
+            Err(e) => return Box::new(future::err(Error::InvalidEntry(e))),
+        };
+        let chain_id = BlakeTwo256::hash(&external_ids);
+        let entry_hash = BlakeTwo256::hash_of(&EntryStruct::new(content.clone(), external_ids.clone(), chain_id));
+        let call = Call::Entries(EntryCall::put_chain(content, external_ids));
+        Box::new(self.submit(signer, call).map(move |extrinsic| SubmittedEntry {
+            extrinsic,
+            entry_hash,
+            chain_id,
+        }))
+    }
+
+    /// Send factoids to an FA address
+    pub fn transfer_factoids(&self, signer: &Signer, to: &str, amount: u128) -> RpcFuture<Hash> {
+        match account(to, AddressKind::FactoidPublic) {
+            Ok(to) => self.submit(
+                signer,
+                Call::Factoids(FactoidCall::transfer_factoids(sr25519::Public(to), amount)),
+            ),
+            Err(e) => Box::new(future::err(e)),
+        }
+    }
+
+    /// Buy entry credits for an EC address, at the entry credit rate
+    pub fn buy_entry_credits(&self, signer: &Signer, to: &str, amount: u128) -> RpcFuture<Hash> {
+        match account(to, AddressKind::EntryCreditPublic) {
+            Ok(to) => self.submit(
+                signer,
+                Call::Factoids(FactoidCall::buy_entry_credits(sr25519::Public(to), amount)),
+            ),
+            Err(e) => Box::new(future::err(e)),
+        }
+    }
+
+    /// Sign a call with the next nonce of the signer, submit it, and return the
+    /// hash of the extrinsic
+    pub fn submit(&self, signer: &Signer, call: Call) -> RpcFuture<Hash> {
+        let signer = signer.clone();
+        let account = signer.account();
+        let node = self.node.clone();
+        let nonces = self.nonces.clone();
+        Box::new(
+            self.genesis_hash()
+                .join(self.next_nonce(account))
+                .and_then(move |(genesis, nonce)| {
+                    let extrinsic = signer.sign(call, nonce, genesis);
+                    node.call::<Hash>(
+                        "author_submitExtrinsic",
+                        json!([format!("0x{}", hex::encode(extrinsic.encode()))]),
+                    )
+                    .map(move |hash| {
+                        lock(&nonces).insert(account, nonce + 1);
+                        hash
+                    })
+                }),
+        )
+    }
+
+    /// Sign calls with the next nonces of the signer, without submitting them
+    ///
+    /// The nonces count as used, so that calls composed one after another may be
+    /// submitted in that order, through this client or any other.
+    pub fn compose(&self, signer: &Signer, calls: Vec<Call>) -> RpcFuture<Vec<UncheckedExtrinsic>> {
+        let signer = signer.clone();
+        let account = signer.account();
+        let nonces = self.nonces.clone();
+        Box::new(
+            self.genesis_hash()
+                .join(self.next_nonce(account))
+                .map(move |(genesis, nonce)| {
+                    let next = nonce + calls.len() as Nonce;
+                    let extrinsics = calls
+                        .into_iter()
+                        .zip(nonce..)
+                        .map(|(call, nonce)| signer.sign(call, nonce, genesis))
+                        .collect();
+                    lock(&nonces).insert(account, next);
+                    extrinsics
+                }),
+        )
+    }
+
+    /// Nonce of the next call of an account
+    pub fn next_nonce(&self, account: [u8; 32]) -> RpcFuture<Nonce> {
+        let nonces = self.nonces.clone();
+        Box::new(self.account_nonce(&account).map(move |on_chain| {
+            let submitted = lock(&nonces).get(&account).cloned().unwrap_or(0);
+            on_chain.max(submitted)
+        }))
+    }
+
+    /// Hash of the genesis block, which immortal calls are signed against
+    pub fn genesis_hash(&self) -> RpcFuture<Hash> {
+        if let Some(hash) = *lock(&self.genesis) {
+            return Box::new(future::ok(hash));
+        }
+        let genesis = self.genesis.clone();
+        Box::new(
+            self.node
+                .call::<Option<Hash>>("chain_getBlockHash", json!([0]))
+                .and_then(move |hash| {
+                    let hash = hash.ok_or_else(|| Error::InvalidResponse("No genesis block".to_string()))?;
+                    *lock(&genesis) = Some(hash);
+                    Ok(hash)
+                }),
+        )
+    }
+}
+
+/// Account key of a public address of the expected kind
+fn account(address: &str, kind: AddressKind) -> Result<[u8; 32], Error> {
+    Address::parse_kind(address, kind)
+        .and_then(|address| address.to_account())
+        .map_err(|_| Error::InvalidAddress(address.to_string()))
+}
+
+/// Shared state stays usable even if a holder panicked
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
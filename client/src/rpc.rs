@@ -0,0 +1,142 @@
+//! # JSON-RPC Transport
+//!
+//! Requests to a single JSON-RPC endpoint over HTTP. Results are deserialized
+//! into the type the caller asks for, and error responses become `Error::Rpc`
+//! with the code, message and data the server gave.
+//!
+use crate::Error;
+use futures::Future;
+use reqwest::r#async::Client as HttpClient;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Result of a call, resolved once the server answers
+pub type RpcFuture<T> = Box<dyn Future<Item = T, Error = Error> + Send>;
+
+#[derive(Serialize)]
+struct Request<'a> {
+    jsonrpc: &'static str,
+    id: usize,
+    method: &'a str,
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    #[serde(default)]
+    result: Value,
+    error: Option<ErrorObject>,
+}
+
+#[derive(Deserialize)]
+struct ErrorObject {
+    code: i64,
+    message: String,
+    #[serde(default)]
+    data: Option<Value>,
+}
+
+/// A JSON-RPC endpoint, such as factomd or walletd
+#[derive(Clone)]
+pub struct Endpoint {
+    url: reqwest::Url,
+    http: HttpClient,
+    credentials: Option<(String, String)>,
+    next_id: Arc<AtomicUsize>,
+}
+
+impl Endpoint {
+    pub fn new(url: &str) -> Result<Self, Error> {
+        Ok(Endpoint {
+            url: url.parse().map_err(|_| Error::InvalidUrl(url.to_string()))?,
+            http: HttpClient::new(),
+            credentials: None,
+            next_id: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// Authenticate every call with HTTP basic authentication, as walletd expects
+    pub fn with_credentials(mut self, user: &str, password: &str) -> Self {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        self.url.as_str()
+    }
+
+    /// Call a method and deserialize its result
+    pub fn call<T>(&self, method: &str, params: Value) -> RpcFuture<T>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let request = Request {
+            jsonrpc: "2.0",
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            method,
+            params,
+        };
+        let mut builder = self.http.post(self.url.clone()).json(&request);
+        if let Some((user, password)) = &self.credentials {
+            builder = builder.basic_auth(user, Some(password));
+        }
+
+        let method = method.to_string();
+        Box::new(
+            builder
+                .send()
+                .and_then(|response| response.error_for_status())
+                .and_then(|mut response| response.json::<Response>())
+                .map_err(Error::Http)
+                .and_then(move |response| parse(&method, response)),
+        )
+    }
+}
+
+fn parse<T: DeserializeOwned>(method: &str, response: Response) -> Result<T, Error> {
+    if let Some(error) = response.error {
+        return Err(Error::Rpc {
+            code: error.code,
+            message: error.message,
+            data: error.data,
+        });
+    }
+    serde_json::from_value(response.result)
+        .map_err(|e| Error::InvalidResponse(format!("{}: {}", method, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn response(value: Value) -> Response {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let rate: u64 = parse("rate", response(json!({ "jsonrpc": "2.0", "id": 0, "result": 1000 }))).unwrap();
+        assert_eq!(rate, 1000);
+
+        let missing: Option<String> = parse("storage", response(json!({ "jsonrpc": "2.0", "id": 0, "result": null }))).unwrap();
+        assert_eq!(missing, None);
+
+        let error = json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "error": { "code": -32009, "message": "Missing Chain Head" }
+        });
+        match parse::<u64>("chain-head", response(error)) {
+            Err(Error::Rpc { code: -32009, message, data: None }) => assert_eq!(message, "Missing Chain Head"),
+            other => panic!("Expected an RPC error, got {:?}", other),
+        }
+        match parse::<u64>("rate", response(json!({ "jsonrpc": "2.0", "id": 0, "result": "many" }))) {
+            Err(Error::InvalidResponse(_)) => {}
+            other => panic!("Expected an invalid response, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! # Chain State
+//!
+//! Balances, chains and costs read from runtime storage through `state_getStorage`
+//! of the node's own JSON-RPC API, at the best block:
+//!
+//! | Call                 | Storage                                                 |
+//! |----------------------|---------------------------------------------------------|
+//! | `balance`            | `Balances FreeBalance` of an FA account, factoshis      |
+//! |                      | `Instance0Balances FreeBalance` of an EC account        |
+//! | `ec_rate`            | `EntryCredit EcRate`, factoshis per entry credit        |
+//! | `write_costs`        | `Entry EntryCost` and `Entry ChainCost`                 |
+//! | `latest_entry`       | `Entry TotalEntries` and `Entry ChainEntries`           |
+//! | `legacy_import`      | `Entry LegacyImported` and `Entry LegacyImportComplete` |
+//!
+//! Map keys are the Blake2 hash of the storage name and the encoded key, value
+//! keys the xxHash of the storage name. Storage the runtime never wrote reads as
+//! its default.
+//!
+use crate::rpc::RpcFuture;
+use crate::{Client, Error};
+use factomd_address::{Address, AddressKind};
+use factomd_runtime::{Hash, Nonce};
+use futures::{future, Future};
+use parity_codec::{Decode, Encode};
+use primitives::{blake2_256, twox_128};
+use serde_json::json;
+
+/// Default of `Entry EntryCost`
+const DEFAULT_ENTRY_COST: u64 = 1;
+/// Default of `Entry ChainCost`
+const DEFAULT_CHAIN_COST: u64 = 2;
+/// Default of `EntryCredit EcRate`
+const DEFAULT_EC_RATE: u64 = 1;
+
+/// Entry credits an entry spends, see `factomd_runtime::write_cost`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WriteCosts {
+    /// Per KiB of an entry
+    pub entry_cost: u64,
+    /// For the first KiB of an entry that starts a chain
+    pub chain_cost: u64,
+}
+
+/// The last entry written to a chain
+#[derive(Clone, Debug, PartialEq)]
+pub struct LatestEntry {
+    pub entry_hash: Hash,
+    /// Entries the chain has
+    pub entries: u64,
+}
+
+/// Progress of the import of legacy entries
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LegacyImport {
+    /// Entries imported, at genesis or in batches
+    pub imported: u64,
+    /// Whether the last batch has been imported
+    pub complete: bool,
+}
+
+impl Client {
+    /// Factoshis of an FA address, or entry credits of an EC address
+    pub fn balance(&self, address: &str) -> RpcFuture<u128> {
+        let invalid = || Error::InvalidAddress(address.to_string());
+        let parsed = match address.parse::<Address>() {
+            Ok(parsed) => parsed,
+            Err(_) => return Box::new(future::err(invalid())),
+        };
+        let name: &[u8] = match parsed.kind() {
+            AddressKind::FactoidPublic => b"Balances FreeBalance",
+            AddressKind::EntryCreditPublic => b"Instance0Balances FreeBalance",
+            _ => return Box::new(future::err(invalid())),
+        };
+        let account = match parsed.to_account() {
+            Ok(account) => account,
+            Err(_) => return Box::new(future::err(invalid())),
+        };
+        Box::new(self.storage(map_key(name, &account)).map(Option::unwrap_or_default))
+    }
+
+    /// Factoshis one entry credit costs
+    pub fn ec_rate(&self) -> RpcFuture<u64> {
+        Box::new(
+            self.storage(value_key(b"EntryCredit EcRate"))
+                .map(|rate| rate.unwrap_or(DEFAULT_EC_RATE)),
+        )
+    }
+
+    /// What entries and new chains cost
+    pub fn write_costs(&self) -> RpcFuture<WriteCosts> {
+        Box::new(
+            self.storage(value_key(b"Entry EntryCost"))
+                .join(self.storage(value_key(b"Entry ChainCost")))
+                .map(|(entry_cost, chain_cost)| WriteCosts {
+                    entry_cost: entry_cost.unwrap_or(DEFAULT_ENTRY_COST),
+                    chain_cost: chain_cost.unwrap_or(DEFAULT_CHAIN_COST),
+                }),
+        )
+    }
+
+    /// The last entry of a chain, or none for a chain that doesn't exist
+    pub fn latest_entry(&self, chain_id: Hash) -> RpcFuture<Option<LatestEntry>> {
+        let client = self.clone();
+        Box::new(
+            self.storage::<u64>(map_key(b"Entry TotalEntries", &chain_id))
+                .and_then(move |entries| -> RpcFuture<Option<LatestEntry>> {
+                    let entries = match entries {
+                        Some(entries) if entries > 0 => entries,
+                        _ => return Box::new(future::ok(None)),
+                    };
+                    // Positions in a chain count from 1, the last one is the number of entries
+                    Box::new(
+                        client
+                            .storage::<Hash>(map_key(b"Entry ChainEntries", &(chain_id, entries)))
+                            .and_then(move |entry_hash| {
+                                let entry_hash = entry_hash.ok_or_else(|| {
+                                    Error::InvalidResponse(format!("Chain {:?} misses its last entry", chain_id))
+                                })?;
+                                Ok(Some(LatestEntry { entry_hash, entries }))
+                            }),
+                    )
+                }),
+        )
+    }
+
+    /// How far the import of legacy entries got
+    pub fn legacy_import(&self) -> RpcFuture<LegacyImport> {
+        Box::new(
+            self.storage(value_key(b"Entry LegacyImported"))
+                .join(self.storage(value_key(b"Entry LegacyImportComplete")))
+                .map(|(imported, complete)| LegacyImport {
+                    imported: imported.unwrap_or_default(),
+                    complete: complete.unwrap_or_default(),
+                }),
+        )
+    }
+
+    /// Nonce the system module holds for an account
+    pub(crate) fn account_nonce(&self, account: &[u8; 32]) -> RpcFuture<Nonce> {
+        Box::new(
+            self.storage(map_key(b"System AccountNonce", account))
+                .map(Option::unwrap_or_default),
+        )
+    }
+
+    /// Decoded value under a storage key, if there is one
+    fn storage<T: Decode + Send + 'static>(&self, key: Vec<u8>) -> RpcFuture<Option<T>> {
+        Box::new(
+            self.node
+                .call::<Option<String>>("state_getStorage", json!([format!("0x{}", hex::encode(key))]))
+                .and_then(|value| value.map(|value| decode_hex(&value)).transpose()),
+        )
+    }
+}
+
+/// Storage key of an entry of a map, such as `map_key(b"Entry TotalEntries", &chain_id)`
+pub fn map_key<K: Encode>(name: &[u8], key: &K) -> Vec<u8> {
+    let mut data = name.to_vec();
+    key.encode_to(&mut data);
+    blake2_256(&data).to_vec()
+}
+
+/// Storage key of a value, such as `value_key(b"EntryCredit EcRate")`
+pub fn value_key(name: &[u8]) -> Vec<u8> {
+    twox_128(name).to_vec()
+}
+
+fn decode_hex<T: Decode>(value: &str) -> Result<T, Error> {
+    let invalid = || Error::InvalidResponse(format!("Undecodable storage value {}", value));
+    let bytes = hex::decode(value.trim_start_matches("0x")).map_err(|_| invalid())?;
+    T::decode(&mut &bytes[..]).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocking;
+    use jsonrpc_core::{IoHandler, Params, Value};
+    use jsonrpc_http_server::ServerBuilder;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_storage() {
+        let fa = Address::new(AddressKind::FactoidPublic, [1u8; 32]).to_string();
+        let ec = Address::new(AddressKind::EntryCreditPublic, [1u8; 32]).to_string();
+        let chain_id = Hash::from([2u8; 32]);
+        let entry_hash = Hash::from([3u8; 32]);
+        let mut storage = HashMap::new();
+        storage.insert(map_key(b"Balances FreeBalance", &[1u8; 32]), 500u128.encode());
+        storage.insert(map_key(b"Instance0Balances FreeBalance", &[1u8; 32]), 7u128.encode());
+        storage.insert(value_key(b"Entry ChainCost"), 10u64.encode());
+        storage.insert(map_key(b"Entry TotalEntries", &chain_id), 2u64.encode());
+        storage.insert(map_key(b"Entry ChainEntries", &(chain_id, 2u64)), entry_hash.encode());
+        storage.insert(value_key(b"Entry LegacyImported"), 5u64.encode());
+
+        let mut io = IoHandler::new();
+        io.add_method("state_getStorage", move |params: Params| {
+            let (key,): (String,) = params.parse()?;
+            let key = hex::decode(&key[2..]).unwrap();
+            Ok(storage
+                .get(&key)
+                .map(|value| Value::String(format!("0x{}", hex::encode(value))))
+                .unwrap_or(Value::Null))
+        });
+        let server = ServerBuilder::new(io)
+            .start_http(&"127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        let url = format!("http://{}", server.address());
+        let client = blocking::Client::new(&url, &url).unwrap();
+
+        assert_eq!(client.balance(&fa).unwrap(), 500);
+        assert_eq!(client.balance(&ec).unwrap(), 7);
+        // The account of a legacy address holds nothing until its balance is claimed
+        assert_eq!(client.balance("FA2jK2HcLnRdS94dEcU27rF3meoJfpUcZPSinpb7AwQvPRY6RL1Q").unwrap(), 0);
+        assert!(client.balance("Fs1KWJrpLdfucvmYwN2nWrwepLn8ercpMbzXshd1g8zyhKXLVLWj").is_err());
+        assert_eq!(client.ec_rate().unwrap(), DEFAULT_EC_RATE);
+        assert_eq!(
+            client.write_costs().unwrap(),
+            WriteCosts {
+                entry_cost: DEFAULT_ENTRY_COST,
+                chain_cost: 10
+            }
+        );
+        assert_eq!(
+            client.latest_entry(chain_id).unwrap(),
+            Some(LatestEntry { entry_hash, entries: 2 })
+        );
+        assert_eq!(client.latest_entry(entry_hash).unwrap(), None);
+        assert_eq!(
+            client.legacy_import().unwrap(),
+            LegacyImport {
+                imported: 5,
+                complete: false
+            }
+        );
+    }
+}
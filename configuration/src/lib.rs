@@ -73,10 +73,30 @@ pub struct Rpc {
     /// HTTP-RPC listening port
     #[structopt(long = "rpc-port", default_value = "8088")]
     pub rpc_port: u16,
+
+    /// WebSocket listening port for subscriptions, on the HTTP-RPC interface
+    #[structopt(long = "ws-port", default_value = "8087")]
+    pub ws_port: u16,
+}
+
+/// Prometheus metrics settings
+#[derive(StructOpt, Debug, Deserialize)]
+pub struct Metrics {
+    /// Serve Prometheus metrics at /metrics
+    #[structopt(long = "enable-metrics")]
+    pub enable_metrics: bool,
+
+    /// Metrics listening interface
+    #[structopt(long = "metrics-addr", default_value = "127.0.0.1")]
+    pub metrics_addr: String,
+
+    /// Metrics listening port
+    #[structopt(long = "metrics-port", default_value = "9615")]
+    pub metrics_port: u16,
 }
 
 /// Walletd Settings
-/// Takes a env var for password, may use a password entry input in the future
+/// Takes an env var for the password, prompted for if empty
 #[derive(StructOpt, Debug, Deserialize)]
 pub struct Walletd {
     /// Set walletd user for authentication
@@ -86,12 +106,78 @@ pub struct Walletd {
     /// Set env variable to get walletd password
     #[structopt(long = "walletd-env-var", default_value = "")]
     pub walletd_env_var: String,
+
+    /// Directory of the encrypted wallet
+    #[structopt(long = "wallet-path", default_value = "wallet")]
+    pub wallet_path: String,
+
+    /// Set env variable with the 12 word mnemonic to restore the wallet from
+    #[structopt(long = "walletd-mnemonic-env", default_value = "")]
+    pub walletd_mnemonic_env: String,
+
+    /// Set walletd JSON-RPC address
+    #[structopt(long = "walletd-addr", default_value = "127.0.0.1")]
+    pub walletd_addr: String,
+
+    /// Set walletd JSON-RPC port
+    #[structopt(long = "walletd-port", default_value = "8089")]
+    pub walletd_port: u16,
+
+    /// JSON-RPC API of the node that composed extrinsics are signed for
+    #[structopt(long = "walletd-node-url", default_value = "http://localhost:9933")]
+    pub walletd_node_url: String,
+}
+
+/// Keystore Settings
+#[derive(StructOpt, Debug, Deserialize)]
+pub struct Keystore {
+    /// Directory holding encrypted keys
+    #[structopt(long = "keystore-path", default_value = "keystore")]
+    pub keystore_path: String,
+
+    /// Environment variable holding the keystore password, prompted for if empty
+    #[structopt(long = "keystore-password-env", default_value = "")]
+    pub keystore_password_env: String,
+}
+
+/// Event export settings
+///
+/// Every sink left empty is disabled.
+#[derive(StructOpt, Debug, Deserialize)]
+pub struct Export {
+    /// Stream events as JSON lines to a TCP consumer, host:port
+    #[structopt(long = "export-tcp", default_value = "")]
+    pub export_tcp: String,
+
+    /// Append events as JSON lines to a file
+    #[structopt(long = "export-file", default_value = "")]
+    pub export_file: String,
+
+    /// POST events as JSON lines to a webhook URL
+    #[structopt(long = "export-webhook", default_value = "")]
+    pub export_webhook: String,
+
+    /// Events a sink may fall behind by before new ones are dropped
+    #[structopt(long = "export-queue", default_value = "10000")]
+    pub export_queue: usize,
+}
+
+/// Entry index settings
+#[derive(StructOpt, Debug, Deserialize)]
+pub struct Index {
+    /// Index imported blocks into SQLite and answer the search methods
+    #[structopt(long = "enable-index")]
+    pub enable_index: bool,
+
+    /// SQLite database of the index
+    #[structopt(long = "index-path", default_value = "index.sqlite")]
+    pub index_path: String,
 }
 
 /// Factom Server Settings
 #[derive(StructOpt, Debug, Deserialize)]
 pub struct Server {
-    /// Set network to join
+    /// Set network to join: dev, local, staging or a chain spec JSON file
     #[structopt(short = "n", long = "network", default_value = "local")]
     pub network: String,
 
@@ -119,6 +205,474 @@ pub struct Server {
     /// Base path for database
     #[structopt(long = "base-path", default_value = "")]
     pub base_path: String,
+
+    /// Blocks of state to keep, or archive to keep all
+    #[structopt(long = "pruning", default_value = "256")]
+    pub pruning: String,
+
+    /// Size of the state cache in bytes
+    #[structopt(long = "state-cache-size", default_value = "67108864")]
+    pub state_cache_size: usize,
+
+    /// Maximum number of inbound peers
+    #[structopt(long = "in-peers", default_value = "25")]
+    pub in_peers: u32,
+
+    /// Maximum number of outbound peers
+    #[structopt(long = "out-peers", default_value = "25")]
+    pub out_peers: u32,
+
+    /// Nodes to stay connected to, as multiaddresses ending in /p2p/<peer id>
+    #[structopt(long = "reserved-nodes", raw(number_of_values = "1"))]
+    pub reserved_nodes: Vec<String>,
+
+    /// Only connect to reserved nodes
+    #[structopt(long = "reserved-only")]
+    pub reserved_only: bool,
+
+    /// Multiaddresses to listen on, instead of every interface at port
+    #[structopt(long = "listen-addr", raw(number_of_values = "1"))]
+    pub listen_addresses: Vec<String>,
+
+    /// Telemetry servers to report to, as ws:// or wss:// URLs
+    #[structopt(long = "telemetry-url", raw(number_of_values = "1"))]
+    pub telemetry_urls: Vec<String>,
+
+    /// Telemetry verbosity, 0 reports the least
+    #[structopt(long = "telemetry-verbosity", default_value = "0")]
+    pub telemetry_verbosity: u8,
+
+    /// Don't report to any telemetry server, including those of the chain spec
+    #[structopt(long = "no-telemetry")]
+    pub no_telemetry: bool,
+
+    /// Sentry nodes an authority connects through, and only through
+    #[structopt(long = "sentry-nodes", raw(number_of_values = "1"))]
+    pub sentry_nodes: Vec<String>,
+
+    /// Authorities a full node is a sentry for
+    #[structopt(long = "sentry-for", raw(number_of_values = "1"))]
+    pub sentry_for: Vec<String>,
+}
+
+impl Server {
+    /// Check the settings that their types don't
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pruning != "archive" {
+            match self.pruning.parse::<u32>() {
+                Ok(blocks) if blocks > 0 => {}
+                _ => {
+                    return Err(format!(
+                        "Invalid pruning {}, expected a number of blocks or archive",
+                        self.pruning
+                    ))
+                }
+            }
+        }
+        if self.reserved_only && self.reserved_nodes.is_empty() {
+            return Err("reserved_only is set without any reserved_nodes".to_string());
+        }
+        for node in &self.reserved_nodes {
+            check_peer_address("reserved node", node)?;
+        }
+        for node in &self.sentry_nodes {
+            check_peer_address("sentry node", node)?;
+        }
+        for node in &self.sentry_for {
+            check_peer_address("sentry_for authority", node)?;
+        }
+        for addr in &self.listen_addresses {
+            if !addr.starts_with('/') {
+                return Err(format!("Invalid listen address {}, expected a multiaddress", addr));
+            }
+        }
+        for url in &self.telemetry_urls {
+            if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                return Err(format!("Invalid telemetry URL {}, expected ws:// or wss://", url));
+            }
+        }
+        if self.no_telemetry && !self.telemetry_urls.is_empty() {
+            return Err("no_telemetry is set along with telemetry_urls".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Peers are dialed by multiaddresses that end in their peer id
+fn check_peer_address(what: &str, addr: &str) -> Result<(), String> {
+    if !addr.starts_with('/') || !addr.contains("/p2p/") {
+        return Err(format!(
+            "Invalid {} {}, expected a multiaddress ending in /p2p/<peer id>",
+            what, addr
+        ));
+    }
+    Ok(())
+}
+
+/// Balance of an account at genesis
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct GenesisBalance {
+    /// SS58 account key, FA or EC address, or secret URI, e.g. `//Alice`
+    pub account: String,
+
+    /// Balance in base units
+    pub amount: u64,
+}
+
+/// Genesis Settings
+///
+/// Used to build the chain spec when the network is `genesis`. These are only read
+/// from configuration files, a genesis is too much to type on the command line.
+/// Keys are SS58 encoded public keys or secret URIs such as `//Alice`. Accounts may
+/// also be given as FA or EC addresses, balances of legacy keys go in `snapshot`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Genesis {
+    /// Human readable name of the network
+    pub name: String,
+
+    /// Network id, also used as the directory name for the database
+    pub id: String,
+
+    /// Bootnodes written into the chain spec
+    pub bootnodes: Vec<String>,
+
+    /// ed25519 keys of the initial authorities
+    pub authorities: Vec<String>,
+
+    /// Factoid balances
+    pub fct_balances: Vec<GenesisBalance>,
+
+    /// Entry credit balances
+    pub ec_balances: Vec<GenesisBalance>,
+
+    /// Account allowed to dispatch privileged calls
+    pub sudo_key: String,
+
+    /// Factoid units spent to buy one entry credit
+    pub ec_rate: u64,
+
+    /// Maximum combined size in bytes of an entry's content and external ids
+    pub max_entry_size: u32,
+
+    /// Entry credits spent per KiB of an entry
+    pub entry_cost: u64,
+
+    /// Entry credits spent for the first KiB of an entry that starts a chain
+    pub chain_cost: u64,
+
+    /// Smallest balance an account may hold
+    pub existential_deposit: u64,
+
+    /// Legacy balance snapshot, `.csv` or `.json`, to add to the balances
+    pub snapshot: String,
+}
+
+impl Default for Genesis {
+    fn default() -> Self {
+        Genesis {
+            name: "Factom".to_string(),
+            id: "factom".to_string(),
+            bootnodes: vec![],
+            authorities: vec![],
+            fct_balances: vec![],
+            ec_balances: vec![],
+            sudo_key: "".to_string(),
+            ec_rate: 1,
+            max_entry_size: 1024,
+            entry_cost: 1,
+            chain_cost: 2,
+            existential_deposit: 500,
+            snapshot: "".to_string(),
+        }
+    }
+}
+
+impl Genesis {
+    /// Check the settings a genesis can't be built without
+    ///
+    /// Only checked when the spec is built from this section, other networks leave
+    /// it at its defaults.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.authorities.is_empty() {
+            return Err(ConfigError::Message(
+                "Genesis needs at least one authority".to_string(),
+            ));
+        }
+        if self.sudo_key.is_empty() {
+            return Err(ConfigError::Message(
+                "Genesis needs a sudo_key, an empty key is the public development key".to_string(),
+            ));
+        }
+        if self.ec_rate == 0 {
+            return Err(ConfigError::Message(
+                "Genesis ec_rate must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Genesis is flattened into FactomConfig so that it is read along with the rest of
+// the configuration files, but it adds nothing to the command line. These stand in
+// for what `derive(StructOpt)` would generate.
+#[doc(hidden)]
+impl Genesis {
+    pub fn augment_clap<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app
+    }
+
+    pub fn is_subcommand() -> bool {
+        false
+    }
+}
+
+impl StructOpt for Genesis {
+    fn clap<'a, 'b>() -> App<'a, 'b> {
+        Genesis::augment_clap(App::new("genesis"))
+    }
+
+    fn from_clap(_matches: &clap::ArgMatches) -> Self {
+        Genesis::default()
+    }
+}
+
+/// Chain spec export settings
+#[derive(StructOpt, Debug)]
+pub struct BuildSpec {
+    /// Write genesis storage in its raw key/value form
+    #[structopt(long = "raw")]
+    pub raw: bool,
+
+    /// File to write the chain spec to, stdout if empty
+    #[structopt(long = "output", short = "o", default_value = "")]
+    pub output: String,
+}
+
+/// Legacy snapshot check settings
+#[derive(StructOpt, Debug)]
+pub struct CheckSnapshot {
+    /// Legacy balance snapshot, `.csv` or `.json`
+    #[structopt(long = "file", short = "f")]
+    pub file: String,
+}
+
+/// Legacy import settings
+#[derive(StructOpt, Debug)]
+pub struct ImportLegacy {
+    /// Directory with the `dblocks`, `eblocks` and `entries` of a legacy node
+    #[structopt(long = "dump", short = "d")]
+    pub dump: String,
+
+    /// Write genesis storage in its raw key/value form
+    #[structopt(long = "raw")]
+    pub raw: bool,
+
+    /// File to write the chain spec to, stdout if empty
+    #[structopt(long = "output", short = "o", default_value = "")]
+    pub output: String,
+
+    /// JSON-RPC URL of a running node to submit the entries to in batches, rather
+    /// than writing them into genesis
+    #[structopt(long = "node", default_value = "")]
+    pub node: String,
+
+    /// Keystore name of the FCT key of the sudo account, which signs the batches
+    #[structopt(long = "key", default_value = "")]
+    pub key: String,
+}
+
+/// Chain database removal settings
+#[derive(StructOpt, Debug)]
+pub struct PurgeChain {
+    /// Remove the database without asking for confirmation
+    #[structopt(long = "yes", short = "y")]
+    pub yes: bool,
+}
+
+/// Block export settings
+#[derive(StructOpt, Debug)]
+pub struct ExportBlocks {
+    /// File to write the blocks to, stdout if empty
+    #[structopt(long = "output", short = "o", default_value = "")]
+    pub output: String,
+
+    /// First block to export
+    #[structopt(long = "from")]
+    pub from: Option<u64>,
+
+    /// Last block to export, the best block if not given
+    #[structopt(long = "to")]
+    pub to: Option<u64>,
+
+    /// Write blocks as JSON rather than binary
+    #[structopt(long = "json")]
+    pub json: bool,
+}
+
+/// Block import settings
+#[derive(StructOpt, Debug)]
+pub struct ImportBlocks {
+    /// File to read binary blocks from, stdin if empty
+    #[structopt(long = "input", short = "i", default_value = "")]
+    pub input: String,
+}
+
+/// Revert settings
+#[derive(StructOpt, Debug)]
+pub struct Revert {
+    /// Number of unfinalized blocks to revert
+    #[structopt(long = "blocks", default_value = "256")]
+    pub blocks: u64,
+}
+
+/// Local network settings
+#[derive(StructOpt, Debug)]
+pub struct Localnet {
+    /// Number of authority nodes to start
+    #[structopt(long = "nodes", default_value = "2")]
+    pub nodes: u16,
+
+    /// P2P port of the first node, the others count up from it
+    #[structopt(long = "base-p2p-port", default_value = "30333")]
+    pub base_p2p_port: u16,
+
+    /// RPC port of the first node, the others count up from it
+    #[structopt(long = "base-rpc-port", default_value = "8088")]
+    pub base_rpc_port: u16,
+
+    /// Directory for keys, databases and logs, a temporary one if empty
+    #[structopt(long = "dir", default_value = "")]
+    pub dir: String,
+}
+
+/// Key generation settings
+#[derive(StructOpt, Debug)]
+pub struct KeyGenerate {
+    /// Kind of key: authority, network, fct or ec
+    #[structopt(long = "kind", raw(possible_values = "&KEY_KINDS"))]
+    pub kind: String,
+
+    /// Name to store the key under
+    #[structopt(long = "name")]
+    pub name: String,
+
+    /// Derive an FCT or EC key from a new 12 word mnemonic, printed once
+    #[structopt(long = "mnemonic")]
+    pub mnemonic: bool,
+}
+
+/// Key import settings
+#[derive(StructOpt, Debug)]
+pub struct KeyImport {
+    /// Kind of key: authority, network, fct or ec
+    #[structopt(long = "kind", raw(possible_values = "&KEY_KINDS"))]
+    pub kind: String,
+
+    /// Name to store the key under
+    #[structopt(long = "name")]
+    pub name: String,
+
+    /// Hex encoded 32 byte seed, or an Fs or Es address; prompted for if empty
+    #[structopt(long = "secret", default_value = "")]
+    pub secret: String,
+
+    /// Read the secret as a 12 word mnemonic and derive an FCT or EC key from it
+    #[structopt(long = "mnemonic")]
+    pub mnemonic: bool,
+
+    /// Address index of the key derived from a mnemonic
+    #[structopt(long = "index", default_value = "0")]
+    pub index: u32,
+}
+
+/// Key inspection settings
+#[derive(StructOpt, Debug)]
+pub struct KeyInspect {
+    /// Name of the key
+    #[structopt(long = "name")]
+    pub name: String,
+
+    /// Decrypt the key and print its secret as well
+    #[structopt(long = "show-secret")]
+    pub show_secret: bool,
+}
+
+/// Key listing settings
+#[derive(StructOpt, Debug)]
+pub struct KeyList {
+    /// Only list keys of this kind
+    #[structopt(long = "kind", raw(possible_values = "&KEY_KINDS"))]
+    pub kind: Option<String>,
+}
+
+/// Kinds of keys the keystore holds
+pub const KEY_KINDS: [&str; 4] = ["authority", "network", "fct", "ec"];
+
+/// Keystore subcommands
+#[derive(StructOpt, Debug)]
+pub enum KeyCommand {
+    /// Generate a new key and store it encrypted
+    #[structopt(name = "generate")]
+    Generate(KeyGenerate),
+
+    /// Store an existing secret encrypted
+    #[structopt(name = "import")]
+    Import(KeyImport),
+
+    /// Print the public key and addresses of a stored key
+    #[structopt(name = "inspect")]
+    Inspect(KeyInspect),
+
+    /// List stored keys
+    #[structopt(name = "list")]
+    List(KeyList),
+}
+
+/// Factomd subcommands
+///
+/// Running factomd without a subcommand starts a node.
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Write the chain spec of the selected network as JSON
+    #[structopt(name = "build-spec")]
+    BuildSpec(BuildSpec),
+
+    /// Convert a legacy balance snapshot and report the supply it carries over
+    #[structopt(name = "check-snapshot")]
+    CheckSnapshot(CheckSnapshot),
+
+    /// Validate legacy blocks and import their entries into genesis or a running network
+    #[structopt(name = "import-legacy")]
+    ImportLegacy(ImportLegacy),
+
+    /// Manage the keys in the keystore
+    #[structopt(name = "key")]
+    Key(KeyCommand),
+
+    /// Remove the chain database of the selected network
+    #[structopt(name = "purge-chain")]
+    PurgeChain(PurgeChain),
+
+    /// Export blocks of the selected network to a file
+    #[structopt(name = "export-blocks")]
+    ExportBlocks(ExportBlocks),
+
+    /// Import blocks into the selected network from a file
+    #[structopt(name = "import-blocks")]
+    ImportBlocks(ImportBlocks),
+
+    /// Revert the chain of the selected network by a number of blocks
+    #[structopt(name = "revert")]
+    Revert(Revert),
+
+    /// Start a network of authority nodes on this machine
+    #[structopt(name = "localnet")]
+    Localnet(Localnet),
+
+    /// Serve the wallet over the legacy factom-walletd JSON-RPC API
+    #[structopt(name = "walletd")]
+    Walletd,
 }
 
 /// FactomConfig used for setting up your Factom node
@@ -141,13 +695,39 @@ pub struct FactomConfig {
     #[structopt(flatten)]
     pub rpc: Rpc,
 
+    #[allow(missing_docs)]
+    #[structopt(flatten)]
+    pub metrics: Metrics,
+
     #[allow(missing_docs)]
     #[structopt(flatten)]
     pub walletd: Walletd,
 
+    #[allow(missing_docs)]
+    #[structopt(flatten)]
+    pub keystore: Keystore,
+
+    #[allow(missing_docs)]
+    #[structopt(flatten)]
+    pub export: Export,
+
+    #[allow(missing_docs)]
+    #[structopt(flatten)]
+    pub index: Index,
+
+    #[allow(missing_docs)]
+    #[structopt(flatten)]
+    #[serde(default)]
+    pub genesis: Genesis,
+
     /// Generate completions
     #[structopt(long = "completions", raw(possible_values = "&Shell::variants()"))]
     pub completions: Option<String>,
+
+    /// Subcommand to run instead of starting a node
+    #[serde(skip)]
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
 }
 
 /// Factom Configuration has a specific override order
@@ -170,7 +750,9 @@ impl FactomConfig {
         }
 
         let file_args = FactomConfig::load_from_path(&cli_args.custom_config)?;
-        let final_config = FactomConfig::check_cli(matches, file_args);
+        let mut final_config = FactomConfig::check_cli(matches, file_args);
+        final_config.command = cli_args.command;
+        final_config.server.validate().map_err(ConfigError::Message)?;
 
         Ok(final_config)
     }
@@ -227,6 +809,62 @@ impl FactomConfig {
                 config.server.base_path = value.to_string();
             }
         }
+        if matches.occurrences_of("pruning") > 0 {
+            if let Some(value) = matches.value_of("pruning") {
+                config.server.pruning = value.to_string();
+            }
+        }
+        if matches.occurrences_of("state_cache_size") > 0 {
+            if let Some(value) = matches.value_of("state_cache_size") {
+                config.server.state_cache_size = value.parse::<usize>().expect("Invalid state cache size!");
+            }
+        }
+        if matches.occurrences_of("in_peers") > 0 {
+            if let Some(value) = matches.value_of("in_peers") {
+                config.server.in_peers = value.parse::<u32>().expect("Invalid number of peers!");
+            }
+        }
+        if matches.occurrences_of("out_peers") > 0 {
+            if let Some(value) = matches.value_of("out_peers") {
+                config.server.out_peers = value.parse::<u32>().expect("Invalid number of peers!");
+            }
+        }
+        if matches.occurrences_of("reserved_nodes") > 0 {
+            if let Some(values) = matches.values_of("reserved_nodes") {
+                config.server.reserved_nodes = values.map(String::from).collect();
+            }
+        }
+        if matches.occurrences_of("reserved_only") > 0 {
+            config.server.reserved_only = true;
+        }
+        if matches.occurrences_of("listen_addresses") > 0 {
+            if let Some(values) = matches.values_of("listen_addresses") {
+                config.server.listen_addresses = values.map(String::from).collect();
+            }
+        }
+        if matches.occurrences_of("telemetry_urls") > 0 {
+            if let Some(values) = matches.values_of("telemetry_urls") {
+                config.server.telemetry_urls = values.map(String::from).collect();
+            }
+        }
+        if matches.occurrences_of("telemetry_verbosity") > 0 {
+            if let Some(value) = matches.value_of("telemetry_verbosity") {
+                config.server.telemetry_verbosity = value.parse::<u8>().expect("Invalid telemetry verbosity!");
+            }
+        }
+        if matches.occurrences_of("no_telemetry") > 0 {
+            config.server.no_telemetry = true;
+        }
+        if matches.occurrences_of("sentry_nodes") > 0 {
+            if let Some(values) = matches.values_of("sentry_nodes") {
+                config.server.sentry_nodes = values.map(String::from).collect();
+            }
+        }
+        if matches.occurrences_of("sentry_for") > 0 {
+            if let Some(values) = matches.values_of("sentry_for") {
+                config.server.sentry_for = values.map(String::from).collect();
+            }
+        }
         if matches.occurrences_of("log_level") > 0 {
             if let Some(value) = matches.value_of("log_level") {
                 config.log.log_level = value.parse::<LogLevel>().expect("Invalid log level!");
@@ -243,6 +881,69 @@ impl FactomConfig {
                 println!("{:?}", config.walletd.walletd_env_var);
             }
         }
+        if matches.occurrences_of("wallet_path") > 0 {
+            if let Some(value) = matches.value_of("wallet_path") {
+                config.walletd.wallet_path = value.to_string();
+            }
+        }
+        if matches.occurrences_of("walletd_mnemonic_env") > 0 {
+            if let Some(value) = matches.value_of("walletd_mnemonic_env") {
+                config.walletd.walletd_mnemonic_env = value.to_string();
+            }
+        }
+        if matches.occurrences_of("walletd_addr") > 0 {
+            if let Some(value) = matches.value_of("walletd_addr") {
+                config.walletd.walletd_addr = value.to_string();
+            }
+        }
+        if matches.occurrences_of("walletd_port") > 0 {
+            if let Some(value) = matches.value_of("walletd_port") {
+                config.walletd.walletd_port = value.parse::<u16>().expect("Invalid port value!");
+            }
+        }
+        if matches.occurrences_of("walletd_node_url") > 0 {
+            if let Some(value) = matches.value_of("walletd_node_url") {
+                config.walletd.walletd_node_url = value.to_string();
+            }
+        }
+        if matches.occurrences_of("keystore_path") > 0 {
+            if let Some(value) = matches.value_of("keystore_path") {
+                config.keystore.keystore_path = value.to_string();
+            }
+        }
+        if matches.occurrences_of("keystore_password_env") > 0 {
+            if let Some(value) = matches.value_of("keystore_password_env") {
+                config.keystore.keystore_password_env = value.to_string();
+            }
+        }
+        if matches.occurrences_of("export_tcp") > 0 {
+            if let Some(value) = matches.value_of("export_tcp") {
+                config.export.export_tcp = value.to_string();
+            }
+        }
+        if matches.occurrences_of("export_file") > 0 {
+            if let Some(value) = matches.value_of("export_file") {
+                config.export.export_file = value.to_string();
+            }
+        }
+        if matches.occurrences_of("export_webhook") > 0 {
+            if let Some(value) = matches.value_of("export_webhook") {
+                config.export.export_webhook = value.to_string();
+            }
+        }
+        if matches.occurrences_of("export_queue") > 0 {
+            if let Some(value) = matches.value_of("export_queue") {
+                config.export.export_queue = value.parse::<usize>().expect("Invalid export queue size!");
+            }
+        }
+        if matches.occurrences_of("enable_index") > 0 {
+            config.index.enable_index = true;
+        }
+        if matches.occurrences_of("index_path") > 0 {
+            if let Some(value) = matches.value_of("index_path") {
+                config.index.index_path = value.to_string();
+            }
+        }
         if matches.occurrences_of("rpc_addr") > 0 {
             if let Some(value) = matches.value_of("rpc_addr") {
                 config.rpc.rpc_addr = value.to_string();
@@ -253,9 +954,27 @@ impl FactomConfig {
                 config.rpc.rpc_port = value.parse::<u16>().expect("Invalid port value!");
             }
         }
+        if matches.occurrences_of("ws_port") > 0 {
+            if let Some(value) = matches.value_of("ws_port") {
+                config.rpc.ws_port = value.parse::<u16>().expect("Invalid port value!");
+            }
+        }
         if matches.occurrences_of("disable_rpc") > 0 {
             config.rpc.disable_rpc = true;
         }
+        if matches.occurrences_of("enable_metrics") > 0 {
+            config.metrics.enable_metrics = true;
+        }
+        if matches.occurrences_of("metrics_addr") > 0 {
+            if let Some(value) = matches.value_of("metrics_addr") {
+                config.metrics.metrics_addr = value.to_string();
+            }
+        }
+        if matches.occurrences_of("metrics_port") > 0 {
+            if let Some(value) = matches.value_of("metrics_port") {
+                config.metrics.metrics_port = value.parse::<u16>().expect("Invalid port value!");
+            }
+        }
         config
     }
 
@@ -282,14 +1001,46 @@ mod tests {
 
         assert_eq!(config.rpc.rpc_addr, "127.0.0.1");
         assert_eq!(config.rpc.rpc_port, 8088);
+        assert_eq!(config.rpc.ws_port, 8087);
+        assert!(!config.metrics.enable_metrics);
+        assert_eq!(config.metrics.metrics_addr, "127.0.0.1");
+        assert_eq!(config.metrics.metrics_port, 9615);
         assert_eq!(config.server.network, "local");
         assert_eq!(config.log.log_level, LogLevel::DEBUG);
         assert_eq!(config.walletd.walletd_user, "");
         assert_eq!(config.walletd.walletd_env_var, "");
+        assert_eq!(config.walletd.wallet_path, "wallet");
+        assert_eq!(config.walletd.walletd_mnemonic_env, "");
+        assert_eq!(config.walletd.walletd_addr, "127.0.0.1");
+        assert_eq!(config.walletd.walletd_port, 8089);
+        assert_eq!(config.walletd.walletd_node_url, "http://localhost:9933");
+        assert_eq!(config.keystore.keystore_path, "keystore");
+        assert_eq!(config.keystore.keystore_password_env, "");
+        assert_eq!(config.export.export_tcp, "");
+        assert_eq!(config.export.export_file, "");
+        assert_eq!(config.export.export_webhook, "");
+        assert_eq!(config.export.export_queue, 10000);
+        assert!(!config.index.enable_index);
+        assert_eq!(config.index.index_path, "index.sqlite");
         assert_eq!(config.server.role, Role::FULL);
         assert_eq!(config.server.node_key_env, "");
         assert_eq!(config.server.port, 30333);
         assert_eq!(config.server.bootnodes, "");
+        assert_eq!(config.server.pruning, "256");
+        assert_eq!(config.server.state_cache_size, 67_108_864);
+        assert_eq!(config.server.in_peers, 25);
+        assert_eq!(config.server.out_peers, 25);
+        assert!(config.server.reserved_nodes.is_empty());
+        assert!(!config.server.reserved_only);
+        assert!(config.server.listen_addresses.is_empty());
+        assert!(config.server.telemetry_urls.is_empty());
+        assert!(!config.server.no_telemetry);
+        assert!(config.server.sentry_nodes.is_empty());
+        assert!(config.server.sentry_for.is_empty());
+        assert!(config.server.validate().is_ok());
+        assert!(config.genesis.authorities.is_empty());
+        assert_eq!(config.genesis.ec_rate, 1);
+        assert_eq!(config.genesis.max_entry_size, 1024);
     }
 
     #[test]
@@ -297,10 +1048,27 @@ mod tests {
         let nondefault_config = FactomConfig::load_from_path("tests/nondefaults.yml").unwrap();
         assert_eq!(nondefault_config.rpc.rpc_addr, "192.0.0.1");
         assert_eq!(nondefault_config.rpc.rpc_port, 7777);
+        assert_eq!(nondefault_config.rpc.ws_port, 7776);
+        assert!(nondefault_config.metrics.enable_metrics);
+        assert_eq!(nondefault_config.metrics.metrics_addr, "0.0.0.0");
+        assert_eq!(nondefault_config.metrics.metrics_port, 9616);
         assert_eq!(nondefault_config.server.network, "test");
         assert_eq!(nondefault_config.log.log_level, LogLevel::CRITICAL);
         assert_eq!(nondefault_config.walletd.walletd_user, "test");
         assert_eq!(nondefault_config.walletd.walletd_env_var, "TEST");
+        assert_eq!(nondefault_config.walletd.wallet_path, "/tmp/wallet");
+        assert_eq!(nondefault_config.walletd.walletd_mnemonic_env, "WALLET_MNEMONIC");
+        assert_eq!(nondefault_config.walletd.walletd_addr, "0.0.0.0");
+        assert_eq!(nondefault_config.walletd.walletd_port, 8090);
+        assert_eq!(nondefault_config.walletd.walletd_node_url, "http://10.0.0.1:9933");
+        assert_eq!(nondefault_config.keystore.keystore_path, "/tmp/keystore");
+        assert_eq!(nondefault_config.keystore.keystore_password_env, "FACTOMD_KEYSTORE_PASSWORD");
+        assert_eq!(nondefault_config.export.export_tcp, "10.0.0.9:9000");
+        assert_eq!(nondefault_config.export.export_file, "/tmp/events.jsonl");
+        assert_eq!(nondefault_config.export.export_webhook, "https://example.com/events");
+        assert_eq!(nondefault_config.export.export_queue, 500);
+        assert!(nondefault_config.index.enable_index);
+        assert_eq!(nondefault_config.index.index_path, "/tmp/index.sqlite");
         assert_eq!(nondefault_config.server.role, Role::LIGHT);
         assert_eq!(nondefault_config.server.node_key_env, "FACTOMD_NODE_KEY");
         assert_eq!(nondefault_config.server.port, 30334);
@@ -309,6 +1077,41 @@ mod tests {
             "/ip4/127.0.0.1/tcp/30333/p2p/QmRpheLN4JWdAnY7HGJfWFNbfkQCb6tFf4vvA6hgjMZKrR"
         );
         assert_eq!(nondefault_config.server.base_path, "/tmp/db");
+        assert_eq!(nondefault_config.server.pruning, "archive");
+        assert_eq!(nondefault_config.server.state_cache_size, 1024);
+        assert_eq!(nondefault_config.server.in_peers, 10);
+        assert_eq!(nondefault_config.server.out_peers, 5);
+        assert_eq!(
+            nondefault_config.server.reserved_nodes,
+            vec!["/ip4/10.0.0.2/tcp/30333/p2p/QmRpheLN4JWdAnY7HGJfWFNbfkQCb6tFf4vvA6hgjMZKrR"]
+        );
+        assert!(nondefault_config.server.reserved_only);
+        assert_eq!(nondefault_config.server.listen_addresses, vec!["/ip4/10.0.0.1/tcp/30334"]);
+        assert_eq!(nondefault_config.server.telemetry_urls, vec!["wss://telemetry.example.com/submit/"]);
+        assert_eq!(nondefault_config.server.telemetry_verbosity, 1);
+        assert!(!nondefault_config.server.no_telemetry);
+        assert_eq!(
+            nondefault_config.server.sentry_nodes,
+            vec!["/ip4/10.0.0.5/tcp/30333/p2p/QmRpheLN4JWdAnY7HGJfWFNbfkQCb6tFf4vvA6hgjMZKrR"]
+        );
+        assert!(nondefault_config.server.sentry_for.is_empty());
+        assert!(nondefault_config.server.validate().is_ok());
+        assert_eq!(nondefault_config.genesis.name, "Test Network");
+        assert_eq!(nondefault_config.genesis.authorities, vec!["//Alice", "//Bob"]);
+        assert_eq!(
+            nondefault_config.genesis.fct_balances,
+            vec![GenesisBalance {
+                account: "//Alice".to_string(),
+                amount: 1000
+            }]
+        );
+        assert_eq!(nondefault_config.genesis.ec_balances.len(), 2);
+        assert_eq!(nondefault_config.genesis.sudo_key, "//Alice");
+        assert_eq!(nondefault_config.genesis.ec_rate, 1000);
+        assert_eq!(nondefault_config.genesis.max_entry_size, 10240);
+        assert_eq!(nondefault_config.genesis.entry_cost, 2);
+        assert_eq!(nondefault_config.genesis.chain_cost, 10);
+        assert_eq!(nondefault_config.genesis.snapshot, "tests/snapshot.csv");
     }
 
     #[test]
@@ -323,6 +1126,8 @@ mod tests {
             "8099",
             "--rpc-addr",
             "8.8.8.8",
+            "--ws-port",
+            "8097",
             "--disable-rpc",
             "--network",
             "custom",
@@ -332,6 +1137,12 @@ mod tests {
             "USER123",
             "--walletd-env-var",
             "WALLETD_ENV",
+            "--wallet-path",
+            "/var/lib/factomd/wallet",
+            "--walletd-mnemonic-env",
+            "WALLETD_MNEMONIC",
+            "--walletd-port",
+            "9089",
             "--log-level",
             "WARN",
             "--port",
@@ -340,6 +1151,34 @@ mod tests {
             "/ip4/127.0.0.1/tcp/30333/p2p/QmRpheLN4JWdAnY7HGJfWFNbfkQCb6tFf4vvA6hgjMZKrR",
             "--base-path",
             "/tmp/db",
+            "--metrics-addr",
+            "10.0.0.1",
+            "--metrics-port",
+            "9999",
+            "--pruning",
+            "1000",
+            "--in-peers",
+            "50",
+            "--reserved-nodes",
+            "/ip4/10.0.0.3/tcp/30333/p2p/QmRpheLN4JWdAnY7HGJfWFNbfkQCb6tFf4vvA6hgjMZKrR",
+            "--reserved-nodes",
+            "/ip4/10.0.0.4/tcp/30333/p2p/QmRpheLN4JWdAnY7HGJfWFNbfkQCb6tFf4vvA6hgjMZKrR",
+            "--listen-addr",
+            "/ip6/::/tcp/30333",
+            "--no-telemetry",
+            "--sentry-for",
+            "/ip4/10.0.0.6/tcp/30333/p2p/QmRpheLN4JWdAnY7HGJfWFNbfkQCb6tFf4vvA6hgjMZKrR",
+            "--keystore-path",
+            "/var/lib/factomd/keystore",
+            "--keystore-password-env",
+            "KEYSTORE_PASSWORD",
+            "--export-tcp",
+            "127.0.0.1:5151",
+            "--export-queue",
+            "100",
+            "--enable-index",
+            "--index-path",
+            "/var/lib/factomd/index.sqlite",
         ];
 
         let yaml = load_yaml!("../cli.yml");
@@ -352,11 +1191,16 @@ mod tests {
         assert_eq!(final_config.server.role, Role::AUTHORITY);
         assert_eq!(final_config.rpc.rpc_port, 8099);
         assert_eq!(final_config.rpc.rpc_addr, "8.8.8.8");
+        assert_eq!(final_config.rpc.ws_port, 8097);
         assert_eq!(final_config.rpc.disable_rpc, true);
         assert_eq!(final_config.server.network, "custom");
         assert_eq!(final_config.server.node_key_env, "NODE_KEY_EXAMPLE");
         assert_eq!(final_config.walletd.walletd_user, "USER123");
         assert_eq!(final_config.walletd.walletd_env_var, "WALLETD_ENV");
+        assert_eq!(final_config.walletd.wallet_path, "/var/lib/factomd/wallet");
+        assert_eq!(final_config.walletd.walletd_mnemonic_env, "WALLETD_MNEMONIC");
+        assert_eq!(final_config.walletd.walletd_addr, "0.0.0.0");
+        assert_eq!(final_config.walletd.walletd_port, 9089);
         assert_eq!(final_config.log.log_level, LogLevel::WARN);
         assert_eq!(final_config.server.port, 30334);
         assert_eq!(
@@ -364,5 +1208,275 @@ mod tests {
             "/ip4/127.0.0.1/tcp/30333/p2p/QmRpheLN4JWdAnY7HGJfWFNbfkQCb6tFf4vvA6hgjMZKrR"
         );
         assert_eq!(final_config.server.base_path, "/tmp/db");
+        assert_eq!(final_config.metrics.metrics_addr, "10.0.0.1");
+        assert_eq!(final_config.metrics.metrics_port, 9999);
+        assert_eq!(final_config.keystore.keystore_path, "/var/lib/factomd/keystore");
+        assert_eq!(final_config.server.pruning, "1000");
+        assert_eq!(final_config.server.in_peers, 50);
+        assert_eq!(final_config.server.out_peers, 5);
+        assert_eq!(final_config.server.reserved_nodes.len(), 2);
+        assert_eq!(final_config.server.listen_addresses, vec!["/ip6/::/tcp/30333"]);
+        assert!(final_config.server.no_telemetry);
+        assert_eq!(final_config.server.sentry_for.len(), 1);
+        assert_eq!(final_config.keystore.keystore_password_env, "KEYSTORE_PASSWORD");
+        assert_eq!(final_config.export.export_tcp, "127.0.0.1:5151");
+        assert_eq!(final_config.export.export_queue, 100);
+        assert!(final_config.index.enable_index);
+        assert_eq!(final_config.index.index_path, "/var/lib/factomd/index.sqlite");
+    }
+
+    #[test]
+    fn test_build_spec_command() {
+        let vec = vec![
+            "factomd",
+            "--network",
+            "dev",
+            "build-spec",
+            "--raw",
+            "--output",
+            "/tmp/spec.json",
+        ];
+
+        let yaml = load_yaml!("../cli.yml");
+        let matches = App::get_matches_from(App::from_yaml(yaml), vec.clone());
+        assert_eq!(matches.subcommand_name(), Some("build-spec"));
+
+        match FactomConfig::from_iter(vec).command {
+            Some(Command::BuildSpec(cmd)) => {
+                assert!(cmd.raw);
+                assert_eq!(cmd.output, "/tmp/spec.json");
+            }
+            other => panic!("Expected build-spec, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_import_legacy_command() {
+        let vec = vec![
+            "factomd",
+            "import-legacy",
+            "--dump",
+            "/tmp/legacy",
+            "-o",
+            "/tmp/spec.json",
+        ];
+
+        let yaml = load_yaml!("../cli.yml");
+        let matches = App::get_matches_from(App::from_yaml(yaml), vec.clone());
+        assert_eq!(matches.subcommand_name(), Some("import-legacy"));
+
+        match FactomConfig::from_iter(vec).command {
+            Some(Command::ImportLegacy(cmd)) => {
+                assert_eq!(cmd.dump, "/tmp/legacy");
+                assert!(!cmd.raw);
+                assert_eq!(cmd.output, "/tmp/spec.json");
+                assert_eq!(cmd.node, "");
+            }
+            other => panic!("Expected import-legacy, got {:?}", other),
+        }
+
+        let vec = vec![
+            "factomd",
+            "import-legacy",
+            "--dump",
+            "/tmp/legacy",
+            "--node",
+            "http://localhost:9933",
+            "--key",
+            "sudo",
+        ];
+        let matches = App::get_matches_from(App::from_yaml(yaml), vec.clone());
+        assert_eq!(matches.subcommand_name(), Some("import-legacy"));
+
+        match FactomConfig::from_iter(vec).command {
+            Some(Command::ImportLegacy(cmd)) => {
+                assert_eq!(cmd.node, "http://localhost:9933");
+                assert_eq!(cmd.key, "sudo");
+            }
+            other => panic!("Expected import-legacy, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_key_command() {
+        let vec = vec![
+            "factomd",
+            "key",
+            "generate",
+            "--kind",
+            "authority",
+            "--name",
+            "alice",
+        ];
+
+        let yaml = load_yaml!("../cli.yml");
+        let matches = App::get_matches_from(App::from_yaml(yaml), vec.clone());
+        assert_eq!(matches.subcommand_name(), Some("key"));
+
+        match FactomConfig::from_iter(vec).command {
+            Some(Command::Key(KeyCommand::Generate(cmd))) => {
+                assert_eq!(cmd.kind, "authority");
+                assert_eq!(cmd.name, "alice");
+                assert!(!cmd.mnemonic);
+            }
+            other => panic!("Expected key generate, got {:?}", other),
+        }
+
+        let vec = vec![
+            "factomd",
+            "key",
+            "import",
+            "--kind",
+            "fct",
+            "--name",
+            "restored",
+            "--mnemonic",
+            "--index",
+            "3",
+        ];
+        let yaml = load_yaml!("../cli.yml");
+        App::get_matches_from(App::from_yaml(yaml), vec.clone());
+        match FactomConfig::from_iter(vec).command {
+            Some(Command::Key(KeyCommand::Import(cmd))) => {
+                assert!(cmd.mnemonic);
+                assert_eq!(cmd.index, 3);
+                assert_eq!(cmd.secret, "");
+            }
+            other => panic!("Expected key import, got {:?}", other),
+        }
+
+        let vec = vec!["factomd", "key", "list"];
+        let yaml = load_yaml!("../cli.yml");
+        App::get_matches_from(App::from_yaml(yaml), vec.clone());
+        match FactomConfig::from_iter(vec).command {
+            Some(Command::Key(KeyCommand::List(cmd))) => assert_eq!(cmd.kind, None),
+            other => panic!("Expected key list, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_maintenance_commands() {
+        let vec = vec![
+            "factomd",
+            "--base-path",
+            "/tmp/db",
+            "export-blocks",
+            "--to",
+            "100",
+            "--json",
+            "-o",
+            "/tmp/blocks.json",
+        ];
+
+        let yaml = load_yaml!("../cli.yml");
+        let matches = App::get_matches_from(App::from_yaml(yaml), vec.clone());
+        assert_eq!(matches.subcommand_name(), Some("export-blocks"));
+
+        match FactomConfig::from_iter(vec).command {
+            Some(Command::ExportBlocks(cmd)) => {
+                assert_eq!(cmd.output, "/tmp/blocks.json");
+                assert_eq!(cmd.from, None);
+                assert_eq!(cmd.to, Some(100));
+                assert!(cmd.json);
+            }
+            other => panic!("Expected export-blocks, got {:?}", other),
+        }
+
+        let vec = vec!["factomd", "revert", "--blocks", "10"];
+        let yaml = load_yaml!("../cli.yml");
+        App::get_matches_from(App::from_yaml(yaml), vec.clone());
+        match FactomConfig::from_iter(vec).command {
+            Some(Command::Revert(cmd)) => assert_eq!(cmd.blocks, 10),
+            other => panic!("Expected revert, got {:?}", other),
+        }
+
+        let vec = vec!["factomd", "localnet", "--nodes", "4", "--base-rpc-port", "9000"];
+        let yaml = load_yaml!("../cli.yml");
+        App::get_matches_from(App::from_yaml(yaml), vec.clone());
+        match FactomConfig::from_iter(vec).command {
+            Some(Command::Localnet(cmd)) => {
+                assert_eq!(cmd.nodes, 4);
+                assert_eq!(cmd.base_p2p_port, 30333);
+                assert_eq!(cmd.base_rpc_port, 9000);
+                assert_eq!(cmd.dir, "");
+            }
+            other => panic!("Expected localnet, got {:?}", other),
+        }
+
+        let vec = vec!["factomd", "--walletd-port", "9089", "walletd"];
+        let yaml = load_yaml!("../cli.yml");
+        App::get_matches_from(App::from_yaml(yaml), vec.clone());
+        match FactomConfig::from_iter(vec) {
+            FactomConfig {
+                command: Some(Command::Walletd),
+                walletd,
+                ..
+            } => assert_eq!(walletd.walletd_port, 9089),
+            other => panic!("Expected walletd, got {:?}", other.command),
+        }
+
+        let vec = vec!["factomd", "purge-chain", "-y"];
+        let yaml = load_yaml!("../cli.yml");
+        App::get_matches_from(App::from_yaml(yaml), vec.clone());
+        match FactomConfig::from_iter(vec).command {
+            Some(Command::PurgeChain(cmd)) => assert!(cmd.yes),
+            other => panic!("Expected purge-chain, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_server_validation() {
+        let valid = || FactomConfig::load_from_path("").unwrap().server;
+
+        let mut server = valid();
+        server.pruning = "0".to_string();
+        assert!(server.validate().is_err());
+        server.pruning = "all".to_string();
+        assert!(server.validate().is_err());
+
+        let mut server = valid();
+        server.reserved_only = true;
+        assert!(server.validate().is_err());
+        server.reserved_nodes = vec!["/ip4/127.0.0.1/tcp/30333".to_string()];
+        assert!(server.validate().is_err());
+
+        let mut server = valid();
+        server.listen_addresses = vec!["0.0.0.0:30333".to_string()];
+        assert!(server.validate().is_err());
+
+        let mut server = valid();
+        server.telemetry_urls = vec!["https://telemetry.example.com".to_string()];
+        assert!(server.validate().is_err());
+        server.telemetry_urls = vec!["wss://telemetry.example.com".to_string()];
+        assert!(server.validate().is_ok());
+        server.no_telemetry = true;
+        assert!(server.validate().is_err());
+
+        let mut server = valid();
+        server.sentry_nodes = vec!["/ip4/10.0.0.5/tcp/30333".to_string()];
+        assert!(server.validate().is_err());
+    }
+
+    #[test]
+    fn test_genesis_validation() {
+        let valid = || {
+            let mut genesis = FactomConfig::load_from_path("").unwrap().genesis;
+            genesis.authorities = vec!["//Alice".to_string()];
+            genesis.sudo_key = "//Alice".to_string();
+            genesis
+        };
+        assert!(valid().validate().is_ok());
+
+        let mut genesis = valid();
+        genesis.authorities = vec![];
+        assert!(genesis.validate().is_err());
+
+        let mut genesis = valid();
+        genesis.sudo_key = "".to_string();
+        assert!(genesis.validate().is_err());
+
+        let mut genesis = valid();
+        genesis.ec_rate = 0;
+        assert!(genesis.validate().is_err());
     }
 }
@@ -0,0 +1,161 @@
+//! # HD Keys
+//!
+//! FCT and EC seeds derived from a BIP39 mnemonic along the BIP44 paths of legacy
+//! Factom wallets:
+//!
+//! | Kind | Path                   |
+//! |------|------------------------|
+//! | fct  | `m/44'/131'/0'/0/<index>` |
+//! | ec   | `m/44'/132'/0'/0/<index>` |
+//!
+//! Derivation is BIP32 over secp256k1, starting from the BIP39 seed of the mnemonic
+//! without a passphrase. The private key at the end of the path is the seed, so the
+//! same words give the same Fs and Es secrets, and the same legacy addresses, as
+//! legacy wallets.
+//!
+use crate::{Error, KeyKind};
+use bip39::{Language, Mnemonic, MnemonicType, Seed};
+use hmac::{Hmac, Mac};
+use secp256k1::{PublicKey, SecretKey};
+use sha2::Sha512;
+
+/// BIP44 coin type of factoid addresses
+pub const FCT_COIN_TYPE: u32 = 131;
+/// BIP44 coin type of entry credit addresses
+pub const EC_COIN_TYPE: u32 = 132;
+
+const HARDENED: u32 = 0x8000_0000;
+
+/// A private key and chain code along a path
+struct ExtendedKey {
+    key: SecretKey,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    fn master(seed: &[u8]) -> Result<Self, Error> {
+        Self::from_hmac(b"Bitcoin seed", seed, None)
+    }
+
+    fn child(&self, index: u32) -> Result<Self, Error> {
+        let mut data = Vec::with_capacity(37);
+        if index >= HARDENED {
+            data.push(0);
+            data.extend_from_slice(&self.key.serialize());
+        } else {
+            data.extend_from_slice(&PublicKey::from_secret_key(&self.key).serialize_compressed());
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+        Self::from_hmac(&self.chain_code, &data, Some(&self.key))
+    }
+
+    /// The left half of the HMAC is the key, added to the parent key if any
+    fn from_hmac(key: &[u8], data: &[u8], parent: Option<&SecretKey>) -> Result<Self, Error> {
+        let mut mac = Hmac::<Sha512>::new_varkey(key).expect("HMAC takes keys of any size; qed");
+        mac.input(data);
+        let result = mac.result().code();
+
+        let mut left = [0u8; 32];
+        left.copy_from_slice(&result[..32]);
+        // Fails for the odd value outside the curve order, as in legacy wallets
+        let mut key = SecretKey::parse(&left).map_err(|_| Error::InvalidDerivation)?;
+        if let Some(parent) = parent {
+            key.tweak_add_assign(parent).map_err(|_| Error::InvalidDerivation)?;
+        }
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&result[32..]);
+        Ok(ExtendedKey { key, chain_code })
+    }
+}
+
+/// A new random 12 word English mnemonic
+pub fn generate_mnemonic() -> String {
+    Mnemonic::new(MnemonicType::Words12, Language::English).into_phrase()
+}
+
+/// Check the words and checksum of a mnemonic, and single space its words
+pub fn normalize_mnemonic(phrase: &str) -> Result<String, Error> {
+    parse(phrase).map(Mnemonic::into_phrase)
+}
+
+/// The seed of an FCT or EC key at an index of a mnemonic
+pub fn derive_seed(phrase: &str, kind: KeyKind, index: u32) -> Result<[u8; 32], Error> {
+    let coin_type = match kind {
+        KeyKind::Fct => FCT_COIN_TYPE,
+        KeyKind::Ec => EC_COIN_TYPE,
+        KeyKind::Authority | KeyKind::Network => return Err(Error::NotDerivable(kind)),
+    };
+    if index >= HARDENED {
+        return Err(Error::InvalidDerivation);
+    }
+
+    let seed = Seed::new(&parse(phrase)?, "");
+    let path = [44 | HARDENED, coin_type | HARDENED, HARDENED, 0, index];
+    let mut key = ExtendedKey::master(seed.as_bytes())?;
+    for child in path.iter() {
+        key = key.child(*child)?;
+    }
+    Ok(key.key.serialize())
+}
+
+fn parse(phrase: &str) -> Result<Mnemonic, Error> {
+    let words = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+    Mnemonic::from_phrase(&words, Language::English).map_err(|e| Error::InvalidMnemonic(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YELLOW: &str = "yellow yellow yellow yellow yellow yellow yellow yellow yellow yellow yellow yellow";
+
+    #[test]
+    fn test_bip32_vector() {
+        // Test vector 1 of BIP32, m/0'/1/2'/2/1000000000
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let mut key = ExtendedKey::master(&seed).unwrap();
+        for child in [HARDENED, 1, 2 | HARDENED, 2, 1_000_000_000].iter() {
+            key = key.child(*child).unwrap();
+        }
+        assert_eq!(
+            hex::encode(key.key.serialize()),
+            "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8"
+        );
+    }
+
+    #[test]
+    fn test_derive_seed() {
+        // Legacy wallets derive FA22de5NSG2FA2HmMaD4h8qSAZAJyztmmnwgLPghCQKoSekwYYct
+        // and EC2KnJQN86MYq4pQyeSGTHSiVdkhRCPXS3udzD4im6BXRBjZFMmR from these
+        let fct = derive_seed(YELLOW, KeyKind::Fct, 0).unwrap();
+        let ec = derive_seed(YELLOW, KeyKind::Ec, 0).unwrap();
+        assert_eq!(
+            hex::encode(fct),
+            "36422e9560f56e0ead53a83b33aec9571d379291b5e292b88dec641a98ef05d8"
+        );
+        assert_eq!(
+            hex::encode(ec),
+            "59bf006983f72f226d073bac578cef91f3947fc2e213568b852baf0d107831a2"
+        );
+        assert_ne!(fct, derive_seed(YELLOW, KeyKind::Fct, 1).unwrap());
+        // Spacing doesn't matter
+        assert_eq!(derive_seed(&format!("  {}\n", YELLOW.replace(' ', "  ")), KeyKind::Fct, 0).unwrap(), fct);
+
+        match derive_seed(YELLOW, KeyKind::Authority, 0) {
+            Err(Error::NotDerivable(KeyKind::Authority)) => {}
+            other => panic!("Expected underivable kind, got {:?}", other),
+        }
+        match derive_seed(&YELLOW.replace("yellow yellow", "yellow"), KeyKind::Fct, 0) {
+            Err(Error::InvalidMnemonic(_)) => {}
+            other => panic!("Expected invalid mnemonic, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_generate_mnemonic() {
+        let phrase = generate_mnemonic();
+        assert_eq!(phrase.split(' ').count(), 12);
+        assert_eq!(normalize_mnemonic(&format!(" {} ", phrase)).unwrap(), phrase);
+        assert_ne!(phrase, generate_mnemonic());
+    }
+}
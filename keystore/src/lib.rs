@@ -0,0 +1,474 @@
+//! # Factomd Keystore
+//!
+//! Keys kept on disk, one JSON file per key, named after the key. Each file holds
+//! the kind of key, its public key and its 32 byte secret seed encrypted with a
+//! password:
+//!
+//! | Step       | Algorithm                                             |
+//! |------------|-------------------------------------------------------|
+//! | Derivation | PBKDF2-HMAC-SHA256 of the password, random 32 byte salt |
+//! | Encryption | AES-128-CTR with the first half of the derived key     |
+//! | MAC        | HMAC-SHA256 of IV and ciphertext with the second half  |
+//!
+//! The keystore only deals in seeds; turning a seed into a key pair, and a key pair
+//! into an address, is left to its users. FCT and EC seeds may be derived from a
+//! mnemonic, see `hd`. A keystore can keep one mnemonic, encrypted the same way in
+//! `mnemonic.seed`, for wallets to derive their keys from.
+//!
+use aes_ctr::cipher::{NewStreamCipher, SyncStreamCipher};
+use aes_ctr::Aes128Ctr;
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub mod hd;
+
+/// PBKDF2 rounds for newly encrypted keys
+pub const ITERATIONS: u32 = 100_000;
+
+/// File of the keystore mnemonic, apart from the `.json` key files
+const MNEMONIC_FILE: &str = "mnemonic.seed";
+
+/// Kinds of keys a node uses
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyKind {
+    /// ed25519 key an authority signs blocks with
+    Authority,
+    /// ed25519 key identifying the node on the network
+    Network,
+    /// Factoid account
+    Fct,
+    /// Entry credit account
+    Ec,
+}
+
+impl KeyKind {
+    pub fn variants() -> [&'static str; 4] {
+        ["authority", "network", "fct", "ec"]
+    }
+}
+
+impl fmt::Display for KeyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            KeyKind::Authority => "authority",
+            KeyKind::Network => "network",
+            KeyKind::Fct => "fct",
+            KeyKind::Ec => "ec",
+        };
+        f.pad(name)
+    }
+}
+
+impl FromStr for KeyKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "authority" => Ok(KeyKind::Authority),
+            "network" => Ok(KeyKind::Network),
+            "fct" => Ok(KeyKind::Fct),
+            "ec" => Ok(KeyKind::Ec),
+            _ => Err(Error::UnknownKind(s.to_string())),
+        }
+    }
+}
+
+/// Keystore failures
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The operating system failed to provide randomness
+    Random(rand::Error),
+    /// A key file that isn't valid JSON or misses fields
+    InvalidFile(PathBuf, serde_json::Error),
+    /// A key file whose encrypted parts have the wrong sizes
+    Corrupt(String),
+    /// Key names may only hold letters, digits, `-` and `_`
+    InvalidName(String),
+    UnknownKind(String),
+    NotFound(String),
+    AlreadyExists(String),
+    /// The password doesn't match the one the key was encrypted with
+    WrongPassword,
+    /// Not a valid BIP39 mnemonic
+    InvalidMnemonic(String),
+    /// Only FCT and EC keys have a derivation path
+    NotDerivable(KeyKind),
+    /// A hardened address index, or a path leading to an invalid key
+    InvalidDerivation,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "Keystore error: {}", e),
+            Error::Random(e) => write!(f, "Failed to generate randomness: {}", e),
+            Error::InvalidFile(path, e) => write!(f, "Invalid key file {}: {}", path.display(), e),
+            Error::Corrupt(name) => write!(f, "Key {} is corrupt", name),
+            Error::InvalidName(name) => write!(
+                f,
+                "Invalid key name {:?}, use letters, digits, - and _",
+                name
+            ),
+            Error::UnknownKind(kind) => write!(
+                f,
+                "Unknown key kind {}, expected one of {}",
+                kind,
+                KeyKind::variants().join(", ")
+            ),
+            Error::NotFound(name) => write!(f, "No key named {}", name),
+            Error::AlreadyExists(name) => write!(f, "A key named {} already exists", name),
+            Error::WrongPassword => write!(f, "Wrong keystore password"),
+            Error::InvalidMnemonic(e) => write!(f, "Invalid mnemonic: {}", e),
+            Error::NotDerivable(kind) => write!(f, "{} keys can't be derived from a mnemonic", kind),
+            Error::InvalidDerivation => write!(f, "No valid key at that derivation path"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Encrypted seed and what is needed to decrypt it, all hex encoded
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Crypto {
+    salt: String,
+    iterations: u32,
+    iv: String,
+    ciphertext: String,
+    mac: String,
+}
+
+/// A key as stored on disk
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredKey {
+    pub name: String,
+    pub kind: KeyKind,
+    /// Hex encoded public key
+    pub public: String,
+    crypto: Crypto,
+}
+
+impl StoredKey {
+    /// Decrypt the secret seed
+    pub fn seed(&self, password: &str) -> Result<[u8; 32], Error> {
+        let seed = self.crypto.decrypt(&self.name, password)?;
+        if seed.len() != 32 {
+            return Err(Error::Corrupt(self.name.clone()));
+        }
+        let mut result = [0u8; 32];
+        result.copy_from_slice(&seed);
+        Ok(result)
+    }
+}
+
+impl Crypto {
+    fn encrypt(secret: &[u8], password: &str) -> Result<Self, Error> {
+        let mut salt = [0u8; 32];
+        let mut iv = [0u8; 16];
+        let mut rng = os_rng()?;
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut iv);
+
+        let key = derive_key(password, &salt, ITERATIONS);
+        let mut ciphertext = secret.to_vec();
+        apply_cipher(&key, &iv, &mut ciphertext);
+        let mac = mac_of(&key, &iv, &ciphertext).result().code();
+        Ok(Crypto {
+            salt: hex::encode(salt),
+            iterations: ITERATIONS,
+            iv: hex::encode(iv),
+            ciphertext: hex::encode(ciphertext),
+            mac: hex::encode(mac),
+        })
+    }
+
+    /// Decrypt the secret of the key or mnemonic with a name
+    fn decrypt(&self, name: &str, password: &str) -> Result<Vec<u8>, Error> {
+        let corrupt = || Error::Corrupt(name.to_string());
+        let salt = hex::decode(&self.salt).map_err(|_| corrupt())?;
+        let iv = hex::decode(&self.iv).map_err(|_| corrupt())?;
+        let mut secret = hex::decode(&self.ciphertext).map_err(|_| corrupt())?;
+        let mac = hex::decode(&self.mac).map_err(|_| corrupt())?;
+        if iv.len() != 16 {
+            return Err(corrupt());
+        }
+
+        let key = derive_key(password, &salt, self.iterations);
+        mac_of(&key, &iv, &secret)
+            .verify(&mac)
+            .map_err(|_| Error::WrongPassword)?;
+        apply_cipher(&key, &iv, &mut secret);
+        Ok(secret)
+    }
+}
+
+/// Directory of encrypted keys
+pub struct Keystore {
+    path: PathBuf,
+}
+
+impl Keystore {
+    /// Open a keystore, creating its directory if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(&path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o700))?;
+        }
+        Ok(Keystore { path })
+    }
+
+    /// Encrypt and store a seed under a new name
+    pub fn insert(
+        &self,
+        name: &str,
+        kind: KeyKind,
+        public: &[u8],
+        seed: &[u8; 32],
+        password: &str,
+    ) -> Result<StoredKey, Error> {
+        let path = self.key_path(name)?;
+        if path.exists() {
+            return Err(Error::AlreadyExists(name.to_string()));
+        }
+
+        let stored = StoredKey {
+            name: name.to_string(),
+            kind,
+            public: hex::encode(public),
+            crypto: Crypto::encrypt(seed, password)?,
+        };
+        let json = serde_json::to_string_pretty(&stored)
+            .map_err(|e| Error::InvalidFile(path.clone(), e))?;
+        write_private(&path, json.as_bytes())?;
+        Ok(stored)
+    }
+
+    /// The key stored under a name
+    pub fn get(&self, name: &str) -> Result<StoredKey, Error> {
+        let path = self.key_path(name)?;
+        if !path.exists() {
+            return Err(Error::NotFound(name.to_string()));
+        }
+        read_key(&path)
+    }
+
+    /// All stored keys, ordered by name
+    pub fn list(&self) -> Result<Vec<StoredKey>, Error> {
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let path = entry?.path();
+            if path.extension() == Some(OsStr::new("json")) {
+                keys.push(read_key(&path)?);
+            }
+        }
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(keys)
+    }
+
+    /// The first key of a kind, by name
+    pub fn first(&self, kind: KeyKind) -> Result<Option<StoredKey>, Error> {
+        Ok(self.list()?.into_iter().find(|key| key.kind == kind))
+    }
+
+    /// Encrypt and store the mnemonic of the keystore
+    ///
+    /// A keystore keeps a single mnemonic, which is never replaced.
+    pub fn set_mnemonic(&self, phrase: &str, password: &str) -> Result<(), Error> {
+        let phrase = hd::normalize_mnemonic(phrase)?;
+        let path = self.path.join(MNEMONIC_FILE);
+        if path.exists() {
+            return Err(Error::AlreadyExists("mnemonic".to_string()));
+        }
+        let crypto = Crypto::encrypt(phrase.as_bytes(), password)?;
+        let json = serde_json::to_string_pretty(&crypto)
+            .map_err(|e| Error::InvalidFile(path.clone(), e))?;
+        write_private(&path, json.as_bytes())
+    }
+
+    /// Decrypt the mnemonic of the keystore, if it has one
+    pub fn mnemonic(&self, password: &str) -> Result<Option<String>, Error> {
+        let path = self.path.join(MNEMONIC_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let json = fs::read_to_string(&path)?;
+        let crypto: Crypto = serde_json::from_str(&json).map_err(|e| Error::InvalidFile(path, e))?;
+        let phrase = crypto.decrypt("mnemonic", password)?;
+        String::from_utf8(phrase)
+            .map(Some)
+            .map_err(|_| Error::Corrupt("mnemonic".to_string()))
+    }
+
+    fn key_path(&self, name: &str) -> Result<PathBuf, Error> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(Error::InvalidName(name.to_string()));
+        }
+        Ok(self.path.join(format!("{}.json", name)))
+    }
+}
+
+/// A new random seed
+pub fn random_seed() -> Result<[u8; 32], Error> {
+    let mut seed = [0u8; 32];
+    os_rng()?.fill_bytes(&mut seed);
+    Ok(seed)
+}
+
+fn os_rng() -> Result<OsRng, Error> {
+    OsRng::new().map_err(Error::Random)
+}
+
+fn read_key(path: &Path) -> Result<StoredKey, Error> {
+    let json = fs::read_to_string(path)?;
+    serde_json::from_str(&json).map_err(|e| Error::InvalidFile(path.to_path_buf(), e))
+}
+
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
+
+fn derive_key(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, iterations as usize, &mut key);
+    key
+}
+
+fn apply_cipher(key: &[u8; 32], iv: &[u8], data: &mut [u8]) {
+    Aes128Ctr::new(key[..16].into(), iv.into()).apply_keystream(data);
+}
+
+fn mac_of(key: &[u8; 32], iv: &[u8], ciphertext: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_varkey(&key[16..]).expect("HMAC takes keys of any size; qed");
+    mac.input(iv);
+    mac.input(ciphertext);
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_keystore(name: &str) -> Keystore {
+        let path = std::env::temp_dir().join(format!("factomd-keystore-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        Keystore::open(path).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let keystore = temp_keystore("round-trip");
+        let seed = random_seed().unwrap();
+        keystore
+            .insert("alice", KeyKind::Authority, &[1u8; 32], &seed, "secret")
+            .unwrap();
+
+        let stored = keystore.get("alice").unwrap();
+        assert_eq!(stored.kind, KeyKind::Authority);
+        assert_eq!(stored.public, hex::encode([1u8; 32]));
+        assert_eq!(stored.seed("secret").unwrap(), seed);
+        assert_ne!(stored.crypto.ciphertext, hex::encode(seed));
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        let keystore = temp_keystore("wrong-password");
+        keystore
+            .insert("bob", KeyKind::Fct, &[2u8; 32], &[3u8; 32], "secret")
+            .unwrap();
+
+        match keystore.get("bob").unwrap().seed("guess") {
+            Err(Error::WrongPassword) => {}
+            other => panic!("Expected wrong password, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_list() {
+        let keystore = temp_keystore("list");
+        keystore
+            .insert("network", KeyKind::Network, &[1u8; 32], &[1u8; 32], "")
+            .unwrap();
+        keystore
+            .insert("authority", KeyKind::Authority, &[2u8; 32], &[2u8; 32], "")
+            .unwrap();
+
+        let names: Vec<String> = keystore.list().unwrap().into_iter().map(|key| key.name).collect();
+        assert_eq!(names, vec!["authority", "network"]);
+        assert_eq!(keystore.first(KeyKind::Network).unwrap().unwrap().name, "network");
+        assert!(keystore.first(KeyKind::Ec).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let keystore = temp_keystore("invalid");
+        keystore
+            .insert("carol", KeyKind::Ec, &[1u8; 32], &[1u8; 32], "")
+            .unwrap();
+
+        match keystore.insert("carol", KeyKind::Ec, &[1u8; 32], &[1u8; 32], "") {
+            Err(Error::AlreadyExists(_)) => {}
+            other => panic!("Expected duplicate, got {:?}", other),
+        }
+        match keystore.insert("../carol", KeyKind::Ec, &[1u8; 32], &[1u8; 32], "") {
+            Err(Error::InvalidName(_)) => {}
+            other => panic!("Expected invalid name, got {:?}", other),
+        }
+        match keystore.get("dave") {
+            Err(Error::NotFound(_)) => {}
+            other => panic!("Expected missing key, got {:?}", other),
+        }
+        assert_eq!("EC".parse::<KeyKind>().unwrap(), KeyKind::Ec);
+        assert!("account".parse::<KeyKind>().is_err());
+    }
+
+    #[test]
+    fn test_mnemonic() {
+        let keystore = temp_keystore("mnemonic");
+        assert!(keystore.mnemonic("secret").unwrap().is_none());
+        let phrase = hd::generate_mnemonic();
+        keystore.set_mnemonic(&phrase, "secret").unwrap();
+        assert_eq!(keystore.mnemonic("secret").unwrap(), Some(phrase.clone()));
+        assert!(keystore.list().unwrap().is_empty());
+
+        match keystore.set_mnemonic(&hd::generate_mnemonic(), "secret") {
+            Err(Error::AlreadyExists(_)) => {}
+            other => panic!("Expected existing mnemonic, got {:?}", other),
+        }
+        match keystore.mnemonic("guess") {
+            Err(Error::WrongPassword) => {}
+            other => panic!("Expected wrong password, got {:?}", other),
+        }
+    }
+}
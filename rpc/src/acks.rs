@@ -0,0 +1,305 @@
+//! # Acknowledgements
+//!
+//! Legacy clients learn what became of a submission by polling its ack:
+//!
+//! | Method        | Params | Result                                                        |
+//! |---------------|--------|---------------------------------------------------------------|
+//! | `entry-ack`   | `txid` | status of an entry, by its entry hash or the txid of its call |
+//! | `factoid-ack` | `txid` | status of a factoid transaction, by its txid                  |
+//!
+//! The txid of a submission is the hash of its extrinsic, as returned by the
+//! methods of `write` or `author_submitExtrinsic`. The service tracks submissions from the
+//! transaction pool through block import:
+//!
+//! | Status            | When                                                  |
+//! |-------------------|-------------------------------------------------------|
+//! | `Unknown`         | not seen, dropped from the pool, or failed in a block |
+//! | `NotConfirmed`    | waiting in the transaction pool                       |
+//! | `TransactionACK`  | included in a block of the best chain                 |
+//! | `DBlockConfirmed` | included in a confirmed block                         |
+//!
+//! The transaction date is when the node first saw a submission, the block date
+//! the timestamp of the block including it, both in milliseconds and as a UTC
+//! date string. Dates that aren't known yet are left out. Only the latest
+//! submissions are remembered, see `MAX_TRACKED`.
+//!
+use chrono::{TimeZone, Utc};
+use jsonrpc_core::{Error, Params};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{AckStatus, RpcHandler};
+
+/// Submissions remembered, the oldest are forgotten first
+pub const MAX_TRACKED: usize = 100_000;
+
+/// Entry hash or txid
+pub type TxHash = [u8; 32];
+
+/// What the node knows of a submission
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ack {
+    pub status: AckStatus,
+    /// When the node first saw it, milliseconds
+    pub transaction_date: Option<u64>,
+    /// Timestamp of the block including it, milliseconds
+    pub block_date: Option<u64>,
+}
+
+impl Default for Ack {
+    fn default() -> Self {
+        Ack {
+            status: AckStatus::Unknown,
+            transaction_date: None,
+            block_date: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Tracked {
+    /// Acks by txid
+    acks: HashMap<TxHash, Ack>,
+    /// Txid of the latest call writing an entry, by entry hash
+    entries: HashMap<TxHash, TxHash>,
+    /// Entry hash written by a call, by txid
+    writes: HashMap<TxHash, TxHash>,
+    /// Txids, oldest first
+    order: VecDeque<TxHash>,
+}
+
+impl Tracked {
+    /// Ack of a submission, tracking it if it is new
+    fn track(&mut self, txid: TxHash, entry_hash: Option<TxHash>) -> &mut Ack {
+        if !self.acks.contains_key(&txid) {
+            self.order.push_back(txid);
+            while self.order.len() > MAX_TRACKED {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.forget(&oldest);
+                }
+            }
+        }
+        if let Some(entry_hash) = entry_hash {
+            self.entries.insert(entry_hash, txid);
+            self.writes.insert(txid, entry_hash);
+        }
+        self.acks.entry(txid).or_default()
+    }
+
+    fn forget(&mut self, txid: &TxHash) {
+        self.acks.remove(txid);
+        if let Some(entry_hash) = self.writes.remove(txid) {
+            if self.entries.get(&entry_hash) == Some(txid) {
+                self.entries.remove(&entry_hash);
+            }
+        }
+    }
+}
+
+/// Acks of the submissions the node has seen
+#[derive(Default)]
+pub struct AckTracker {
+    tracked: Mutex<Tracked>,
+}
+
+/// Acks shared between the service, which tracks submissions, and the RPC server
+pub type SharedAcks = Arc<AckTracker>;
+
+impl AckTracker {
+    pub fn new() -> SharedAcks {
+        Arc::new(AckTracker::default())
+    }
+
+    /// A submission is waiting in the transaction pool, seen at `date`
+    ///
+    /// Submissions already included in a block keep their status.
+    pub fn pending(&self, txid: TxHash, entry_hash: Option<TxHash>, date: u64) {
+        let mut tracked = self.lock();
+        let ack = tracked.track(txid, entry_hash);
+        if ack.status == AckStatus::Unknown {
+            ack.status = AckStatus::NotConfirmed;
+        }
+        ack.transaction_date.get_or_insert(date);
+    }
+
+    /// A submission was included in a new best block with timestamp `date`
+    ///
+    /// Submissions never seen in the pool are first seen now.
+    pub fn included(&self, txid: TxHash, entry_hash: Option<TxHash>, date: u64) {
+        let mut tracked = self.lock();
+        let ack = tracked.track(txid, entry_hash);
+        ack.status = AckStatus::TransactionAck;
+        ack.transaction_date.get_or_insert(date);
+        ack.block_date = Some(date);
+    }
+
+    /// The block including a submission was confirmed, by finality or depth
+    pub fn confirmed(&self, txid: &TxHash) {
+        if let Some(ack) = self.lock().acks.get_mut(txid) {
+            if ack.status == AckStatus::TransactionAck {
+                ack.status = AckStatus::DBlockConfirmed;
+            }
+        }
+    }
+
+    /// Forget a submission, as when it failed in a block
+    pub fn forget(&self, txid: &TxHash) {
+        self.lock().forget(txid);
+    }
+
+    /// Forget the pending submissions that left the pool without being included
+    pub fn retain_pending(&self, pool: &HashSet<TxHash>) {
+        let mut tracked = self.lock();
+        let dropped: Vec<TxHash> = tracked
+            .acks
+            .iter()
+            .filter(|(txid, ack)| ack.status == AckStatus::NotConfirmed && !pool.contains(*txid))
+            .map(|(txid, _)| *txid)
+            .collect();
+        for txid in dropped {
+            tracked.forget(&txid);
+        }
+    }
+
+    /// Ack of a submission by its txid
+    pub fn transaction(&self, txid: &TxHash) -> Option<Ack> {
+        self.lock().acks.get(txid).copied()
+    }
+
+    /// Txid, entry hash and ack of an entry, by its entry hash or txid
+    pub fn entry(&self, hash: &TxHash) -> Option<(TxHash, TxHash, Ack)> {
+        let tracked = self.lock();
+        let (txid, entry_hash) = match (tracked.entries.get(hash), tracked.writes.get(hash)) {
+            (Some(txid), _) => (*txid, *hash),
+            (None, Some(entry_hash)) => (*hash, *entry_hash),
+            (None, None) => return None,
+        };
+        tracked.acks.get(&txid).map(|ack| (txid, entry_hash, *ack))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Tracked> {
+        self.tracked.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Deserialize)]
+struct TxidParams {
+    txid: String,
+}
+
+/// Dates and status of an ack as the legacy API gives them
+#[derive(Serialize)]
+struct AckData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transactiondate: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transactiondatestring: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blockdate: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blockdatestring: Option<String>,
+    status: AckStatus,
+}
+
+impl From<Ack> for AckData {
+    fn from(ack: Ack) -> Self {
+        AckData {
+            transactiondate: ack.transaction_date,
+            transactiondatestring: ack.transaction_date.map(date_string),
+            blockdate: ack.block_date,
+            blockdatestring: ack.block_date.map(date_string),
+            status: ack.status,
+        }
+    }
+}
+
+/// Add the ack methods to an RPC handler
+pub(crate) fn add_methods(io: &mut RpcHandler, acks: SharedAcks) {
+    let tracker = acks.clone();
+    io.add_method("entry-ack", move |params: Params| {
+        let params: TxidParams = params.parse()?;
+        let hash = decode(&params.txid)?;
+        let (committxid, entryhash, ack) = match tracker.entry(&hash) {
+            Some((txid, entry_hash, ack)) => (hex::encode(txid), hex::encode(entry_hash), ack),
+            None => (String::new(), hex::encode(hash), Ack::default()),
+        };
+        // The entry is written by the call that paid for it
+        let entrydata = AckData {
+            transactiondate: None,
+            transactiondatestring: None,
+            ..AckData::from(ack)
+        };
+        Ok(json!({
+            "committxid": committxid,
+            "entryhash": entryhash,
+            "commitdata": AckData::from(ack),
+            "entrydata": entrydata,
+        }))
+    });
+
+    io.add_method("factoid-ack", move |params: Params| {
+        let params: TxidParams = params.parse()?;
+        let txid = decode(&params.txid)?;
+        let mut result = json!(AckData::from(acks.transaction(&txid).unwrap_or_default()));
+        result["txid"] = json!(hex::encode(txid));
+        Ok(result)
+    });
+}
+
+fn decode(value: &str) -> Result<TxHash, Error> {
+    let mut hash = [0u8; 32];
+    match hex::decode(value) {
+        Ok(ref bytes) if bytes.len() == hash.len() => {
+            hash.copy_from_slice(bytes);
+            Ok(hash)
+        }
+        _ => Err(Error::invalid_params(format!("Invalid hash {}", value))),
+    }
+}
+
+/// UTC date of a time in milliseconds
+fn date_string(millis: u64) -> String {
+    Utc.timestamp_millis_opt(millis as i64)
+        .single()
+        .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transitions() {
+        let acks = AckTracker::new();
+        let txid = [1u8; 32];
+        let entry_hash = [2u8; 32];
+
+        // Only included submissions are promoted to confirmed
+        acks.pending(txid, Some(entry_hash), 1_000);
+        acks.confirmed(&txid);
+        assert_eq!(acks.transaction(&txid).unwrap().status, AckStatus::NotConfirmed);
+
+        acks.included(txid, Some(entry_hash), 2_000);
+        let ack = acks.transaction(&txid).unwrap();
+        assert_eq!(ack.status, AckStatus::TransactionAck);
+        assert_eq!((ack.transaction_date, ack.block_date), (Some(1_000), Some(2_000)));
+
+        acks.confirmed(&txid);
+        let (committxid, hash, ack) = acks.entry(&entry_hash).unwrap();
+        assert_eq!((committxid, hash), (txid, entry_hash));
+        assert_eq!(ack.status, AckStatus::DBlockConfirmed);
+
+        // Seeing the call again in the pool doesn't demote it
+        acks.pending(txid, Some(entry_hash), 3_000);
+        assert_eq!(acks.transaction(&txid).unwrap().status, AckStatus::DBlockConfirmed);
+
+        acks.retain_pending(&HashSet::new());
+        assert!(acks.transaction(&txid).is_some());
+        acks.forget(&txid);
+        assert!(acks.transaction(&txid).is_none());
+        assert!(acks.entry(&entry_hash).is_none());
+    }
+}
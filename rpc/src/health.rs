@@ -0,0 +1,173 @@
+//! # Health Endpoints
+//!
+//! `/health` and `/ready` are answered on the RPC port for load balancers and
+//! orchestrators. Both return the node status as JSON, with `200 OK` when the
+//! check passes and `503 Service Unavailable` when it doesn't.
+//!
+//! * `/health` passes once the node has started and, unless it runs alone on a
+//!   development network, is connected to peers.
+//! * `/ready` also requires the node to have caught up with the network and its
+//!   best block to still advance, so that the legacy API answers with current
+//!   data.
+//!
+use jsonrpc_http_server::hyper::{header, Body, Request, Response, StatusCode};
+use jsonrpc_http_server::RequestMiddlewareAction;
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+
+/// Status of the node, kept up to date by the service
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct NodeHealth {
+    /// The node has started and is following the chain
+    pub started: bool,
+    /// Blocks are being downloaded from peers
+    pub is_syncing: bool,
+    pub peers: usize,
+    /// Whether having no peers is a problem; not on a development network
+    pub should_have_peers: bool,
+    pub best_block: u64,
+    /// The best block hasn't advanced for a while, though the node isn't syncing
+    pub is_stalled: bool,
+    pub finalized_block: u64,
+    /// The node is configured as an authority
+    pub is_authority: bool,
+    /// The node authored a block recently
+    pub is_producing: bool,
+}
+
+impl NodeHealth {
+    /// Health of a node that hasn't started yet
+    pub fn starting(is_authority: bool, should_have_peers: bool) -> Self {
+        NodeHealth {
+            is_authority,
+            should_have_peers,
+            ..NodeHealth::default()
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.started && (self.peers > 0 || !self.should_have_peers)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.is_healthy() && !self.is_syncing && !self.is_stalled
+    }
+}
+
+/// Node health shared between the service and the RPC server
+pub type SharedHealth = Arc<RwLock<NodeHealth>>;
+
+/// Answer the health endpoints, pass anything else on to the RPC handler
+pub(crate) fn middleware(health: SharedHealth) -> impl Fn(Request<Body>) -> RequestMiddlewareAction {
+    move |request: Request<Body>| {
+        let check: fn(&NodeHealth) -> bool = match request.uri().path() {
+            "/health" => NodeHealth::is_healthy,
+            "/ready" => NodeHealth::is_ready,
+            _ => {
+                return RequestMiddlewareAction::Proceed {
+                    should_continue_on_invalid_cors: false,
+                    request,
+                }
+            }
+        };
+
+        let status = health
+            .read()
+            .map(|health| health.clone())
+            .unwrap_or_default();
+        let code = if check(&status) {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        let body = serde_json::to_string(&status).unwrap_or_default();
+
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = code;
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        RequestMiddlewareAction::Respond {
+            should_validate_hosts: true,
+            response: Box::new(futures::future::ok(response)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::Future;
+
+    fn synced() -> NodeHealth {
+        NodeHealth {
+            started: true,
+            peers: 3,
+            best_block: 10,
+            ..NodeHealth::starting(false, true)
+        }
+    }
+
+    /// Status code the middleware answers `path` with, none if it passes it on
+    fn status(health: NodeHealth, path: &str) -> Option<StatusCode> {
+        let middleware = middleware(Arc::new(RwLock::new(health)));
+        let request = Request::builder().uri(path).body(Body::empty()).unwrap();
+        match middleware(request) {
+            RequestMiddlewareAction::Respond { response, .. } => Some(response.wait().unwrap().status()),
+            RequestMiddlewareAction::Proceed { .. } => None,
+        }
+    }
+
+    #[test]
+    fn test_checks() {
+        assert!(synced().is_healthy());
+        assert!(synced().is_ready());
+
+        let starting = NodeHealth::starting(false, true);
+        assert!(!starting.is_healthy());
+        assert!(!starting.is_ready());
+
+        let syncing = NodeHealth {
+            is_syncing: true,
+            ..synced()
+        };
+        assert!(syncing.is_healthy());
+        assert!(!syncing.is_ready());
+
+        let alone = NodeHealth { peers: 0, ..synced() };
+        assert!(!alone.is_healthy());
+        assert!(!alone.is_ready());
+        // A development node runs without peers
+        let dev = NodeHealth {
+            should_have_peers: false,
+            ..alone
+        };
+        assert!(dev.is_healthy());
+        assert!(dev.is_ready());
+
+        let stalled = NodeHealth {
+            is_stalled: true,
+            ..synced()
+        };
+        assert!(stalled.is_healthy());
+        assert!(!stalled.is_ready());
+    }
+
+    #[test]
+    fn test_middleware() {
+        assert_eq!(status(synced(), "/health"), Some(StatusCode::OK));
+        assert_eq!(status(synced(), "/ready"), Some(StatusCode::OK));
+        let syncing = NodeHealth {
+            is_syncing: true,
+            ..synced()
+        };
+        assert_eq!(status(syncing.clone(), "/health"), Some(StatusCode::OK));
+        assert_eq!(status(syncing, "/ready"), Some(StatusCode::SERVICE_UNAVAILABLE));
+        let starting = NodeHealth::starting(true, true);
+        assert_eq!(status(starting.clone(), "/health"), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(status(starting, "/ready"), Some(StatusCode::SERVICE_UNAVAILABLE));
+        // RPC calls go on to the handler
+        assert_eq!(status(synced(), "/"), None);
+    }
+}
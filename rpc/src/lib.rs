@@ -3,4 +3,99 @@
 //! This is the most crucial layer for backwards compatibility. Clients running on factomd must be
 //! able to drop in this version without complication.
 //!
-pub fn start_rpc_server(_addr: &str, _port: u16) {}
+//! Next to the HTTP server, a WebSocket server takes subscriptions to new blocks,
+//! entries, EC balances and entry acks, see `subscriptions`. Nodes keeping an
+//! entry index also answer the methods of `search`. Full nodes answer the
+//! legacy ack methods of `acks` and show their transaction pool, see `pending`,
+//! and take the legacy commits, reveals and transactions of `write`.
+//!
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate prometheus;
+
+use jsonrpc_core::MetaIoHandler;
+use jsonrpc_http_server::ServerBuilder;
+use jsonrpc_pubsub::Session;
+use jsonrpc_ws_server::RequestContext;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+pub use acks::{Ack, AckTracker, SharedAcks};
+pub use health::{NodeHealth, SharedHealth};
+pub use jsonrpc_http_server::Server;
+pub use jsonrpc_ws_server::Server as WsServer;
+pub use pending::{PendingEntry, PendingPool, PendingTransaction, SharedPending};
+pub use search::{EntryIndex, IndexedChain, IndexedEntry, SharedIndex};
+pub use subscriptions::{
+    AckStatus, EcBalance, EntryAck, NewBlock, NewEntry, Notification, SharedSubscriptions, Subscriptions,
+};
+pub use write::{SharedSubmitter, Submitter};
+
+pub mod acks;
+mod health;
+mod metrics;
+pub mod pending;
+pub mod search;
+pub mod subscriptions;
+pub mod write;
+
+/// Handler for all RPC methods, with every call timed
+pub type RpcHandler = MetaIoHandler<(), metrics::Metered>;
+
+/// Build the RPC handler
+pub fn rpc_handler() -> RpcHandler {
+    MetaIoHandler::with_middleware(metrics::Metered)
+}
+
+/// Start the HTTP RPC server
+///
+/// `/health` and `/ready` are answered from `health`, the ack methods from
+/// `acks`, the pending methods from `pending`, the search methods from `index`
+/// and the write methods through `submitter`, which needs `acks` to check
+/// reveals, if given. The server stops when the returned handle is dropped.
+pub fn start_rpc_server(
+    addr: &str,
+    port: u16,
+    health: SharedHealth,
+    acks: Option<SharedAcks>,
+    pending: Option<SharedPending>,
+    index: Option<SharedIndex>,
+    submitter: Option<SharedSubmitter>,
+) -> Result<Server, String> {
+    let socket: SocketAddr = format!("{}:{}", addr, port)
+        .parse()
+        .map_err(|e| format!("Invalid RPC address {}:{}: {}", addr, port, e))?;
+    let mut io = rpc_handler();
+    if let (Some(submitter), Some(acks)) = (submitter, acks.clone()) {
+        write::add_methods(&mut io, submitter, acks);
+    }
+    if let Some(acks) = acks {
+        acks::add_methods(&mut io, acks);
+    }
+    if let Some(pending) = pending {
+        pending::add_methods(&mut io, pending);
+    }
+    if let Some(index) = index {
+        search::add_methods(&mut io, index);
+    }
+    ServerBuilder::new(io)
+        .request_middleware(health::middleware(health))
+        .start_http(&socket)
+        .map_err(|e| format!("Failed to start RPC server on {}: {}", socket, e))
+}
+
+/// Start the WebSocket server for subscriptions
+///
+/// The server stops when the returned handle is dropped.
+pub fn start_ws_server(addr: &str, port: u16, subscriptions: SharedSubscriptions) -> Result<WsServer, String> {
+    let socket: SocketAddr = format!("{}:{}", addr, port)
+        .parse()
+        .map_err(|e| format!("Invalid WebSocket address {}:{}: {}", addr, port, e))?;
+    jsonrpc_ws_server::ServerBuilder::with_meta_extractor(
+        subscriptions::handler(subscriptions),
+        |context: &RequestContext| Arc::new(Session::new(context.sender())),
+    )
+    .start(&socket)
+    .map_err(|e| format!("Failed to start WebSocket server on {}: {}", socket, e))
+}
@@ -0,0 +1,68 @@
+//! # RPC Metrics
+//!
+//! Request counts and latencies per method, registered with the default Prometheus
+//! registry so that they are served along with the node's own metrics.
+//!
+use futures::future::{Either, Future};
+use jsonrpc_core::{Call, ErrorCode, Metadata, Middleware, Output, Response};
+use prometheus::{HistogramVec, IntCounterVec};
+use std::time::Instant;
+
+lazy_static! {
+    static ref RPC_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "factomd_rpc_requests_total",
+        "RPC calls by method and outcome",
+        &["method", "status"]
+    )
+    .expect("RPC request counter is registered once with valid labels");
+    static ref RPC_LATENCY: HistogramVec = register_histogram_vec!(
+        "factomd_rpc_request_duration_seconds",
+        "Time taken to answer RPC calls by method",
+        &["method"]
+    )
+    .expect("RPC latency histogram is registered once with valid labels");
+}
+
+type RequestFuture = Box<dyn Future<Item = Option<Response>, Error = ()> + Send>;
+type CallFuture = Box<dyn Future<Item = Option<Output>, Error = ()> + Send>;
+
+/// Middleware that times every call
+///
+/// Calls to methods that don't exist are counted as `unknown`, so that clients
+/// can't add arbitrary label values.
+#[derive(Default)]
+pub struct Metered;
+
+impl<M: Metadata> Middleware<M> for Metered {
+    type Future = RequestFuture;
+    type CallFuture = CallFuture;
+
+    fn on_call<F, X>(&self, call: Call, meta: M, next: F) -> Either<Self::CallFuture, X>
+    where
+        F: Fn(Call, M) -> X + Send + Sync,
+        X: Future<Item = Option<Output>, Error = ()> + Send + 'static,
+    {
+        let method = match &call {
+            Call::MethodCall(call) => call.method.clone(),
+            Call::Notification(notification) => notification.method.clone(),
+            Call::Invalid { .. } => "invalid".to_string(),
+        };
+        let started = Instant::now();
+
+        Either::A(Box::new(next(call, meta).map(move |output| {
+            let (method, status) = match &output {
+                Some(Output::Failure(failure)) if failure.error.code == ErrorCode::MethodNotFound => {
+                    ("unknown", "error")
+                }
+                Some(Output::Failure(_)) => (method.as_str(), "error"),
+                _ => (method.as_str(), "ok"),
+            };
+            let elapsed = started.elapsed();
+            RPC_LATENCY
+                .with_label_values(&[method])
+                .observe(elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9);
+            RPC_REQUESTS.with_label_values(&[method, status]).inc();
+            output
+        })))
+    }
+}
@@ -0,0 +1,129 @@
+//! # Pending Submissions
+//!
+//! What waits in the transaction pool of a full node, for operators looking into
+//! writes that don't make it into blocks:
+//!
+//! | Method                 | Params             | Result                                               |
+//! |------------------------|--------------------|------------------------------------------------------|
+//! | `pending-entries`      | `address` optional | entries waiting, or those paid by an EC address      |
+//! | `pending-transactions` | `address` optional | factoid transactions waiting, or those of an address |
+//!
+//! A transaction is of an address when it is sent from it or to it. The service
+//! refreshes the pending submissions as calls enter the pool and as blocks are
+//! imported. Their status is `NotConfirmed`, or `TransactionACK` for calls a new
+//! block included before the pool let go of them.
+//!
+use jsonrpc_core::Params;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{AckStatus, RpcHandler};
+
+/// An entry call waiting in the transaction pool
+#[derive(Clone, Debug, Serialize)]
+pub struct PendingEntry {
+    pub entryhash: String,
+    pub chainid: String,
+    /// EC address paying for the entry
+    pub sender: String,
+    /// Txid of the call
+    pub txid: String,
+    pub status: AckStatus,
+}
+
+/// A factoid transfer or entry credit purchase waiting in the transaction pool
+#[derive(Clone, Debug, Serialize)]
+pub struct PendingTransaction {
+    pub txid: String,
+    /// FA address sending the factoids
+    pub from: String,
+    /// FA address receiving the factoids, or EC address receiving entry credits
+    pub to: String,
+    /// Factoshis
+    pub amount: u64,
+    pub status: AckStatus,
+}
+
+#[derive(Default)]
+struct Pending {
+    entries: Vec<PendingEntry>,
+    transactions: Vec<PendingTransaction>,
+}
+
+/// Submissions waiting in the transaction pool
+#[derive(Default)]
+pub struct PendingPool {
+    pending: Mutex<Pending>,
+}
+
+/// Pending submissions shared between the service, which refreshes them, and the
+/// RPC server
+pub type SharedPending = Arc<PendingPool>;
+
+impl PendingPool {
+    pub fn new() -> SharedPending {
+        Arc::new(PendingPool::default())
+    }
+
+    /// Replace the pending submissions with those now in the pool
+    pub fn update(&self, entries: Vec<PendingEntry>, transactions: Vec<PendingTransaction>) {
+        *self.lock() = Pending { entries, transactions };
+    }
+
+    /// Entries waiting, of one EC address or of all
+    pub fn entries(&self, address: Option<&str>) -> Vec<PendingEntry> {
+        self.lock()
+            .entries
+            .iter()
+            .filter(|entry| address.map(|address| address == entry.sender).unwrap_or(true))
+            .cloned()
+            .collect()
+    }
+
+    /// Transactions waiting, of one address or of all
+    pub fn transactions(&self, address: Option<&str>) -> Vec<PendingTransaction> {
+        self.lock()
+            .transactions
+            .iter()
+            .filter(|transaction| {
+                address
+                    .map(|address| address == transaction.from || address == transaction.to)
+                    .unwrap_or(true)
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Pending> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct AddressParams {
+    address: Option<String>,
+}
+
+/// Add the pending methods to an RPC handler
+pub(crate) fn add_methods(io: &mut RpcHandler, pending: SharedPending) {
+    let pool = pending.clone();
+    io.add_method("pending-entries", move |params: Params| {
+        let params = address_params(params)?;
+        Ok(json!(pool.entries(params.address.as_ref().map(|address| &address[..]))))
+    });
+
+    io.add_method("pending-transactions", move |params: Params| {
+        let params = address_params(params)?;
+        Ok(json!(
+            pending.transactions(params.address.as_ref().map(|address| &address[..]))
+        ))
+    });
+}
+
+fn address_params(params: Params) -> jsonrpc_core::Result<AddressParams> {
+    match params {
+        Params::None => Ok(AddressParams::default()),
+        params => params.parse(),
+    }
+}
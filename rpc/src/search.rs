@@ -0,0 +1,140 @@
+//! # Entry Search
+//!
+//! Methods answered from the node's entry index, when it keeps one, so that
+//! finding entries doesn't mean scanning whole chains:
+//!
+//! | Method              | Params                                    | Result                                         |
+//! |---------------------|-------------------------------------------|------------------------------------------------|
+//! | `search-extid`      | `prefix`, `chainid` optional, `limit`     | entries with an external id starting with it   |
+//! | `search-chains`     | `extids`, `limit`                         | chains whose first entry starts with these ids |
+//! | `entries-by-height` | `from`, `to`, `chainid` optional, `limit` | entries written by the blocks `from` to `to`   |
+//!
+//! External ids, chain ids and hashes are hex encoded. Results are in the order
+//! the entries were written, at most `limit` of them, 100 by default.
+//!
+use jsonrpc_core::{Error, ErrorCode, Params};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::RpcHandler;
+
+/// Results given when the call doesn't say
+const DEFAULT_LIMIT: u32 = 100;
+/// Most results a call may ask for
+const MAX_LIMIT: u32 = 1000;
+
+/// An entry as the index knows it
+#[derive(Clone, Debug, Serialize)]
+pub struct IndexedEntry {
+    pub entryhash: String,
+    pub chainid: String,
+    pub extids: Vec<String>,
+    /// Height of the block that wrote the entry
+    pub height: u64,
+}
+
+/// A chain as the index knows it
+#[derive(Clone, Debug, Serialize)]
+pub struct IndexedChain {
+    pub chainid: String,
+    /// Hash of the entry that started the chain
+    pub firstentry: String,
+    pub extids: Vec<String>,
+    pub height: u64,
+}
+
+/// Searches an entry index can answer
+pub trait EntryIndex: Send + Sync {
+    /// Entries with an external id starting with `prefix`, of one chain or of all
+    fn entries_by_ext_id(&self, prefix: &[u8], chain_id: Option<&[u8]>, limit: u32) -> Result<Vec<IndexedEntry>, String>;
+
+    /// Chains whose first entry has `ext_ids` as its leading external ids
+    fn chains_by_ext_ids(&self, ext_ids: &[Vec<u8>], limit: u32) -> Result<Vec<IndexedChain>, String>;
+
+    /// Entries written by the blocks from `from` to `to`, of one chain or of all
+    fn entries_by_height(&self, from: u64, to: u64, chain_id: Option<&[u8]>, limit: u32)
+        -> Result<Vec<IndexedEntry>, String>;
+}
+
+/// Index shared between the service, which writes it, and the RPC server
+pub type SharedIndex = Arc<dyn EntryIndex>;
+
+#[derive(Deserialize)]
+struct ExtIdParams {
+    prefix: String,
+    chainid: Option<String>,
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ChainParams {
+    extids: Vec<String>,
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct HeightParams {
+    from: u64,
+    to: u64,
+    chainid: Option<String>,
+    limit: Option<u32>,
+}
+
+/// Add the search methods to an RPC handler
+pub(crate) fn add_methods(io: &mut RpcHandler, index: SharedIndex) {
+    let search = index.clone();
+    io.add_method("search-extid", move |params: Params| {
+        let params: ExtIdParams = params.parse()?;
+        let chain_id = params.chainid.as_ref().map(|id| decode(id)).transpose()?;
+        let entries = search
+            .entries_by_ext_id(&decode(&params.prefix)?, chain_id.as_ref().map(|id| &id[..]), limit(params.limit)?)
+            .map_err(internal)?;
+        Ok(json!({ "entries": entries }))
+    });
+
+    let search = index.clone();
+    io.add_method("search-chains", move |params: Params| {
+        let params: ChainParams = params.parse()?;
+        if params.extids.is_empty() {
+            return Err(Error::invalid_params("extids is empty"));
+        }
+        let ext_ids = params.extids.iter().map(|id| decode(id)).collect::<Result<Vec<_>, _>>()?;
+        let chains = search
+            .chains_by_ext_ids(&ext_ids, limit(params.limit)?)
+            .map_err(internal)?;
+        Ok(json!({ "chains": chains }))
+    });
+
+    io.add_method("entries-by-height", move |params: Params| {
+        let params: HeightParams = params.parse()?;
+        if params.from > params.to {
+            return Err(Error::invalid_params("from is above to"));
+        }
+        let chain_id = params.chainid.as_ref().map(|id| decode(id)).transpose()?;
+        let entries = index
+            .entries_by_height(params.from, params.to, chain_id.as_ref().map(|id| &id[..]), limit(params.limit)?)
+            .map_err(internal)?;
+        Ok(json!({ "entries": entries }))
+    });
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(value).map_err(|_| Error::invalid_params(format!("Invalid hex {}", value)))
+}
+
+fn limit(limit: Option<u32>) -> Result<u32, Error> {
+    match limit.unwrap_or(DEFAULT_LIMIT) {
+        0 => Err(Error::invalid_params("limit is 0")),
+        limit if limit > MAX_LIMIT => Err(Error::invalid_params(format!("limit is above {}", MAX_LIMIT))),
+        limit => Ok(limit),
+    }
+}
+
+fn internal(message: String) -> Error {
+    Error {
+        code: ErrorCode::InternalError,
+        message,
+        data: None,
+    }
+}
@@ -0,0 +1,389 @@
+//! # WebSocket Subscriptions
+//!
+//! Clients subscribe over WebSocket instead of polling `chain-head`. Each
+//! subscription has a subscribe and an unsubscribe method, and its
+//! notifications carry the subscription id along with the result:
+//!
+//! | Subscribe               | Params                 | Notification | Sent for                         |
+//! |-------------------------|------------------------|--------------|----------------------------------|
+//! | `subscribe_newBlocks`   |                        | `newBlock`   | each new best block              |
+//! | `subscribe_newEntries`  | `chainid`, optional    | `newEntry`   | entries of the chain, or of all  |
+//! | `subscribe_ecBalance`   | `address`              | `ecBalance`  | balance changes of an EC address |
+//! | `subscribe_entryAck`    | `hash`                 | `entryAck`   | status changes of an entry       |
+//!
+//! Unsubscribing takes the subscription id, as in `unsubscribe_newBlocks`, and
+//! only cancels subscriptions made over the same connection.
+//!
+//! The service publishes notifications as it imports blocks. A subscriber that
+//! doesn't keep up misses notifications rather than holding up the node, and
+//! one that disconnected is dropped on the next notification.
+//!
+use futures::{AsyncSink, Sink as _};
+use jsonrpc_core::{Error, MetaIoHandler, Params, Value};
+use jsonrpc_pubsub::{PubSubHandler, Session, Sink, Subscriber, SubscriptionId};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use crate::metrics;
+
+/// A new best block
+#[derive(Clone, Debug, Serialize)]
+pub struct NewBlock {
+    pub height: u64,
+    pub hash: String,
+    /// Seconds
+    pub timestamp: u64,
+}
+
+/// An entry written by a new best block
+#[derive(Clone, Debug, Serialize)]
+pub struct NewEntry {
+    pub chainid: String,
+    pub entryhash: String,
+    pub height: u64,
+    /// The entry started its chain
+    pub newchain: bool,
+}
+
+/// The balance of an EC address after a new best block changed it
+#[derive(Clone, Debug, Serialize)]
+pub struct EcBalance {
+    pub address: String,
+    pub balance: u64,
+    pub height: u64,
+}
+
+/// Status of a submitted entry or transaction, as the legacy ack methods give it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum AckStatus {
+    /// Not known to the node
+    Unknown,
+    /// Waiting in the transaction pool
+    NotConfirmed,
+    /// Included in a block
+    #[serde(rename = "TransactionACK")]
+    TransactionAck,
+    /// Included in a confirmed block
+    DBlockConfirmed,
+}
+
+/// A change of the status of an entry
+#[derive(Clone, Debug, Serialize)]
+pub struct EntryAck {
+    pub entryhash: String,
+    pub status: AckStatus,
+    /// Height of the block the status refers to
+    pub height: u64,
+}
+
+/// Something subscribers may be notified of
+#[derive(Clone, Debug)]
+pub enum Notification {
+    NewBlock(NewBlock),
+    NewEntry(NewEntry),
+    EcBalance(EcBalance),
+    EntryAck(EntryAck),
+}
+
+/// What a subscription was made for
+#[derive(Clone, Debug, PartialEq)]
+enum Topic {
+    NewBlocks,
+    /// Entries of one chain, or of every chain
+    NewEntries(Option<String>),
+    EcBalance(String),
+    EntryAck(String),
+}
+
+#[derive(Default, Deserialize)]
+struct ChainParams {
+    chainid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AddressParams {
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct HashParams {
+    hash: String,
+}
+
+impl Topic {
+    fn matches(&self, notification: &Notification) -> bool {
+        match (self, notification) {
+            (Topic::NewBlocks, Notification::NewBlock(_)) => true,
+            (Topic::NewEntries(None), Notification::NewEntry(_)) => true,
+            (Topic::NewEntries(Some(chain_id)), Notification::NewEntry(entry)) => *chain_id == entry.chainid,
+            (Topic::EcBalance(address), Notification::EcBalance(balance)) => *address == balance.address,
+            (Topic::EntryAck(hash), Notification::EntryAck(ack)) => *hash == ack.entryhash,
+            _ => false,
+        }
+    }
+}
+
+impl Notification {
+    fn result(&self) -> Value {
+        match self {
+            Notification::NewBlock(block) => json!(block),
+            Notification::NewEntry(entry) => json!(entry),
+            Notification::EcBalance(balance) => json!(balance),
+            Notification::EntryAck(ack) => json!(ack),
+        }
+    }
+}
+
+struct Subscription {
+    topic: Topic,
+    sink: Sink,
+    /// Connection the subscription was made over
+    session: Weak<Session>,
+}
+
+/// Subscriptions of every connected client
+#[derive(Default)]
+pub struct Subscriptions {
+    next_id: AtomicUsize,
+    subscriptions: Mutex<HashMap<SubscriptionId, Subscription>>,
+}
+
+/// Subscriptions shared between the service and the WebSocket server
+pub type SharedSubscriptions = Arc<Subscriptions>;
+
+impl Subscriptions {
+    pub fn new() -> SharedSubscriptions {
+        Arc::new(Subscriptions::default())
+    }
+
+    /// Notify the subscribers of a topic
+    ///
+    /// Must be called from a task, like the service's block import handlers.
+    /// Notifications that don't fit in a subscriber's queue are dropped.
+    pub fn publish(&self, notification: &Notification) {
+        let mut subscriptions = self.lock();
+        let mut closed = Vec::new();
+        for (id, subscription) in subscriptions.iter_mut() {
+            if !subscription.topic.matches(notification) {
+                continue;
+            }
+            let mut params = serde_json::Map::new();
+            params.insert("subscription".to_string(), id_value(id));
+            params.insert("result".to_string(), notification.result());
+            match subscription.sink.start_send(Params::Map(params)) {
+                Ok(AsyncSink::Ready) | Ok(AsyncSink::NotReady(_)) => {
+                    if subscription.sink.poll_complete().is_err() {
+                        closed.push(id.clone());
+                    }
+                }
+                Err(_) => closed.push(id.clone()),
+            }
+        }
+        for id in closed {
+            subscriptions.remove(&id);
+        }
+    }
+
+    /// Whether anyone is subscribed, so that the service can skip work otherwise
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn subscribe(&self, topic: Topic, subscriber: Subscriber, session: &Arc<Session>) {
+        let id = SubscriptionId::Number(self.next_id.fetch_add(1, Ordering::SeqCst) as u64);
+        if let Ok(sink) = subscriber.assign_id(id.clone()) {
+            let session = Arc::downgrade(session);
+            self.lock().insert(id, Subscription { topic, sink, session });
+        }
+    }
+
+    /// Cancel a subscription made over the connection of `session`
+    fn unsubscribe(&self, id: &SubscriptionId, session: &Arc<Session>) -> bool {
+        let mut subscriptions = self.lock();
+        match subscriptions.get(id) {
+            Some(subscription) if Weak::ptr_eq(&subscription.session, &Arc::downgrade(session)) => {
+                subscriptions.remove(id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<SubscriptionId, Subscription>> {
+        self.subscriptions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn id_value(id: &SubscriptionId) -> Value {
+    match id {
+        SubscriptionId::Number(id) => json!(id),
+        SubscriptionId::String(id) => json!(id),
+    }
+}
+
+/// Reads the topic of a subscription from its params
+type TopicParser = fn(Params) -> Result<Topic, Error>;
+
+/// Handler for the subscription methods, with every call timed
+pub(crate) fn handler(subscriptions: SharedSubscriptions) -> PubSubHandler<Arc<Session>, metrics::Metered> {
+    // Subscriptions by method name, with their notification and topic
+    let topics: [(&str, &str, TopicParser); 4] = [
+        ("newBlocks", "newBlock", |_| Ok(Topic::NewBlocks)),
+        ("newEntries", "newEntry", |params| {
+            let params: ChainParams = match params {
+                Params::None => ChainParams::default(),
+                params => params.parse()?,
+            };
+            Ok(Topic::NewEntries(params.chainid.map(|id| id.to_lowercase())))
+        }),
+        ("ecBalance", "ecBalance", |params| {
+            Ok(Topic::EcBalance(params.parse::<AddressParams>()?.address))
+        }),
+        ("entryAck", "entryAck", |params| {
+            Ok(Topic::EntryAck(params.parse::<HashParams>()?.hash.to_lowercase()))
+        }),
+    ];
+
+    let mut io = PubSubHandler::new(MetaIoHandler::with_middleware(metrics::Metered));
+    for &(name, notification, parse) in &topics {
+        let subscribe = subscriptions.clone();
+        let unsubscribe = subscriptions.clone();
+        io.add_subscription(
+            notification,
+            (
+                &format!("subscribe_{}", name),
+                move |params: Params, session: Arc<Session>, subscriber: Subscriber| match parse(params) {
+                    Ok(topic) => subscribe.subscribe(topic, subscriber, &session),
+                    Err(e) => {
+                        let _ = subscriber.reject(e);
+                    }
+                },
+            ),
+            (
+                &format!("unsubscribe_{}", name),
+                move |id: SubscriptionId, session: Option<Arc<Session>>| -> jsonrpc_core::Result<Value> {
+                    // Subscriptions are only cancelled by the connection that made them
+                    match session {
+                        Some(session) => Ok(Value::Bool(unsubscribe.unsubscribe(&id, &session))),
+                        None => Ok(Value::Bool(false)),
+                    }
+                },
+            ),
+        );
+    }
+    io
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::sync::mpsc;
+    use futures::{future, Future, Stream};
+
+    fn block(height: u64) -> Notification {
+        Notification::NewBlock(NewBlock {
+            height,
+            hash: "00".repeat(32),
+            timestamp: 0,
+        })
+    }
+
+    fn entry(chain_id: &str) -> Notification {
+        Notification::NewEntry(NewEntry {
+            chainid: chain_id.to_string(),
+            entryhash: "01".repeat(32),
+            height: 1,
+            newchain: false,
+        })
+    }
+
+    /// A WebSocket connection, with the messages the server sends over it
+    fn connect() -> (Arc<Session>, mpsc::Receiver<String>) {
+        let (sender, receiver) = mpsc::channel(8);
+        (Arc::new(Session::new(sender)), receiver)
+    }
+
+    fn call(io: &PubSubHandler<Arc<Session>, metrics::Metered>, session: &Arc<Session>, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response = io.handle_request_sync(&request.to_string(), session.clone()).unwrap();
+        serde_json::from_str::<Value>(&response).unwrap()["result"].clone()
+    }
+
+    fn publish(subscriptions: &Subscriptions, notification: Notification) {
+        // Notifications are sent from a task
+        future::lazy(|| {
+            subscriptions.publish(&notification);
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_matches() {
+        let chain_id = "02".repeat(32);
+        assert!(Topic::NewBlocks.matches(&block(1)));
+        assert!(!Topic::NewBlocks.matches(&entry(&chain_id)));
+        assert!(Topic::NewEntries(None).matches(&entry(&chain_id)));
+        assert!(Topic::NewEntries(Some(chain_id.clone())).matches(&entry(&chain_id)));
+        assert!(!Topic::NewEntries(Some(chain_id.clone())).matches(&entry(&"03".repeat(32))));
+
+        let balance = Notification::EcBalance(EcBalance {
+            address: "EC2KnJQN86MYq4pQyeSGTHSiVdkhRCPXS3udzD4im6BXRBjZFMmR".to_string(),
+            balance: 5,
+            height: 1,
+        });
+        assert!(Topic::EcBalance("EC2KnJQN86MYq4pQyeSGTHSiVdkhRCPXS3udzD4im6BXRBjZFMmR".to_string()).matches(&balance));
+        assert!(!Topic::EcBalance("EC3".to_string()).matches(&balance));
+
+        let ack = Notification::EntryAck(EntryAck {
+            entryhash: "01".repeat(32),
+            status: AckStatus::TransactionAck,
+            height: 1,
+        });
+        assert!(Topic::EntryAck("01".repeat(32)).matches(&ack));
+        assert!(!Topic::EntryAck("04".repeat(32)).matches(&ack));
+        assert!(!Topic::EntryAck("01".repeat(32)).matches(&block(1)));
+    }
+
+    #[test]
+    fn test_subscribe_and_publish() {
+        let subscriptions = Subscriptions::new();
+        let io = handler(subscriptions.clone());
+        let (session, receiver) = connect();
+        let chain_id = "02".repeat(32);
+
+        let id = call(&io, &session, "subscribe_newEntries", json!({ "chainid": chain_id.to_uppercase() }));
+        assert!(!subscriptions.is_empty());
+        publish(&subscriptions, block(1));
+        publish(&subscriptions, entry(&"03".repeat(32)));
+        publish(&subscriptions, entry(&chain_id));
+
+        // Only the entry of the chain subscribed to is sent
+        let (message, _) = receiver.into_future().wait().map_err(|_| ()).unwrap();
+        let message: Value = serde_json::from_str(&message.unwrap()).unwrap();
+        assert_eq!(message["method"], "newEntry");
+        assert_eq!(message["params"]["subscription"], id);
+        assert_eq!(message["params"]["result"]["chainid"], json!(chain_id));
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let subscriptions = Subscriptions::new();
+        let io = handler(subscriptions.clone());
+        let (session, _receiver) = connect();
+        let (other, _other_receiver) = connect();
+
+        let id = call(&io, &session, "subscribe_newBlocks", json!([]));
+        // Another connection can't cancel it
+        assert_eq!(call(&io, &other, "unsubscribe_newBlocks", json!([id])), json!(false));
+        assert!(!subscriptions.is_empty());
+
+        assert_eq!(call(&io, &session, "unsubscribe_newBlocks", json!([id])), json!(true));
+        assert!(subscriptions.is_empty());
+        assert_eq!(call(&io, &session, "unsubscribe_newBlocks", json!([id])), json!(false));
+    }
+}
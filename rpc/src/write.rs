@@ -0,0 +1,202 @@
+//! # Writes
+//!
+//! The legacy write methods, taking the requests walletd composes:
+//!
+//! | Method           | Params        | Result                                 |
+//! |------------------|---------------|----------------------------------------|
+//! | `commit-chain`   | `message`     | txid and entry hash of the first entry |
+//! | `commit-entry`   | `message`     | txid and entry hash of the entry       |
+//! | `reveal-chain`   | `entry`       | entry hash and chain id                |
+//! | `reveal-entry`   | `entry`       | entry hash and chain id                |
+//! | `factoid-submit` | `transaction` | txids of the transaction               |
+//!
+//! A commit message is a signed extrinsic writing the entry, hex encoded. It pays
+//! for the entry and writes it at once, so committing submits it to the
+//! transaction pool and revealing only checks that the entry, hex encoded in the
+//! legacy format, is the one a commit writes. A transaction is the SCALE encoded
+//! list of its signed extrinsics, one per output, submitted in order.
+//!
+use jsonrpc_core::{Error, ErrorCode, Params};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::acks::TxHash;
+use crate::{RpcHandler, SharedAcks};
+
+/// Submissions to the node's transaction pool
+pub trait Submitter: Send + Sync {
+    /// Submit a signed extrinsic writing an entry, giving its txid and entry hash
+    fn commit(&self, extrinsic: &[u8]) -> Result<(TxHash, TxHash), String>;
+
+    /// Submit the signed extrinsics of a transaction, giving their txids
+    fn submit_transaction(&self, extrinsics: &[u8]) -> Result<Vec<TxHash>, String>;
+
+    /// Chain id and entry hash of an entry in the legacy format
+    fn entry_ids(&self, entry: &[u8]) -> Result<(TxHash, TxHash), String>;
+}
+
+/// Submitter shared between the service, which owns the pool, and the RPC server
+pub type SharedSubmitter = Arc<dyn Submitter>;
+
+#[derive(Deserialize)]
+struct CommitParams {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct RevealParams {
+    entry: String,
+}
+
+#[derive(Deserialize)]
+struct TransactionParams {
+    transaction: String,
+}
+
+/// Add the write methods to an RPC handler
+///
+/// Reveals are checked against the commits `acks` tracks.
+pub(crate) fn add_methods(io: &mut RpcHandler, submitter: SharedSubmitter, acks: SharedAcks) {
+    for &(method, message) in &[("commit-chain", "Chain Commit Success"), ("commit-entry", "Entry Commit Success")] {
+        let submitter = submitter.clone();
+        io.add_method(method, move |params: Params| {
+            let params: CommitParams = params.parse()?;
+            let (txid, entry_hash) = submitter.commit(&decode(&params.message)?).map_err(rejected)?;
+            Ok(json!({
+                "message": message,
+                "txid": hex::encode(txid),
+                "entryhash": hex::encode(entry_hash),
+            }))
+        });
+    }
+
+    for &method in &["reveal-chain", "reveal-entry"] {
+        let (submitter, acks) = (submitter.clone(), acks.clone());
+        io.add_method(method, move |params: Params| {
+            let params: RevealParams = params.parse()?;
+            let (chain_id, entry_hash) = submitter
+                .entry_ids(&decode(&params.entry)?)
+                .map_err(Error::invalid_params)?;
+            if acks.entry(&entry_hash).is_none() {
+                return Err(rejected("Entry hasn't been committed".to_string()));
+            }
+            Ok(json!({
+                "message": "Entry Reveal Success",
+                "entryhash": hex::encode(entry_hash),
+                "chainid": hex::encode(chain_id),
+            }))
+        });
+    }
+
+    io.add_method("factoid-submit", move |params: Params| {
+        let params: TransactionParams = params.parse()?;
+        let txids = submitter
+            .submit_transaction(&decode(&params.transaction)?)
+            .map_err(rejected)?;
+        let txids: Vec<_> = txids.iter().map(hex::encode).collect();
+        Ok(json!({
+            "message": "Successfully submitted the transaction",
+            "txid": txids.first(),
+            "txids": txids,
+        }))
+    });
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(value).map_err(|_| Error::invalid_params(format!("Invalid hex {}", value)))
+}
+
+/// A write the node didn't accept
+fn rejected(message: String) -> Error {
+    Error {
+        code: ErrorCode::ServerError(-32011),
+        message,
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rpc_handler, AckTracker};
+    use serde_json::Value;
+
+    /// Commits entries with a one byte chain id and entry hash, and transactions of as many extrinsics as bytes
+    struct MockSubmitter(SharedAcks);
+
+    impl Submitter for MockSubmitter {
+        fn commit(&self, extrinsic: &[u8]) -> Result<(TxHash, TxHash), String> {
+            match *extrinsic {
+                [txid, entry_hash] => {
+                    self.0.pending([txid; 32], Some([entry_hash; 32]), 1_000);
+                    Ok(([txid; 32], [entry_hash; 32]))
+                }
+                _ => Err("Not an entry".to_string()),
+            }
+        }
+
+        fn submit_transaction(&self, extrinsics: &[u8]) -> Result<Vec<TxHash>, String> {
+            Ok(extrinsics.iter().map(|&txid| [txid; 32]).collect())
+        }
+
+        fn entry_ids(&self, entry: &[u8]) -> Result<(TxHash, TxHash), String> {
+            match *entry {
+                [chain_id, entry_hash] => Ok(([chain_id; 32], [entry_hash; 32])),
+                _ => Err("Invalid entry".to_string()),
+            }
+        }
+    }
+
+    fn call(io: &RpcHandler, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response = io.handle_request_sync(&request.to_string(), ()).unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[test]
+    fn test_commit_and_reveal() {
+        let acks = AckTracker::new();
+        let mut io = rpc_handler();
+        add_methods(&mut io, Arc::new(MockSubmitter(acks.clone())), acks);
+
+        // Revealing before committing fails
+        let response = call(&io, "reveal-entry", json!({ "entry": "0302" }));
+        assert_eq!(response["error"]["message"], "Entry hasn't been committed");
+
+        let response = call(&io, "commit-entry", json!({ "message": "0102" }));
+        assert_eq!(
+            response["result"],
+            json!({
+                "message": "Entry Commit Success",
+                "txid": hex::encode([1u8; 32]),
+                "entryhash": hex::encode([2u8; 32]),
+            })
+        );
+        let response = call(&io, "reveal-entry", json!({ "entry": "0302" }));
+        assert_eq!(
+            response["result"],
+            json!({
+                "message": "Entry Reveal Success",
+                "entryhash": hex::encode([2u8; 32]),
+                "chainid": hex::encode([3u8; 32]),
+            })
+        );
+
+        let response = call(&io, "commit-chain", json!({ "message": "01" }));
+        assert_eq!(response["error"]["message"], "Not an entry");
+        let response = call(&io, "reveal-chain", json!({ "entry": "zz" }));
+        assert_eq!(response["error"]["message"], "Invalid hex zz");
+    }
+
+    #[test]
+    fn test_factoid_submit() {
+        let acks = AckTracker::new();
+        let mut io = rpc_handler();
+        add_methods(&mut io, Arc::new(MockSubmitter(acks.clone())), acks);
+
+        let response = call(&io, "factoid-submit", json!({ "transaction": "0405" }));
+        assert_eq!(response["result"]["txid"], hex::encode([4u8; 32]));
+        assert_eq!(response["result"]["txids"], json!([hex::encode([4u8; 32]), hex::encode([5u8; 32])]));
+    }
+}
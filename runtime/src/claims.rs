@@ -0,0 +1,420 @@
+//! ## Legacy Claims
+//!
+//! Balances carried over from the legacy Factom network are held here until their
+//! owners claim them. Legacy keys are ed25519, while accounts on this chain are
+//! sr25519, so the owner proves ownership by signing the destination account with
+//! the legacy key. The signed message includes the genesis hash, so a claim made
+//! for one network can't be replayed on another one holding the same snapshot.
+//!
+//! Factoid balances are recorded under the RCD hash of the legacy key, which is
+//! what a legacy FA address encodes. Entry credit balances are recorded under the
+//! legacy public key itself, which is what a legacy EC address encodes.
+//!
+//! `claim` is an unsigned extrinsic: the destination account may not hold any
+//! balance yet to pay for a signed one. A claim is removed once it is paid out,
+//! so the same signature can't be used twice. A factoid balance that would leave
+//! the destination below the existential deposit is refused rather than paid
+//! out, as `balances` would destroy it; it can still be claimed to an account
+//! already holding enough.
+//!
+use crate::entry_credit;
+use crate::migration::{self, MigrationStatus, VersionedStorage};
+use parity_codec::Encode;
+use primitives::ed25519;
+use rstd::prelude::*;
+use runtime_primitives::traits::{ValidateUnsigned, Verify, Zero};
+use runtime_primitives::transaction_validity::{TransactionLongevity, TransactionValidity};
+use sha2::{Digest, Sha256};
+use support::{
+    decl_module, decl_storage, dispatch::Result, ensure, traits::Currency, StorageMap,
+    StorageValue,
+};
+use system::ensure_none;
+
+/// Double SHA-256 hash of a legacy RCD, the key of a legacy FA address
+pub type RcdHash = [u8; 32];
+
+/// Factoid balance
+pub type FctBalanceOf<T> = <T as balances::Trait>::Balance;
+
+/// Entry credit balance
+pub type EcBalanceOf<T> = <T as balances::Trait<balances::Instance0>>::Balance;
+
+/// Signed by the legacy key, followed by the genesis hash and the encoded
+/// destination account
+const CLAIM_PREFIX: &[u8] = b"Pay legacy Factom balances to:";
+
+/// Priority of claims in the transaction pool
+const CLAIM_PRIORITY: u64 = 100;
+
+// Errors reported by validate_unsigned, positive values belong to ApplyError
+const INVALID_SIGNATURE: i8 = -10;
+const NO_CLAIM: i8 = -20;
+const INVALID_CALL: i8 = -30;
+const BELOW_EXISTENTIAL_DEPOSIT: i8 = -40;
+
+pub trait Trait: balances::Trait + entry_credit::Trait {}
+
+decl_storage! {
+    trait Store for Module<T: Trait> as Claims {
+        /// Version of the storage layout, see `migration`
+        StorageVersion get(storage_version): u32;
+        /// Unclaimed factoid balances by RCD hash
+        Claims get(claims) build(|config: &GenesisConfig<T>| config.claims.clone()):
+            map RcdHash => Option<FctBalanceOf<T>>;
+        /// Unclaimed entry credit balances by legacy public key
+        EcClaims get(ec_claims) build(|config: &GenesisConfig<T>| config.ec_claims.clone()):
+            map [u8; 32] => Option<EcBalanceOf<T>>;
+        /// Sum of the unclaimed factoid balances
+        Total get(total) build(|config: &GenesisConfig<T>| {
+            config.claims.iter().fold(Zero::zero(), |acc: FctBalanceOf<T>, &(_, n)| acc + n)
+        }): FctBalanceOf<T>;
+    }
+    add_extra_genesis {
+        config(claims): Vec<(RcdHash, FctBalanceOf<T>)>;
+        config(ec_claims): Vec<([u8; 32], EcBalanceOf<T>)>;
+    }
+}
+
+decl_module! {
+    pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        /// Run storage migrations left pending by a runtime upgrade
+        fn on_initialize(_n: T::BlockNumber) {
+            migration::migrate::<Self>();
+        }
+
+        /// Claim legacy balances
+        ///
+        /// Moves the factoid balance held by the RCD hash of `legacy_key` and the
+        /// entry credit balance held by `legacy_key` to `dest`. The signature must
+        /// be made by `legacy_key` over the `claim_message` for `dest`.
+        fn claim(origin, dest: T::AccountId, legacy_key: ed25519::Public, signature: ed25519::Signature) -> Result {
+            ensure_none(origin)?;
+            ensure!(Self::verify_claim(&dest, &legacy_key, &signature), "Invalid legacy signature");
+            ensure!(Self::has_claim(&legacy_key), "Legacy key has no claim");
+            ensure!(!Self::is_dust(&dest, &legacy_key), "Claim is below the existential deposit");
+
+            let fct = <Claims<T>>::take(rcd_hash(&legacy_key));
+            let ec = <EcClaims<T>>::take(legacy_key.0);
+
+            if let Some(fct) = fct {
+                <Total<T>>::mutate(|total| *total -= fct);
+                let _imbalance = <balances::Module<T> as Currency<_>>::deposit_creating(&dest, fct);
+            }
+            if let Some(ec) = ec {
+                <entry_credit::Module<T>>::increase_ec_balance(dest, ec)?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+impl<T: Trait> Module<T> {
+    /// Check the legacy key signed the claim for `dest`
+    fn verify_claim(
+        dest: &T::AccountId,
+        legacy_key: &ed25519::Public,
+        signature: &ed25519::Signature,
+    ) -> bool {
+        signature.verify(&Self::claim_message(dest)[..], legacy_key)
+    }
+
+    /// Message the legacy key signs to claim for `dest`
+    ///
+    /// `CLAIM_PREFIX`, then the genesis hash and the encoded `dest`. `system` only
+    /// records the genesis hash once block 1 is initialized, so claims can't be made
+    /// before.
+    pub fn claim_message(dest: &T::AccountId) -> Vec<u8> {
+        let mut message = CLAIM_PREFIX.to_vec();
+        <system::Module<T>>::block_hash(T::BlockNumber::zero())
+            .using_encoded(|encoded| message.extend_from_slice(encoded));
+        dest.using_encoded(|encoded| message.extend_from_slice(encoded));
+        message
+    }
+
+    /// Check whether a legacy key has anything left to claim
+    fn has_claim(legacy_key: &ed25519::Public) -> bool {
+        <Claims<T>>::exists(rcd_hash(legacy_key)) || <EcClaims<T>>::exists(legacy_key.0)
+    }
+
+    /// Check whether paying the factoid claim of a legacy key would leave `dest`
+    /// below the existential deposit
+    fn is_dust(dest: &T::AccountId, legacy_key: &ed25519::Public) -> bool {
+        match <Claims<T>>::get(rcd_hash(legacy_key)) {
+            Some(fct) => {
+                fct + <balances::Module<T>>::free_balance(dest)
+                    < <balances::Module<T>>::existential_deposit()
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T: Trait> ValidateUnsigned for Module<T> {
+    type Call = Call<T>;
+
+    /// Only claims with a valid signature for an existing claim enter the pool.
+    /// A claim provides its legacy key, so a second claim by the same key is
+    /// dropped from the pool.
+    fn validate_unsigned(call: &Self::Call) -> TransactionValidity {
+        match call {
+            Call::claim(dest, legacy_key, signature) => {
+                if !Self::verify_claim(dest, legacy_key, signature) {
+                    return TransactionValidity::Invalid(INVALID_SIGNATURE);
+                }
+                if !Self::has_claim(legacy_key) {
+                    return TransactionValidity::Invalid(NO_CLAIM);
+                }
+                if Self::is_dust(dest, legacy_key) {
+                    return TransactionValidity::Invalid(BELOW_EXISTENTIAL_DEPOSIT);
+                }
+                TransactionValidity::Valid {
+                    priority: CLAIM_PRIORITY,
+                    requires: vec![],
+                    provides: vec![legacy_key.encode()],
+                    longevity: TransactionLongevity::max_value(),
+                }
+            }
+            _ => TransactionValidity::Invalid(INVALID_CALL),
+        }
+    }
+}
+
+impl<T: Trait> VersionedStorage for Module<T> {
+    const STORAGE_VERSION: u32 = 0;
+
+    fn stored_version() -> u32 {
+        Self::storage_version()
+    }
+
+    fn put_stored_version(version: u32) {
+        <StorageVersion<T>>::put(version);
+    }
+
+    /// Nothing to migrate yet, version 0 is the first layout
+    fn migrate_from(_version: u32) -> MigrationStatus {
+        MigrationStatus::Complete
+    }
+}
+
+/// RCD hash of a legacy key
+///
+/// A type 1 RCD is the byte `0x01` followed by the ed25519 public key. Its double
+/// SHA-256 hash is the key encoded in a legacy FA address.
+pub fn rcd_hash(legacy_key: &ed25519::Public) -> RcdHash {
+    let mut rcd = [0u8; 33];
+    rcd[0] = 0x01;
+    rcd[1..].copy_from_slice(&legacy_key.0);
+
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Sha256::digest(&Sha256::digest(&rcd)));
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{genesis, Balances, Claims, EcBalances, Origin, Test};
+    use primitives::{Blake2Hasher, Pair, H256};
+    use runtime_io::{with_externalities, TestExternalities};
+    use runtime_primitives::BuildStorage;
+    use support::{assert_noop, assert_ok};
+
+    const DEST: u64 = 42;
+
+    fn legacy_pair() -> ed25519::Pair {
+        ed25519::Pair::from_string("//Legacy", None).unwrap()
+    }
+
+    /// A chain past block 1, with claims for the legacy key
+    fn new_test_ext() -> TestExternalities<Blake2Hasher> {
+        test_ext_with_deposit(0)
+    }
+
+    /// A chain past block 1 with an existential deposit, and claims for the legacy key
+    fn test_ext_with_deposit(existential_deposit: u64) -> TestExternalities<Blake2Hasher> {
+        let legacy_key = legacy_pair().public();
+        let mut storage = genesis();
+        storage.extend(
+            balances::GenesisConfig::<Test> {
+                balances: vec![],
+                vesting: vec![],
+                transaction_base_fee: 0,
+                transaction_byte_fee: 0,
+                existential_deposit,
+                transfer_fee: 0,
+                creation_fee: 0,
+            }
+            .build_storage()
+            .unwrap()
+            .0,
+        );
+        storage.extend(
+            GenesisConfig::<Test> {
+                claims: vec![(rcd_hash(&legacy_key), 100)],
+                ec_claims: vec![(legacy_key.0, 50)],
+            }
+            .build_storage()
+            .unwrap()
+            .0,
+        );
+        let mut ext: TestExternalities<Blake2Hasher> = storage.into();
+        with_externalities(&mut ext, || {
+            <system::BlockHash<Test>>::insert(0, H256::from([7; 32]));
+        });
+        ext
+    }
+
+    fn sign_claim(pair: &ed25519::Pair, dest: u64) -> ed25519::Signature {
+        pair.sign(&Claims::claim_message(&dest))
+    }
+
+    fn is_invalid(call: Call<Test>, error: i8) -> bool {
+        match Claims::validate_unsigned(&call) {
+            TransactionValidity::Invalid(e) => e == error,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn valid_claim() {
+        with_externalities(&mut new_test_ext(), || {
+            let pair = legacy_pair();
+            let signature = sign_claim(&pair, DEST);
+            let call = Call::claim(DEST, pair.public(), signature.clone());
+            match Claims::validate_unsigned(&call) {
+                TransactionValidity::Valid { provides, .. } => {
+                    assert_eq!(provides, vec![pair.public().encode()])
+                }
+                _ => panic!("Expected a valid claim"),
+            }
+
+            assert_eq!(Claims::total(), 100);
+            assert_ok!(Claims::claim(Origin::NONE, DEST, pair.public(), signature));
+            assert_eq!(Balances::free_balance(&DEST), 100);
+            assert_eq!(EcBalances::free_balance(&DEST), 50);
+            assert_eq!(Claims::total(), 0);
+            assert_eq!(Claims::claims(rcd_hash(&pair.public())), None);
+            assert_eq!(Claims::ec_claims(pair.public().0), None);
+        });
+    }
+
+    #[test]
+    fn bad_signature() {
+        with_externalities(&mut new_test_ext(), || {
+            let pair = legacy_pair();
+            // Signed for another destination
+            let signature = sign_claim(&pair, DEST + 1);
+            assert!(is_invalid(
+                Call::claim(DEST, pair.public(), signature.clone()),
+                INVALID_SIGNATURE
+            ));
+            assert_noop!(
+                Claims::claim(Origin::NONE, DEST, pair.public(), signature),
+                "Invalid legacy signature"
+            );
+
+            // Signed by another key
+            let other = ed25519::Pair::from_string("//Other", None).unwrap();
+            let signature = sign_claim(&other, DEST);
+            assert_noop!(
+                Claims::claim(Origin::NONE, DEST, pair.public(), signature),
+                "Invalid legacy signature"
+            );
+        });
+    }
+
+    #[test]
+    fn replayed_claim() {
+        with_externalities(&mut new_test_ext(), || {
+            let pair = legacy_pair();
+            let signature = sign_claim(&pair, DEST);
+            assert_ok!(Claims::claim(Origin::NONE, DEST, pair.public(), signature.clone()));
+
+            assert!(is_invalid(
+                Call::claim(DEST, pair.public(), signature.clone()),
+                NO_CLAIM
+            ));
+            assert_noop!(
+                Claims::claim(Origin::NONE, DEST, pair.public(), signature),
+                "Legacy key has no claim"
+            );
+            assert_eq!(Balances::free_balance(&DEST), 100);
+        });
+    }
+
+    #[test]
+    fn claim_for_another_network() {
+        let pair = legacy_pair();
+        let mut other_network = new_test_ext();
+        let signature = with_externalities(&mut other_network, || {
+            <system::BlockHash<Test>>::insert(0, H256::from([8; 32]));
+            sign_claim(&pair, DEST)
+        });
+
+        with_externalities(&mut new_test_ext(), || {
+            assert!(is_invalid(
+                Call::claim(DEST, pair.public(), signature.clone()),
+                INVALID_SIGNATURE
+            ));
+            assert_noop!(
+                Claims::claim(Origin::NONE, DEST, pair.public(), signature),
+                "Invalid legacy signature"
+            );
+        });
+    }
+
+    #[test]
+    fn unknown_legacy_key() {
+        with_externalities(&mut new_test_ext(), || {
+            let unknown = ed25519::Pair::from_string("//Unknown", None).unwrap();
+            let signature = sign_claim(&unknown, DEST);
+            assert!(is_invalid(
+                Call::claim(DEST, unknown.public(), signature.clone()),
+                NO_CLAIM
+            ));
+            assert_noop!(
+                Claims::claim(Origin::NONE, DEST, unknown.public(), signature),
+                "Legacy key has no claim"
+            );
+        });
+    }
+
+    #[test]
+    fn dust_claim() {
+        with_externalities(&mut test_ext_with_deposit(200), || {
+            let pair = legacy_pair();
+            let signature = sign_claim(&pair, DEST);
+            // Paying 100 to a new account would destroy it
+            assert!(is_invalid(
+                Call::claim(DEST, pair.public(), signature.clone()),
+                BELOW_EXISTENTIAL_DEPOSIT
+            ));
+            assert_noop!(
+                Claims::claim(Origin::NONE, DEST, pair.public(), signature.clone()),
+                "Claim is below the existential deposit"
+            );
+            assert_eq!(Claims::claims(rcd_hash(&pair.public())), Some(100));
+            assert_eq!(Claims::ec_claims(pair.public().0), Some(50));
+
+            // An account that already exists takes it
+            Balances::make_free_balance_be(&DEST, 200);
+            assert_ok!(Claims::claim(Origin::NONE, DEST, pair.public(), signature));
+            assert_eq!(Balances::free_balance(&DEST), 300);
+            assert_eq!(Claims::total(), 0);
+        });
+    }
+
+    #[test]
+    fn signed_claim() {
+        with_externalities(&mut new_test_ext(), || {
+            let pair = legacy_pair();
+            let signature = sign_claim(&pair, DEST);
+            assert_noop!(
+                Claims::claim(Origin::signed(DEST), DEST, pair.public(), signature),
+                "bad origin: expected to be no origin"
+            );
+        });
+    }
+}
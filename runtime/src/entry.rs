@@ -5,14 +5,72 @@
 //! entry content are stored as `Vec<u8>`. For now, the client is responsible
 //! for performing the Vec<u8> enoding; it is possible to accept a string
 //! and perform the encoding here but that hasn't been tried in the PoC or here.
+//!
+//! ### Storage layout
+//!
+//! Version 0 stored each entry directly under `(chain id, n)` in `EntryData`.
+//! Version 1 stores each entry once under its entry hash in `Entries` and
+//! keeps the order of a chain in `ChainEntries`, which lets entries be looked
+//! up by hash. Chain ids can't be enumerated on chain, so root queues the chains
+//! written under version 0 with `queue_legacy_chains`, marking the last batch of
+//! chain ids as complete. Their entries are moved a batch per block, and the
+//! stored version is only bumped once the queue is complete and drained. Until
+//! a chain has been moved, reads fall back to the version 0 layout.
+//!
+//! ### Legacy entries
+//!
+//! Entries imported from the legacy Factom network are written at genesis, or
+//! for an import too large for genesis, by root in batches with
+//! `import_legacy_entries` once the network runs. They keep their legacy entry
+//! hash and chain id, which are SHA-256 based rather than
+//! Blake2, so that existing references to them stay valid. Entries revealed in
+//! the legacy binary format with `reveal_entry` are stored the same way.
+//!
+//! The same external ids thus give a legacy chain id and a Blake2 chain id. Only
+//! one of the two chains may exist: `put_chain` refuses external ids whose legacy
+//! chain exists, and `reveal_entry` refuses to start a legacy chain whose Blake2
+//! chain exists.
+//!
+//! ### Costs
+//!
+//! As with legacy Factom, writing an entry costs `EntryCost` for every started
+//! KiB of its content and external ids. Starting a chain charges `ChainCost`
+//! in place of the first KiB. See `write_cost`.
+//!
+//! ### Events
+//!
+//! Every entry written deposits `EntryWritten`, after `ChainStarted` if it
+//! starts its chain. Entries moved between storage layouts deposit nothing.
 use crate::entry_credit;
+use crate::legacy_entry::{self, LegacyEntry};
+use crate::migration::{self, MigrationStatus, VersionedStorage};
 use parity_codec::{Decode, Encode};
+use rstd::cmp;
 use rstd::vec::Vec;
+#[cfg(feature = "std")]
+use runtime_io::with_storage;
 use runtime_primitives::traits::Hash;
-use support::{decl_module, decl_storage, dispatch::Result, ensure, StorageMap};
-use system::ensure_signed;
+#[cfg(feature = "std")]
+use serde_derive::{Deserialize, Serialize};
+use support::{decl_event, decl_module, decl_storage, dispatch::Result, ensure, StorageMap, StorageValue};
+use system::{ensure_root, ensure_signed};
 
-pub trait Trait: system::Trait + entry_credit::Trait {}
+pub trait Trait: system::Trait + entry_credit::Trait {
+    /// The overarching event type.
+    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+}
+
+decl_event!(
+    pub enum Event<T>
+    where
+        Hash = <T as system::Trait>::Hash
+    {
+        /// An entry was written: its chain id and entry hash
+        EntryWritten(Hash, Hash),
+        /// A chain was started, by the entry written along with this event
+        ChainStarted(Hash),
+    }
+);
 
 /// Entry Data
 ///
@@ -20,39 +78,146 @@ pub trait Trait: system::Trait + entry_credit::Trait {}
 /// will have its own hash which we can query. That query will also have
 /// the chain id along with it.
 #[derive(Encode, Decode, Default, Clone, PartialEq)]
-#[cfg_attr(feature = "std", derive(Debug))]
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
 pub struct EntryStruct<Hash> {
     content: Vec<u8>,      // Content of arbitrary kind converted to Vec<u8>
     external_ids: Vec<u8>, // Collection of ext ids converted to Vec<u8>
     chain_id: Hash,        // Chain ID is a hash, stored along with entry
 }
 
-// Current design will store entry data as an association of the chain id, u64 to
-// the entry struct. This association is useful for future new entries, as well as
-// retreiving entries from storage later. We must manage, however, the total entries
-// that a hash has accumulated separately.
+/// Maximum number of version 0 entries moved to the current layout per block
+const MIGRATION_BATCH: u64 = 256;
+
+// Entries are stored once under their hash. The chain id, u64 association is kept
+// separately to record the order of entries in a chain, which is useful for future
+// new entries as well as retreiving entries from storage later. We must manage,
+// however, the total entries that a hash has accumulated separately.
 decl_storage! {
     trait Store for Module<T: Trait> as Entry {
-        EntryData get(entry_data): map (T::Hash, u64) => EntryStruct<T::Hash>;
+        /// Version of the storage layout, see `migration`
+        StorageVersion get(storage_version): u32;
+        Entries get(entry): map T::Hash => Option<EntryStruct<T::Hash>>;
+        ChainEntries get(chain_entry): map (T::Hash, u64) => Option<T::Hash>;
         TotalEntries get(total_entries): map T::Hash => u64;
+
+        /// Maximum combined size in bytes of an entry's content and external ids
+        MaxEntrySize get(max_entry_size) config(): u32 = 1024;
+        /// Entry credits spent per KiB of an entry
+        EntryCost get(entry_cost) config(): u64 = 1;
+        /// Entry credits spent for the first KiB of an entry that starts a chain
+        ChainCost get(chain_cost) config(): u64 = 2;
+
+        /// Version 0 layout, only read while migrating
+        EntryData: map (T::Hash, u64) => Option<EntryStruct<T::Hash>>;
+
+        /// Chains with entries still in the version 0 layout
+        LegacyChains get(legacy_chains): Vec<T::Hash>;
+        /// Whether every chain with entries in the version 0 layout has been queued
+        LegacyQueueComplete get(legacy_queue_complete): bool;
+        /// Number of entries already moved for the chain at the front of `LegacyChains`
+        LegacyProgress get(legacy_progress): map T::Hash => u64;
+
+        /// Number of entries imported from the legacy network, at genesis or in batches
+        LegacyImported get(legacy_imported): u64;
+        /// Whether the last batch of legacy entries has been imported
+        LegacyImportComplete get(legacy_import_complete): bool;
+    }
+    add_extra_genesis {
+        // Entries imported from the legacy network with their legacy entry hashes,
+        // in the order they were added to their chains
+        config(legacy_entries): Vec<(T::Hash, EntryStruct<T::Hash>)>;
+        build(|storage: &mut runtime_primitives::StorageOverlay, _: &mut runtime_primitives::ChildrenStorageOverlay, config: &GenesisConfig<T>| {
+            with_storage(storage, || {
+                // A new chain starts out in the current layout
+                <StorageVersion<T>>::put(<Module<T> as VersionedStorage>::STORAGE_VERSION);
+                for (entry_hash, entry) in &config.legacy_entries {
+                    <Module<T>>::append_legacy_entry(*entry_hash, entry.clone());
+                }
+                <LegacyImported<T>>::put(config.legacy_entries.len() as u64);
+            });
+        });
+    }
+}
+
+impl<Hash> EntryStruct<Hash> {
+    /// Entry as stored on chain, with external ids in their encoded form
+    pub fn new(content: Vec<u8>, external_ids: Vec<u8>, chain_id: Hash) -> Self {
+        EntryStruct {
+            content,
+            external_ids,
+            chain_id,
+        }
+    }
+
+    /// External ids in their encoded form, length-prefixed unless written raw
+    pub fn external_ids(&self) -> &[u8] {
+        &self.external_ids
+    }
+}
+
+impl<Hash: Decode + AsRef<[u8]>> EntryStruct<Hash> {
+    /// Convert a legacy entry, keeping its external ids length-prefixed
+    pub fn from_legacy(entry: &LegacyEntry) -> rstd::result::Result<Self, legacy_entry::Error> {
+        Ok(EntryStruct {
+            content: entry.content.clone(),
+            external_ids: legacy_entry::encode_external_ids(&entry.external_ids)?,
+            chain_id: Hash::decode(&mut &entry.chain_id[..])
+                .ok_or(legacy_entry::Error::InvalidChainId)?,
+        })
+    }
+
+    /// Convert to a legacy entry
+    ///
+    /// Fails for entries written with `put_entry` or `put_chain` whose external ids
+    /// aren't length-prefixed.
+    pub fn to_legacy(&self) -> rstd::result::Result<LegacyEntry, legacy_entry::Error> {
+        let chain_id = self.chain_id.as_ref();
+        if chain_id.len() != 32 {
+            return Err(legacy_entry::Error::InvalidChainId);
+        }
+        let mut legacy_chain_id = [0u8; 32];
+        legacy_chain_id.copy_from_slice(chain_id);
+        Ok(LegacyEntry {
+            chain_id: legacy_chain_id,
+            external_ids: legacy_entry::decode_external_ids(&self.external_ids)?,
+            content: self.content.clone(),
+        })
     }
 }
 
 /// Validate Entry Data
 ///
-/// 1. Combined sizes do no exceed 1kb. We can get more specific or add
-/// additional fees for number of ext_ids, for example, but for now a
-/// check of the total combined lengths should be in the same neighborhood.
-fn validate_entry_data(content: &[u8], ext_ids: &[u8]) -> Result {
-    if content.len() + ext_ids.len() <= 1024 {
+/// 1. Combined sizes do no exceed `max_size`, 1kb unless set otherwise at genesis.
+/// We can get more specific or add additional fees for number of ext_ids, for
+/// example, but for now a check of the total combined lengths should be in the
+/// same neighborhood.
+fn validate_entry_data(content: &[u8], ext_ids: &[u8], max_size: u32) -> Result {
+    if content.len() + ext_ids.len() <= max_size as usize {
         Ok(())
     } else {
         Err("EntryTooLarge")
     }
 }
 
+/// Entry credits spent to write an entry of `size` bytes of content and external ids
+///
+/// Every started KiB costs `entry_cost`, and an empty entry counts as one KiB.
+/// An entry that starts a chain pays `chain_cost` for its first KiB instead.
+pub fn write_cost(size: usize, entry_cost: u64, chain_cost: Option<u64>) -> u64 {
+    let kib = cmp::max(1, (size as u64).saturating_add(1023) / 1024);
+    let first = chain_cost.unwrap_or(entry_cost);
+    entry_cost.saturating_mul(kib - 1).saturating_add(first)
+}
+
 decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        fn deposit_event<T>() = default;
+
+        /// Run storage migrations left pending by a runtime upgrade
+        fn on_initialize(_n: T::BlockNumber) {
+            migration::migrate::<Self>();
+        }
+
         /// Put Entry
         ///
         /// The user has a chain id to use and will be providing a new entry. Will spend
@@ -64,9 +229,9 @@ decl_module! {
         /// also check that the hash already exists in storage.
         fn put_entry (origin, content: Vec<u8>, external_ids: Vec<u8>, chain_id: T::Hash) -> Result {
             let sender = ensure_signed(origin)?;
-            validate_entry_data(&content, &external_ids)?;
+            validate_entry_data(&content, &external_ids, Self::max_entry_size())?;
 
-            ensure!(<EntryData<T>>::exists((chain_id, 1)), "This chain does not exist.");
+            ensure!(Self::chain_exists(chain_id), "This chain does not exist.");
 
             let entries_total = Self::total_entries(chain_id);
             let incr_entries_total = entries_total.checked_add(1).ok_or("Overflow entries total!")?;
@@ -77,9 +242,11 @@ decl_module! {
                 chain_id: chain_id
             };
 
-            <entry_credit::Module<T>>::spend_entry_credits(sender.clone(), 1)?;
+            let cost = Self::cost_of(&new_entry, false);
+            <entry_credit::Module<T>>::spend_entry_credits(sender.clone(), cost)?;
             <TotalEntries<T>>::insert(chain_id, incr_entries_total);
-            <EntryData<T>>::insert((chain_id, incr_entries_total), new_entry);
+            let entry_hash = Self::insert_entry(chain_id, incr_entries_total, new_entry);
+            Self::deposit_event(RawEvent::EntryWritten(chain_id, entry_hash));
 
             Ok(())
         }
@@ -89,12 +256,22 @@ decl_module! {
         /// The user does not have a chain id and wants to add an entry. This function
         /// will provide a new chain id for this entry. Will spend from free balance of
         /// entry credits.
+        ///
+        /// The chain id is the Blake2 hash of the external ids. If they are length
+        /// prefixed, the chain legacy clients derive from them must not exist either.
         fn put_chain (origin, content: Vec<u8>, external_ids: Vec<u8>) -> Result {
             let sender = ensure_signed(origin)?;
-            validate_entry_data(&content, &external_ids)?;
+            validate_entry_data(&content, &external_ids, Self::max_entry_size())?;
             let chain_id = <T as system::Trait>::Hashing::hash(&external_ids);
 
-            ensure!(!<EntryData<T>>::exists((chain_id, 1)), "This chain already exists.");
+            ensure!(!Self::chain_exists(chain_id), "This chain already exists.");
+            if let Ok(legacy_ids) = legacy_entry::decode_external_ids(&external_ids) {
+                let legacy_chain_id = T::Hash::decode(&mut &LegacyEntry::chain_id_of(&legacy_ids)[..]);
+                ensure!(
+                    !legacy_chain_id.map(Self::chain_exists).unwrap_or(false),
+                    "A legacy chain with these external ids already exists"
+                );
+            }
 
             let new_entry = EntryStruct {
                 content: content,
@@ -102,11 +279,481 @@ decl_module! {
                 chain_id: chain_id
             };
 
-            <entry_credit::Module<T>>::spend_entry_credits(sender.clone(), 2)?;
+            let cost = Self::cost_of(&new_entry, true);
+            <entry_credit::Module<T>>::spend_entry_credits(sender.clone(), cost)?;
             <TotalEntries<T>>::insert(chain_id, 1);
-            <EntryData<T>>::insert((chain_id, 1), new_entry);
+            let entry_hash = Self::insert_entry(chain_id, 1, new_entry);
+            Self::deposit_event(RawEvent::ChainStarted(chain_id));
+            Self::deposit_event(RawEvent::EntryWritten(chain_id, entry_hash));
+            Ok(())
+        }
+
+        /// Reveal Entry
+        ///
+        /// The entry is given in the legacy binary format and stored under its legacy
+        /// entry hash. As with legacy Factom, the same entry may be revealed more than
+        /// once and then appears at each position. If its chain doesn't exist yet, the entry starts it, in which case
+        /// the chain id must be the one legacy clients derive from the external ids, and
+        /// no chain may have been started with the same external ids by `put_chain`.
+        ///
+        /// Charged per KiB of content and external ids, see `write_cost`.
+        fn reveal_entry(origin, entry: Vec<u8>) -> Result {
+            let sender = ensure_signed(origin)?;
+            let legacy = LegacyEntry::decode(&entry).map_err(legacy_entry::Error::as_str)?;
+            let new_entry = EntryStruct::<T::Hash>::from_legacy(&legacy)
+                .map_err(legacy_entry::Error::as_str)?;
+            validate_entry_data(&new_entry.content, &new_entry.external_ids, Self::max_entry_size())?;
+
+            let chain_id = new_entry.chain_id;
+            let index = if Self::chain_exists(chain_id) {
+                Self::total_entries(chain_id).checked_add(1).ok_or("Overflow entries total!")?
+            } else {
+                ensure!(
+                    legacy.chain_id == LegacyEntry::chain_id_of(&legacy.external_ids),
+                    "Chain id doesn't match the external ids"
+                );
+                ensure!(
+                    !Self::chain_exists(<T as system::Trait>::Hashing::hash(&new_entry.external_ids)),
+                    "A chain with these external ids already exists"
+                );
+                1
+            };
+            let cost = Self::cost_of(&new_entry, index == 1);
+            let entry_hash = T::Hash::decode(&mut &LegacyEntry::hash(&entry)[..])
+                .ok_or("Invalid legacy entry hash")?;
+
+            <entry_credit::Module<T>>::spend_entry_credits(sender, cost)?;
+            <TotalEntries<T>>::insert(chain_id, index);
+            <Entries<T>>::insert(entry_hash, new_entry);
+            <ChainEntries<T>>::insert((chain_id, index), entry_hash);
+            if index == 1 {
+                Self::deposit_event(RawEvent::ChainStarted(chain_id));
+            }
+            Self::deposit_event(RawEvent::EntryWritten(chain_id, entry_hash));
             Ok(())
         }
 
+        /// Queue Legacy Chains
+        ///
+        /// Privileged call to queue chains whose entries are still stored in the
+        /// version 0 layout. Their entries are moved a batch at a time in
+        /// `on_initialize`. Chains that were never written under version 0 are
+        /// skipped when their turn comes.
+        ///
+        /// The chain ids may be queued over several calls, the last of which sets
+        /// `complete`. The migration only finishes once the queue is complete.
+        fn queue_legacy_chains(origin, chain_ids: Vec<T::Hash>, complete: bool) -> Result {
+            ensure_root(origin)?;
+            ensure!(
+                Self::storage_version() < <Self as VersionedStorage>::STORAGE_VERSION,
+                "Entries are already migrated"
+            );
+            ensure!(!Self::legacy_queue_complete(), "Legacy chains are already queued");
+
+            <LegacyChains<T>>::mutate(|queue| queue.extend(chain_ids));
+            if complete {
+                <LegacyQueueComplete<T>>::put(true);
+            }
+            Ok(())
+        }
+
+        /// Import Legacy Entries
+        ///
+        /// Privileged call appending a batch of entries imported from the legacy
+        /// network to their chains, under their legacy entry hashes as at genesis.
+        /// `first` is the position of the batch in the import, counting the entries
+        /// written at genesis, so that a batch is neither skipped nor applied twice.
+        ///
+        /// The last batch sets `complete`, after which nothing more is imported.
+        fn import_legacy_entries(
+            origin,
+            first: u64,
+            entries: Vec<(T::Hash, EntryStruct<T::Hash>)>,
+            complete: bool
+        ) -> Result {
+            ensure_root(origin)?;
+            ensure!(!Self::legacy_import_complete(), "Legacy entries are already imported");
+            ensure!(first == Self::legacy_imported(), "Legacy entries out of order");
+            let imported = first
+                .checked_add(entries.len() as u64)
+                .ok_or("Overflow entries total!")?;
+
+            for (entry_hash, entry) in entries {
+                Self::append_legacy_entry(entry_hash, entry);
+            }
+            <LegacyImported<T>>::put(imported);
+            if complete {
+                <LegacyImportComplete<T>>::put(true);
+            }
+            Ok(())
+        }
+
+    }
+}
+
+impl<T: Trait> Module<T> {
+    /// Check whether a chain has been started, in either storage layout
+    pub fn chain_exists(chain_id: T::Hash) -> bool {
+        <ChainEntries<T>>::exists((chain_id, 1)) || <EntryData<T>>::exists((chain_id, 1))
+    }
+
+    /// Get the entry at a given position of a chain, counting from 1
+    pub fn entry_at(chain_id: T::Hash, index: u64) -> Option<EntryStruct<T::Hash>> {
+        match Self::chain_entry((chain_id, index)) {
+            Some(entry_hash) => Self::entry(entry_hash),
+            None => <EntryData<T>>::get((chain_id, index)),
+        }
+    }
+
+    /// Entry credits spent to write an entry, see `write_cost`
+    pub fn cost_of(entry: &EntryStruct<T::Hash>, starts_chain: bool) -> u64 {
+        let size = entry.content.len() + entry.external_ids.len();
+        let chain_cost = if starts_chain { Some(Self::chain_cost()) } else { None };
+        write_cost(size, Self::entry_cost(), chain_cost)
+    }
+
+    /// Store an entry under its hash and record its position in the chain
+    fn insert_entry(chain_id: T::Hash, index: u64, entry: EntryStruct<T::Hash>) -> T::Hash {
+        let entry_hash = <T as system::Trait>::Hashing::hash_of(&entry);
+        <Entries<T>>::insert(entry_hash, entry);
+        <ChainEntries<T>>::insert((chain_id, index), entry_hash);
+        entry_hash
+    }
+
+    /// Append an entry of the legacy network to its chain, under its legacy entry hash
+    fn append_legacy_entry(entry_hash: T::Hash, entry: EntryStruct<T::Hash>) {
+        let index = Self::total_entries(entry.chain_id) + 1;
+        <TotalEntries<T>>::insert(entry.chain_id, index);
+        <ChainEntries<T>>::insert((entry.chain_id, index), entry_hash);
+        <Entries<T>>::insert(entry_hash, entry);
+    }
+
+    /// Move up to `budget` entries from the version 0 layout
+    ///
+    /// Works through `LegacyChains` front to back, remembering how far it got
+    /// into the current chain so the next block can pick up from there.
+    fn migrate_legacy_entries(mut budget: u64) {
+        let mut queue = Self::legacy_chains();
+        if queue.is_empty() {
+            return;
+        }
+
+        while budget > 0 && !queue.is_empty() {
+            let chain_id = queue[0];
+            let total = Self::total_entries(chain_id);
+            let mut index = Self::legacy_progress(chain_id);
+
+            while budget > 0 && index < total {
+                index += 1;
+                budget -= 1;
+                if let Some(entry) = <EntryData<T>>::take((chain_id, index)) {
+                    Self::insert_entry(chain_id, index, entry);
+                }
+            }
+
+            if index < total {
+                <LegacyProgress<T>>::insert(chain_id, index);
+            } else {
+                <LegacyProgress<T>>::remove(chain_id);
+                queue.remove(0);
+            }
+        }
+
+        <LegacyChains<T>>::put(queue);
+    }
+}
+
+impl<T: Trait> VersionedStorage for Module<T> {
+    const STORAGE_VERSION: u32 = 1;
+
+    fn stored_version() -> u32 {
+        Self::storage_version()
+    }
+
+    fn put_stored_version(version: u32) {
+        <StorageVersion<T>>::put(version);
+    }
+
+    /// Version 0 to 1: entries keyed by hash
+    ///
+    /// New entries go into the version 1 layout as soon as this code runs and
+    /// reads fall back to `EntryData`. Existing entries are moved a batch per
+    /// block, until every queued chain has been moved and root has marked the
+    /// queue complete.
+    fn migrate_from(version: u32) -> MigrationStatus {
+        if version > 0 {
+            return MigrationStatus::Complete;
+        }
+
+        Self::migrate_legacy_entries(MIGRATION_BATCH);
+        if Self::legacy_queue_complete() && Self::legacy_chains().is_empty() {
+            <LegacyQueueComplete<T>>::kill();
+            MigrationStatus::Complete
+        } else {
+            MigrationStatus::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{new_test_ext, EcBalances, Entries, Origin, Test};
+    use primitives::H256;
+    use runtime_io::with_externalities;
+    use runtime_primitives::traits::{BlakeTwo256, OnInitialize};
+    use support::{assert_noop, assert_ok};
+
+    fn chain(n: u8) -> H256 {
+        H256::from([n; 32])
+    }
+
+    fn entry(chain_id: H256, index: u64) -> EntryStruct<H256> {
+        EntryStruct::new(index.encode(), vec![], chain_id)
+    }
+
+    /// Write entries in the version 0 layout, as the runtime before version 1 did
+    fn write_v0(chain_id: H256, count: u64) {
+        for index in 1..=count {
+            <EntryData<Test>>::insert((chain_id, index), entry(chain_id, index));
+        }
+        <TotalEntries<Test>>::insert(chain_id, count);
+    }
+
+    fn assert_migrated(chain_id: H256, count: u64) {
+        for index in 1..=count {
+            let entry_hash = BlakeTwo256::hash_of(&entry(chain_id, index));
+            assert_eq!(Entries::chain_entry((chain_id, index)), Some(entry_hash));
+            assert_eq!(Entries::entry(entry_hash), Some(entry(chain_id, index)));
+            assert!(!<EntryData<Test>>::exists((chain_id, index)));
+        }
+        assert_eq!(Entries::total_entries(chain_id), count);
+    }
+
+    #[test]
+    fn migrates_version_0_entries() {
+        with_externalities(&mut new_test_ext(), || {
+            write_v0(chain(1), 3);
+            write_v0(chain(2), 2);
+            assert_eq!(Entries::entry_at(chain(1), 2), Some(entry(chain(1), 2)));
+
+            // A chain never written under version 0 is skipped
+            assert_ok!(Entries::queue_legacy_chains(
+                Origin::ROOT,
+                vec![chain(1), chain(3), chain(2)],
+                true
+            ));
+            Entries::on_initialize(1);
+
+            assert_migrated(chain(1), 3);
+            assert_migrated(chain(2), 2);
+            assert_eq!(Entries::entry_at(chain(1), 2), Some(entry(chain(1), 2)));
+            assert!(!Entries::chain_exists(chain(3)));
+            assert!(Entries::legacy_chains().is_empty());
+            assert_eq!(Entries::storage_version(), 1);
+        });
+    }
+
+    #[test]
+    fn migration_spans_blocks() {
+        with_externalities(&mut new_test_ext(), || {
+            let count = MIGRATION_BATCH * 2 + 10;
+            write_v0(chain(1), count);
+            assert_ok!(Entries::queue_legacy_chains(Origin::ROOT, vec![chain(1)], true));
+
+            Entries::on_initialize(1);
+            assert_eq!(Entries::legacy_progress(chain(1)), MIGRATION_BATCH);
+            assert_eq!(Entries::storage_version(), 0);
+            // Moved entries are read from the new layout, the others from the old one
+            assert!(Entries::chain_entry((chain(1), MIGRATION_BATCH)).is_some());
+            assert!(Entries::chain_entry((chain(1), MIGRATION_BATCH + 1)).is_none());
+            assert_eq!(Entries::entry_at(chain(1), count), Some(entry(chain(1), count)));
+
+            Entries::on_initialize(2);
+            assert_eq!(Entries::legacy_progress(chain(1)), MIGRATION_BATCH * 2);
+            assert_eq!(Entries::storage_version(), 0);
+
+            Entries::on_initialize(3);
+            assert_migrated(chain(1), count);
+            assert_eq!(Entries::legacy_progress(chain(1)), 0);
+            assert_eq!(Entries::storage_version(), 1);
+        });
+    }
+
+    #[test]
+    fn migration_waits_for_complete_queue() {
+        with_externalities(&mut new_test_ext(), || {
+            write_v0(chain(1), 2);
+            write_v0(chain(2), 2);
+            assert_ok!(Entries::queue_legacy_chains(Origin::ROOT, vec![chain(1)], false));
+
+            Entries::on_initialize(1);
+            assert_migrated(chain(1), 2);
+            assert_eq!(Entries::storage_version(), 0);
+
+            assert_ok!(Entries::queue_legacy_chains(Origin::ROOT, vec![chain(2)], true));
+            assert_noop!(
+                Entries::queue_legacy_chains(Origin::ROOT, vec![chain(3)], true),
+                "Legacy chains are already queued"
+            );
+
+            Entries::on_initialize(2);
+            assert_migrated(chain(2), 2);
+            assert_eq!(Entries::storage_version(), 1);
+            assert!(!Entries::legacy_queue_complete());
+        });
+    }
+
+    #[test]
+    fn repeated_migration_changes_nothing() {
+        with_externalities(&mut new_test_ext(), || {
+            write_v0(chain(1), 3);
+            assert_ok!(Entries::queue_legacy_chains(Origin::ROOT, vec![chain(1)], true));
+            Entries::on_initialize(1);
+            assert_eq!(Entries::storage_version(), 1);
+
+            // An entry written after the migration stays where it is
+            <TotalEntries<Test>>::insert(chain(1), 4);
+            let entry_hash = Entries::insert_entry(chain(1), 4, entry(chain(1), 4));
+
+            Entries::on_initialize(2);
+            assert_migrated(chain(1), 4);
+            assert_eq!(Entries::chain_entry((chain(1), 4)), Some(entry_hash));
+            assert_eq!(Entries::storage_version(), 1);
+            assert_noop!(
+                Entries::queue_legacy_chains(Origin::ROOT, vec![chain(1)], true),
+                "Entries are already migrated"
+            );
+        });
+    }
+
+    fn legacy_chain(external_ids: Vec<Vec<u8>>) -> Vec<u8> {
+        LegacyEntry {
+            chain_id: LegacyEntry::chain_id_of(&external_ids),
+            external_ids,
+            content: b"first".to_vec(),
+        }
+        .encode()
+        .unwrap()
+    }
+
+    #[test]
+    fn put_chain_refuses_legacy_chain() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(<entry_credit::Module<Test>>::increase_ec_balance(1, 100));
+            let external_ids = vec![b"my".to_vec(), b"chain".to_vec()];
+            assert_ok!(Entries::reveal_entry(Origin::signed(1), legacy_chain(external_ids.clone())));
+
+            let encoded = legacy_entry::encode_external_ids(&external_ids).unwrap();
+            assert_noop!(
+                Entries::put_chain(Origin::signed(1), b"again".to_vec(), encoded),
+                "A legacy chain with these external ids already exists"
+            );
+            // External ids that aren't length-prefixed have no legacy chain id
+            assert_ok!(Entries::put_chain(Origin::signed(1), b"raw".to_vec(), b"mychain".to_vec()));
+        });
+    }
+
+    #[test]
+    fn reveal_entry_refuses_blake2_chain() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_ok!(<entry_credit::Module<Test>>::increase_ec_balance(1, 100));
+            let external_ids = vec![b"my".to_vec(), b"chain".to_vec()];
+            let encoded = legacy_entry::encode_external_ids(&external_ids).unwrap();
+            assert_ok!(Entries::put_chain(Origin::signed(1), b"first".to_vec(), encoded));
+
+            assert_noop!(
+                Entries::reveal_entry(Origin::signed(1), legacy_chain(external_ids)),
+                "A chain with these external ids already exists"
+            );
+        });
+    }
+
+    #[test]
+    fn costs_per_kib() {
+        assert_eq!(write_cost(0, 1, None), 1);
+        assert_eq!(write_cost(1024, 1, None), 1);
+        assert_eq!(write_cost(1025, 1, None), 2);
+        assert_eq!(write_cost(10240, 3, None), 30);
+        assert_eq!(write_cost(1, 1, Some(2)), 2);
+        assert_eq!(write_cost(2049, 1, Some(10)), 12);
+        assert_eq!(write_cost(usize::max_value(), u64::max_value(), Some(1)), u64::max_value());
+    }
+
+    #[test]
+    fn reveal_entry_charges_per_kib() {
+        with_externalities(&mut new_test_ext(), || {
+            <MaxEntrySize<Test>>::put(4096);
+            assert_ok!(<entry_credit::Module<Test>>::increase_ec_balance(1, 100));
+            let external_ids = vec![b"my".to_vec(), b"chain".to_vec()];
+            let first = LegacyEntry {
+                chain_id: LegacyEntry::chain_id_of(&external_ids),
+                external_ids,
+                content: vec![0; 2500],
+            };
+            // 2511 bytes: the chain cost and two more KiB
+            assert_ok!(Entries::reveal_entry(Origin::signed(1), first.encode().unwrap()));
+            assert_eq!(EcBalances::free_balance(&1), 96);
+
+            let next = LegacyEntry {
+                chain_id: first.chain_id,
+                external_ids: vec![],
+                content: vec![0; 1024],
+            };
+            assert_ok!(Entries::reveal_entry(Origin::signed(1), next.encode().unwrap()));
+            assert_eq!(EcBalances::free_balance(&1), 95);
+        });
+    }
+
+    #[test]
+    fn queue_requires_root() {
+        with_externalities(&mut new_test_ext(), || {
+            assert_noop!(
+                Entries::queue_legacy_chains(Origin::signed(1), vec![chain(1)], true),
+                "bad origin: expected to be a root origin"
+            );
+            assert_noop!(
+                Entries::import_legacy_entries(Origin::signed(1), 0, vec![], true),
+                "bad origin: expected to be a root origin"
+            );
+        });
+    }
+
+    #[test]
+    fn imports_legacy_batches() {
+        with_externalities(&mut new_test_ext(), || {
+            let legacy = |n: u8, index: u64| (H256::from([n; 32]), entry(chain(1), index));
+            assert_ok!(Entries::import_legacy_entries(
+                Origin::ROOT,
+                0,
+                vec![legacy(10, 1), legacy(11, 2)],
+                false
+            ));
+            assert_eq!(Entries::legacy_imported(), 2);
+
+            // A batch applied twice or out of order changes nothing
+            assert_noop!(
+                Entries::import_legacy_entries(Origin::ROOT, 0, vec![legacy(10, 1)], false),
+                "Legacy entries out of order"
+            );
+            assert_noop!(
+                Entries::import_legacy_entries(Origin::ROOT, 3, vec![legacy(13, 4)], false),
+                "Legacy entries out of order"
+            );
+
+            assert_ok!(Entries::import_legacy_entries(Origin::ROOT, 2, vec![legacy(12, 3)], true));
+            assert_noop!(
+                Entries::import_legacy_entries(Origin::ROOT, 3, vec![legacy(13, 4)], false),
+                "Legacy entries are already imported"
+            );
+
+            // Entries keep their legacy hashes and their order
+            assert_eq!(Entries::total_entries(chain(1)), 3);
+            for index in 1..=3 {
+                let entry_hash = H256::from([9 + index as u8; 32]);
+                assert_eq!(Entries::chain_entry((chain(1), index)), Some(entry_hash));
+                assert_eq!(Entries::entry(entry_hash), Some(entry(chain(1), index)));
+            }
+        });
     }
 }
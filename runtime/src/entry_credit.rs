@@ -4,15 +4,55 @@
 //! are purchased with Factoids and are burned when used to pay for an entry.
 //!
 //! The conversion for FCT -> EC is determined by an oracle. This is to ensure that
-//! ECs are consistently priced.
+//! ECs are consistently priced. Until the oracle is in place, the rate is set at
+//! genesis.
 //!
+//! Every change of an entry credit balance is deposited as an event along with
+//! the new balance, so that the node can follow balances without reading them.
+//!
+use crate::migration::{self, MigrationStatus, VersionedStorage};
 use runtime_primitives::traits::As;
-use support::{decl_module, dispatch::Result, ensure, traits::Currency};
+use support::{
+    decl_event, decl_module, decl_storage, dispatch::Result, ensure, traits::Currency, StorageValue,
+};
 
-pub trait Trait: balances::Trait<balances::Instance0> {}
+pub trait Trait: balances::Trait<balances::Instance0> {
+    /// The overarching event type.
+    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+}
+
+decl_event!(
+    pub enum Event<T>
+    where
+        AccountId = <T as system::Trait>::AccountId,
+        Balance = <T as balances::Trait<balances::Instance0>>::Balance
+    {
+        /// Entry credits were added to an account: the account, the credits added
+        /// and its new balance
+        Credited(AccountId, Balance, Balance),
+        /// An account spent entry credits: the account, the credits spent and its
+        /// new balance
+        Spent(AccountId, Balance, Balance),
+    }
+);
+
+decl_storage! {
+    trait Store for Module<T: Trait> as EntryCredit {
+        /// Version of the storage layout, see `migration`
+        StorageVersion get(storage_version): u32;
+        /// Factoid units spent to buy one entry credit
+        EcRate get(ec_rate) config(): u64 = 1;
+    }
+}
 
 decl_module! {
 pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+    fn deposit_event<T>() = default;
+
+    /// Run storage migrations left pending by a runtime upgrade
+    fn on_initialize(_n: T::BlockNumber) {
+        migration::migrate::<Self>();
+    }
 }}
 
 impl<T: Trait> Module<T> {
@@ -26,6 +66,8 @@ impl<T: Trait> Module<T> {
             <balances::Module<T, balances::Instance0> as Currency<_>>::deposit_creating(
                 &who, increase,
             );
+        let balance = <balances::Module<T, balances::Instance0> as Currency<_>>::free_balance(&who);
+        Self::deposit_event(RawEvent::Credited(who, increase, balance));
         Ok(())
     }
 
@@ -42,6 +84,25 @@ impl<T: Trait> Module<T> {
 
         let _imbalance =
             <balances::Module<T, balances::Instance0> as Currency<_>>::slash(&who, bal);
+        let balance = <balances::Module<T, balances::Instance0> as Currency<_>>::free_balance(&who);
+        Self::deposit_event(RawEvent::Spent(who, bal, balance));
         Ok(())
     }
 }
+
+impl<T: Trait> VersionedStorage for Module<T> {
+    const STORAGE_VERSION: u32 = 0;
+
+    fn stored_version() -> u32 {
+        Self::storage_version()
+    }
+
+    fn put_stored_version(version: u32) {
+        <StorageVersion<T>>::put(version);
+    }
+
+    /// Nothing to migrate yet, version 0 is the first layout
+    fn migrate_from(_version: u32) -> MigrationStatus {
+        MigrationStatus::Complete
+    }
+}
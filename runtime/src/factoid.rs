@@ -3,18 +3,54 @@
 //! Factom's tradeable currency. This is used to reward validators and
 //! converted to Entry Credits to, in turn, buy entries on Factom.
 //!
+//! Transfers and entry credit purchases are deposited as events, so that the
+//! node can export factoid transactions.
+//!
+use crate::migration::{self, MigrationStatus, VersionedStorage};
 use crate::EntryCredits;
 use primitives::sr25519::Public;
 use runtime_primitives::traits::As;
-use support::{decl_module, dispatch::Result, traits::Currency};
+use support::{
+    decl_event, decl_module, decl_storage, dispatch::Result, ensure, traits::Currency, StorageValue,
+};
 use system::ensure_signed;
 
 /// Module config
-/// TODO Include events, tom
-pub trait Trait: balances::Trait {}
+pub trait Trait: balances::Trait {
+    /// The overarching event type.
+    type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+}
+
+decl_event!(
+    pub enum Event<T>
+    where
+        AccountId = <T as system::Trait>::AccountId,
+        Balance = <T as balances::Trait>::Balance
+    {
+        /// Factoids were sent: the sender, the receiver and the amount
+        Transferred(AccountId, AccountId, Balance),
+        /// Factoids were converted to entry credits: the buyer, the EC address
+        /// credited, the factoids spent and the entry credits bought
+        EntryCreditsBought(AccountId, Public, Balance, u64),
+    }
+);
+
+decl_storage! {
+    trait Store for Module<T: Trait> as Factoid {
+        /// Version of the storage layout, see `migration`
+        StorageVersion get(storage_version): u32;
+    }
+}
 
 decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+    fn deposit_event<T>() = default;
+
+    /// Run storage migrations left pending by a runtime upgrade
+    fn on_initialize(_n: T::BlockNumber) {
+        migration::migrate::<Self>();
+    }
+
     /// Transfer factoids
     ///
     /// Send factoids from one address to another address. Right now there is not
@@ -24,6 +60,7 @@ decl_module! {
 
         // Simple transfer
         <balances::Module<T> as Currency<_>>::transfer(&sender, &to, value)?;
+        Self::deposit_event(RawEvent::Transferred(sender, to, value));
 
         Ok(())
     }
@@ -33,23 +70,29 @@ decl_module! {
     /// In order to write entries to Factom, one must have entry credits. To obtain
     /// entry credits, you must convert some of your Factoid balance to Entry Credits.
     ///
-    /// The price oracle will be added later. For now, 1 FCT = 1 EC.
+    /// The price oracle will be added later. For now, the rate is the `ec_rate`
+    /// set at genesis, in factoid units per entry credit. Only the part of `value`
+    /// that buys whole entry credits is spent.
     ///
     /// Factoids are slashed from the account upon purchase. Imbalance is ignored.
-    /// Presently, the result of the increase is also ignored until we implement
-    /// Events for these modules.
+    /// Presently, the result of the increase is also ignored.
     fn buy_entry_credits(origin, to_ec_addr: Public, value: T::Balance) -> Result {
         let sender = ensure_signed(origin)?;
 
+        // Conversion necessary from Balance to rust primitive
+        let ec_rate = <EntryCredits>::ec_rate();
+        ensure!(ec_rate > 0, "Entry credit rate is not set");
+        let ec_value = value.as_() / ec_rate;
+        let cost = T::Balance::sa(ec_value * ec_rate);
+
         // Check if there is enough free balance for requested exchange
-        let can_slash = <balances::Module<T> as Currency<_>>::can_slash(&sender, value);
+        let can_slash = <balances::Module<T> as Currency<_>>::can_slash(&sender, cost);
 
         if can_slash {
             // remove requested amounmt
-            let _imbalance = <balances::Module<T> as Currency<_>>::slash(&sender, value);
-            // Conversion necessary from Balance to rust primitive
-            let value_as = value.as_();
-            let _increased_ec = <EntryCredits>::increase_ec_balance(to_ec_addr, value_as.into());
+            let _imbalance = <balances::Module<T> as Currency<_>>::slash(&sender, cost);
+            let _increased_ec = <EntryCredits>::increase_ec_balance(to_ec_addr.clone(), ec_value.into());
+            Self::deposit_event(RawEvent::EntryCreditsBought(sender, to_ec_addr, cost, ec_value));
         }
 
         Ok(())
@@ -57,3 +100,20 @@ decl_module! {
 
 }
 }
+
+impl<T: Trait> VersionedStorage for Module<T> {
+    const STORAGE_VERSION: u32 = 0;
+
+    fn stored_version() -> u32 {
+        Self::storage_version()
+    }
+
+    fn put_stored_version(version: u32) {
+        <StorageVersion<T>>::put(version);
+    }
+
+    /// Nothing to migrate yet, version 0 is the first layout
+    fn migrate_from(_version: u32) -> MigrationStatus {
+        MigrationStatus::Complete
+    }
+}
@@ -0,0 +1,301 @@
+//! ## Legacy Entry Format
+//!
+//! Entries as marshalled by legacy Factom clients:
+//!
+//! | Bytes | Field                                             |
+//! |-------|---------------------------------------------------|
+//! | 1     | version, always 0                                 |
+//! | 32    | chain id                                          |
+//! | 2     | size of the external ids                          |
+//! | ...   | external ids, each prefixed with a two byte length |
+//! | ...   | content                                           |
+//!
+//! Sizes are big endian. `EntryStruct` keeps the external ids in this same
+//! length-prefixed form, see `EntryStruct::from_legacy`.
+//!
+//! Decoding never panics; malformed input of any kind is reported as an `Error`.
+//!
+//! The codec is used by `entry::Module::reveal_entry`, whose signed extrinsic is
+//! the message of the legacy `commit-entry` and `commit-chain` methods of the
+//! node, or is submitted through `author_submitExtrinsic`. The legacy
+//! `reveal-entry` and `reveal-chain` methods take the entry in this format and
+//! check it against its commit. The service also uses the codec to read legacy
+//! dumps and to index and acknowledge revealed entries.
+//!
+use rstd::prelude::*;
+use sha2::{Digest, Sha256, Sha512};
+
+/// Size of an entry header: version, chain id and external ids size
+pub const HEADER_SIZE: usize = 35;
+
+/// Largest combined size of external ids and content a legacy entry may have
+pub const MAX_PAYLOAD_SIZE: usize = 10240;
+
+/// Reasons legacy entry bytes fail to decode or encode
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum Error {
+    /// Shorter than the entry header
+    TooShort,
+    /// Version byte other than 0
+    UnsupportedVersion,
+    /// The external ids size runs past the end of the entry
+    ExtIdsOverrun,
+    /// Fewer than two bytes left for an external id length
+    TruncatedExtIdLength,
+    /// An external id runs past the end of the external ids
+    ExtIdOverrun,
+    /// External ids and content exceed `MAX_PAYLOAD_SIZE`
+    TooLarge,
+    /// The chain id doesn't fit the runtime's hash type
+    InvalidChainId,
+}
+
+impl Error {
+    /// Description usable as a dispatch error
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Error::TooShort => "Legacy entry shorter than its header",
+            Error::UnsupportedVersion => "Unsupported legacy entry version",
+            Error::ExtIdsOverrun => "Legacy entry external ids overrun the entry",
+            Error::TruncatedExtIdLength => "Truncated legacy external id length",
+            Error::ExtIdOverrun => "Legacy external id overruns the external ids",
+            Error::TooLarge => "Legacy entry too large",
+            Error::InvalidChainId => "Invalid legacy chain id",
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// An entry in the legacy format
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct LegacyEntry {
+    pub chain_id: [u8; 32],
+    pub external_ids: Vec<Vec<u8>>,
+    pub content: Vec<u8>,
+}
+
+impl LegacyEntry {
+    /// Decode a marshalled entry
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < HEADER_SIZE {
+            return Err(Error::TooShort);
+        }
+        if data[0] != 0 {
+            return Err(Error::UnsupportedVersion);
+        }
+        if data.len() - HEADER_SIZE > MAX_PAYLOAD_SIZE {
+            return Err(Error::TooLarge);
+        }
+        let ext_ids_end = HEADER_SIZE + read_u16(&data[33..35]) as usize;
+        if data.len() < ext_ids_end {
+            return Err(Error::ExtIdsOverrun);
+        }
+
+        let mut chain_id = [0u8; 32];
+        chain_id.copy_from_slice(&data[1..33]);
+        Ok(LegacyEntry {
+            chain_id,
+            external_ids: decode_external_ids(&data[HEADER_SIZE..ext_ids_end])?,
+            content: data[ext_ids_end..].to_vec(),
+        })
+    }
+
+    /// Marshal the entry
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let external_ids = encode_external_ids(&self.external_ids)?;
+        if external_ids.len() + self.content.len() > MAX_PAYLOAD_SIZE {
+            return Err(Error::TooLarge);
+        }
+
+        let mut data = Vec::with_capacity(HEADER_SIZE + external_ids.len() + self.content.len());
+        data.push(0);
+        data.extend_from_slice(&self.chain_id);
+        data.extend_from_slice(&(external_ids.len() as u16).to_be_bytes());
+        data.extend_from_slice(&external_ids);
+        data.extend_from_slice(&self.content);
+        Ok(data)
+    }
+
+    /// Entry hash: SHA-256 of the SHA-512 hash of the marshalled entry followed by the entry
+    pub fn hash(data: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.input(Sha512::digest(data));
+        hasher.input(data);
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&hasher.result());
+        hash
+    }
+
+    /// Chain id of a chain started with the given external ids
+    ///
+    /// SHA-256 of the SHA-256 hashes of each external id, one after the other.
+    pub fn chain_id_of(external_ids: &[Vec<u8>]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for ext_id in external_ids {
+            hasher.input(Sha256::digest(ext_id));
+        }
+        let mut chain_id = [0u8; 32];
+        chain_id.copy_from_slice(&hasher.result());
+        chain_id
+    }
+}
+
+/// Decode length-prefixed external ids
+pub fn decode_external_ids(mut data: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    let mut external_ids = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 2 {
+            return Err(Error::TruncatedExtIdLength);
+        }
+        let end = 2 + read_u16(&data[..2]) as usize;
+        if data.len() < end {
+            return Err(Error::ExtIdOverrun);
+        }
+        external_ids.push(data[2..end].to_vec());
+        data = &data[end..];
+    }
+    Ok(external_ids)
+}
+
+/// Encode external ids, each prefixed with its length
+pub fn encode_external_ids(external_ids: &[Vec<u8>]) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    for ext_id in external_ids {
+        if ext_id.len() > MAX_PAYLOAD_SIZE {
+            return Err(Error::TooLarge);
+        }
+        data.extend_from_slice(&(ext_id.len() as u16).to_be_bytes());
+        data.extend_from_slice(ext_id);
+    }
+    if data.len() > MAX_PAYLOAD_SIZE {
+        return Err(Error::TooLarge);
+    }
+    Ok(data)
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    u16::from(data[0]) << 8 | u16::from(data[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An entry of mainnet chain 0caff62e..., as returned by the legacy `raw-data` method
+    const ENTRY: &str = "000caff62ea5b5aa015c706add7b2463a5be07e1f0537617f553558090f23c7f5600420040e57283e4618f13b18c2be8d14926999331ef4ab905639a82d748634201cd85ae1c22b6186a72eee3f4ae12b8f6fa9c73a8a98b5eae238ed6133424bcef062f0e7b224150494d6574686f64223a2268747470733a2f2f706f6c6f6e6965782e636f6d2f7075626c69633f636f6d6d616e643d72657475726e4f72646572426f6f6b5c753030323663757272656e6379506169723d4254435f4e58545c753030323664657074683d34222c2252657475726e44617461223a227b5c2261736b735c223a5b5b5c22302e30303030313334315c222c343437342e37323033353739345d2c5b5c22302e30303030313334325c222c363038302e39363930373133355d2c5b5c22302e30303030313334355c222c31343831342e38353833353730375d2c5b5c22302e30303030313337385c222c38303030305d5d2c5c22626964735c223a5b5b5c22302e30303030313332375c222c363032382e303333313537355d2c5b5c22302e30303030313332365c222c3236302e34333839313430335d2c5b5c22302e30303030313332355c222c3130393931352e30363731363938315d2c5b5c22302e30303030313332335c222c31323030305d5d2c5c22697346726f7a656e5c223a5c22305c227d222c2254696d657374616d70223a313435303134373830317d";
+    const ENTRY_HASH: &str = "0ae2ab2cf543eed52a13a5a405bded712444cc8f8b6724a00602e1c8550a4ec2";
+
+    fn from_hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn entry(external_ids: Vec<Vec<u8>>, content: &[u8]) -> LegacyEntry {
+        LegacyEntry {
+            chain_id: [7u8; 32],
+            external_ids,
+            content: content.to_vec(),
+        }
+    }
+
+    #[test]
+    fn mainnet_entry() {
+        let data = from_hex(ENTRY);
+        assert_eq!(LegacyEntry::hash(&data)[..], from_hex(ENTRY_HASH)[..]);
+
+        let entry = LegacyEntry::decode(&data).unwrap();
+        assert_eq!(
+            entry.chain_id[..],
+            from_hex("0caff62ea5b5aa015c706add7b2463a5be07e1f0537617f553558090f23c7f56")[..]
+        );
+        assert_eq!(entry.external_ids.len(), 1);
+        assert_eq!(entry.external_ids[0].len(), 0x40);
+        assert_eq!(entry.content.len(), data.len() - HEADER_SIZE - 0x42);
+        assert_eq!(entry.encode().unwrap(), data);
+    }
+
+    #[test]
+    fn mainnet_chain_id() {
+        assert_eq!(
+            LegacyEntry::chain_id_of(&[b"FactomAnchorChain".to_vec()])[..],
+            from_hex("df3ade9eec4b08d5379cc64270c30ea7315d8a8a1a69efe2b98a60ecdd69e604")[..]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let entries = vec![
+            entry(vec![], b""),
+            entry(vec![], b"content"),
+            entry(vec![vec![], b"id".to_vec()], b""),
+            entry(vec![vec![0xff; 300], b"id".to_vec()], &[0u8; 1000]),
+            entry(vec![], &[1u8; MAX_PAYLOAD_SIZE]),
+        ];
+        for entry in entries {
+            let data = entry.encode().unwrap();
+            assert_eq!(LegacyEntry::decode(&data).unwrap(), entry);
+        }
+    }
+
+    #[test]
+    fn malformed_entries() {
+        let valid = entry(vec![b"id".to_vec()], b"content").encode().unwrap();
+        let with = |change: &dyn Fn(&mut Vec<u8>)| {
+            let mut data = valid.clone();
+            change(&mut data);
+            LegacyEntry::decode(&data)
+        };
+
+        assert_eq!(LegacyEntry::decode(&[]), Err(Error::TooShort));
+        assert_eq!(LegacyEntry::decode(&valid[..HEADER_SIZE - 1]), Err(Error::TooShort));
+        assert_eq!(with(&|data| data[0] = 1), Err(Error::UnsupportedVersion));
+        // External ids size past the end of the entry
+        assert_eq!(with(&|data| data[34] = 0xff), Err(Error::ExtIdsOverrun));
+        assert_eq!(LegacyEntry::decode(&valid[..HEADER_SIZE + 3]), Err(Error::ExtIdsOverrun));
+        // One byte left for the second external id length
+        assert_eq!(with(&|data| data[34] = 5), Err(Error::TruncatedExtIdLength));
+        // External id length past the external ids size
+        assert_eq!(with(&|data| data[36] = 0xff), Err(Error::ExtIdOverrun));
+        assert_eq!(with(&|data| data[35] = 0xff), Err(Error::ExtIdOverrun));
+        assert_eq!(with(&|data| data.resize(HEADER_SIZE + MAX_PAYLOAD_SIZE + 1, 0)), Err(Error::TooLarge));
+    }
+
+    #[test]
+    fn truncated_entries() {
+        let data = from_hex(ENTRY);
+        let ext_ids_end = HEADER_SIZE + 0x42;
+        for end in 0..data.len() {
+            let decoded = LegacyEntry::decode(&data[..end]);
+            if end < ext_ids_end {
+                assert!(decoded.is_err());
+            } else {
+                assert_eq!(decoded.unwrap().content, data[ext_ids_end..end].to_vec());
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_entries() {
+        assert_eq!(entry(vec![], &[0u8; MAX_PAYLOAD_SIZE + 1]).encode(), Err(Error::TooLarge));
+        assert_eq!(entry(vec![vec![0u8; 5000]; 3], b"").encode(), Err(Error::TooLarge));
+        // An external id whose length doesn't fit the two byte prefix
+        assert_eq!(encode_external_ids(&[vec![0u8; 0x10000]]), Err(Error::TooLarge));
+        assert_eq!(
+            entry(vec![vec![0u8; MAX_PAYLOAD_SIZE - 2]], b"!").encode(),
+            Err(Error::TooLarge)
+        );
+    }
+}
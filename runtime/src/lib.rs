@@ -11,7 +11,7 @@
 
 use client::{
     block_builder::api::{self as block_builder_api, CheckInherentsResult, InherentData},
-    impl_runtime_apis, runtime_api,
+    decl_runtime_apis, impl_runtime_apis, runtime_api,
 };
 use parity_codec::{Decode, Encode};
 #[cfg(feature = "std")]
@@ -33,10 +33,19 @@ use version::RuntimeVersion;
 // A few exports that help ease life for downstream crates.
 pub use balances::Call as BalancesCall;
 pub use consensus::Call as ConsensusCall;
+pub use entry::Call as EntryCall;
+pub use entry::EntryStruct;
+pub use entry::RawEvent as EntryEvent;
+pub use entry::write_cost;
+pub use entry_credit::RawEvent as EntryCreditEvent;
+pub use factoid::Call as FactoidCall;
+pub use factoid::RawEvent as FactoidEvent;
+pub use sudo::Call as SudoCall;
 #[cfg(any(feature = "std", test))]
 pub use runtime_primitives::BuildStorage;
 pub use runtime_primitives::{Perbill, Permill};
 pub use support::{construct_runtime, StorageValue};
+pub use system::{Event as SystemEvent, Phase};
 pub use timestamp::BlockPeriod;
 pub use timestamp::Call as TimestampCall;
 
@@ -71,6 +80,18 @@ mod entry_credit;
 /// Include Entry
 mod entry;
 
+/// Include Storage Migrations
+mod migration;
+
+/// Include Legacy Claims
+mod claims;
+
+/// Include Legacy Entry Format
+pub mod legacy_entry;
+
+#[cfg(test)]
+mod mock;
+
 /// Opaque types. These are used by the CLI to instantiate machinery that don't need to know
 /// the specifics of the runtime. They can then be made to be agnostic over specific formats
 /// of data like extrinsics, allowing for them to continue syncing the network through upgrades
@@ -112,7 +133,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
     spec_name: create_runtime_str!("factom"),
     impl_name: create_runtime_str!("factom"),
     authoring_version: 3,
-    spec_version: 3,
+    spec_version: 11,
     impl_version: 0,
     apis: RUNTIME_API_VERSIONS,
 };
@@ -220,13 +241,25 @@ impl sudo::Trait for Runtime {
 }
 
 /// Used for the module factoid
-impl factoid::Trait for Runtime {}
+impl factoid::Trait for Runtime {
+    /// The uniquitous event type.
+    type Event = Event;
+}
 
 /// Used for the module entry_credit
-impl entry_credit::Trait for Runtime {}
+impl entry_credit::Trait for Runtime {
+    /// The uniquitous event type.
+    type Event = Event;
+}
 
 /// Used for the module entry
-impl entry::Trait for Runtime {}
+impl entry::Trait for Runtime {
+    /// The uniquitous event type.
+    type Event = Event;
+}
+
+/// Used for the module claims
+impl claims::Trait for Runtime {}
 
 construct_runtime!(
 	pub enum Runtime with Log(InternalLog: DigestItem<Hash, AuthorityId, AuthoritySignature>) where
@@ -240,11 +273,12 @@ construct_runtime!(
 		Aura: aura::{Module},
 		Indices: indices,
 		Balances: balances,
-		BalancesCopy: balances::<Instance0>::{Module, Call, Storage, Event<T, I>},
+		BalancesCopy: balances::<Instance0>::{Module, Call, Storage, Config<T, I>, Event<T, I>},
 		Sudo: sudo,
-		EntryCredits: entry_credit::{Module, Call},
-		Factoids: factoid::{Module, Call},
-        Entries: entry::{Module, Call, Storage},
+		EntryCredits: entry_credit::{Module, Call, Storage, Config, Event<T>},
+		Factoids: factoid::{Module, Call, Storage, Event<T>},
+        Entries: entry::{Module, Call, Storage, Config<T>, Event<T>},
+        Claims: claims::{Module, Call, Storage, Config<T>, ValidateUnsigned},
 	}
 );
 
@@ -263,8 +297,18 @@ pub type UncheckedExtrinsic =
     generic::UncheckedMortalCompactExtrinsic<Address, Nonce, Call, AccountSignature>;
 /// Extrinsic type that has already been checked.
 pub type CheckedExtrinsic = generic::CheckedExtrinsic<AccountId, Nonce, Call>;
+/// Events of a block, as `System` stores them.
+pub type EventRecord = system::EventRecord<Event>;
 /// Executive: handles dispatch to the various modules.
-pub type Executive = executive::Executive<Runtime, Block, Context, Balances, AllModules>;
+pub type Executive = executive::Executive<Runtime, Block, Context, Balances, Runtime, AllModules>;
+
+decl_runtime_apis! {
+    /// Per block figures read by the node for its metrics
+    pub trait BlockStatsApi {
+        /// Timestamp of the block, in seconds since the unix epoch
+        fn timestamp() -> u64;
+    }
+}
 
 // Implement our runtime API endpoints. This is just a bunch of proxying.
 impl_runtime_apis! {
@@ -337,4 +381,10 @@ impl_runtime_apis! {
             Consensus::authorities()
         }
     }
+
+    impl self::BlockStatsApi<Block> for Runtime {
+        fn timestamp() -> u64 {
+            Timestamp::now()
+        }
+    }
 }
@@ -0,0 +1,60 @@
+//! ## Storage Migrations
+//!
+//! Runtime modules record the version of their storage layout on chain. After
+//! a `set_code` upgrade, the first block initialized by the new runtime finds
+//! that the stored version is behind the version the code expects. The module
+//! then runs its migration steps from `on_initialize`, one version at a time,
+//! until the two match again.
+//!
+//! A step may need more than one block to finish, e.g. when it moves a large
+//! amount of data. It reports `Pending` and is resumed on the next block; the
+//! stored version is only bumped once the step reports `Complete`.
+//!
+
+/// Outcome of a single migration step
+#[derive(PartialEq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum MigrationStatus {
+    /// The step is finished, storage is now at the next version
+    Complete,
+    /// The step has more work to do in a later block
+    Pending,
+}
+
+/// Versioned Storage
+///
+/// Implemented by every runtime module of this crate. The stored version lives
+/// in the module's own `decl_storage` so each module is migrated independently
+/// of the others. An unset version reads as 0, so a module still at version 0
+/// records nothing at genesis; once it moves past 0, its genesis records the
+/// current version as `entry` does.
+pub trait VersionedStorage {
+    /// The storage layout version this code reads and writes.
+    const STORAGE_VERSION: u32;
+
+    /// The storage layout version currently recorded on chain.
+    fn stored_version() -> u32;
+
+    /// Record a new storage layout version on chain.
+    fn put_stored_version(version: u32);
+
+    /// Migrate storage from `version` to `version + 1`.
+    fn migrate_from(version: u32) -> MigrationStatus;
+}
+
+/// Run pending migrations
+///
+/// Called from a module's `on_initialize`. Does nothing once the stored
+/// version has caught up, so the cost on ordinary blocks is one storage read.
+pub fn migrate<M: VersionedStorage>() {
+    let mut version = M::stored_version();
+    while version < M::STORAGE_VERSION {
+        match M::migrate_from(version) {
+            MigrationStatus::Complete => {
+                version += 1;
+                M::put_stored_version(version);
+            }
+            MigrationStatus::Pending => break,
+        }
+    }
+}
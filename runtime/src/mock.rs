@@ -0,0 +1,75 @@
+//! Test runtime with the Factom modules, for their unit tests
+use crate::{claims, entry, entry_credit};
+use primitives::{Blake2Hasher, H256};
+use runtime_primitives::testing::{Digest, DigestItem, Header};
+use runtime_primitives::traits::{BlakeTwo256, IdentityLookup};
+use runtime_primitives::{BuildStorage, StorageOverlay};
+use support::impl_outer_origin;
+
+impl_outer_origin! {
+    pub enum Origin for Test {}
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct Test;
+
+impl system::Trait for Test {
+    type Origin = Origin;
+    type Index = u64;
+    type BlockNumber = u64;
+    type Hash = H256;
+    type Hashing = BlakeTwo256;
+    type Digest = Digest;
+    type AccountId = u64;
+    type Lookup = IdentityLookup<u64>;
+    type Header = Header;
+    type Event = ();
+    type Log = DigestItem;
+}
+
+impl balances::Trait for Test {
+    type Balance = u64;
+    type OnFreeBalanceZero = ();
+    type OnNewAccount = ();
+    type Event = ();
+    type TransactionPayment = ();
+    type DustRemoval = ();
+    type TransferPayment = ();
+}
+
+impl balances::Trait<balances::Instance0> for Test {
+    type Balance = u64;
+    type OnFreeBalanceZero = ();
+    type OnNewAccount = ();
+    type Event = ();
+    type TransactionPayment = ();
+    type DustRemoval = ();
+    type TransferPayment = ();
+}
+
+impl entry_credit::Trait for Test {
+    type Event = ();
+}
+
+impl entry::Trait for Test {
+    type Event = ();
+}
+
+impl claims::Trait for Test {}
+
+pub type Balances = balances::Module<Test>;
+pub type EcBalances = balances::Module<Test, balances::Instance0>;
+pub type Entries = entry::Module<Test>;
+pub type Claims = claims::Module<Test>;
+
+/// Storage of a new chain, without any module genesis but `system`'s
+pub fn genesis() -> StorageOverlay {
+    system::GenesisConfig::<Test>::default()
+        .build_storage()
+        .unwrap()
+        .0
+}
+
+pub fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+    genesis().into()
+}
@@ -0,0 +1,362 @@
+//! # Submission Acks
+//!
+//! Tracks the entries and factoid transactions submitted to a full node for the
+//! legacy `entry-ack` and `factoid-ack` methods of the RPC server:
+//!
+//! * calls entering the transaction pool are `NotConfirmed`,
+//! * calls included in a new best block are `TransactionACK`,
+//! * calls of a confirmed block are `DBlockConfirmed`, see `confirmed_height`.
+//!
+//! Calls failing in a block, or leaving the pool without being included, are
+//! forgotten.
+//!
+//! The tracked calls waiting in the pool are also decoded for the
+//! `pending-entries` and `pending-transactions` methods, along with the address
+//! of the account that signed them.
+//!
+//! Commits and transactions of the legacy write methods enter the pool through
+//! `PoolSubmitter`, and are tracked as soon as the pool takes them.
+//!
+use crate::events::{account_address, block_records, confirmed_height};
+use crate::wrapper::Factory;
+use factomd_address::AddressKind;
+use factomd_rpc::acks::TxHash;
+use factomd_rpc::{
+    AckStatus, AckTracker, PendingEntry, PendingPool, PendingTransaction, SharedAcks, SharedPending, Submitter,
+};
+use factomd_runtime::legacy_entry::LegacyEntry;
+use factomd_runtime::{
+    opaque, AccountId, BlockStatsApi, Call, EntryCall, EntryStruct, Event, EventRecord, FactoidCall, Hash, Phase,
+    SystemEvent, UncheckedExtrinsic,
+};
+use futures::{Future, Stream};
+use parity_codec::{Decode, Encode};
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::{BlakeTwo256, Hash as HashT, Header as HeaderT, ProvideRuntimeApi};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use substrate_service::{FullComponents, Service, TaskExecutor};
+
+/// A call the acks track
+#[derive(Clone, Debug, PartialEq)]
+pub enum Submission {
+    /// Writes an entry, starting its chain with `put_chain`
+    Entry { chain_id: Hash, entry_hash: Hash },
+    /// Transfers factoids or buys entry credits
+    Factoid,
+}
+
+impl Submission {
+    /// What a call submits, none for calls that aren't tracked
+    ///
+    /// The entry hash is the one the runtime records for the call: the Blake2
+    /// hash of the entry for `put_entry` and `put_chain`, the legacy SHA-256
+    /// entry hash for `reveal_entry`, whose chains are addressed the legacy way.
+    pub fn of(call: &Call) -> Option<Submission> {
+        match call {
+            Call::Entries(EntryCall::put_entry(content, external_ids, chain_id)) => {
+                Some(Submission::entry(content, external_ids, *chain_id))
+            }
+            Call::Entries(EntryCall::put_chain(content, external_ids)) => Some(Submission::entry(
+                content,
+                external_ids,
+                BlakeTwo256::hash(external_ids),
+            )),
+            Call::Entries(EntryCall::reveal_entry(data)) => {
+                LegacyEntry::decode(data).ok().map(|entry| Submission::Entry {
+                    chain_id: Hash::from(entry.chain_id),
+                    entry_hash: Hash::from(LegacyEntry::hash(data)),
+                })
+            }
+            Call::Factoids(FactoidCall::transfer_factoids(..)) | Call::Factoids(FactoidCall::buy_entry_credits(..)) => {
+                Some(Submission::Factoid)
+            }
+            _ => None,
+        }
+    }
+
+    /// Hash of the entry written, none for factoid transactions
+    pub fn entry_hash(&self) -> Option<TxHash> {
+        match self {
+            Submission::Entry { entry_hash, .. } => Some(tx_hash(entry_hash)),
+            Submission::Factoid => None,
+        }
+    }
+
+    /// Entry of `put_entry` or `put_chain`, hashed as the entry module stores it
+    fn entry(content: &[u8], external_ids: &[u8], chain_id: Hash) -> Submission {
+        let entry = EntryStruct::new(content.to_vec(), external_ids.to_vec(), chain_id);
+        Submission::Entry {
+            chain_id,
+            entry_hash: BlakeTwo256::hash_of(&entry),
+        }
+    }
+}
+
+/// A tracked call waiting in the transaction pool
+struct PoolCall {
+    txid: Hash,
+    extrinsic: UncheckedExtrinsic,
+    submission: Submission,
+}
+
+/// Decode an extrinsic of a block or of the transaction pool
+pub fn decode(extrinsic: &opaque::UncheckedExtrinsic) -> Option<UncheckedExtrinsic> {
+    // The opaque extrinsic encodes to the bytes of the extrinsic it wraps
+    UncheckedExtrinsic::decode(&mut &extrinsic.encode()[..])
+}
+
+/// Submission of an extrinsic to the transaction pool, giving its hash
+type SubmitFn = Box<dyn Fn(opaque::UncheckedExtrinsic) -> Result<Hash, String> + Send + Sync>;
+
+/// Submits the legacy writes to the transaction pool of a full node
+///
+/// The RPC server starts before the node, writes are refused until the node
+/// attaches its pool.
+pub struct PoolSubmitter {
+    acks: SharedAcks,
+    submit: RwLock<Option<SubmitFn>>,
+}
+
+impl PoolSubmitter {
+    pub fn new(acks: SharedAcks) -> Arc<Self> {
+        Arc::new(PoolSubmitter {
+            acks,
+            submit: RwLock::new(None),
+        })
+    }
+
+    /// Submit to the transaction pool of a started node, at its best block
+    pub fn attach(&self, service: &Service<FullComponents<Factory>>) {
+        let client = service.client();
+        let txpool = service.transaction_pool();
+        let submit = move |extrinsic: opaque::UncheckedExtrinsic| {
+            let best = client.info().map_err(|e| format!("{:?}", e))?.chain.best_hash;
+            txpool
+                .submit_one(&BlockId::hash(best), extrinsic)
+                .map_err(|e| format!("{:?}", e))
+        };
+        *self.submit.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(submit));
+    }
+
+    /// Submit extrinsics in order, along with the entry hash each writes, giving their txids
+    fn submit(&self, extrinsics: Vec<(opaque::UncheckedExtrinsic, Option<TxHash>)>) -> Result<Vec<TxHash>, String> {
+        let submit = self.submit.read().unwrap_or_else(|e| e.into_inner());
+        let submit = submit.as_ref().ok_or("Node is starting")?;
+        let mut txids = Vec::new();
+        for (extrinsic, entry_hash) in extrinsics {
+            let txid = tx_hash(&submit(extrinsic)?);
+            self.acks.pending(txid, entry_hash, now());
+            txids.push(txid);
+        }
+        Ok(txids)
+    }
+}
+
+impl Submitter for PoolSubmitter {
+    fn commit(&self, extrinsic: &[u8]) -> Result<(TxHash, TxHash), String> {
+        let extrinsic = opaque::UncheckedExtrinsic::decode(&mut &extrinsic[..]).ok_or("Invalid extrinsic")?;
+        let entry_hash = match submitted(&extrinsic) {
+            Some(Submission::Entry { entry_hash, .. }) => tx_hash(&entry_hash),
+            _ => return Err("Extrinsic doesn't write an entry".to_string()),
+        };
+        let txids = self.submit(vec![(extrinsic, Some(entry_hash))])?;
+        Ok((txids[0], entry_hash))
+    }
+
+    fn submit_transaction(&self, extrinsics: &[u8]) -> Result<Vec<TxHash>, String> {
+        let extrinsics =
+            Vec::<opaque::UncheckedExtrinsic>::decode(&mut &extrinsics[..]).ok_or("Invalid transaction")?;
+        let extrinsics = extrinsics
+            .into_iter()
+            .map(|extrinsic| match submitted(&extrinsic) {
+                Some(Submission::Factoid) => Ok((extrinsic, None)),
+                _ => Err("Extrinsic isn't a factoid transaction".to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.submit(extrinsics)
+    }
+
+    fn entry_ids(&self, entry: &[u8]) -> Result<(TxHash, TxHash), String> {
+        let chain_id = LegacyEntry::decode(entry).map_err(|e| format!("Invalid entry: {}", e))?.chain_id;
+        Ok((chain_id, LegacyEntry::hash(entry)))
+    }
+}
+
+/// Track the submissions of a full node from the transaction pool to a confirmed
+/// block, and keep the pending ones
+pub fn start(
+    service: &Service<FullComponents<Factory>>,
+    acks: SharedAcks,
+    pending: SharedPending,
+    executor: &TaskExecutor,
+) {
+    let client = service.client();
+    let txpool = service.transaction_pool();
+
+    let pooled = {
+        let acks = acks.clone();
+        let pending = pending.clone();
+        let pool = txpool.clone();
+        txpool.import_notification_stream().for_each(move |_| {
+            let calls: Vec<PoolCall> = pool
+                .ready()
+                .filter_map(|transaction| pool_call(transaction.hash, &transaction.data))
+                .collect();
+            let date = now();
+            for call in &calls {
+                acks.pending(tx_hash(&call.txid), call.submission.entry_hash(), date);
+            }
+            update_pending(&pending, &acks, &calls);
+            Ok(())
+        })
+    };
+
+    let mut confirmed = confirmed_height(&client);
+    let imported = client
+        .import_notification_stream()
+        .filter(|notification| notification.is_new_best)
+        .for_each(move |notification| {
+            let at = BlockId::<opaque::Block>::hash(notification.hash);
+            let date = client.runtime_api().timestamp(&at).unwrap_or_default() * 1000;
+            let failed = failed_extrinsics(&block_records(&client, notification.hash));
+            let body = client.body(&at).ok().and_then(|body| body).unwrap_or_default();
+            for (index, extrinsic) in body.iter().enumerate() {
+                if let Some(submission) = submitted(extrinsic) {
+                    let txid = tx_hash(&BlakeTwo256::hash_of(extrinsic));
+                    if failed.contains(&(index as u32)) {
+                        acks.forget(&txid);
+                    } else {
+                        acks.included(txid, submission.entry_hash(), date);
+                    }
+                }
+            }
+            // Blocks may be confirmed several at a time
+            let newly_confirmed = confirmed + 1..=confirmed_height(&client);
+            confirmed = confirmed.max(*newly_confirmed.end());
+            for height in newly_confirmed {
+                let body = client
+                    .body(&BlockId::<opaque::Block>::number(height))
+                    .ok()
+                    .and_then(|body| body);
+                for extrinsic in body.unwrap_or_default() {
+                    acks.confirmed(&tx_hash(&BlakeTwo256::hash_of(&extrinsic)));
+                }
+            }
+            // Calls the pool dropped as invalid won't be included anymore
+            let calls: Vec<PoolCall> = txpool
+                .ready()
+                .filter_map(|transaction| pool_call(transaction.hash, &transaction.data))
+                .collect();
+            let txids: HashSet<TxHash> = calls.iter().map(|call| tx_hash(&call.txid)).collect();
+            acks.retain_pending(&txids);
+            update_pending(&pending, &acks, &calls);
+            Ok(())
+        });
+
+    let exit = service.on_exit();
+    executor.spawn(exit.clone().until(pooled).map(|_| ()));
+    executor.spawn(exit.until(imported).map(|_| ()));
+}
+
+/// What an extrinsic submits, if it is tracked
+fn submitted(extrinsic: &opaque::UncheckedExtrinsic) -> Option<Submission> {
+    decode(extrinsic).and_then(|extrinsic| Submission::of(&extrinsic.function))
+}
+
+/// A call of the transaction pool, if it is tracked
+fn pool_call(txid: Hash, extrinsic: &opaque::UncheckedExtrinsic) -> Option<PoolCall> {
+    let extrinsic = decode(extrinsic)?;
+    let submission = Submission::of(&extrinsic.function)?;
+    Some(PoolCall {
+        txid,
+        extrinsic,
+        submission,
+    })
+}
+
+/// Show the calls waiting in the pool to the pending methods
+fn update_pending(pending: &PendingPool, acks: &AckTracker, calls: &[PoolCall]) {
+    let mut entries = Vec::new();
+    let mut transactions = Vec::new();
+    for call in calls {
+        let txid = tx_hash(&call.txid);
+        // A new best block may include calls before the pool lets go of them
+        let status = acks
+            .transaction(&txid)
+            .map(|ack| ack.status)
+            .unwrap_or(AckStatus::NotConfirmed);
+        let account = signer(&call.extrinsic);
+        let sender = |kind| {
+            account
+                .as_ref()
+                .map(|account| account_address(kind, &account.0))
+                .unwrap_or_default()
+        };
+        if let Submission::Entry { chain_id, entry_hash } = &call.submission {
+            entries.push(PendingEntry {
+                entryhash: hex::encode(entry_hash),
+                chainid: hex::encode(chain_id),
+                sender: sender(AddressKind::EntryCreditPublic),
+                txid: hex::encode(txid),
+                status,
+            });
+            continue;
+        }
+        let (to, amount) = match &call.extrinsic.function {
+            Call::Factoids(FactoidCall::transfer_factoids(to, amount)) => {
+                (account_address(AddressKind::FactoidPublic, &to.0), amount)
+            }
+            Call::Factoids(FactoidCall::buy_entry_credits(to, amount)) => {
+                (account_address(AddressKind::EntryCreditPublic, &to.0), amount)
+            }
+            _ => continue,
+        };
+        transactions.push(PendingTransaction {
+            txid: hex::encode(txid),
+            from: sender(AddressKind::FactoidPublic),
+            to,
+            amount: u64::try_from(*amount).unwrap_or(!0),
+            status,
+        });
+    }
+    pending.update(entries, transactions);
+}
+
+/// Account that signed an extrinsic, none if unsigned or signed by account index
+fn signer(extrinsic: &UncheckedExtrinsic) -> Option<AccountId> {
+    let (address, ..) = extrinsic.signature.as_ref()?;
+    // An address given by account id encodes as 0xff followed by the account id
+    let encoded = address.encode();
+    if encoded.first() != Some(&0xff) {
+        return None;
+    }
+    AccountId::decode(&mut &encoded[1..])
+}
+
+/// Indexes of the extrinsics that failed in a block
+fn failed_extrinsics(records: &[EventRecord]) -> HashSet<u32> {
+    records
+        .iter()
+        .filter_map(|record| match (&record.phase, &record.event) {
+            (Phase::ApplyExtrinsic(index), Event::system(SystemEvent::ExtrinsicFailed)) => Some(*index),
+            _ => None,
+        })
+        .collect()
+}
+
+fn tx_hash(hash: &Hash) -> TxHash {
+    let mut txid = TxHash::default();
+    txid.copy_from_slice(hash.as_ref());
+    txid
+}
+
+/// Milliseconds since the epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() * 1000 + u64::from(since.subsec_millis()))
+        .unwrap_or_default()
+}
@@ -4,13 +4,24 @@
 //! Set telemetry URL. Set public accounts for staging/local QA and developers to use for testing. Set authority
 //! nodes for testing purposes and initial launch. Set network behaviors like block time.
 //!
+//! Networks with real keys are built from the genesis section of the configuration
+//! by selecting the `genesis` network. `import-legacy` builds the same spec with the
+//! entries of the legacy network added.
 //!
 use ed25519::Public as AuthorityId;
+use factomd_address::Address;
+use factomd_configuration::{Genesis, GenesisBalance};
 use factomd_runtime::{
-    AccountId, BalancesConfig, ConsensusConfig, GenesisConfig, IndicesConfig, SudoConfig,
+    AccountId, BalancesConfig, BalancesCopyConfig, ClaimsConfig, ConsensusConfig, EntriesConfig,
+    EntryCreditsConfig, EntryStruct, GenesisConfig, Hash, IndicesConfig, SudoConfig,
     TimestampConfig,
 };
-use primitives::{ed25519, sr25519, Pair};
+use crate::legacy::LegacyDump;
+use crate::snapshot::{add_balance, BalanceKind, LegacyClaim, Snapshot};
+use primitives::{blake2_256, crypto::Ss58Codec, ed25519, sr25519, Pair};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
 use substrate_service;
 
 pub type ChainSpec = substrate_service::ChainSpec<GenesisConfig>;
@@ -37,7 +48,7 @@ fn authority_key(s: &str) -> AuthorityId {
         .public()
 }
 
-/// Get AuthorityId from str
+/// Get AccountId from str
 ///
 /// This will generate a sr25519 pair from a given string
 /// in Substrate it is common to use the first names of
@@ -48,6 +59,173 @@ fn account_key(s: &str) -> AccountId {
         .public()
 }
 
+/// Everything `make_genesis` needs to build the genesis block
+struct GenesisSetup {
+    authorities: Vec<AuthorityId>,
+    fct_balances: BTreeMap<AccountId, u128>,
+    ec_balances: BTreeMap<AccountId, u128>,
+    claims: Vec<LegacyClaim>,
+    root_key: AccountId,
+    ec_rate: u64,
+    max_entry_size: u32,
+    entry_cost: u64,
+    chain_cost: u64,
+    existential_deposit: u128,
+    legacy_entries: Vec<(Hash, EntryStruct<Hash>)>,
+}
+
+impl GenesisSetup {
+    /// Setup for test networks
+    ///
+    /// Every endowed account gets the same FCT balance.
+    fn testnet(
+        authorities: Vec<AuthorityId>,
+        endowed_accounts: Vec<AccountId>,
+        root_key: AccountId,
+    ) -> Self {
+        GenesisSetup {
+            authorities,
+            fct_balances: endowed_accounts.into_iter().map(|k| (k, 1 << 60)).collect(),
+            ec_balances: BTreeMap::new(),
+            claims: vec![],
+            root_key,
+            ec_rate: 1,
+            max_entry_size: 1024,
+            entry_cost: 1,
+            chain_cost: 2,
+            existential_deposit: 500,
+            legacy_entries: vec![],
+        }
+    }
+
+    /// Setup from the genesis section of the configuration
+    ///
+    /// Keys are parsed up front so that a typo is reported before the node starts.
+    /// Balances from a legacy snapshot are added to the configured balances.
+    fn from_config(genesis: &Genesis) -> Result<Self, String> {
+        genesis.validate().map_err(|e| e.to_string())?;
+        let mut setup = GenesisSetup {
+            authorities: genesis
+                .authorities
+                .iter()
+                .map(|a| parse_authority(a))
+                .collect::<Result<_, _>>()?,
+            fct_balances: parse_balances(&genesis.fct_balances)?,
+            ec_balances: parse_balances(&genesis.ec_balances)?,
+            claims: vec![],
+            root_key: parse_account(&genesis.sudo_key)?,
+            ec_rate: genesis.ec_rate,
+            max_entry_size: genesis.max_entry_size,
+            entry_cost: genesis.entry_cost,
+            chain_cost: genesis.chain_cost,
+            existential_deposit: u128::from(genesis.existential_deposit),
+            legacy_entries: vec![],
+        };
+
+        if !genesis.snapshot.is_empty() {
+            setup.merge_snapshot(Snapshot::load(&genesis.snapshot)?)?;
+        }
+        Ok(setup)
+    }
+
+    /// Add the balances and claims of a legacy snapshot
+    fn merge_snapshot(&mut self, snapshot: Snapshot) -> Result<(), String> {
+        for (account, amount) in snapshot.fct_balances {
+            add_balance(&mut self.fct_balances, account, amount);
+        }
+        for (account, amount) in snapshot.ec_balances {
+            add_balance(&mut self.ec_balances, account, amount);
+        }
+        self.claims.extend(snapshot.claims);
+        Ok(())
+    }
+
+    /// Add the entries of a legacy dump under their legacy entry hashes
+    ///
+    /// Legacy entries may be larger than `max_entry_size`, which only limits new ones.
+    fn merge_legacy(&mut self, dump: &LegacyDump) -> Result<(), String> {
+        let legacy_entries = &mut self.legacy_entries;
+        dump.replay(|entry_hash, entry| {
+            let entry = EntryStruct::from_legacy(&entry).map_err(|e| e.to_string())?;
+            legacy_entries.push((Hash::from(entry_hash), entry));
+            Ok(())
+        })?;
+        Ok(())
+    }
+}
+
+/// Parse an authority key
+///
+/// Accepts an SS58 encoded ed25519 public key or a secret URI like `//Alice`.
+fn parse_authority(s: &str) -> Result<AuthorityId, String> {
+    AuthorityId::from_ss58check(s)
+        .or_else(|_| ed25519::Pair::from_string(s, None).map(|pair| pair.public()))
+        .map_err(|_| format!("Invalid authority key: {}", s))
+}
+
+/// Parse an account key
+///
+/// Accepts an SS58 encoded sr25519 public key, the FA or EC address of an account,
+/// or a secret URI like `//Alice`. Legacy balances belong in the snapshot, to be
+/// claimed with the legacy key, not under the account of a legacy address.
+pub(crate) fn parse_account(s: &str) -> Result<AccountId, String> {
+    if let Ok(address) = s.parse::<Address>() {
+        return address
+            .to_account()
+            .map(AccountId::from_raw)
+            .map_err(|e| format!("{} {}", e, s));
+    }
+    AccountId::from_ss58check(s)
+        .or_else(|_| sr25519::Pair::from_string(s, None).map(|pair| pair.public()))
+        .map_err(|_| format!("Invalid account key: {}", s))
+}
+
+fn parse_balances(balances: &[GenesisBalance]) -> Result<BTreeMap<AccountId, u128>, String> {
+    let mut parsed = BTreeMap::new();
+    for b in balances {
+        add_balance(&mut parsed, parse_account(&b.account)?, u128::from(b.amount));
+    }
+    Ok(parsed)
+}
+
+/// Build a chain spec from the genesis section of the configuration
+///
+/// `ChainSpec::from_genesis` only takes a plain `fn`, which can't carry the
+/// configured genesis, so the spec is written out as JSON and loaded from that
+/// file. The spec reads its genesis back from the file whenever it builds the
+/// storage, so the file is left in the temp directory. It is named after the
+/// chain id and a hash of its contents, so every node of the same genesis
+/// shares one file and a changed genesis never reads a stale one.
+pub(crate) fn from_genesis_config(
+    genesis: &Genesis,
+    legacy: Option<&LegacyDump>,
+) -> Result<ChainSpec, String> {
+    let mut setup = GenesisSetup::from_config(genesis)?;
+    if let Some(dump) = legacy {
+        setup.merge_legacy(dump)?;
+    }
+
+    let spec = json!({
+        "name": genesis.name,
+        "id": genesis.id,
+        "bootNodes": genesis.bootnodes,
+        "telemetryEndpoints": null,
+        "protocolId": null,
+        "consensusEngine": null,
+        "properties": null,
+        "genesis": { "runtime": make_genesis(setup) },
+    });
+    let json = serde_json::to_vec(&spec).map_err(|e| format!("Invalid genesis: {}", e))?;
+    let path = std::env::temp_dir().join(format!(
+        "factomd-{}-{}.json",
+        genesis.id,
+        hex::encode(&blake2_256(&json)[..8])
+    ));
+    fs::write(&path, &json)
+        .map_err(|e| format!("Failed to write chain spec {}: {}", path.display(), e))?;
+    ChainSpec::from_json_file(path)
+}
+
 /// Implement an alternative
 ///
 /// This is selected from the factomd-configuration
@@ -61,11 +239,11 @@ impl Alternative {
                 "Development",
                 "dev",
                 || {
-                    make_genesis(
+                    make_genesis(GenesisSetup::testnet(
                         vec![authority_key("Alice")],
                         vec![account_key("Alice")],
                         account_key("Alice"),
-                    )
+                    ))
                 },
                 vec![],
                 None,
@@ -77,7 +255,7 @@ impl Alternative {
                 "Local Testnet",
                 "local_testnet",
                 || {
-                    make_genesis(
+                    make_genesis(GenesisSetup::testnet(
                         vec![authority_key("Alice"), authority_key("Bob")],
                         vec![
                             account_key("Alice"),
@@ -88,7 +266,7 @@ impl Alternative {
                             account_key("Ferdie"),
                         ],
                         account_key("Alice"),
-                    )
+                    ))
                 },
                 vec![],
                 None,
@@ -100,7 +278,7 @@ impl Alternative {
                 "Staging Testnet",
                 "staging_testnet",
                 || {
-                    make_genesis(
+                    make_genesis(GenesisSetup::testnet(
                         vec![authority_key("Alice"), authority_key("Bob")],
                         vec![
                             account_key("Alice"),
@@ -111,7 +289,7 @@ impl Alternative {
                             account_key("Ferdie"),
                         ],
                         account_key("Alice"),
-                    )
+                    ))
                 },
                 vec![],
                 None,
@@ -136,34 +314,72 @@ impl Alternative {
 ///
 /// This is also possible via json, however the wasm blob will get thrown in. This
 /// should help keep things compact by simply hardcoding the genesis.
-fn make_genesis(
-    initial_authorities: Vec<AuthorityId>,
-    endowed_accounts: Vec<AccountId>,
-    root_key: AccountId,
-) -> GenesisConfig {
+fn make_genesis(setup: GenesisSetup) -> GenesisConfig {
+    let mut indexed_accounts: Vec<AccountId> = setup
+        .fct_balances
+        .iter()
+        .chain(setup.ec_balances.iter())
+        .map(|(k, _)| k.clone())
+        .collect();
+    indexed_accounts.sort();
+    indexed_accounts.dedup();
+
+    // One record per legacy key
+    let mut claims = BTreeMap::new();
+    let mut ec_claims = BTreeMap::new();
+    for claim in setup.claims {
+        let target = match claim.kind {
+            BalanceKind::Factoid => &mut claims,
+            BalanceKind::EntryCredit => &mut ec_claims,
+        };
+        *target.entry(claim.key).or_insert(0) += claim.amount;
+    }
+
     GenesisConfig {
 		consensus: Some(ConsensusConfig {
 			code: include_bytes!("../../runtime/wasm/target/wasm32-unknown-unknown/release/factom_runtime_wasm.compact.wasm").to_vec(),
-			authorities: initial_authorities.clone(),
+			authorities: setup.authorities,
 		}),
 		system: None,
 		timestamp: Some(TimestampConfig {
 			minimum_period: 5,
 		}),
 		indices: Some(IndicesConfig {
-			ids: endowed_accounts.clone(),
+			ids: indexed_accounts,
 		}),
 		balances: Some(BalancesConfig {
 			transaction_base_fee: 0,
 			transaction_byte_fee: 0,
-			existential_deposit: 500,
+			existential_deposit: setup.existential_deposit,
+			transfer_fee: 0,
+			creation_fee: 0,
+			balances: setup.fct_balances.into_iter().collect(),
+			vesting: vec![],
+		}),
+		balances_Instance0: Some(BalancesCopyConfig {
+			transaction_base_fee: 0,
+			transaction_byte_fee: 0,
+			existential_deposit: 0,
 			transfer_fee: 0,
 			creation_fee: 0,
-			balances: endowed_accounts.iter().cloned().map(|k|(k, 1 << 60)).collect(),
+			balances: setup.ec_balances.into_iter().collect(),
 			vesting: vec![],
 		}),
 		sudo: Some(SudoConfig {
-			key: root_key,
+			key: setup.root_key,
+		}),
+		entry_credit: Some(EntryCreditsConfig {
+			ec_rate: setup.ec_rate,
+		}),
+		entry: Some(EntriesConfig {
+			max_entry_size: setup.max_entry_size,
+			entry_cost: setup.entry_cost,
+			chain_cost: setup.chain_cost,
+			legacy_entries: setup.legacy_entries,
+		}),
+		claims: Some(ClaimsConfig {
+			claims: claims.into_iter().collect(),
+			ec_claims: ec_claims.into_iter().collect(),
 		}),
 	}
 }
@@ -0,0 +1,217 @@
+//! # Block Events
+//!
+//! Feeds the WebSocket subscriptions of the RPC server from block imports. For
+//! each new best block of a full node the events the runtime deposited are read
+//! back from its state and turned into notifications:
+//!
+//! * the block itself,
+//! * each entry written, with whether it started its chain,
+//! * the new balance of each EC address whose entry credits changed,
+//! * an entry ack of `TransactionACK` for each entry written.
+//!
+//! Entries get an ack of `DBlockConfirmed` once their block is confirmed, see
+//! `confirmed_height`.
+//!
+use crate::wrapper::Factory;
+use factomd_address::{Address, AddressKind};
+use factomd_rpc::{AckStatus, EcBalance, EntryAck, NewBlock, NewEntry, Notification, SharedSubscriptions};
+use factomd_runtime::{opaque::Block, BlockStatsApi, EntryCreditEvent, EntryEvent, Event, EventRecord, Hash};
+use futures::{Future, Stream};
+use parity_codec::Decode;
+use primitives::storage::StorageKey;
+use primitives::twox_128;
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::{Header as HeaderT, ProvideRuntimeApi};
+use std::cmp;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use substrate_service::{FullClient, TaskExecutor};
+
+/// Storage key of the events of the current block in the system module
+const EVENTS_KEY: &[u8] = b"System Events";
+
+/// Blocks this far under the best block are taken as confirmed when none are finalized
+pub const CONFIRMATION_DEPTH: u64 = 10;
+
+/// What a block wrote, as counted from its events
+#[derive(Default, Debug, PartialEq)]
+pub struct BlockActivity {
+    /// Entries written, including the first entry of each new chain
+    pub entries: u32,
+    /// Chains started
+    pub chains: u32,
+    /// Entry credits spent on entries and chains
+    pub ec_spent: u64,
+}
+
+impl BlockActivity {
+    /// Count what a block wrote from its events
+    pub fn of(events: &[Event]) -> Self {
+        let mut activity = BlockActivity::default();
+        for event in events {
+            match event {
+                Event::entry(EntryEvent::EntryWritten(..)) => activity.entries += 1,
+                Event::entry(EntryEvent::ChainStarted(..)) => activity.chains += 1,
+                Event::entry_credit(EntryCreditEvent::Spent(_, spent, _)) => {
+                    let spent = u64::try_from(*spent).unwrap_or(!0);
+                    activity.ec_spent = activity.ec_spent.saturating_add(spent);
+                }
+                _ => {}
+            }
+        }
+        activity
+    }
+}
+
+/// Publish the blocks, entries, EC balances and entry acks of a full node
+pub fn start(
+    client: Arc<FullClient<Factory>>,
+    subscriptions: SharedSubscriptions,
+    exit: exit_future::Exit,
+    executor: &TaskExecutor,
+) {
+    let mut confirmed = confirmed_height(&client);
+    let imported = client
+        .import_notification_stream()
+        .filter(|notification| notification.is_new_best)
+        .for_each(move |notification| {
+            // Blocks may be confirmed several at a time
+            let newly_confirmed = confirmed + 1..=confirmed_height(&client);
+            confirmed = confirmed.max(*newly_confirmed.end());
+            if subscriptions.is_empty() {
+                return Ok(());
+            }
+            let height = *notification.header.number();
+            let timestamp = client
+                .runtime_api()
+                .timestamp(&BlockId::<Block>::hash(notification.hash))
+                .unwrap_or_default();
+            subscriptions.publish(&Notification::NewBlock(NewBlock {
+                height,
+                hash: hex::encode(notification.hash),
+                timestamp,
+            }));
+            for event in notifications(&block_events(&client, notification.hash), height) {
+                subscriptions.publish(&event);
+            }
+            for height in newly_confirmed {
+                if let Ok(Some(hash)) = client.block_hash(height) {
+                    for entry_hash in written_entries(&block_events(&client, hash)) {
+                        subscriptions.publish(&Notification::EntryAck(EntryAck {
+                            entryhash: hex::encode(entry_hash),
+                            status: AckStatus::DBlockConfirmed,
+                            height,
+                        }));
+                    }
+                }
+            }
+            Ok(())
+        });
+
+    executor.spawn(exit.until(imported).map(|_| ()));
+}
+
+/// Events the runtime deposited in a block, none if its state is gone
+pub fn block_events(client: &FullClient<Factory>, hash: Hash) -> Vec<Event> {
+    block_records(client, hash).into_iter().map(|record| record.event).collect()
+}
+
+/// Height up to which blocks are confirmed
+///
+/// Blocks are confirmed once finalized, or once `CONFIRMATION_DEPTH` blocks were
+/// built on top of them, as networks without a finality gadget never finalize any.
+pub fn confirmed_height(client: &FullClient<Factory>) -> u64 {
+    client
+        .info()
+        .map(|info| confirmed_at(info.chain.best_number, info.chain.finalized_number))
+        .unwrap_or(0)
+}
+
+/// Height up to which blocks are confirmed, given the best and finalized heights
+pub fn confirmed_at(best: u64, finalized: u64) -> u64 {
+    cmp::max(finalized, best.saturating_sub(CONFIRMATION_DEPTH))
+}
+
+/// Events of a block along with the phase each was deposited in
+pub fn block_records(client: &FullClient<Factory>, hash: Hash) -> Vec<EventRecord> {
+    let key = StorageKey(twox_128(EVENTS_KEY).to_vec());
+    client
+        .storage(&BlockId::<Block>::hash(hash), &key)
+        .ok()
+        .and_then(|data| data)
+        .and_then(|data| Vec::<EventRecord>::decode(&mut &data.0[..]))
+        .unwrap_or_default()
+}
+
+/// FA or EC address of an account, empty if it has no address of that kind
+pub fn account_address(kind: AddressKind, account: &[u8; 32]) -> String {
+    Address::from_account(kind, *account)
+        .map(|address| address.to_string())
+        .unwrap_or_default()
+}
+
+/// Notifications of the entries and EC balances of a block at `height`
+fn notifications(events: &[Event], height: u64) -> Vec<Notification> {
+    let mut notifications = Vec::new();
+    // A chain is started by the entry written right after it
+    let mut started = None;
+    for event in events {
+        match event {
+            Event::entry(EntryEvent::ChainStarted(chain_id)) => started = Some(*chain_id),
+            Event::entry(EntryEvent::EntryWritten(chain_id, entry_hash)) => {
+                let entryhash = hex::encode(entry_hash);
+                notifications.push(Notification::NewEntry(NewEntry {
+                    chainid: hex::encode(chain_id),
+                    entryhash: entryhash.clone(),
+                    height,
+                    newchain: started.take() == Some(*chain_id),
+                }));
+                notifications.push(Notification::EntryAck(EntryAck {
+                    entryhash,
+                    status: AckStatus::TransactionAck,
+                    height,
+                }));
+            }
+            Event::entry_credit(EntryCreditEvent::Credited(account, _, balance))
+            | Event::entry_credit(EntryCreditEvent::Spent(account, _, balance)) => {
+                if let Ok(address) = Address::from_account(AddressKind::EntryCreditPublic, account.0) {
+                    notifications.push(Notification::EcBalance(EcBalance {
+                        address: address.to_string(),
+                        balance: u64::try_from(*balance).unwrap_or(!0),
+                        height,
+                    }));
+                }
+            }
+            _ => {}
+        }
+    }
+    notifications
+}
+
+/// Hashes of the entries a block wrote
+fn written_entries(events: &[Event]) -> Vec<Hash> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::entry(EntryEvent::EntryWritten(_, entry_hash)) => Some(*entry_hash),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confirmed_at() {
+        // Without finality, blocks are confirmed by depth alone
+        assert_eq!(confirmed_at(5, 0), 0);
+        assert_eq!(confirmed_at(CONFIRMATION_DEPTH, 0), 0);
+        assert_eq!(confirmed_at(CONFIRMATION_DEPTH + 1, 0), 1);
+        assert_eq!(confirmed_at(100, 0), 100 - CONFIRMATION_DEPTH);
+        // Finalized blocks are confirmed whatever their depth
+        assert_eq!(confirmed_at(100, 95), 95);
+        assert_eq!(confirmed_at(100, 50), 100 - CONFIRMATION_DEPTH);
+    }
+}
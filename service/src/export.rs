@@ -0,0 +1,415 @@
+//! # Event Export
+//!
+//! Pushes a live feed of a full node to external consumers, like the LiveFeed
+//! of legacy factomd, so that they can ingest without polling. Every event is a
+//! JSON object on a line of its own, with `event` naming it and a `seq` number
+//! counting up from 0 since the node started:
+//!
+//! | Event                 | Sent for                                                 |
+//! |-----------------------|----------------------------------------------------------|
+//! | `BlockCommit`         | each new best block, with its entry and chain counts     |
+//! | `ChainCommit`         | each chain the block started, with its first entry       |
+//! | `EntryCommit`         | each entry the block wrote, first entries of chains too  |
+//! | `FactoidTransfer`     | each factoid transfer of the block                       |
+//! | `EntryCreditPurchase` | each conversion of factoids to entry credits             |
+//! | `NodeState`           | changes of sync state, peers and block production        |
+//!
+//! The events of a block follow its `BlockCommit`.
+//!
+//! Events go to any of three sinks: a TCP consumer, a file they are appended
+//! to, and a webhook they are POSTed to in batches. Each sink is fed from its
+//! own thread through a queue of `export_queue` events, so that a slow consumer
+//! never holds up the node. A sink that fails retries with a growing delay,
+//! reconnecting or reopening as needed. Events that don't fit in the queue of a
+//! sink that fell behind are dropped and counted in `factomd_export_dropped_total`,
+//! which consumers see as gaps in `seq`. A batch that failed part way is sent
+//! again, so consumers may also see an event twice.
+//!
+use crate::events::{account_address, block_events, BlockActivity};
+use crate::metrics;
+use crate::wrapper::Factory;
+use factomd_address::AddressKind;
+use factomd_configuration::Export;
+use factomd_rpc::SharedHealth;
+use factomd_runtime::{opaque::Block, BlockStatsApi, EntryEvent, Event, FactoidEvent};
+use futures::{Future, Stream};
+use reqwest::header::CONTENT_TYPE;
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::{Header as HeaderT, ProvideRuntimeApi};
+use serde::Serialize;
+use slog::Logger;
+use std::cmp;
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use substrate_service::{FullComponents, Service, TaskExecutor};
+
+/// Most events delivered at once
+const MAX_BATCH: usize = 500;
+/// Delay before the first retry of a failed sink, doubled up to `MAX_RETRY_DELAY`
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+/// How long a consumer may take to accept a batch
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Something that happened on the node
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event")]
+pub enum ExportEvent {
+    BlockCommit {
+        height: u64,
+        hash: String,
+        /// Seconds
+        timestamp: u64,
+        entries: u32,
+        chains: u32,
+    },
+    ChainCommit {
+        chainid: String,
+        /// Hash of the first entry
+        entryhash: String,
+        height: u64,
+    },
+    EntryCommit {
+        chainid: String,
+        entryhash: String,
+        height: u64,
+    },
+    FactoidTransfer {
+        from: String,
+        to: String,
+        /// Factoshis
+        amount: u64,
+        height: u64,
+    },
+    EntryCreditPurchase {
+        from: String,
+        /// The EC address credited
+        to: String,
+        /// Factoshis spent
+        amount: u64,
+        credits: u64,
+        height: u64,
+    },
+    NodeState {
+        is_syncing: bool,
+        peers: usize,
+        is_producing: bool,
+        best_block: u64,
+        finalized_block: u64,
+    },
+}
+
+/// An event as it is written out
+#[derive(Serialize)]
+struct Record<'a> {
+    seq: usize,
+    #[serde(flatten)]
+    event: &'a ExportEvent,
+}
+
+/// Hands events to the threads of the configured sinks
+pub struct Exporter {
+    seq: AtomicUsize,
+    sinks: Vec<(&'static str, SyncSender<String>)>,
+}
+
+impl Exporter {
+    /// Start a thread for each configured sink, none if no sink is configured
+    ///
+    /// Each thread stops once the exporter is dropped and its queue is delivered.
+    pub fn new(config: &Export, log: Option<Logger>) -> Result<Option<Exporter>, String> {
+        let mut sinks = Vec::new();
+        if !config.export_tcp.is_empty() {
+            let sink = TcpSink {
+                addr: config.export_tcp.to_string(),
+                stream: None,
+            };
+            sinks.push(("tcp", spawn("tcp", sink, config.export_queue, log.clone())?));
+        }
+        if !config.export_file.is_empty() {
+            let sink = FileSink {
+                path: config.export_file.to_string(),
+                file: None,
+            };
+            sinks.push(("file", spawn("file", sink, config.export_queue, log.clone())?));
+        }
+        if !config.export_webhook.is_empty() {
+            let url = reqwest::Url::parse(&config.export_webhook)
+                .map_err(|e| format!("Invalid export_webhook {}: {}", config.export_webhook, e))?;
+            let client = reqwest::Client::builder()
+                .timeout(TIMEOUT)
+                .build()
+                .map_err(|e| e.to_string())?;
+            let sink = WebhookSink { url, client };
+            sinks.push(("webhook", spawn("webhook", sink, config.export_queue, log)?));
+        }
+
+        if sinks.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Exporter {
+            seq: AtomicUsize::new(0),
+            sinks,
+        }))
+    }
+
+    /// Names of the sinks events go to
+    pub fn sinks(&self) -> Vec<&'static str> {
+        self.sinks.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// Queue an event for every sink, dropping it for those that fell behind
+    pub fn send(&self, event: &ExportEvent) {
+        let record = Record {
+            seq: self.seq.fetch_add(1, Ordering::SeqCst),
+            event,
+        };
+        let line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        for (name, queue) in &self.sinks {
+            if let Err(TrySendError::Full(_)) = queue.try_send(line.clone()) {
+                metrics::EXPORT_DROPPED.with_label_values(&[name]).inc();
+            }
+        }
+    }
+}
+
+/// Export the blocks, entries, factoid transactions and state changes of a full node
+pub fn start(
+    service: &Service<FullComponents<Factory>>,
+    health: SharedHealth,
+    exporter: Exporter,
+    executor: &TaskExecutor,
+) {
+    let exporter = Arc::new(exporter);
+    let client = service.client();
+
+    let imported = {
+        let exporter = exporter.clone();
+        client
+            .import_notification_stream()
+            .filter(|notification| notification.is_new_best)
+            .for_each(move |notification| {
+                let height = *notification.header.number();
+                let api = client.runtime_api();
+                let at = BlockId::<Block>::hash(notification.hash);
+                let events = block_events(&client, notification.hash);
+                let activity = BlockActivity::of(&events);
+                exporter.send(&ExportEvent::BlockCommit {
+                    height,
+                    hash: hex::encode(notification.hash),
+                    timestamp: api.timestamp(&at).unwrap_or_default(),
+                    entries: activity.entries,
+                    chains: activity.chains,
+                });
+                for event in block_exports(&events, height) {
+                    exporter.send(&event);
+                }
+                Ok(())
+            })
+    };
+
+    // Health is kept up to date from the same network status updates
+    let mut last = None;
+    let status = service.network().status().for_each(move |_| {
+        let health = match health.read() {
+            Ok(health) => health.clone(),
+            Err(_) => return Ok(()),
+        };
+        let state = (health.is_syncing, health.peers, health.is_producing);
+        if !health.started || last == Some(state) {
+            return Ok(());
+        }
+        last = Some(state);
+        exporter.send(&ExportEvent::NodeState {
+            is_syncing: health.is_syncing,
+            peers: health.peers,
+            is_producing: health.is_producing,
+            best_block: health.best_block,
+            finalized_block: health.finalized_block,
+        });
+        Ok(())
+    });
+
+    let exit = service.on_exit();
+    executor.spawn(exit.clone().until(imported).map(|_| ()));
+    executor.spawn(exit.until(status).map(|_| ()));
+}
+
+/// Chain, entry and factoid events of a block at `height`
+fn block_exports(events: &[Event], height: u64) -> Vec<ExportEvent> {
+    let mut exports = Vec::new();
+    // A chain is started by the entry written right after it
+    let mut started = None;
+    for event in events {
+        match event {
+            Event::entry(EntryEvent::ChainStarted(chain_id)) => started = Some(*chain_id),
+            Event::entry(EntryEvent::EntryWritten(chain_id, entry_hash)) => {
+                let chainid = hex::encode(chain_id);
+                let entryhash = hex::encode(entry_hash);
+                if started.take() == Some(*chain_id) {
+                    exports.push(ExportEvent::ChainCommit {
+                        chainid: chainid.clone(),
+                        entryhash: entryhash.clone(),
+                        height,
+                    });
+                }
+                exports.push(ExportEvent::EntryCommit {
+                    chainid,
+                    entryhash,
+                    height,
+                });
+            }
+            Event::factoid(FactoidEvent::Transferred(from, to, amount)) => {
+                exports.push(ExportEvent::FactoidTransfer {
+                    from: account_address(AddressKind::FactoidPublic, &from.0),
+                    to: account_address(AddressKind::FactoidPublic, &to.0),
+                    amount: u64::try_from(*amount).unwrap_or(!0),
+                    height,
+                });
+            }
+            Event::factoid(FactoidEvent::EntryCreditsBought(from, to, amount, credits)) => {
+                exports.push(ExportEvent::EntryCreditPurchase {
+                    from: account_address(AddressKind::FactoidPublic, &from.0),
+                    to: account_address(AddressKind::EntryCreditPublic, &to.0),
+                    amount: u64::try_from(*amount).unwrap_or(!0),
+                    credits: *credits,
+                    height,
+                });
+            }
+            _ => {}
+        }
+    }
+    exports
+}
+
+/// Where events are delivered to
+trait Sink: Send + 'static {
+    /// Deliver events, one per line
+    fn deliver(&mut self, lines: &[u8]) -> Result<(), String>;
+}
+
+/// A consumer listening on TCP, connected to again after errors
+struct TcpSink {
+    addr: String,
+    stream: Option<TcpStream>,
+}
+
+impl Sink for TcpSink {
+    fn deliver(&mut self, lines: &[u8]) -> Result<(), String> {
+        let mut stream = match self.stream.take() {
+            Some(stream) if !is_closed(&stream) => stream,
+            _ => {
+                let stream = TcpStream::connect(&self.addr).map_err(|e| e.to_string())?;
+                stream.set_write_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+                stream
+            }
+        };
+        stream.write_all(lines).map_err(|e| e.to_string())?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+}
+
+/// Whether a consumer closed its connection
+///
+/// Writes only fail a while after that, so the events written in between would
+/// be lost. Anything the consumer sent is discarded.
+fn is_closed(mut stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return true;
+    }
+    let mut buf = [0; 512];
+    let closed = loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break true,
+            Ok(_) => continue,
+            Err(e) => break e.kind() != io::ErrorKind::WouldBlock,
+        }
+    };
+    stream.set_nonblocking(false).is_err() || closed
+}
+
+/// A file events are appended to, opened again after errors
+struct FileSink {
+    path: String,
+    file: Option<File>,
+}
+
+impl Sink for FileSink {
+    fn deliver(&mut self, lines: &[u8]) -> Result<(), String> {
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .map_err(|e| e.to_string())?,
+        };
+        file.write_all(lines).and_then(|_| file.flush()).map_err(|e| e.to_string())?;
+        self.file = Some(file);
+        Ok(())
+    }
+}
+
+/// A URL batches of events are POSTed to
+struct WebhookSink {
+    url: reqwest::Url,
+    client: reqwest::Client,
+}
+
+impl Sink for WebhookSink {
+    fn deliver(&mut self, lines: &[u8]) -> Result<(), String> {
+        let response = self
+            .client
+            .post(self.url.clone())
+            .header(CONTENT_TYPE, "application/x-ndjson")
+            .body(lines.to_vec())
+            .send()
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("Webhook answered {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Start the thread of a sink, returning its queue
+fn spawn<S: Sink>(name: &'static str, sink: S, queue: usize, log: Option<Logger>) -> Result<SyncSender<String>, String> {
+    let (sender, receiver) = mpsc::sync_channel(queue);
+    thread::Builder::new()
+        .name(format!("export-{}", name))
+        .spawn(move || run(name, sink, &receiver, log))
+        .map_err(|e| format!("Failed to start the {} export: {}", name, e))?;
+    Ok(sender)
+}
+
+/// Deliver the events of a queue until the exporter is dropped
+fn run<S: Sink>(name: &str, mut sink: S, queue: &Receiver<String>, log: Option<Logger>) {
+    while let Ok(line) = queue.recv() {
+        // Whatever else is waiting goes along
+        let mut lines = line + "\n";
+        for line in queue.try_iter().take(MAX_BATCH - 1) {
+            lines.push_str(&line);
+            lines.push('\n');
+        }
+
+        let mut delay = RETRY_DELAY;
+        while let Err(e) = sink.deliver(lines.as_bytes()) {
+            if let Some(log) = &log {
+                warn!(log, "Event export failed, retrying"; "sink" => name, "error" => e, "delay" => ?delay);
+            }
+            thread::sleep(delay);
+            delay = cmp::min(delay * 2, MAX_RETRY_DELAY);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! # Node Health
+//!
+//! Keeps the status behind the `/health` and `/ready` endpoints of the RPC
+//! server up to date. Sync state, peers and block heights are refreshed on every
+//! network status update.
+//!
+//! An authority counts as producing while it has authored a block within
+//! `PRODUCING_WINDOW`. A node that isn't syncing counts as stalled once its best
+//! block hasn't advanced within `STALL_WINDOW`.
+//!
+use consensus_common::BlockOrigin;
+use factomd_rpc::SharedHealth;
+use futures::{Future, Stream};
+use network::SyncState;
+use runtime_primitives::traits::As;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use substrate_service::{Components, Service, TaskExecutor};
+
+/// How recently an authority must have authored a block to count as producing
+const PRODUCING_WINDOW: Duration = Duration::from_secs(60);
+/// How long the best block may stay the same before the node counts as stalled
+const STALL_WINDOW: Duration = Duration::from_secs(300);
+
+/// Follow the sync state, peers and block production of a node
+pub fn start<C>(service: &Service<C>, health: SharedHealth, exit: exit_future::Exit, executor: TaskExecutor)
+where
+    C: Components,
+{
+    let client = service.client();
+    let last_authored: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+    // Best block and when it was first seen
+    let mut last_best: Option<(u64, Instant)> = None;
+
+    let authored = last_authored.clone();
+    let imports = client
+        .import_notification_stream()
+        .filter(|notification| notification.origin == BlockOrigin::Own)
+        .for_each(move |_| {
+            if let Ok(mut authored) = authored.lock() {
+                *authored = Some(Instant::now());
+            }
+            Ok(())
+        });
+
+    let status = service.network().status().for_each(move |status| {
+        let info = client.info().ok();
+        let is_producing = last_authored
+            .lock()
+            .ok()
+            .and_then(|authored| *authored)
+            .map(|authored| authored.elapsed() < PRODUCING_WINDOW)
+            .unwrap_or(false);
+
+        if let Ok(mut health) = health.write() {
+            health.started = true;
+            health.is_syncing = match status.sync.state {
+                SyncState::Downloading => true,
+                SyncState::Idle => false,
+            };
+            health.peers = status.num_peers;
+            if let Some(info) = info {
+                health.best_block = info.chain.best_number.as_();
+                health.finalized_block = info.chain.finalized_number.as_();
+            }
+            let best = health.best_block;
+            let since = match last_best {
+                Some((height, since)) if height == best => since,
+                _ => Instant::now(),
+            };
+            last_best = Some((best, since));
+            health.is_stalled = !health.is_syncing && since.elapsed() > STALL_WINDOW;
+            health.is_producing = health.is_authority && is_producing;
+        }
+        Ok(())
+    });
+
+    executor.spawn(exit.clone().until(imports).map(|_| ()));
+    executor.spawn(exit.until(status).map(|_| ()));
+}
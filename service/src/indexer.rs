@@ -0,0 +1,505 @@
+//! # Entry Index
+//!
+//! Follows the best chain of a full node into an SQLite database, so that the
+//! search methods of the RPC server find entries by external id without
+//! scanning chains. For each block the index keeps:
+//!
+//! * the block, with its hash and timestamp,
+//! * the chains it started and the entries it wrote, with their external ids,
+//! * entry credit credits and spends, factoid transfers and EC purchases,
+//! * every event the runtime deposited, in its debug form.
+//!
+//! Blocks a reorg replaced are dropped along with everything above them before
+//! the new ones are indexed, and on start the index catches up from the last
+//! block it holds.
+//!
+//! Entries and transactions are found from the events kept in block state, so
+//! blocks whose state was pruned are indexed without them; an archive node
+//! indexes the whole history. Entries imported at genesis deposit no events and
+//! aren't indexed.
+//!
+use crate::events::{account_address, block_events};
+use crate::wrapper::Factory;
+use factomd_address::AddressKind;
+use factomd_rpc::{EntryIndex, IndexedChain, IndexedEntry};
+use factomd_runtime::legacy_entry;
+use factomd_runtime::{
+    opaque::Block, BlockStatsApi, EntryCreditEvent, EntryEvent, EntryStruct, Event, FactoidEvent, Hash,
+};
+use futures::{stream, Future, Stream};
+use parity_codec::{Decode, Encode};
+use primitives::blake2_256;
+use primitives::storage::StorageKey;
+use rusqlite::types::ToSql;
+use rusqlite::{params, Connection, Row, Transaction};
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::{Header as HeaderT, ProvideRuntimeApi};
+use slog::Logger;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex, MutexGuard};
+use substrate_service::{FullClient, TaskExecutor};
+
+/// Tables with rows for each block, emptied from a height on when a reorg replaced it
+const BLOCK_TABLES: [&str; 7] = [
+    "blocks",
+    "entries",
+    "ext_ids",
+    "chains",
+    "ec_transactions",
+    "fct_transactions",
+    "events",
+];
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    CREATE TABLE IF NOT EXISTS blocks (
+        height INTEGER PRIMARY KEY,
+        hash BLOB NOT NULL,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS entries (
+        height INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        entry_hash BLOB NOT NULL,
+        chain_id BLOB NOT NULL,
+        PRIMARY KEY (height, position)
+    );
+    CREATE INDEX IF NOT EXISTS entries_by_chain ON entries (chain_id, height, position);
+    CREATE INDEX IF NOT EXISTS entries_by_hash ON entries (entry_hash);
+    CREATE TABLE IF NOT EXISTS ext_ids (
+        height INTEGER NOT NULL,
+        entry_position INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        ext_id BLOB NOT NULL,
+        PRIMARY KEY (height, entry_position, position)
+    );
+    CREATE INDEX IF NOT EXISTS ext_ids_by_value ON ext_ids (ext_id);
+    CREATE TABLE IF NOT EXISTS chains (
+        chain_id BLOB PRIMARY KEY,
+        height INTEGER NOT NULL,
+        entry_position INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS chains_by_height ON chains (height);
+    CREATE TABLE IF NOT EXISTS ec_transactions (
+        height INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        address TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        balance INTEGER NOT NULL,
+        PRIMARY KEY (height, position)
+    );
+    CREATE INDEX IF NOT EXISTS ec_transactions_by_address ON ec_transactions (address, height);
+    CREATE TABLE IF NOT EXISTS fct_transactions (
+        height INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        from_address TEXT NOT NULL,
+        to_address TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        credits INTEGER,
+        PRIMARY KEY (height, position)
+    );
+    CREATE INDEX IF NOT EXISTS fct_transactions_by_from ON fct_transactions (from_address, height);
+    CREATE INDEX IF NOT EXISTS fct_transactions_by_to ON fct_transactions (to_address, height);
+    CREATE TABLE IF NOT EXISTS events (
+        height INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        event TEXT NOT NULL,
+        PRIMARY KEY (height, position)
+    );
+";
+
+/// Largest SQLite integer, which larger amounts are capped at
+const MAX_INTEGER: i64 = 0x7fff_ffff_ffff_ffff;
+
+/// Index of the entries and transactions of the best chain
+pub struct Indexer {
+    connection: Mutex<Connection>,
+}
+
+/// What the index keeps of a block
+struct IndexedBlock {
+    height: u64,
+    hash: Hash,
+    timestamp: u64,
+    events: Vec<Event>,
+    /// External ids of the entries the block wrote
+    ext_ids: HashMap<Hash, Vec<Vec<u8>>>,
+}
+
+/// An entry row, before its external ids are read
+struct EntryRow {
+    height: i64,
+    position: i64,
+    entry_hash: Vec<u8>,
+    chain_id: Vec<u8>,
+}
+
+impl Indexer {
+    /// Open the index at `path`, creating it if needed
+    pub fn open(path: &str) -> Result<Self, String> {
+        let connection = Connection::open(path).map_err(|e| format!("Failed to open index {}: {}", path, e))?;
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(|e| format!("Failed to create index {}: {}", path, e))?;
+        Ok(Indexer {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Hash of the block indexed at `height`
+    fn block_hash(&self, height: u64) -> Result<Option<Vec<u8>>, String> {
+        let connection = self.lock();
+        let mut statement = connection
+            .prepare("SELECT hash FROM blocks WHERE height = ?1")
+            .map_err(|e| e.to_string())?;
+        let mut rows = statement
+            .query_map(params![height as i64], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        rows.next().transpose().map_err(|e| e.to_string())
+    }
+
+    /// Index a block, replacing the one at its height and every one above
+    fn write_block(&self, block: &IndexedBlock) -> Result<(), String> {
+        let mut connection = self.lock();
+        let tx = connection.transaction().map_err(|e| e.to_string())?;
+        write_block(&tx, block)
+            .and_then(|_| tx.commit())
+            .map_err(|e| format!("Failed to index block {}: {}", block.height, e))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl EntryIndex for Indexer {
+    fn entries_by_ext_id(&self, prefix: &[u8], chain_id: Option<&[u8]>, limit: u32) -> Result<Vec<IndexedEntry>, String> {
+        let connection = self.lock();
+        // External ids starting with the prefix sort from it up to its end, if it has one
+        let sql = |end: &str| {
+            format!(
+                "SELECT DISTINCT e.height, e.position, e.entry_hash, e.chain_id FROM ext_ids x
+                 JOIN entries e ON e.height = x.height AND e.position = x.entry_position
+                 WHERE x.ext_id >= ?1 {} AND (?2 IS NULL OR e.chain_id = ?2)
+                 ORDER BY e.height, e.position LIMIT ?3",
+                end
+            )
+        };
+        let rows = match prefix_end(prefix) {
+            Some(end) => entry_rows(
+                &connection,
+                &sql("AND x.ext_id < ?4"),
+                params![prefix, chain_id, limit, end],
+            )?,
+            None => entry_rows(&connection, &sql(""), params![prefix, chain_id, limit])?,
+        };
+        with_ext_ids(&connection, rows)
+    }
+
+    fn chains_by_ext_ids(&self, ext_ids: &[Vec<u8>], limit: u32) -> Result<Vec<IndexedChain>, String> {
+        let connection = self.lock();
+        // The first external id is looked up, the others are checked
+        let mut sql = "SELECT c.height, c.entry_position, e.entry_hash, c.chain_id FROM ext_ids x
+             JOIN chains c ON c.height = x.height AND c.entry_position = x.entry_position
+             JOIN entries e ON e.height = c.height AND e.position = c.entry_position
+             WHERE x.position = 0 AND x.ext_id = ?1"
+            .to_string();
+        for position in 1..ext_ids.len() {
+            sql += &format!(
+                " AND EXISTS (SELECT 1 FROM ext_ids o WHERE o.height = c.height
+                 AND o.entry_position = c.entry_position AND o.position = {} AND o.ext_id = ?{})",
+                position,
+                position + 1
+            );
+        }
+        sql += &format!(" ORDER BY c.height, c.entry_position LIMIT {}", limit);
+
+        let rows = entry_rows(&connection, &sql, ext_ids)?;
+        Ok(with_ext_ids(&connection, rows)?
+            .into_iter()
+            .map(|entry| IndexedChain {
+                chainid: entry.chainid,
+                firstentry: entry.entryhash,
+                extids: entry.extids,
+                height: entry.height,
+            })
+            .collect())
+    }
+
+    fn entries_by_height(&self, from: u64, to: u64, chain_id: Option<&[u8]>, limit: u32) -> Result<Vec<IndexedEntry>, String> {
+        let connection = self.lock();
+        let rows = entry_rows(
+            &connection,
+            "SELECT height, position, entry_hash, chain_id FROM entries
+             WHERE height BETWEEN ?1 AND ?2 AND (?3 IS NULL OR chain_id = ?3)
+             ORDER BY height, position LIMIT ?4",
+            params![from as i64, to as i64, chain_id, limit],
+        )?;
+        with_ext_ids(&connection, rows)
+    }
+}
+
+/// Index the best chain of a full node as blocks are imported
+pub fn start(
+    client: Arc<FullClient<Factory>>,
+    index: Arc<Indexer>,
+    log: Option<Logger>,
+    exit: exit_future::Exit,
+    executor: &TaskExecutor,
+) {
+    // Catch up with the current best block first
+    let best = client.info().map(|info| info.chain.best_hash).ok();
+    let blocks = stream::iter_ok(best).chain(
+        client
+            .import_notification_stream()
+            .filter(|notification| notification.is_new_best)
+            .map(|notification| notification.hash),
+    );
+    let indexing = blocks.for_each(move |best| {
+        if let Err(e) = follow(&client, &index, best) {
+            if let Some(log) = &log {
+                warn!(log, "Entry index fell behind"; "error" => e);
+            }
+        }
+        Ok(())
+    });
+
+    executor.spawn(exit.until(indexing).map(|_| ()));
+}
+
+/// Index the chain up to `best`, back to the last block both have in common
+fn follow(client: &FullClient<Factory>, index: &Indexer, best: Hash) -> Result<(), String> {
+    // Blocks not indexed yet, newest first
+    let mut route = Vec::new();
+    let mut hash = best;
+    loop {
+        let header = client
+            .header(&BlockId::<Block>::hash(hash))
+            .map_err(|e| format!("{:?}", e))?
+            .ok_or_else(|| format!("Unknown block {:?}", hash))?;
+        let height = *header.number();
+        if index.block_hash(height)?.as_ref().map(Vec::as_slice) == Some(bytes(&hash)) {
+            break;
+        }
+        route.push((height, hash));
+        if height == 0 {
+            break;
+        }
+        hash = *header.parent_hash();
+    }
+
+    for (height, hash) in route.into_iter().rev() {
+        index.write_block(&read_block(client, best, height, hash))?;
+    }
+    Ok(())
+}
+
+/// Read what the index keeps of a block
+///
+/// Entries are read at the best block if the state of their own is gone.
+fn read_block(client: &FullClient<Factory>, best: Hash, height: u64, hash: Hash) -> IndexedBlock {
+    let events = block_events(client, hash);
+    let ext_ids = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::entry(EntryEvent::EntryWritten(_, entry_hash)) => {
+                let entry = read_entry(client, hash, entry_hash).or_else(|| read_entry(client, best, entry_hash))?;
+                let ext_ids = legacy_entry::decode_external_ids(entry.external_ids())
+                    .unwrap_or_else(|_| vec![entry.external_ids().to_vec()]);
+                Some((*entry_hash, ext_ids))
+            }
+            _ => None,
+        })
+        .collect();
+    IndexedBlock {
+        height,
+        hash,
+        timestamp: client
+            .runtime_api()
+            .timestamp(&BlockId::<Block>::hash(hash))
+            .unwrap_or_default(),
+        events,
+        ext_ids,
+    }
+}
+
+/// An entry as stored by the entry module at a block
+fn read_entry(client: &FullClient<Factory>, at: Hash, entry_hash: &Hash) -> Option<EntryStruct<Hash>> {
+    let mut key = b"Entry Entries".to_vec();
+    entry_hash.encode_to(&mut key);
+    client
+        .storage(&BlockId::<Block>::hash(at), &StorageKey(blake2_256(&key).to_vec()))
+        .ok()
+        .and_then(|data| data)
+        .and_then(|data| EntryStruct::decode(&mut &data.0[..]))
+}
+
+fn write_block(tx: &Transaction, block: &IndexedBlock) -> rusqlite::Result<()> {
+    let height = block.height as i64;
+    for table in &BLOCK_TABLES {
+        tx.execute(&format!("DELETE FROM {} WHERE height >= ?1", table), params![height])?;
+    }
+    tx.execute(
+        "INSERT INTO blocks (height, hash, timestamp) VALUES (?1, ?2, ?3)",
+        params![height, bytes(&block.hash), block.timestamp as i64],
+    )?;
+
+    let mut entry_position = 0i64;
+    // A chain is started by the entry written right after it
+    let mut started = None;
+    for (position, event) in block.events.iter().enumerate() {
+        let position = position as i64;
+        tx.execute(
+            "INSERT INTO events (height, position, event) VALUES (?1, ?2, ?3)",
+            params![height, position, format!("{:?}", event)],
+        )?;
+        match event {
+            Event::entry(EntryEvent::ChainStarted(chain_id)) => started = Some(*chain_id),
+            Event::entry(EntryEvent::EntryWritten(chain_id, entry_hash)) => {
+                if started.take() == Some(*chain_id) {
+                    tx.execute(
+                        "INSERT OR REPLACE INTO chains (chain_id, height, entry_position) VALUES (?1, ?2, ?3)",
+                        params![bytes(chain_id), height, entry_position],
+                    )?;
+                }
+                tx.execute(
+                    "INSERT INTO entries (height, position, entry_hash, chain_id) VALUES (?1, ?2, ?3, ?4)",
+                    params![height, entry_position, bytes(entry_hash), bytes(chain_id)],
+                )?;
+                let ext_ids = block.ext_ids.get(entry_hash).map(Vec::as_slice).unwrap_or_default();
+                for (ext_id_position, ext_id) in ext_ids.iter().enumerate() {
+                    tx.execute(
+                        "INSERT INTO ext_ids (height, entry_position, position, ext_id) VALUES (?1, ?2, ?3, ?4)",
+                        params![height, entry_position, ext_id_position as i64, ext_id],
+                    )?;
+                }
+                entry_position += 1;
+            }
+            Event::entry_credit(EntryCreditEvent::Credited(account, amount, balance)) => {
+                write_ec_transaction(tx, height, position, "credit", &account.0, integer(*amount), integer(*balance))?;
+            }
+            Event::entry_credit(EntryCreditEvent::Spent(account, amount, balance)) => {
+                write_ec_transaction(tx, height, position, "spend", &account.0, integer(*amount), integer(*balance))?;
+            }
+            Event::factoid(FactoidEvent::Transferred(from, to, amount)) => {
+                tx.execute(
+                    "INSERT INTO fct_transactions (height, position, kind, from_address, to_address, amount)
+                     VALUES (?1, ?2, 'transfer', ?3, ?4, ?5)",
+                    params![
+                        height,
+                        position,
+                        account_address(AddressKind::FactoidPublic, &from.0),
+                        account_address(AddressKind::FactoidPublic, &to.0),
+                        integer(*amount)
+                    ],
+                )?;
+            }
+            Event::factoid(FactoidEvent::EntryCreditsBought(from, to, amount, credits)) => {
+                tx.execute(
+                    "INSERT INTO fct_transactions (height, position, kind, from_address, to_address, amount, credits)
+                     VALUES (?1, ?2, 'ec_purchase', ?3, ?4, ?5, ?6)",
+                    params![
+                        height,
+                        position,
+                        account_address(AddressKind::FactoidPublic, &from.0),
+                        account_address(AddressKind::EntryCreditPublic, &to.0),
+                        integer(*amount),
+                        integer(*credits)
+                    ],
+                )?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn write_ec_transaction(
+    tx: &Transaction,
+    height: i64,
+    position: i64,
+    kind: &str,
+    account: &[u8; 32],
+    amount: i64,
+    balance: i64,
+) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT INTO ec_transactions (height, position, address, kind, amount, balance)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            height,
+            position,
+            account_address(AddressKind::EntryCreditPublic, account),
+            kind,
+            amount,
+            balance
+        ],
+    )?;
+    Ok(())
+}
+
+fn bytes(hash: &Hash) -> &[u8] {
+    hash.as_ref()
+}
+
+/// An amount as an SQLite integer
+fn integer<T>(value: T) -> i64
+where
+    i64: TryFrom<T>,
+{
+    i64::try_from(value).unwrap_or(MAX_INTEGER)
+}
+
+/// The smallest value above every value starting with `prefix`, none if there isn't one
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+fn entry_rows<P>(connection: &Connection, sql: &str, params: P) -> Result<Vec<EntryRow>, String>
+where
+    P: IntoIterator,
+    P::Item: ToSql,
+{
+    let mut statement = connection.prepare(sql).map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map(params, |row: &Row| {
+            Ok(EntryRow {
+                height: row.get(0)?,
+                position: row.get(1)?,
+                entry_hash: row.get(2)?,
+                chain_id: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())
+}
+
+fn with_ext_ids(connection: &Connection, rows: Vec<EntryRow>) -> Result<Vec<IndexedEntry>, String> {
+    let mut statement = connection
+        .prepare("SELECT ext_id FROM ext_ids WHERE height = ?1 AND entry_position = ?2 ORDER BY position")
+        .map_err(|e| e.to_string())?;
+    rows.into_iter()
+        .map(|row| {
+            let ext_ids = statement
+                .query_map(params![row.height, row.position], |ext_id| ext_id.get::<_, Vec<u8>>(0))
+                .and_then(|ext_ids| ext_ids.collect::<rusqlite::Result<Vec<_>>>())
+                .map_err(|e| e.to_string())?;
+            Ok(IndexedEntry {
+                entryhash: hex::encode(row.entry_hash),
+                chainid: hex::encode(row.chain_id),
+                extids: ext_ids.into_iter().map(hex::encode).collect(),
+                height: row.height as u64,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,265 @@
+//! # Key Management
+//!
+//! The `key` subcommands, and the keys an authority node starts with. Keys are kept
+//! encrypted in the keystore at `keystore_path`. The password is read from the
+//! variable named by `keystore_password_env`, or prompted for.
+//!
+//! Authority and network keys are ed25519. FCT and EC keys are sr25519 accounts on
+//! this chain, shown as FA and EC addresses. The same seed read as an ed25519 key
+//! gives its legacy address: importing an Fs or Es key from a legacy wallet shows
+//! the address its legacy balance is claimed from.
+//!
+//! With `--mnemonic`, FCT and EC keys are derived from 12 words along the BIP44
+//! paths of legacy wallets, see `factomd_keystore::hd`. Importing the words of a
+//! legacy wallet with an `--index` gives the key of its address at that index.
+//!
+use factomd_address::{Address, AddressKind};
+use factomd_configuration::{FactomConfig, KeyCommand, Keystore as KeystoreConfig};
+use factomd_keystore::{hd, random_seed, KeyKind, Keystore, StoredKey};
+use primitives::{crypto::Ss58Codec, ed25519, sr25519, Pair};
+
+/// Run a `key` subcommand
+pub fn run(config: &KeystoreConfig, command: &KeyCommand) -> Result<(), String> {
+    let keystore = Keystore::open(&config.keystore_path).map_err(|e| e.to_string())?;
+
+    match command {
+        KeyCommand::Generate(cmd) => {
+            let kind = cmd.kind.parse().map_err(|e: factomd_keystore::Error| e.to_string())?;
+            let mnemonic = if cmd.mnemonic {
+                Some(hd::generate_mnemonic())
+            } else {
+                None
+            };
+            let seed = match &mnemonic {
+                Some(mnemonic) => hd::derive_seed(mnemonic, kind, 0),
+                None => random_seed(),
+            }
+            .map_err(|e| e.to_string())?;
+            let password = password(config, true)?;
+            let stored = keystore
+                .insert(&cmd.name, kind, &public_key(kind, &seed), &seed, &password)
+                .map_err(|e| e.to_string())?;
+            print_key(&stored, mnemonic.as_ref().map(|_| &seed), false);
+            if let Some(mnemonic) = mnemonic {
+                println!("Mnemonic:        {}", mnemonic);
+                println!("Write down the mnemonic, it restores this key and isn't stored");
+            }
+        }
+        KeyCommand::Import(cmd) => {
+            let kind = cmd.kind.parse().map_err(|e: factomd_keystore::Error| e.to_string())?;
+            let secret = if cmd.secret.is_empty() {
+                let prompt = if cmd.mnemonic { "Mnemonic: " } else { "Secret: " };
+                rpassword::prompt_password_stderr(prompt).map_err(|e| e.to_string())?
+            } else {
+                cmd.secret.clone()
+            };
+            let seed = if cmd.mnemonic {
+                hd::derive_seed(&secret, kind, cmd.index).map_err(|e| e.to_string())?
+            } else {
+                parse_secret(kind, secret.trim())?
+            };
+            let password = password(config, true)?;
+            let stored = keystore
+                .insert(&cmd.name, kind, &public_key(kind, &seed), &seed, &password)
+                .map_err(|e| e.to_string())?;
+            print_key(&stored, Some(&seed), false);
+        }
+        KeyCommand::Inspect(cmd) => {
+            let stored = keystore.get(&cmd.name).map_err(|e| e.to_string())?;
+            if cmd.show_secret {
+                let seed = stored
+                    .seed(&password(config, false)?)
+                    .map_err(|e| e.to_string())?;
+                print_key(&stored, Some(&seed), true);
+            } else {
+                print_key(&stored, None, false);
+            }
+        }
+        KeyCommand::List(cmd) => {
+            let kind: Option<KeyKind> = match &cmd.kind {
+                Some(kind) => Some(kind.parse().map_err(|e: factomd_keystore::Error| e.to_string())?),
+                None => None,
+            };
+            for key in keystore.list().map_err(|e| e.to_string())? {
+                if kind.is_none() || kind == Some(key.kind) {
+                    println!("{:<24} {:<10} {}", key.name, key.kind, address(&key)?);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Substrate arguments for the keys of an authority node
+///
+/// The network key comes from the variable named by `node_key_env` when set, as it
+/// did before the keystore, otherwise from the keystore. The authority key always
+/// comes from the keystore; without one, a node still using `node_key_env` leaves
+/// it to substrate as before.
+pub fn authority_args(config: &FactomConfig) -> Result<Vec<String>, String> {
+    let keystore = Keystore::open(&config.keystore.keystore_path).map_err(|e| e.to_string())?;
+    let node_key_env = &config.server.node_key_env;
+    // Prompted for at most once, and only when a key is read
+    let mut cached_password = None;
+    let mut decrypt = |key: &StoredKey| -> Result<[u8; 32], String> {
+        if cached_password.is_none() {
+            cached_password = Some(password(&config.keystore, false)?);
+        }
+        key.seed(cached_password.as_ref().expect("set above; qed"))
+            .map_err(|e| e.to_string())
+    };
+
+    let mut args = Vec::new();
+    if node_key_env.is_empty() {
+        let network = keystore
+            .first(KeyKind::Network)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| missing_key(config, KeyKind::Network))?;
+        args.push("--node-key-type".to_string());
+        args.push("ed25519".to_string());
+        args.push("--node-key".to_string());
+        args.push(hex::encode(decrypt(&network)?));
+    } else {
+        let node_key = std::env::var(node_key_env)
+            .map_err(|_| format!("Failed to find node key in {}", node_key_env))?;
+        args.push("--node-key".to_string());
+        args.push(node_key);
+    }
+
+    match keystore.first(KeyKind::Authority).map_err(|e| e.to_string())? {
+        Some(authority) => {
+            args.push("--key".to_string());
+            args.push(format!("0x{}", hex::encode(decrypt(&authority)?)));
+        }
+        None if node_key_env.is_empty() => return Err(missing_key(config, KeyKind::Authority)),
+        None => {}
+    }
+    Ok(args)
+}
+
+/// Seed of the FCT key stored under `name`, to sign calls of its account with
+pub fn account_seed(config: &KeystoreConfig, name: &str) -> Result<[u8; 32], String> {
+    let keystore = Keystore::open(&config.keystore_path).map_err(|e| e.to_string())?;
+    let key = keystore.get(name).map_err(|e| e.to_string())?;
+    if key.kind != KeyKind::Fct {
+        return Err(format!("Key {} is not an {} key", name, KeyKind::Fct));
+    }
+    key.seed(&password(config, false)?).map_err(|e| e.to_string())
+}
+
+fn missing_key(config: &FactomConfig, kind: KeyKind) -> String {
+    format!(
+        "No {} key in keystore {}, add one with `factomd key generate --kind {} --name <NAME>`",
+        kind, config.keystore.keystore_path, kind
+    )
+}
+
+/// The keystore password, from the environment or prompted for
+fn password(config: &KeystoreConfig, confirm: bool) -> Result<String, String> {
+    let env = &config.keystore_password_env;
+    if !env.is_empty() {
+        return std::env::var(env).map_err(|_| format!("Failed to find keystore password in {}", env));
+    }
+
+    let password = rpassword::prompt_password_stderr("Keystore password: ").map_err(|e| e.to_string())?;
+    if confirm {
+        let repeated = rpassword::prompt_password_stderr("Repeat password: ").map_err(|e| e.to_string())?;
+        if repeated != password {
+            return Err("Passwords don't match".to_string());
+        }
+    }
+    Ok(password)
+}
+
+/// Seed from a hex string, or from an Fs or Es address for FCT and EC keys
+fn parse_secret(kind: KeyKind, secret: &str) -> Result<[u8; 32], String> {
+    let hex_secret = secret.trim_start_matches("0x");
+    if hex_secret.len() == 64 {
+        if let Ok(bytes) = hex::decode(hex_secret) {
+            let mut seed = [0u8; 32];
+            seed.copy_from_slice(&bytes);
+            return Ok(seed);
+        }
+    }
+
+    let address_kind = match kind {
+        KeyKind::Fct => AddressKind::FactoidSecret,
+        KeyKind::Ec => AddressKind::EntryCreditSecret,
+        KeyKind::Authority | KeyKind::Network => {
+            return Err(format!("Expected a hex encoded 32 byte seed for a {} key", kind))
+        }
+    };
+    let address = Address::parse_kind(secret, address_kind).map_err(|e| e.to_string())?;
+    Ok(*address.key())
+}
+
+pub(crate) fn public_key(kind: KeyKind, seed: &[u8; 32]) -> [u8; 32] {
+    match kind {
+        KeyKind::Authority | KeyKind::Network => ed25519::Pair::from_seed(seed).public().0,
+        KeyKind::Fct | KeyKind::Ec => sr25519::Pair::from_seed(seed).public().0,
+    }
+}
+
+fn stored_public(key: &StoredKey) -> Result<[u8; 32], String> {
+    let bytes = hex::decode(&key.public).map_err(|e| e.to_string())?;
+    if bytes.len() != 32 {
+        return Err(format!("Key {} has an invalid public key", key.name));
+    }
+    let mut public = [0u8; 32];
+    public.copy_from_slice(&bytes);
+    Ok(public)
+}
+
+/// The address a key is best known by
+fn address(key: &StoredKey) -> Result<String, String> {
+    let public = stored_public(key)?;
+    Ok(match key.kind {
+        KeyKind::Authority => ed25519::Public(public).to_ss58check(),
+        KeyKind::Network => format!("0x{}", key.public),
+        KeyKind::Fct => Address::new(AddressKind::FactoidPublic, public).to_string(),
+        KeyKind::Ec => Address::new(AddressKind::EntryCreditPublic, public).to_string(),
+    })
+}
+
+/// Print the public forms of a key
+///
+/// With the seed, FCT and EC keys also show their legacy address. The secret is
+/// only printed when asked for.
+fn print_key(key: &StoredKey, seed: Option<&[u8; 32]>, show_secret: bool) {
+    let public = match stored_public(key) {
+        Ok(public) => public,
+        Err(e) => return eprintln!("{}", e),
+    };
+    println!("Name:            {}", key.name);
+    println!("Kind:            {}", key.kind);
+    println!("Public key:      0x{}", key.public);
+
+    match key.kind {
+        KeyKind::Authority => println!("SS58 address:    {}", ed25519::Public(public).to_ss58check()),
+        KeyKind::Network => {}
+        KeyKind::Fct | KeyKind::Ec => {
+            let (public_kind, secret_kind) = if key.kind == KeyKind::Fct {
+                (AddressKind::FactoidPublic, AddressKind::FactoidSecret)
+            } else {
+                (AddressKind::EntryCreditPublic, AddressKind::EntryCreditSecret)
+            };
+            println!("SS58 address:    {}", sr25519::Public(public).to_ss58check());
+            println!("Address:         {}", Address::new(public_kind, public));
+            if let Some(seed) = seed {
+                let legacy_public = ed25519::Pair::from_seed(seed).public().0;
+                let legacy = if key.kind == KeyKind::Fct {
+                    Address::factoid(&legacy_public)
+                } else {
+                    Address::entry_credit(&legacy_public)
+                };
+                println!("Legacy address:  {}", legacy);
+                if show_secret {
+                    println!("Legacy secret:   {}", Address::new(secret_kind, *seed));
+                }
+            }
+        }
+    }
+    if let (Some(seed), true) = (seed, show_secret) {
+        println!("Secret seed:     0x{}", hex::encode(seed));
+    }
+}
@@ -0,0 +1,570 @@
+//! # Legacy Factom Import
+//!
+//! Read directory blocks, entry blocks and entries exported from the legacy Factom
+//! network in their binary marshalled form, check them against each other and
+//! replay the entries in their original order. Entries are decoded with
+//! `factomd_runtime::legacy_entry`.
+//!
+//! A dump is a directory with one file per block or entry:
+//!
+//! ```text
+//! <dump>/dblocks/  directory blocks
+//! <dump>/eblocks/  entry blocks
+//! <dump>/entries/  entries
+//! ```
+//!
+//! File names don't matter, blocks and entries are identified by their hashes.
+//! Every directory block must link to the one before it, every entry block it
+//! lists must be present and every entry those list must be present. Chain ids
+//! and entry hashes are kept as they are, so they stay valid after the migration.
+//!
+//! The dump is streamed: entries are read one at a time as they are replayed.
+//! A small dump may go into genesis. A large one is submitted to the running
+//! network in batches, which root appends with `import_legacy_entries`.
+//!
+use factomd_client::blocking::Client;
+use factomd_client::Signer;
+use factomd_runtime::legacy_entry::LegacyEntry;
+use factomd_runtime::{Call, EntryCall, EntryStruct, Hash, SudoCall};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub type Hash32 = [u8; 32];
+
+/// Admin, entry credit and factoid block chains, which hold no entries
+const SYSTEM_CHAINS: [u8; 3] = [0x0a, 0x0c, 0x0f];
+
+/// Size of a directory block header
+const DBLOCK_HEADER_SIZE: usize = 113;
+
+/// Size of an entry block header
+const EBLOCK_HEADER_SIZE: usize = 140;
+
+/// Largest combined size of the entries of a batch, which must fit in a block
+const BATCH_SIZE: usize = 256 * 1024;
+
+/// How long a submitted batch may take to be imported
+const BATCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often the import of a batch is checked for
+const BATCH_POLL: Duration = Duration::from_secs(1);
+
+/// An entry block: the entries added to one chain in one directory block
+pub struct EntryBlock {
+    pub chain_id: Hash32,
+    pub prev_key_mr: Hash32,
+    pub db_height: u32,
+    /// Entry hashes in order, without the minute markers
+    pub entry_hashes: Vec<Hash32>,
+    pub key_mr: Hash32,
+}
+
+impl EntryBlock {
+    /// Unmarshal an entry block and check its body merkle root
+    pub fn unmarshal(data: &[u8]) -> Result<Self, String> {
+        if data.len() < EBLOCK_HEADER_SIZE {
+            return Err("Entry block shorter than its header".to_string());
+        }
+        let header = &data[..EBLOCK_HEADER_SIZE];
+        let count = read_u32(&header[136..140]) as usize;
+        let body = &data[EBLOCK_HEADER_SIZE..];
+        if body.len() != count * 32 {
+            return Err("Entry block body doesn't match its entry count".to_string());
+        }
+
+        let body_hashes: Vec<Hash32> = body.chunks(32).map(read_hash).collect();
+        let body_mr = read_hash(&header[32..64]);
+        if merkle_root(&body_hashes) != body_mr {
+            return Err("Entry block body merkle root mismatch".to_string());
+        }
+
+        Ok(EntryBlock {
+            chain_id: read_hash(&header[..32]),
+            prev_key_mr: read_hash(&header[64..96]),
+            db_height: read_u32(&header[132..136]),
+            entry_hashes: body_hashes
+                .into_iter()
+                .filter(|h| !is_minute_marker(h))
+                .collect(),
+            key_mr: key_mr(header, &body_mr),
+        })
+    }
+}
+
+/// A directory block: the entry blocks of all chains at one height
+pub struct DirectoryBlock {
+    pub prev_key_mr: Hash32,
+    pub height: u32,
+    /// Chain ids and key merkle roots of the entry blocks
+    pub eblocks: Vec<(Hash32, Hash32)>,
+    pub key_mr: Hash32,
+}
+
+impl DirectoryBlock {
+    /// Unmarshal a directory block and check its body merkle root
+    pub fn unmarshal(data: &[u8]) -> Result<Self, String> {
+        if data.len() < DBLOCK_HEADER_SIZE {
+            return Err("Directory block shorter than its header".to_string());
+        }
+        let header = &data[..DBLOCK_HEADER_SIZE];
+        let count = read_u32(&header[109..113]) as usize;
+        let body = &data[DBLOCK_HEADER_SIZE..];
+        if body.len() != count * 64 {
+            return Err("Directory block body doesn't match its block count".to_string());
+        }
+
+        let leaves: Vec<Hash32> = body.chunks(64).map(sha256).collect();
+        let body_mr = read_hash(&header[5..37]);
+        if merkle_root(&leaves) != body_mr {
+            return Err("Directory block body merkle root mismatch".to_string());
+        }
+
+        Ok(DirectoryBlock {
+            prev_key_mr: read_hash(&header[37..69]),
+            height: read_u32(&header[105..109]),
+            eblocks: body
+                .chunks(64)
+                .map(|pair| (read_hash(&pair[..32]), read_hash(&pair[32..])))
+                .collect(),
+            key_mr: key_mr(header, &body_mr),
+        })
+    }
+}
+
+/// A legacy dump, indexed by block height and hash
+///
+/// Only the paths of its files are kept. Blocks and entries are read again as
+/// they are replayed, so a dump of any size is replayed in little memory.
+pub struct LegacyDump {
+    /// Directory block files, by height
+    dblocks: Vec<PathBuf>,
+    /// Entry block files, by key merkle root
+    eblocks: HashMap<Hash32, PathBuf>,
+    /// Entry files, by entry hash
+    entries: HashMap<Hash32, PathBuf>,
+}
+
+/// What a replayed dump holds
+pub struct DumpReport {
+    pub directory_blocks: usize,
+    pub chains: usize,
+    pub entries: usize,
+}
+
+impl LegacyDump {
+    /// Index a dump directory
+    ///
+    /// Every file is read once to find its height or hash. Whether the blocks and
+    /// entries fit together is only checked by `replay`.
+    pub fn open(dir: &str) -> Result<Self, String> {
+        let dir = Path::new(dir);
+
+        let mut heights = BTreeMap::new();
+        for_each_file(&dir.join("dblocks"), |path, data| {
+            heights.insert(DirectoryBlock::unmarshal(&data)?.height, path);
+            Ok(())
+        })?;
+        let mut dblocks = Vec::with_capacity(heights.len());
+        for (expected_height, (height, path)) in heights.into_iter().enumerate() {
+            if height as usize != expected_height {
+                return Err(format!("Missing directory block {}", expected_height));
+            }
+            dblocks.push(path);
+        }
+
+        let mut eblocks = HashMap::new();
+        for_each_file(&dir.join("eblocks"), |path, data| {
+            eblocks.insert(EntryBlock::unmarshal(&data)?.key_mr, path);
+            Ok(())
+        })?;
+
+        let mut entries = HashMap::new();
+        for_each_file(&dir.join("entries"), |path, data| {
+            entries.insert(LegacyEntry::hash(&data), path);
+            Ok(())
+        })?;
+
+        Ok(LegacyDump { dblocks, eblocks, entries })
+    }
+
+    /// Validate the dump and pass its entries with their entry hashes to `f`, in
+    /// the order they were added
+    ///
+    /// Every directory block must link to the one before it, every entry block it
+    /// lists must be present and every entry those list must be present. Entries
+    /// before the first error have already been passed on, so a dump should be
+    /// replayed once without doing anything before it is imported.
+    pub fn replay<F>(&self, mut f: F) -> Result<DumpReport, String>
+    where
+        F: FnMut(Hash32, LegacyEntry) -> Result<(), String>,
+    {
+        let mut chain_heads: HashMap<Hash32, Hash32> = HashMap::new();
+        let mut prev_dblock = [0u8; 32];
+        let mut entries = 0;
+
+        for path in &self.dblocks {
+            let dblock = DirectoryBlock::unmarshal(&read_file(path)?)?;
+            if dblock.prev_key_mr != prev_dblock {
+                return Err(format!("Directory block {} doesn't link to the previous one", dblock.height));
+            }
+            prev_dblock = dblock.key_mr;
+
+            for (chain_id, eblock_key_mr) in &dblock.eblocks {
+                if is_system_chain(chain_id) {
+                    continue;
+                }
+                let eblock = self
+                    .eblocks
+                    .get(eblock_key_mr)
+                    .ok_or_else(|| format!("Missing entry block {}", hex(eblock_key_mr)))
+                    .and_then(|path| EntryBlock::unmarshal(&read_file(path)?))?;
+                if eblock.chain_id != *chain_id || eblock.db_height != dblock.height {
+                    return Err(format!("Entry block {} doesn't match its directory block", hex(eblock_key_mr)));
+                }
+
+                let chain_head = chain_heads.entry(*chain_id).or_insert([0u8; 32]);
+                if eblock.prev_key_mr != *chain_head {
+                    return Err(format!("Entry block {} doesn't link to the previous one", hex(eblock_key_mr)));
+                }
+                *chain_head = eblock.key_mr;
+
+                for entry_hash in &eblock.entry_hashes {
+                    let data = self
+                        .entries
+                        .get(entry_hash)
+                        .ok_or_else(|| format!("Missing entry {}", hex(entry_hash)))
+                        .and_then(|path| read_file(path))?;
+                    let entry = LegacyEntry::decode(&data)
+                        .map_err(|e| format!("Entry {}: {}", hex(entry_hash), e))?;
+                    if entry.chain_id != *chain_id {
+                        return Err(format!("Entry {} is not in chain {}", hex(entry_hash), hex(chain_id)));
+                    }
+                    f(*entry_hash, entry)?;
+                    entries += 1;
+                }
+            }
+        }
+
+        Ok(DumpReport {
+            directory_blocks: self.dblocks.len(),
+            chains: chain_heads.len(),
+            entries,
+        })
+    }
+}
+
+impl fmt::Display for DumpReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Directory blocks: {}", self.directory_blocks)?;
+        writeln!(f, "Chains: {}", self.chains)?;
+        write!(f, "Entries: {}", self.entries)
+    }
+}
+
+/// Submit the entries of a dump to a running network, in batches signed by its
+/// sudo key
+///
+/// Entries the network already holds, from genesis or an earlier run, are
+/// skipped. Each batch is waited for before the next one is submitted, so the
+/// transaction pool holds one batch at a time and a rejected batch stops the
+/// import rather than the ones after it.
+pub fn submit(dump: &LegacyDump, client: &Client, sudo: &Signer) -> Result<u64, String> {
+    let progress = client.legacy_import().map_err(|e| e.to_string())?;
+    if progress.complete {
+        return Err("Legacy entries are already imported".to_string());
+    }
+
+    let mut position = 0;
+    let mut first = progress.imported;
+    let mut batch = Vec::new();
+    let mut batch_size = 0;
+    dump.replay(|entry_hash, entry| {
+        position += 1;
+        if position <= progress.imported {
+            return Ok(());
+        }
+        let entry = EntryStruct::from_legacy(&entry).map_err(|e| e.to_string())?;
+        let size = entry.content.len() + entry.external_ids.len();
+        if !batch.is_empty() && batch_size + size > BATCH_SIZE {
+            first = submit_batch(client, sudo, first, batch.split_off(0), false)?;
+            batch_size = 0;
+        }
+        batch.push((Hash::from(entry_hash), entry));
+        batch_size += size;
+        Ok(())
+    })?;
+    // The last batch completes the import, even if it is empty
+    submit_batch(client, sudo, first, batch, true)
+}
+
+/// Submit one batch and wait for it to be imported, giving the position of the next one
+fn submit_batch(
+    client: &Client,
+    sudo: &Signer,
+    first: u64,
+    batch: Vec<(Hash, EntryStruct<Hash>)>,
+    complete: bool,
+) -> Result<u64, String> {
+    let next = first + batch.len() as u64;
+    let call = Call::Entries(EntryCall::import_legacy_entries(first, batch, complete));
+    client
+        .submit(sudo, Call::Sudo(SudoCall::sudo(Box::new(call))))
+        .map_err(|e| e.to_string())?;
+
+    // Sudo doesn't fail for a failed call, the import shows in storage or not at all
+    let deadline = Instant::now() + BATCH_TIMEOUT;
+    loop {
+        let progress = client.legacy_import().map_err(|e| e.to_string())?;
+        if progress.imported >= next && progress.complete == complete {
+            eprintln!("Imported {} entries", next);
+            return Ok(next);
+        }
+        if Instant::now() > deadline {
+            return Err(format!("Batch of entries {} to {} was not imported", first, next));
+        }
+        thread::sleep(BATCH_POLL);
+    }
+}
+
+/// Pass the path and contents of every file in a directory to `f`, one at a time
+fn for_each_file<F>(dir: &Path, mut f: F) -> Result<(), String>
+where
+    F: FnMut(PathBuf, Vec<u8>) -> Result<(), String>,
+{
+    let error = |e| format!("Failed to read {}: {}", dir.display(), e);
+    for file in fs::read_dir(dir).map_err(error)? {
+        let path = file.map_err(error)?.path();
+        if path.is_file() {
+            let data = read_file(&path)?;
+            f(path, data)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+/// Key merkle root of a block: hash of the header hash followed by the body merkle root
+fn key_mr(header: &[u8], body_mr: &Hash32) -> Hash32 {
+    let mut data = sha256(header).to_vec();
+    data.extend_from_slice(body_mr);
+    sha256(&data)
+}
+
+/// Merkle root as built by legacy factomd
+///
+/// An odd node is paired with itself. The root of no hashes is all zeros.
+fn merkle_root(hashes: &[Hash32]) -> Hash32 {
+    if hashes.is_empty() {
+        return [0u8; 32];
+    }
+    let mut level = hashes.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let right = pair.get(1).unwrap_or(&pair[0]);
+                let mut data = pair[0].to_vec();
+                data.extend_from_slice(right);
+                sha256(&data)
+            })
+            .collect();
+    }
+    level[0]
+}
+
+/// Minute markers are all zeros but for the minute, 1 to 10, in the last byte
+fn is_minute_marker(hash: &Hash32) -> bool {
+    hash[..31].iter().all(|b| *b == 0) && hash[31] >= 1 && hash[31] <= 10
+}
+
+fn is_system_chain(chain_id: &Hash32) -> bool {
+    chain_id[..31].iter().all(|b| *b == 0) && SYSTEM_CHAINS.contains(&chain_id[31])
+}
+
+fn sha256(data: &[u8]) -> Hash32 {
+    read_hash(&Sha256::digest(data))
+}
+
+fn read_hash(data: &[u8]) -> Hash32 {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(data);
+    hash
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    data.iter().fold(0, |acc, b| acc << 8 | u32::from(*b))
+}
+
+fn hex(hash: &Hash32) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The first entry of a new chain, encoded
+    fn chain_entry() -> Vec<u8> {
+        let external_ids = vec![b"legacy".to_vec(), b"chain".to_vec()];
+        LegacyEntry {
+            chain_id: LegacyEntry::chain_id_of(&external_ids),
+            external_ids,
+            content: b"hello".to_vec(),
+        }
+        .encode()
+        .unwrap()
+    }
+
+    fn system_chain(last: u8) -> Hash32 {
+        let mut chain_id = [0u8; 32];
+        chain_id[31] = last;
+        chain_id
+    }
+
+    fn minute(n: u8) -> Hash32 {
+        system_chain(n)
+    }
+
+    /// Marshal an entry block as legacy factomd does
+    fn eblock(chain_id: &Hash32, prev_key_mr: &Hash32, db_height: u32, body: &[Hash32]) -> Vec<u8> {
+        let mut data = chain_id.to_vec();
+        data.extend_from_slice(&merkle_root(body));
+        data.extend_from_slice(prev_key_mr);
+        // Previous full hash and sequence number, not read
+        data.extend_from_slice(&[0u8; 32]);
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(&db_height.to_be_bytes());
+        data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        for hash in body {
+            data.extend_from_slice(hash);
+        }
+        data
+    }
+
+    /// Marshal a directory block as legacy factomd does
+    fn dblock(prev_key_mr: &Hash32, height: u32, eblocks: &[(Hash32, Hash32)]) -> Vec<u8> {
+        let body: Vec<u8> = eblocks
+            .iter()
+            .flat_map(|(chain_id, key_mr)| chain_id.iter().chain(key_mr.iter()).cloned())
+            .collect();
+        let leaves: Vec<Hash32> = body.chunks(64).map(sha256).collect();
+        // Version and mainnet network id
+        let mut data = vec![0x00, 0xfa, 0x92, 0xe5, 0xa2];
+        data.extend_from_slice(&merkle_root(&leaves));
+        data.extend_from_slice(prev_key_mr);
+        // Previous full hash and timestamp, not read
+        data.extend_from_slice(&[0u8; 32]);
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&(eblocks.len() as u32).to_be_bytes());
+        data.extend_from_slice(&body);
+        data
+    }
+
+    fn write_dump(name: &str, dblocks: &[&[u8]], eblocks: &[&[u8]], entries: &[&[u8]]) -> String {
+        let dir = std::env::temp_dir().join(format!("factomd-legacy-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (sub, files) in &[("dblocks", dblocks), ("eblocks", eblocks), ("entries", entries)] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+            for (n, data) in files.iter().enumerate() {
+                fs::write(dir.join(sub).join(n.to_string()), data).unwrap();
+            }
+        }
+        dir.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        let (a, b, c) = ([1u8; 32], [2u8; 32], [3u8; 32]);
+        let pair = |left: &Hash32, right: &Hash32| {
+            let mut data = left.to_vec();
+            data.extend_from_slice(right);
+            sha256(&data)
+        };
+        assert_eq!(merkle_root(&[]), [0u8; 32]);
+        assert_eq!(merkle_root(&[a]), a);
+        assert_eq!(merkle_root(&[a, b]), pair(&a, &b));
+        // The odd hash is paired with itself
+        assert_eq!(merkle_root(&[a, b, c]), pair(&pair(&a, &b), &pair(&c, &c)));
+    }
+
+    #[test]
+    fn test_unmarshal_blocks() {
+        let entry = chain_entry();
+        let chain_id = LegacyEntry::decode(&entry).unwrap().chain_id;
+        let entry_hash = LegacyEntry::hash(&entry);
+        let data = eblock(&chain_id, &[0u8; 32], 0, &[entry_hash, minute(1)]);
+        let eb = EntryBlock::unmarshal(&data).unwrap();
+        assert_eq!(eb.chain_id, chain_id);
+        assert_eq!(eb.entry_hashes, vec![entry_hash]);
+        let body_mr = merkle_root(&[entry_hash, minute(1)]);
+        let mut key_data = sha256(&data[..EBLOCK_HEADER_SIZE]).to_vec();
+        key_data.extend_from_slice(&body_mr);
+        assert_eq!(eb.key_mr, sha256(&key_data));
+
+        let data = dblock(&[0u8; 32], 0, &[(system_chain(0x0a), [1u8; 32]), (chain_id, eb.key_mr)]);
+        let db = DirectoryBlock::unmarshal(&data).unwrap();
+        assert_eq!(db.height, 0);
+        assert_eq!(db.eblocks, vec![(system_chain(0x0a), [1u8; 32]), (chain_id, eb.key_mr)]);
+
+        let mut corrupt = data.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        assert!(DirectoryBlock::unmarshal(&corrupt).is_err());
+        assert!(DirectoryBlock::unmarshal(&data[..DBLOCK_HEADER_SIZE - 1]).is_err());
+        assert!(EntryBlock::unmarshal(&data[..EBLOCK_HEADER_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn test_replay_dump() {
+        let entry = chain_entry();
+        let chain_id = LegacyEntry::decode(&entry).unwrap().chain_id;
+        let entry_hash = LegacyEntry::hash(&entry);
+        let eb = eblock(&chain_id, &[0u8; 32], 1, &[entry_hash, minute(10)]);
+        let eb_key_mr = EntryBlock::unmarshal(&eb).unwrap().key_mr;
+        let db0 = dblock(&[0u8; 32], 0, &[(system_chain(0x0c), [2u8; 32])]);
+        let db0_key_mr = DirectoryBlock::unmarshal(&db0).unwrap().key_mr;
+        let db1 = dblock(&db0_key_mr, 1, &[(system_chain(0x0f), [3u8; 32]), (chain_id, eb_key_mr)]);
+
+        let dir = write_dump("replay", &[&db1, &db0], &[&eb], &[&entry]);
+        let mut entries = Vec::new();
+        let report = LegacyDump::open(&dir)
+            .unwrap()
+            .replay(|entry_hash, entry| {
+                entries.push((entry_hash, entry));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(report.directory_blocks, 2);
+        assert_eq!(report.chains, 1);
+        assert_eq!(report.entries, 1);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, entry_hash);
+        assert_eq!(entries[0].1.chain_id, chain_id);
+
+        // The first directory block is missing
+        let dir = write_dump("gap", &[&db1], &[&eb], &[&entry]);
+        assert_eq!(LegacyDump::open(&dir).err(), Some("Missing directory block 0".to_string()));
+
+        let dir = write_dump("no-entry", &[&db0, &db1], &[&eb], &[]);
+        assert_eq!(
+            LegacyDump::open(&dir).unwrap().replay(|_, _| Ok(())).err(),
+            Some(format!("Missing entry {}", hex(&entry_hash)))
+        );
+
+        // Replaying stops at the first error of the callback
+        let dir = write_dump("stop", &[&db0, &db1], &[&eb], &[&entry]);
+        assert_eq!(
+            LegacyDump::open(&dir).unwrap().replay(|_, _| Err("stop".to_string())).err(),
+            Some("stop".to_string())
+        );
+    }
+}
@@ -2,28 +2,51 @@
 //!
 //! Start a new node based on provided configuration.
 //!
-//! This will start the HTTP RPC API if enabled. It will set up a
+//! This will start the HTTP RPC API and its WebSocket subscriptions if enabled,
+//! track the acks of submissions, export events to the configured sinks and
+//! keep the entry index.
+//! It will set up a
 //! logger for the program to use, and start a new Substrate service
 //! and runtime.
 //!
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate prometheus;
+#[macro_use]
 extern crate slog;
 extern crate slog_async;
 extern crate slog_term;
 
 use core::str::FromStr;
-use factomd_configuration::{FactomConfig, Log, LogLevel, Role};
+use factomd_configuration::{Command, FactomConfig, ImportLegacy, Log, LogLevel, Role};
+use factomd_rpc::{
+    AckTracker, NodeHealth, PendingPool, SharedAcks, SharedHealth, SharedIndex, SharedPending, SharedSubmitter,
+    SharedSubscriptions, Subscriptions,
+};
 use futures::{future, sync::oneshot, Future};
 use slog::Drain;
 use slog::Logger;
 use std::cell::RefCell;
 use std::ops::Deref;
-use std::sync::Mutex;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
 pub use substrate_cli::{error, informant, parse_and_execute, IntoExit, NoCustom, VersionInfo};
 use substrate_service::ServiceFactory;
 use tokio::runtime::Runtime;
 
+mod acks;
 mod chain_spec;
+mod events;
+mod export;
+mod health;
+mod indexer;
+mod keys;
+mod legacy;
+mod localnet;
+mod metrics;
+mod snapshot;
+mod walletd;
 mod wrapper;
 
 /// Build a Logger for the Factom Daemon
@@ -40,23 +63,70 @@ fn make_logger(log_config: &Log) -> Logger {
 }
 
 /// Start the API server
-fn start_rpc_server(log_option: Option<Logger>, config: &FactomConfig) {
-    match log_option {
-        Some(log) => {
-            // Start HTTP RPC if enabled
-            if !config.rpc.disable_rpc {
-                info!(log, "HTTP RPC server enabled"; "addr" => &config.rpc.rpc_addr, "port" => &config.rpc.rpc_port);
-                factomd_rpc::start_rpc_server(&config.rpc.rpc_addr, config.rpc.rpc_port);
-            } else {
-                info!(log, "HTTP RPC Server disabled");
-            }
-        }
-        None => {
-            if !config.rpc.disable_rpc {
-                factomd_rpc::start_rpc_server(&config.rpc.rpc_addr, config.rpc.rpc_port);
-            }
+///
+/// Returns the server handle if enabled; the server stops when it is dropped.
+fn start_rpc_server(
+    log_option: &Option<Logger>,
+    config: &FactomConfig,
+    health: SharedHealth,
+    acks: Option<SharedAcks>,
+    pending: Option<SharedPending>,
+    index: Option<SharedIndex>,
+    submitter: Option<SharedSubmitter>,
+) -> Result<Option<factomd_rpc::Server>, String> {
+    if config.rpc.disable_rpc {
+        if let Some(log) = log_option {
+            info!(log, "HTTP RPC Server disabled");
         }
+        return Ok(None);
     }
+    if let Some(log) = log_option {
+        info!(log, "HTTP RPC server enabled"; "addr" => &config.rpc.rpc_addr, "port" => &config.rpc.rpc_port);
+    }
+    factomd_rpc::start_rpc_server(
+        &config.rpc.rpc_addr,
+        config.rpc.rpc_port,
+        health,
+        acks,
+        pending,
+        index,
+        submitter,
+    )
+    .map(Some)
+}
+
+/// Start the WebSocket server for subscriptions, along with the API server
+fn start_ws_server(
+    log_option: &Option<Logger>,
+    config: &FactomConfig,
+    subscriptions: SharedSubscriptions,
+) -> Result<Option<factomd_rpc::WsServer>, String> {
+    if config.rpc.disable_rpc {
+        return Ok(None);
+    }
+    if let Some(log) = log_option {
+        info!(log, "WebSocket server enabled"; "addr" => &config.rpc.rpc_addr, "port" => &config.rpc.ws_port);
+    }
+    factomd_rpc::start_ws_server(&config.rpc.rpc_addr, config.rpc.ws_port, subscriptions).map(Some)
+}
+
+/// Open the entry index behind the search methods, if enabled
+///
+/// Only full nodes keep one, light nodes don't have the state of blocks.
+fn open_index(
+    log_option: &Option<Logger>,
+    config: &FactomConfig,
+) -> Result<Option<Arc<indexer::Indexer>>, String> {
+    if !config.index.enable_index {
+        return Ok(None);
+    }
+    if config.server.role == Role::LIGHT {
+        return Err("The entry index needs a full node, light nodes don't keep block state".to_string());
+    }
+    if let Some(log) = log_option {
+        info!(log, "Entry index enabled"; "path" => &config.index.index_path);
+    }
+    indexer::Indexer::open(&config.index.index_path).map(|index| Some(Arc::new(index)))
 }
 
 /// # Create Substrate-specific args
@@ -85,63 +155,183 @@ fn start_rpc_server(log_option: Option<Logger>, config: &FactomConfig) {
 /// a vector for use as args by substrate-cli. Those settings include:
 ///
 /// * Chain/Network to join
-/// * Node key for the node to use when in validator role
+/// * Node and authority keys for the node to use when in validator role, see `keys`
 /// * Set Validator mode when in validator role
-fn create_substrate_args(factom_config: &FactomConfig) -> Vec<String> {
-    let node_key;
+/// * Database pruning and state cache
+/// * Peers, reserved nodes, listen addresses and telemetry
+///
+/// The settings were validated when the configuration was loaded.
+fn create_substrate_args(factom_config: &FactomConfig) -> Result<Vec<String>, String> {
     let mut args = Vec::new();
     args.push("_".to_string());
 
     if factom_config.server.role == Role::AUTHORITY {
-        node_key = std::env::var(&factom_config.server.node_key_env)
-            .unwrap_or_else(|_| panic!("Failed to find node key"));
+        check_authority_topology(&factom_config.server)?;
         args.push("--validator".to_string());
-        args.push("--node-key".to_string());
-        args.push(node_key);
+        args.extend(keys::authority_args(factom_config)?);
+    } else if !factom_config.server.sentry_nodes.is_empty() {
+        return Err("sentry_nodes is only for authority nodes".to_string());
     }
-
-    if !factom_config.server.base_path.is_empty() {
-        args.push("--base-path".to_string());
-        args.push(factom_config.server.base_path.to_string());
+    if factom_config.server.role != Role::FULL && !factom_config.server.sentry_for.is_empty() {
+        return Err("Only full nodes can be sentries, sentry_for is set".to_string());
     }
 
     args.push("--bootnodes".to_string());
     args.push(factom_config.server.bootnodes.to_string());
 
-    args.push("--port".to_string());
-    args.push(factom_config.server.port.to_string());
+    let server = &factom_config.server;
+    // Substrate listens on every interface at port unless given addresses
+    if server.listen_addresses.is_empty() {
+        args.push("--port".to_string());
+        args.push(server.port.to_string());
+    }
+    for addr in &server.listen_addresses {
+        args.push("--listen-addr".to_string());
+        args.push(addr.to_string());
+    }
+
+    args.push("--pruning".to_string());
+    args.push(server.pruning.to_string());
+    args.push("--state-cache-size".to_string());
+    args.push(server.state_cache_size.to_string());
+
+    args.push("--in-peers".to_string());
+    args.push(server.in_peers.to_string());
+    args.push("--out-peers".to_string());
+    args.push(server.out_peers.to_string());
+    // Sentries stay connected to their authorities, authorities to their sentries only
+    let reserved = server
+        .reserved_nodes
+        .iter()
+        .chain(&server.sentry_nodes)
+        .chain(&server.sentry_for);
+    for node in reserved {
+        args.push("--reserved-nodes".to_string());
+        args.push(node.to_string());
+    }
+    if server.reserved_only || !server.sentry_nodes.is_empty() {
+        args.push("--reserved-only".to_string());
+    }
+
+    for url in &server.telemetry_urls {
+        args.push("--telemetry-url".to_string());
+        args.push(format!("{} {}", url, server.telemetry_verbosity));
+    }
+    if server.no_telemetry {
+        args.push("--no-telemetry".to_string());
+    }
+
+    args.extend(chain_args(factom_config));
+    Ok(args)
+}
 
+/// Check that an authority behind sentries can't be reached by anyone else
+///
+/// It must listen on given, presumably private, addresses and have no bootnodes;
+/// its sentries are its only peers.
+fn check_authority_topology(server: &factomd_configuration::Server) -> Result<(), String> {
+    if server.sentry_nodes.is_empty() {
+        return Ok(());
+    }
+    if !server.bootnodes.is_empty() {
+        return Err("An authority with sentry_nodes connects only to them, remove its bootnodes".to_string());
+    }
+    if server.listen_addresses.is_empty() {
+        return Err(
+            "An authority with sentry_nodes must listen on private listen_addresses".to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Substrate args selecting the chain and its database
+///
+/// Shared by running a node and the database maintenance subcommands.
+fn chain_args(factom_config: &FactomConfig) -> Vec<String> {
+    let mut args = Vec::new();
+    if !factom_config.server.base_path.is_empty() {
+        args.push("--base-path".to_string());
+        args.push(factom_config.server.base_path.to_string());
+    }
     args.push("--chain=".to_string() + &factom_config.server.network);
     args
 }
 
+fn version_info() -> VersionInfo {
+    VersionInfo {
+        name: "Substrate Node",
+        commit: env!("VERGEN_SHA_SHORT"),
+        version: env!("CARGO_PKG_VERSION"),
+        executable_name: "factomd",
+        author: "tom",
+        description: "factomd node",
+        support_url: "https://github.com/ThomasMeier/factom-rewrite",
+    }
+}
+
 /// Start new node
+///
+/// If a subcommand was given, run it instead and return.
 pub fn run(factom_config: FactomConfig) -> Result<(), substrate_cli::error::Error> {
+    if let Some(command) = &factom_config.command {
+        return run_command(command, &factom_config);
+    }
+
     // Build logger except if variant is OFF.
     let log_option = match &factom_config.log.log_level {
         LogLevel::SILENT => None,
         _ => Some(make_logger(&factom_config.log)),
     };
 
-    start_rpc_server(log_option, &factom_config);
+    // A development node runs alone, elsewhere having no peers is unhealthy.
+    let health: SharedHealth = Arc::new(RwLock::new(NodeHealth::starting(
+        factom_config.server.role == Role::AUTHORITY,
+        factom_config.server.network != "dev",
+    )));
+    // Light nodes don't see the transaction pool or block bodies
+    let (acks, pending) = match factom_config.server.role {
+        Role::LIGHT => (None, None),
+        _ => (Some(AckTracker::new()), Some(PendingPool::new())),
+    };
+    let submitter = acks.clone().map(acks::PoolSubmitter::new);
+    let index = open_index(&log_option, &factom_config)?;
+    let _rpc_server = start_rpc_server(
+        &log_option,
+        &factom_config,
+        health.clone(),
+        acks.clone(),
+        pending.clone(),
+        index.clone().map(|index| index as SharedIndex),
+        submitter.clone().map(|submitter| submitter as SharedSubmitter),
+    )?;
+    let subscriptions = Subscriptions::new();
+    let _ws_server = start_ws_server(&log_option, &factom_config, subscriptions.clone())?;
+    let exporter = export::Exporter::new(&factom_config.export, log_option.clone())?;
+    if exporter.is_some() && factom_config.server.role == Role::LIGHT {
+        return Err("Event export needs a full node, light nodes don't keep block state".to_string().into());
+    }
+    if let (Some(log), Some(exporter)) = (&log_option, &exporter) {
+        info!(log, "Event export enabled"; "sinks" => exporter.sinks().join(", "));
+    }
     let runtime = Runtime::new().map_err(|e| format!("{:?}", e))?;
     let executor = runtime.executor();
 
-    let version = VersionInfo {
-        name: "Substrate Node",
-        commit: env!("VERGEN_SHA_SHORT"),
-        version: env!("CARGO_PKG_VERSION"),
-        executable_name: "factomd",
-        author: "tom",
-        description: "factomd node",
-        support_url: "https://github.com/ThomasMeier/factom-rewrite",
-    };
+    let enable_metrics = factom_config.metrics.enable_metrics;
+    if enable_metrics {
+        let metrics = &factom_config.metrics;
+        if let Some(log) = &log_option {
+            info!(log, "Metrics endpoint enabled"; "addr" => &metrics.metrics_addr, "port" => &metrics.metrics_port);
+        }
+        executor.spawn(metrics::serve(&metrics.metrics_addr, metrics.metrics_port, log_option.clone())?);
+    }
+
+    let version = version_info();
 
     // Empty vector to pass to substrate factory as a placeholder.
-    let substrate_args = create_substrate_args(&factom_config);
+    let substrate_args = create_substrate_args(&factom_config)?;
 
     parse_and_execute::<wrapper::Factory, NoCustom, NoCustom, _, _, _, _, _>(
-        load_spec,
+        |id| load_spec(&factom_config, id),
         &version,
         "factom-node",
         substrate_args,
@@ -153,12 +343,30 @@ pub fn run(factom_config: FactomConfig) -> Result<(), substrate_cli::error::Erro
                     wrapper::Factory::new_light(config, executor)
                         .map_err(|e| format!("{:?}", e))?,
                     exit,
+                    enable_metrics,
+                    health,
                 ),
-                _ => run_until_exit(
-                    runtime,
-                    wrapper::Factory::new_full(config, executor).map_err(|e| format!("{:?}", e))?,
-                    exit,
-                ),
+                _ => {
+                    let service = wrapper::Factory::new_full(config, executor.clone())
+                        .map_err(|e| format!("{:?}", e))?;
+                    if enable_metrics {
+                        metrics::track_entries(service.client(), service.on_exit(), &executor);
+                    }
+                    events::start(service.client(), subscriptions, service.on_exit(), &executor);
+                    if let (Some(acks), Some(pending)) = (acks, pending) {
+                        acks::start(&service, acks, pending, &executor);
+                    }
+                    if let Some(submitter) = submitter {
+                        submitter.attach(&service);
+                    }
+                    if let Some(exporter) = exporter {
+                        export::start(&service, health.clone(), exporter, &executor);
+                    }
+                    if let Some(index) = index {
+                        indexer::start(service.client(), index, log_option.clone(), service.on_exit(), &executor);
+                    }
+                    run_until_exit(runtime, service, exit, enable_metrics, health)
+                }
             }
             .map_err(|e| format!("{:?}", e))
         },
@@ -167,14 +375,150 @@ pub fn run(factom_config: FactomConfig) -> Result<(), substrate_cli::error::Erro
     .map(|_| ())
 }
 
-fn load_spec(id: &str) -> Result<Option<chain_spec::ChainSpec>, String> {
+/// Run a factomd subcommand
+fn run_command(command: &Command, factom_config: &FactomConfig) -> error::Result<()> {
+    match command {
+        Command::BuildSpec(cmd) => build_spec(factom_config, cmd.raw, &cmd.output),
+        Command::CheckSnapshot(cmd) => {
+            let snapshot = snapshot::Snapshot::load(&cmd.file)?;
+            println!("{}", snapshot.report());
+            Ok(())
+        }
+        Command::ImportLegacy(cmd) => import_legacy(factom_config, cmd),
+        Command::Key(cmd) => keys::run(&factom_config.keystore, cmd).map_err(Into::into),
+        Command::PurgeChain(cmd) => {
+            let mut args = Vec::new();
+            if cmd.yes {
+                args.push("-y".to_string());
+            }
+            run_maintenance(factom_config, "purge-chain", args)
+        }
+        Command::ExportBlocks(cmd) => {
+            let mut args = Vec::new();
+            if let Some(from) = cmd.from {
+                args.push("--from".to_string());
+                args.push(from.to_string());
+            }
+            if let Some(to) = cmd.to {
+                args.push("--to".to_string());
+                args.push(to.to_string());
+            }
+            if cmd.json {
+                args.push("--json".to_string());
+            }
+            if !cmd.output.is_empty() {
+                args.push(cmd.output.to_string());
+            }
+            run_maintenance(factom_config, "export-blocks", args)
+        }
+        Command::ImportBlocks(cmd) => {
+            let mut args = Vec::new();
+            if !cmd.input.is_empty() {
+                args.push(cmd.input.to_string());
+            }
+            run_maintenance(factom_config, "import-blocks", args)
+        }
+        Command::Revert(cmd) => run_maintenance(factom_config, "revert", vec![cmd.blocks.to_string()]),
+        Command::Localnet(cmd) => localnet::run(cmd).map_err(Into::into),
+        Command::Walletd => walletd::run(&factom_config.walletd).map_err(Into::into),
+    }
+}
+
+/// Run one of substrate's database maintenance subcommands
+///
+/// The chain and database are those of the configured network and base path,
+/// the same a node started with this configuration would use.
+fn run_maintenance(
+    factom_config: &FactomConfig,
+    subcommand: &str,
+    subcommand_args: Vec<String>,
+) -> error::Result<()> {
+    let mut args = vec!["_".to_string(), subcommand.to_string()];
+    args.extend(chain_args(factom_config));
+    args.extend(subcommand_args);
+
+    parse_and_execute::<wrapper::Factory, NoCustom, NoCustom, _, _, _, _, _>(
+        |id| load_spec(factom_config, id),
+        &version_info(),
+        "factom-node",
+        args,
+        Exit,
+        |_exit, _custom_args, _config| Err(format!("{} did not run", subcommand)),
+    )
+    .map(|_| ())
+}
+
+/// Write the chain spec of the configured network as JSON
+///
+/// The raw form holds genesis storage as key/value pairs. It is what nodes
+/// should share when joining a network, as it no longer depends on the
+/// runtime code that built it.
+fn build_spec(factom_config: &FactomConfig, raw: bool, output: &str) -> error::Result<()> {
+    let spec = load_spec(factom_config, &factom_config.server.network)?
+        .ok_or_else(|| format!("Unknown network {}", factom_config.server.network))?;
+    write_spec(&spec, raw, output)
+}
+
+/// Validate a legacy dump and import its entries
+///
+/// Without a node, writes a genesis chain spec holding the entries, the rest of
+/// genesis coming from the genesis section of the configuration. Nodes join the
+/// resulting network with the written spec rather than the dump. With a node,
+/// submits the entries to its running network in batches signed by the sudo key,
+/// for dumps too large for genesis.
+fn import_legacy(factom_config: &FactomConfig, cmd: &ImportLegacy) -> error::Result<()> {
+    let dump = legacy::LegacyDump::open(&cmd.dump)?;
+    // Checked in full before anything is written or submitted
+    eprintln!("{}", dump.replay(|_, _| Ok(()))?);
+
+    if cmd.node.is_empty() {
+        let spec = chain_spec::from_genesis_config(&factom_config.genesis, Some(&dump))?;
+        return write_spec(&spec, cmd.raw, &cmd.output);
+    }
+    if cmd.key.is_empty() {
+        return Err("Batches are signed by the sudo key, name it with --key".to_string().into());
+    }
+    let client = factomd_client::blocking::Client::new(factomd_client::DEFAULT_LEGACY_URL, &cmd.node)
+        .map_err(|e| e.to_string())?;
+    let sudo = factomd_client::Signer::from_seed(keys::account_seed(&factom_config.keystore, &cmd.key)?);
+    legacy::submit(&dump, &client, &sudo)?;
+    Ok(())
+}
+
+fn write_spec(spec: &chain_spec::ChainSpec, raw: bool, output: &str) -> error::Result<()> {
+    let json = spec.to_json(raw)?;
+
+    if output.is_empty() {
+        println!("{}", json);
+    } else {
+        std::fs::write(output, json)?;
+    }
+    Ok(())
+}
+
+/// Load a chain spec
+///
+/// `id` is either one of the built in alternatives, `genesis` to build the spec
+/// from the genesis section of the configuration, or a path to a chain spec
+/// JSON file, like one written by `build-spec`.
+fn load_spec(
+    factom_config: &FactomConfig,
+    id: &str,
+) -> Result<Option<chain_spec::ChainSpec>, String> {
     Ok(match chain_spec::Alternative::from(id) {
         Some(spec) => Some(spec.load()?),
-        None => None,
+        None if id == "genesis" => Some(chain_spec::from_genesis_config(&factom_config.genesis, None)?),
+        None => Some(chain_spec::ChainSpec::from_json_file(PathBuf::from(id))?),
     })
 }
 
-fn run_until_exit<T, C, E>(mut runtime: Runtime, service: T, e: E) -> error::Result<()>
+fn run_until_exit<T, C, E>(
+    mut runtime: Runtime,
+    service: T,
+    e: E,
+    enable_metrics: bool,
+    health: SharedHealth,
+) -> error::Result<()>
 where
     T: Deref<Target = substrate_service::Service<C>>,
     C: substrate_service::Components,
@@ -184,6 +528,10 @@ where
 
     let executor = runtime.executor();
     informant::start(&service, exit.clone(), executor.clone());
+    health::start(&service, health, exit.clone(), executor.clone());
+    if enable_metrics {
+        metrics::start(&service, exit.clone(), executor.clone());
+    }
 
     let _ = runtime.block_on(e.into_exit());
     exit_send.fire();
@@ -0,0 +1,303 @@
+//! # Local Network
+//!
+//! `factomd localnet` starts a network of authority nodes on this machine, each a
+//! child process of this one with its own keystore, database and ports. Genesis
+//! makes every node an authority and endows the usual test accounts.
+//!
+//! The first node is the bootnode of the others. Its peer id is only known once it
+//! has started, so it is read from its log. Logs go to a file per node, and the
+//! RPC endpoints are printed once every node is up. WebSocket ports count up
+//! from the one after the last RPC port.
+//!
+//! A given directory keeps its keys and chains between runs, a temporary one is
+//! removed on exit.
+//!
+use crate::keys::public_key;
+use factomd_configuration::Localnet;
+use factomd_keystore::{random_seed, KeyKind, Keystore};
+use primitives::{crypto::Ss58Codec, ed25519};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Variable the nodes read the password of their keystore from
+const PASSWORD_ENV: &str = "FACTOMD_LOCALNET_PASSWORD";
+/// Password of the generated keystores, which only hold throwaway keys
+const PASSWORD: &str = "localnet";
+/// How long a node may take to report its peer id
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+/// Accounts endowed with FCT and EC at genesis
+const ENDOWED: [&str; 4] = ["//Alice", "//Bob", "//Charlie", "//Dave"];
+const ENDOWMENT: u64 = 1_000_000_000_000_000;
+
+/// A running node, killed when dropped
+struct Node {
+    child: Child,
+    p2p_port: u16,
+    rpc_port: u16,
+    ws_port: u16,
+    log: PathBuf,
+    peer_id: String,
+}
+
+impl Node {
+    fn multiaddr(&self) -> String {
+        format!("/ip4/127.0.0.1/tcp/{}/p2p/{}", self.p2p_port, self.peer_id)
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Start the nodes and keep them running until Ctrl-C or one of them exits
+pub fn run(cmd: &Localnet) -> Result<(), String> {
+    if cmd.nodes == 0 {
+        return Err("A local network needs at least one node".to_string());
+    }
+    let last_port = |base: u16| base.checked_add(cmd.nodes - 1);
+    let ws_ports = last_port(cmd.base_rpc_port).and_then(|port| port.checked_add(1));
+    if last_port(cmd.base_p2p_port).is_none() || ws_ports.and_then(last_port).is_none() {
+        return Err(format!("Not enough ports above the base ports for {} nodes", cmd.nodes));
+    }
+
+    let temporary = cmd.dir.is_empty();
+    let dir = if temporary {
+        std::env::temp_dir().join(format!("factomd-localnet-{}", std::process::id()))
+    } else {
+        PathBuf::from(&cmd.dir)
+    };
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let result = run_in(cmd, &dir);
+    if temporary {
+        let _ = fs::remove_dir_all(&dir);
+    }
+    result
+}
+
+fn run_in(cmd: &Localnet, dir: &Path) -> Result<(), String> {
+    let authorities = (0..cmd.nodes)
+        .map(|index| node_keys(&node_dir(dir, index)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Set up before the nodes start, so Ctrl-C during startup still stops them
+    let (stop, stopped) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = stop.send(());
+    })
+    .map_err(|e| format!("Error setting Ctrl-C handler: {}", e))?;
+
+    let base_ws_port = cmd.base_rpc_port + cmd.nodes;
+    let mut nodes: Vec<Node> = Vec::new();
+    for index in 0..cmd.nodes {
+        let bootnodes = nodes.first().map(Node::multiaddr).unwrap_or_default();
+        let (p2p_port, rpc_port, ws_port) = (
+            cmd.base_p2p_port + index,
+            cmd.base_rpc_port + index,
+            base_ws_port + index,
+        );
+        let config = write_config(dir, index, p2p_port, rpc_port, ws_port, &bootnodes, &authorities)?;
+        nodes.push(start(&config, p2p_port, rpc_port, ws_port, dir, index)?);
+    }
+
+    println!("Local network of {} authorities in {}", nodes.len(), dir.display());
+    for (index, node) in nodes.iter().enumerate() {
+        println!(
+            "node{:<3} rpc http://127.0.0.1:{}  ws ws://127.0.0.1:{}  p2p {}  log {}",
+            index,
+            node.rpc_port,
+            node.ws_port,
+            node.multiaddr(),
+            node.log.display()
+        );
+    }
+    println!("Press Ctrl-C to stop");
+    let _ = io::stdout().flush();
+
+    loop {
+        match stopped.recv_timeout(Duration::from_secs(1)) {
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            _ => return Ok(()),
+        }
+        for (index, node) in nodes.iter_mut().enumerate() {
+            if let Ok(Some(status)) = node.child.try_wait() {
+                return Err(format!(
+                    "node{} exited with {}, see {}",
+                    index,
+                    status,
+                    node.log.display()
+                ));
+            }
+        }
+    }
+}
+
+fn node_dir(dir: &Path, index: u16) -> PathBuf {
+    dir.join(format!("node{}", index))
+}
+
+/// Authority and network keys of a node, generated unless already there
+///
+/// Returns the SS58 address of the authority key.
+fn node_keys(node_dir: &Path) -> Result<String, String> {
+    let keystore = Keystore::open(node_dir.join("keystore")).map_err(|e| e.to_string())?;
+    let mut authority = None;
+    for &kind in &[KeyKind::Authority, KeyKind::Network] {
+        let public = match keystore.first(kind).map_err(|e| e.to_string())? {
+            Some(stored) => stored.public,
+            None => {
+                let seed = random_seed().map_err(|e| e.to_string())?;
+                keystore
+                    .insert(&kind.to_string(), kind, &public_key(kind, &seed), &seed, PASSWORD)
+                    .map_err(|e| e.to_string())?
+                    .public
+            }
+        };
+        if kind == KeyKind::Authority {
+            authority = Some(public);
+        }
+    }
+
+    let bytes = hex::decode(authority.expect("authority key handled above; qed")).map_err(|e| e.to_string())?;
+    if bytes.len() != 32 {
+        return Err(format!("Invalid authority key in {}", node_dir.display()));
+    }
+    let mut public = [0u8; 32];
+    public.copy_from_slice(&bytes);
+    Ok(ed25519::Public(public).to_ss58check())
+}
+
+/// Configuration of one node, every node sharing the same genesis
+fn write_config(
+    dir: &Path,
+    index: u16,
+    p2p_port: u16,
+    rpc_port: u16,
+    ws_port: u16,
+    bootnodes: &str,
+    authorities: &[String],
+) -> Result<PathBuf, String> {
+    let quoted = |values: &[String]| {
+        values
+            .iter()
+            .map(|value| format!("\"{}\"", value))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let balances = ENDOWED
+        .iter()
+        .map(|account| format!("{{ account: \"{}\", amount: {} }}", account, ENDOWMENT))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let node_dir = node_dir(dir, index);
+    let config = format!(
+        r#"server:
+  network: "genesis"
+  role: "authority"
+  port: {p2p_port}
+  bootnodes: "{bootnodes}"
+  base_path: "{base_path}"
+rpc:
+  rpc_port: {rpc_port}
+  ws_port: {ws_port}
+keystore:
+  keystore_path: "{keystore}"
+  keystore_password_env: "{password_env}"
+genesis:
+  name: "Local Network"
+  id: "localnet"
+  authorities: [{authorities}]
+  sudo_key: "//Alice"
+  fct_balances: [{balances}]
+  ec_balances: [{balances}]
+"#,
+        p2p_port = p2p_port,
+        bootnodes = bootnodes,
+        base_path = node_dir.join("chain").display(),
+        rpc_port = rpc_port,
+        ws_port = ws_port,
+        keystore = node_dir.join("keystore").display(),
+        password_env = PASSWORD_ENV,
+        authorities = quoted(authorities),
+        balances = balances,
+    );
+
+    let path = node_dir.join("config.yml");
+    fs::write(&path, config).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// Start a node and wait for it to log its peer id
+fn start(config: &Path, p2p_port: u16, rpc_port: u16, ws_port: u16, dir: &Path, index: u16) -> Result<Node, String> {
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    let log = dir.join(format!("node{}.log", index));
+    let log_file = File::create(&log).map_err(|e| format!("Failed to create {}: {}", log.display(), e))?;
+    let log_file = Arc::new(Mutex::new(log_file));
+
+    let mut child = Command::new(exe)
+        .arg("--config")
+        .arg(config)
+        .env(PASSWORD_ENV, PASSWORD)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start node{}: {}", index, e))?;
+
+    let (sender, receiver) = mpsc::channel();
+    let stdout = child.stdout.take().expect("stdout is piped; qed");
+    let stderr = child.stderr.take().expect("stderr is piped; qed");
+    copy_log(stdout, log_file.clone(), None);
+    copy_log(stderr, log_file, Some(sender));
+
+    let peer_id = receiver.recv_timeout(START_TIMEOUT);
+    let mut node = Node {
+        child,
+        p2p_port,
+        rpc_port,
+        ws_port,
+        log,
+        peer_id: String::new(),
+    };
+    match peer_id {
+        Ok(peer_id) => {
+            node.peer_id = peer_id;
+            Ok(node)
+        }
+        Err(_) => Err(format!(
+            "node{} didn't report its peer id, see {}",
+            index,
+            node.log.display()
+        )),
+    }
+}
+
+/// Copy the output of a node to its log, reporting the peer id it logs
+///
+/// Keeps draining so the node never blocks on a full pipe.
+fn copy_log<R>(output: R, log: Arc<Mutex<File>>, peer_id: Option<mpsc::Sender<String>>)
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        for line in BufReader::new(output).lines().map_while(Result::ok) {
+            if let Some(sender) = &peer_id {
+                if let Some(index) = line.find("Local node identity is: ") {
+                    let _ = sender.send(line[index + 24..].trim().to_string());
+                }
+            }
+            if let Ok(mut log) = log.lock() {
+                let _ = writeln!(log, "{}", line);
+            }
+        }
+    });
+}
@@ -0,0 +1,194 @@
+//! # Node Metrics
+//!
+//! Prometheus metrics served over HTTP at `/metrics` when `enable_metrics` is set.
+//!
+//! Chain and network figures are refreshed on every network status update. Entry
+//! figures are counted from the events of each new best block, which only a full
+//! node can read. The totals only take in blocks once they are confirmed, so that
+//! blocks dropped by a reorganization aren't counted. RPC metrics are recorded by `factomd-rpc` into the same
+//! registry, and events dropped by the event export are counted per sink.
+//!
+use crate::events::{block_events, confirmed_height, BlockActivity};
+use crate::wrapper::Factory;
+use factomd_runtime::{opaque::Block, BlockStatsApi};
+use futures::{Future, Stream};
+use hyper::header::CONTENT_TYPE;
+use hyper::service::service_fn_ok;
+use hyper::{Body, Request, Response, Server, StatusCode};
+use prometheus::{Encoder, Histogram, IntCounter, IntCounterVec, IntGauge, TextEncoder};
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::{As, ProvideRuntimeApi};
+use slog::Logger;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use substrate_service::{Components, FullClient, Service, TaskExecutor};
+
+lazy_static! {
+    static ref BLOCK_HEIGHT: IntGauge = register_int_gauge!(
+        "factomd_block_height",
+        "Height of the best block"
+    )
+    .expect("Block height gauge is registered once; qed");
+    static ref FINALIZED_HEIGHT: IntGauge = register_int_gauge!(
+        "factomd_finalized_height",
+        "Height of the last finalized block"
+    )
+    .expect("Finalized height gauge is registered once; qed");
+    static ref PEERS: IntGauge = register_int_gauge!(
+        "factomd_peers",
+        "Number of connected peers"
+    )
+    .expect("Peer gauge is registered once; qed");
+    static ref POOL_READY: IntGauge = register_int_gauge!(
+        "factomd_tx_pool_ready",
+        "Transactions in the pool ready to be included"
+    )
+    .expect("Ready pool gauge is registered once; qed");
+    static ref POOL_FUTURE: IntGauge = register_int_gauge!(
+        "factomd_tx_pool_future",
+        "Transactions in the pool waiting on others"
+    )
+    .expect("Future pool gauge is registered once; qed");
+    static ref BLOCK_ENTRIES: IntGauge = register_int_gauge!(
+        "factomd_block_entries",
+        "Entries written by the best block"
+    )
+    .expect("Block entries gauge is registered once; qed");
+    static ref BLOCK_CHAINS: IntGauge = register_int_gauge!(
+        "factomd_block_chains",
+        "Chains started by the best block"
+    )
+    .expect("Block chains gauge is registered once; qed");
+    static ref ENTRIES: IntCounter = register_int_counter!(
+        "factomd_entries_total",
+        "Entries written by confirmed blocks"
+    )
+    .expect("Entry counter is registered once; qed");
+    static ref CHAINS: IntCounter = register_int_counter!(
+        "factomd_chains_total",
+        "Chains started by confirmed blocks"
+    )
+    .expect("Chain counter is registered once; qed");
+    static ref EC_BURNED: IntCounter = register_int_counter!(
+        "factomd_ec_burned_total",
+        "Entry credits spent on entries and chains by confirmed blocks"
+    )
+    .expect("Burned entry credit counter is registered once; qed");
+    static ref IMPORT_DELAY: Histogram = register_histogram!(
+        "factomd_block_import_delay_seconds",
+        "Time from a block's timestamp until this node imported it",
+        vec![0.5, 1.0, 2.0, 5.0, 10.0, 30.0, 60.0, 300.0]
+    )
+    .expect("Import delay histogram is registered once; qed");
+    pub static ref EXPORT_DROPPED: IntCounterVec = register_int_counter_vec!(
+        "factomd_export_dropped_total",
+        "Events an export sink fell too far behind to be given",
+        &["sink"]
+    )
+    .expect("Dropped export counter is registered once with valid labels; qed");
+}
+
+/// HTTP server answering `/metrics`
+pub fn serve(addr: &str, port: u16, log: Option<Logger>) -> Result<impl Future<Item = (), Error = ()>, String> {
+    let socket: SocketAddr = format!("{}:{}", addr, port)
+        .parse()
+        .map_err(|e| format!("Invalid metrics address {}:{}: {}", addr, port, e))?;
+    let server = Server::try_bind(&socket)
+        .map_err(|e| format!("Failed to start metrics endpoint on {}: {}", socket, e))?
+        .serve(|| service_fn_ok(respond))
+        .map_err(move |e| {
+            if let Some(log) = &log {
+                error!(log, "Metrics endpoint failed"; "error" => %e);
+            }
+        });
+    Ok(server)
+}
+
+fn respond(request: Request<Body>) -> Response<Body> {
+    if request.uri().path() != "/metrics" {
+        let mut response = Response::new(Body::from("Not found"));
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        let mut response = Response::new(Body::from(e.to_string()));
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        return response;
+    }
+    let mut response = Response::new(Body::from(buffer));
+    if let Ok(content_type) = encoder.format_type().parse() {
+        response.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+    response
+}
+
+/// Follow the chain, peers and transaction pool of a node
+pub fn start<C>(service: &Service<C>, exit: exit_future::Exit, executor: TaskExecutor)
+where
+    C: Components,
+{
+    let client = service.client();
+    let txpool = service.transaction_pool();
+
+    let status = service.network().status().for_each(move |status| {
+        PEERS.set(status.num_peers as i64);
+        if let Ok(info) = client.info() {
+            BLOCK_HEIGHT.set(info.chain.best_number.as_() as i64);
+            FINALIZED_HEIGHT.set(info.chain.finalized_number.as_() as i64);
+        }
+        let pool = txpool.status();
+        POOL_READY.set(pool.ready as i64);
+        POOL_FUTURE.set(pool.future as i64);
+        Ok(())
+    });
+
+    executor.spawn(exit.until(status).map(|_| ()));
+}
+
+/// Follow the entries written by each new best block of a full node
+///
+/// The block gauges follow the best block, the totals follow confirmed blocks,
+/// see `confirmed_height`.
+pub fn track_entries(
+    client: Arc<FullClient<Factory>>,
+    exit: exit_future::Exit,
+    executor: &TaskExecutor,
+) {
+    let mut counted = confirmed_height(&client);
+    let imports = client
+        .import_notification_stream()
+        .filter(|notification| notification.is_new_best)
+        .for_each(move |notification| {
+            let api = client.runtime_api();
+            let at = BlockId::<Block>::hash(notification.hash);
+
+            let activity = BlockActivity::of(&block_events(&client, notification.hash));
+            BLOCK_ENTRIES.set(i64::from(activity.entries));
+            BLOCK_CHAINS.set(i64::from(activity.chains));
+
+            // Blocks may be confirmed several at a time
+            let confirmed = confirmed_height(&client);
+            for height in counted + 1..=confirmed {
+                if let Ok(Some(hash)) = client.block_hash(height) {
+                    let activity = BlockActivity::of(&block_events(&client, hash));
+                    ENTRIES.inc_by(i64::from(activity.entries));
+                    CHAINS.inc_by(i64::from(activity.chains));
+                    EC_BURNED.inc_by(activity.ec_spent as i64);
+                }
+            }
+            counted = counted.max(confirmed);
+            if let (Ok(timestamp), Ok(now)) =
+                (api.timestamp(&at), SystemTime::now().duration_since(UNIX_EPOCH))
+            {
+                let now = now.as_secs() as f64 + f64::from(now.subsec_millis()) / 1000.0;
+                IMPORT_DELAY.observe((now - timestamp as f64).max(0.0));
+            }
+            Ok(())
+        });
+
+    executor.spawn(exit.until(imports).map(|_| ()));
+}
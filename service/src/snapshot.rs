@@ -0,0 +1,312 @@
+//! # Legacy Balance Snapshot
+//!
+//! Carry FCT and EC balances over from the legacy Factom network into genesis.
+//!
+//! A snapshot lists legacy addresses and their balances, either as CSV with
+//! `address,amount[,account]` rows or as a JSON array of objects with the same
+//! fields. Amounts are in the legacy base units (factoshis for FCT).
+//!
+//! Legacy keys are ed25519 while accounts on this chain are sr25519, so a legacy
+//! balance can't simply be assigned to the legacy key. If the holder has given a
+//! new `account`, the balance is endowed to it directly. Otherwise the balance
+//! becomes a claimable record, keyed by the RCD hash of an FA address or the
+//! public key of an EC address, for the holder to claim with their legacy key.
+//!
+use crate::chain_spec::parse_account;
+use factomd_address::{Address, AddressKind};
+use factomd_runtime::AccountId;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Type of balance held by a legacy address
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BalanceKind {
+    Factoid,
+    EntryCredit,
+}
+
+/// A legacy balance that has to be claimed with the legacy key
+#[derive(Clone, Debug)]
+pub struct LegacyClaim {
+    pub kind: BalanceKind,
+    /// RCD hash of a factoid address or public key of an entry credit address
+    pub key: [u8; 32],
+    pub amount: u128,
+}
+
+/// One row of a snapshot file
+#[derive(Deserialize)]
+struct SnapshotRow {
+    address: String,
+    amount: u64,
+    #[serde(default)]
+    account: Option<String>,
+}
+
+/// Legacy balances converted for genesis
+pub struct Snapshot {
+    pub fct_balances: Vec<(AccountId, u128)>,
+    pub ec_balances: Vec<(AccountId, u128)>,
+    pub claims: Vec<LegacyClaim>,
+}
+
+/// Balances and claims added up as the rows of a snapshot are read
+#[derive(Default)]
+struct Totals {
+    fct_balances: BTreeMap<AccountId, u128>,
+    ec_balances: BTreeMap<AccountId, u128>,
+    /// Claimable amounts by balance type and legacy key
+    claims: BTreeMap<(BalanceKind, [u8; 32]), u128>,
+}
+
+impl Snapshot {
+    /// Load a snapshot from a `.json` or `.csv` file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let rows = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&contents)
+                .map_err(|e| format!("Invalid snapshot {}: {}", path, e))?,
+            Some("csv") => parse_csv(&contents)?,
+            _ => return Err(format!("Snapshot {} must be a .json or .csv file", path)),
+        };
+        Snapshot::from_rows(rows)
+    }
+
+    /// Convert the rows of a snapshot, adding up the rows of the same address
+    fn from_rows(rows: Vec<SnapshotRow>) -> Result<Self, String> {
+        let mut totals = Totals::default();
+        for row in rows {
+            totals.add(row)?;
+        }
+        Ok(Snapshot {
+            fct_balances: totals.fct_balances.into_iter().collect(),
+            ec_balances: totals.ec_balances.into_iter().collect(),
+            claims: totals
+                .claims
+                .into_iter()
+                .map(|((kind, key), amount)| LegacyClaim { kind, key, amount })
+                .collect(),
+        })
+    }
+
+    /// Totals of the converted balances
+    pub fn report(&self) -> SnapshotReport {
+        let sum = |balances: &[(AccountId, u128)]| -> u128 { balances.iter().map(|(_, b)| b).sum() };
+        let claimable = |kind: BalanceKind| -> u128 {
+            self.claims
+                .iter()
+                .filter(|c| c.kind == kind)
+                .map(|c| c.amount)
+                .sum()
+        };
+        SnapshotReport {
+            fct_accounts: self.fct_balances.len(),
+            fct_endowed: sum(&self.fct_balances),
+            fct_claimable: claimable(BalanceKind::Factoid),
+            ec_accounts: self.ec_balances.len(),
+            ec_endowed: sum(&self.ec_balances),
+            ec_claimable: claimable(BalanceKind::EntryCredit),
+            claims: self.claims.len(),
+        }
+    }
+}
+
+impl Totals {
+    fn add(&mut self, row: SnapshotRow) -> Result<(), String> {
+        let (kind, key) = decode_address(&row.address)?;
+        let amount = u128::from(row.amount);
+
+        match row.account {
+            Some(account) => {
+                let account = parse_account(&account)?;
+                let balances = match kind {
+                    BalanceKind::Factoid => &mut self.fct_balances,
+                    BalanceKind::EntryCredit => &mut self.ec_balances,
+                };
+                add_balance(balances, account, amount);
+            }
+            None => *self.claims.entry((kind, key)).or_insert(0) += amount,
+        }
+        Ok(())
+    }
+}
+
+/// Supply carried over by a snapshot
+pub struct SnapshotReport {
+    pub fct_accounts: usize,
+    pub fct_endowed: u128,
+    pub fct_claimable: u128,
+    pub ec_accounts: usize,
+    pub ec_endowed: u128,
+    pub ec_claimable: u128,
+    pub claims: usize,
+}
+
+impl fmt::Display for SnapshotReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "FCT: {} endowed to {} accounts, {} claimable, {} total",
+            self.fct_endowed,
+            self.fct_accounts,
+            self.fct_claimable,
+            self.fct_endowed + self.fct_claimable
+        )?;
+        writeln!(
+            f,
+            "EC: {} endowed to {} accounts, {} claimable, {} total",
+            self.ec_endowed,
+            self.ec_accounts,
+            self.ec_claimable,
+            self.ec_endowed + self.ec_claimable
+        )?;
+        write!(f, "Claimable records: {}", self.claims)
+    }
+}
+
+/// Add to an account's balance, keeping one record per account
+pub fn add_balance(balances: &mut BTreeMap<AccountId, u128>, account: AccountId, amount: u128) {
+    *balances.entry(account).or_insert(0) += amount;
+}
+
+/// Parse `address,amount[,account]` rows, skipping blank lines, `#` comments and a header
+fn parse_csv(contents: &str) -> Result<Vec<SnapshotRow>, String> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .filter(|(_, line)| !line.starts_with("address"))
+        .map(|(n, line)| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            match fields.as_slice() {
+                [address, amount] | [address, amount, ""] => Ok((address, amount, None)),
+                [address, amount, account] => Ok((address, amount, Some(account.to_string()))),
+                _ => Err(format!("Line {}: expected address,amount[,account]", n)),
+            }
+            .and_then(|(address, amount, account)| {
+                Ok(SnapshotRow {
+                    address: address.to_string(),
+                    amount: amount
+                        .parse()
+                        .map_err(|_| format!("Line {}: invalid amount {}", n, amount))?,
+                    account,
+                })
+            })
+        })
+        .collect()
+}
+
+/// Decode a public FA or EC address into its 32 byte key
+fn decode_address(address: &str) -> Result<(BalanceKind, [u8; 32]), String> {
+    let invalid = |e| format!("{} {}", e, address);
+    let decoded: Address = address.parse().map_err(invalid)?;
+    let kind = match decoded.kind() {
+        AddressKind::FactoidPublic => BalanceKind::Factoid,
+        AddressKind::EntryCreditPublic => BalanceKind::EntryCredit,
+        _ => return Err(format!("Not a public address: {}", address)),
+    };
+    Ok((kind, *decoded.key()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCAL_FA: &str = "FA2jK2HcLnRdS94dEcU27rF3meoJfpUcZPSinpb7AwQvPRY6RL1Q";
+    const ZERO_FA: &str = "FA1zT4aFpEvcnPqPCigB3fvGu4Q4mTXY22iiuV69DqE1pNhdF2MC";
+    const ZERO_EC: &str = "EC2DKSYyRcNWf7RS963VFYgMExoHRYLHVeCfQ9PGPmNzwrcmgm2r";
+    const ZERO_FS: &str = "Fs1KWJrpLdfucvmYwN2nWrwepLn8ercpMbzXshd1g8zyhKXLVLWj";
+
+    fn from_csv(contents: &str) -> Result<Snapshot, String> {
+        Snapshot::from_rows(parse_csv(contents)?)
+    }
+
+    #[test]
+    fn test_fixture() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../configuration/tests/snapshot.csv");
+        let report = Snapshot::load(path).unwrap().report();
+        assert_eq!(report.fct_accounts, 1);
+        assert_eq!(report.fct_endowed, 250_000_000);
+        assert_eq!(report.fct_claimable, 1_000_000_005);
+        assert_eq!(report.ec_accounts, 0);
+        assert_eq!(report.ec_endowed, 0);
+        assert_eq!(report.ec_claimable, 500);
+        assert_eq!(report.claims, 2);
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let snapshot = from_csv(&format!(
+            "{fa},10\n{fa},5\n{ec},7\n{ec},3\n{fa},1,//Alice\n{local},2,//Alice",
+            fa = ZERO_FA,
+            ec = ZERO_EC,
+            local = LOCAL_FA,
+        ))
+        .unwrap();
+
+        // The FA and EC address of the same key are claimed separately
+        assert_eq!(snapshot.claims.len(), 2);
+        assert_eq!(snapshot.claims[0].kind, BalanceKind::Factoid);
+        assert_eq!(snapshot.claims[0].amount, 15);
+        assert_eq!(snapshot.claims[1].kind, BalanceKind::EntryCredit);
+        assert_eq!(snapshot.claims[1].amount, 10);
+
+        assert_eq!(snapshot.fct_balances.len(), 1);
+        assert_eq!(snapshot.fct_balances[0].1, 3);
+    }
+
+    #[test]
+    fn test_bad_address() {
+        // Last character changed
+        let bad_checksum = "FA1zT4aFpEvcnPqPCigB3fvGu4Q4mTXY22iiuV69DqE1pNhdF2MD";
+        assert!(from_csv(&format!("{},10", bad_checksum)).is_err());
+        assert!(from_csv("FA1zT4aF,10").is_err());
+        assert_eq!(
+            from_csv(&format!("{},10", ZERO_FS)).err(),
+            Some(format!("Not a public address: {}", ZERO_FS))
+        );
+        assert!(from_csv(&format!("{},10,not an account", ZERO_FA)).is_err());
+    }
+
+    #[test]
+    fn test_bad_amount() {
+        assert_eq!(
+            from_csv(&format!("address,amount\n{},ten", ZERO_FA)).err(),
+            Some("Line 2: invalid amount ten".to_string())
+        );
+        assert!(from_csv(&format!("{},-1", ZERO_FA)).is_err());
+        assert!(from_csv(&format!("{},18446744073709551616", ZERO_FA)).is_err());
+        assert_eq!(
+            from_csv(ZERO_FA).err(),
+            Some("Line 1: expected address,amount[,account]".to_string())
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let snapshot = from_csv(&format!(
+            "{},10\n{},20,//Alice\n{},30\n{},40,//Bob",
+            ZERO_FA, LOCAL_FA, ZERO_EC, ZERO_EC
+        ))
+        .unwrap();
+        let report = snapshot.report();
+        assert_eq!(report.fct_accounts, 1);
+        assert_eq!(report.fct_endowed, 20);
+        assert_eq!(report.fct_claimable, 10);
+        assert_eq!(report.ec_accounts, 1);
+        assert_eq!(report.ec_endowed, 40);
+        assert_eq!(report.ec_claimable, 30);
+        assert_eq!(report.claims, 2);
+        assert_eq!(
+            report.to_string(),
+            "FCT: 20 endowed to 1 accounts, 10 claimable, 30 total\n\
+             EC: 40 endowed to 1 accounts, 30 claimable, 70 total\n\
+             Claimable records: 2"
+        );
+    }
+}
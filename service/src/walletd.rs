@@ -0,0 +1,57 @@
+//! # Walletd
+//!
+//! `factomd walletd` serves the wallet at `wallet_path` on the walletd port until
+//! Ctrl-C. The walletd password, from the variable named by `walletd_env_var` or
+//! prompted for, unlocks the wallet. With `walletd_user` set, clients must also
+//! authenticate with that user and the same password.
+//!
+//! With `walletd_mnemonic_env` set, the wallet is restored from the 12 word
+//! mnemonic in that variable, such as the words of a legacy wallet.
+//!
+use crate::{Exit, IntoExit};
+use factomd_configuration::Walletd;
+use factomd_walletd::{Credentials, Wallet};
+use futures::Future;
+
+/// Serve the wallet until Ctrl-C
+pub fn run(config: &Walletd) -> Result<(), String> {
+    let password = password(config)?;
+    let wallet = if config.walletd_mnemonic_env.is_empty() {
+        Wallet::open(&config.wallet_path, &password)
+    } else {
+        let env = &config.walletd_mnemonic_env;
+        let mnemonic = std::env::var(env).map_err(|_| format!("Failed to find wallet mnemonic in {}", env))?;
+        Wallet::restore(&config.wallet_path, &password, &mnemonic)
+    }
+    .map_err(|e| e.to_string())?;
+    let credentials = if config.walletd_user.is_empty() {
+        None
+    } else {
+        Some(Credentials {
+            user: config.walletd_user.clone(),
+            password,
+        })
+    };
+
+    let _server = factomd_walletd::start_walletd_server(
+        &config.walletd_addr,
+        config.walletd_port,
+        wallet,
+        credentials,
+        &config.walletd_node_url,
+    )?;
+    println!(
+        "Walletd serving {} on {}:{}",
+        config.wallet_path, config.walletd_addr, config.walletd_port
+    );
+    let _ = Exit.into_exit().wait();
+    Ok(())
+}
+
+fn password(config: &Walletd) -> Result<String, String> {
+    let env = &config.walletd_env_var;
+    if env.is_empty() {
+        return rpassword::prompt_password_stderr("Wallet password: ").map_err(|e| e.to_string());
+    }
+    std::env::var(env).map_err(|_| format!("Failed to find walletd password in {}", env))
+}
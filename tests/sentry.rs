@@ -0,0 +1,227 @@
+//! Local network of an authority behind a sentry
+//!
+//! The authority only connects to its sentry. A public full node that only knows
+//! the sentry must still get the authority's blocks, while the authority never
+//! gets a second peer.
+//!
+//! Starts real nodes and takes a minute or two, so `cargo test` skips it. CI runs
+//! it in its own `sentry` job, the same way as locally:
+//! `cargo build && cargo test --test sentry -- --ignored`
+use assert_cmd::prelude::*;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PASSWORD_ENV: &str = "FACTOMD_SENTRY_TEST_PASSWORD";
+const AUTHORITY_SEED: &str = "0x0101010101010101010101010101010101010101010101010101010101010101";
+const NETWORK_SEED: &str = "0x0202020202020202020202020202020202020202020202020202020202020202";
+
+/// How long the nodes get to author blocks and settle their peers
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A port nothing listens on right now
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .expect("No free port")
+}
+
+/// Poll until `done` holds, failing with `what` after the timeout
+fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !done() {
+        assert!(Instant::now() < deadline, "{}", what);
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// A running node, killed when dropped
+struct Node {
+    child: Child,
+    rpc_port: u16,
+    peer_id: String,
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn factomd(config: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("factomd").unwrap();
+    cmd.arg("--config")
+        .arg(config)
+        .env(PASSWORD_ENV, "sentry test");
+    cmd
+}
+
+/// Configuration of one node, sharing genesis and keystore with the others
+fn write_config(dir: &Path, name: &str, authority: &str, server: &str, rpc_port: u16) -> PathBuf {
+    let path = dir.join(format!("{}.yml", name));
+    let config = format!(
+        r#"server:
+  network: "genesis"
+  base_path: "{base_path}"
+{server}
+rpc:
+  rpc_port: {rpc_port}
+keystore:
+  keystore_path: "{keystore}"
+  keystore_password_env: "{password_env}"
+genesis:
+  name: "Sentry Test"
+  id: "sentry_test"
+  authorities: ["{authority}"]
+  sudo_key: "//Alice"
+"#,
+        base_path = dir.join(name).display(),
+        server = server,
+        rpc_port = rpc_port,
+        keystore = dir.join("keystore").display(),
+        password_env = PASSWORD_ENV,
+        authority = authority,
+    );
+    fs::write(&path, config).unwrap();
+    path
+}
+
+/// Start a node and wait for it to log its peer id
+fn start(config: &Path, rpc_port: u16) -> Node {
+    let mut child = factomd(config).stderr(Stdio::piped()).spawn().unwrap();
+    let stderr = child.stderr.take().unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        // Keep draining so the node never blocks on a full pipe
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            if let Some(index) = line.find("Local node identity is: ") {
+                let peer_id = line[index + 24..].trim().to_string();
+                let _ = sender.send(peer_id);
+            }
+        }
+    });
+
+    let peer_id = receiver
+        .recv_timeout(Duration::from_secs(60))
+        .expect("Node didn't report its peer id");
+    Node {
+        child,
+        rpc_port,
+        peer_id,
+    }
+}
+
+/// Status reported by a node's `/health` endpoint
+fn health(node: &Node) -> Option<serde_json::Value> {
+    let mut stream = TcpStream::connect(("127.0.0.1", node.rpc_port)).ok()?;
+    stream
+        .write_all(b"GET /health HTTP/1.0\r\nHost: 127.0.0.1\r\n\r\n")
+        .ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    let body = &response[response.find("\r\n\r\n")? + 4..];
+    serde_json::from_str(body).ok()
+}
+
+fn number(node: &Node, field: &str) -> u64 {
+    health(node)
+        .and_then(|status| status[field].as_u64())
+        .unwrap_or(0)
+}
+
+#[test]
+#[ignore]
+fn test_authority_behind_sentry() {
+    let dir = std::env::temp_dir().join(format!("factomd-sentry-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    // Keys of the authority, in the keystore every node config points at
+    let keys = write_config(&dir, "keys", "//Alice", "", 0);
+    for (kind, seed) in &[("authority", AUTHORITY_SEED), ("network", NETWORK_SEED)] {
+        factomd(&keys)
+            .args(["key", "import", "--kind", *kind, "--name", *kind, "--secret", *seed])
+            .assert()
+            .success();
+    }
+    let inspect = factomd(&keys)
+        .args(["key", "inspect", "--name", "authority"])
+        .output()
+        .unwrap();
+    let authority_key = String::from_utf8_lossy(&inspect.stdout)
+        .lines()
+        .find(|line| line.starts_with("SS58 address:"))
+        .and_then(|line| line.split_whitespace().last())
+        .expect("No authority address")
+        .to_string();
+
+    // The sentry can't list the authority in sentry_for before the authority has
+    // started and shown its peer id; the authority dials it either way.
+    let (sentry_p2p, sentry_rpc) = (free_port(), free_port());
+    let sentry_config = write_config(
+        &dir,
+        "sentry",
+        &authority_key,
+        &format!(
+            "  role: \"full\"\n  listen_addresses: [\"/ip4/127.0.0.1/tcp/{}\"]",
+            sentry_p2p
+        ),
+        sentry_rpc,
+    );
+    let sentry = start(&sentry_config, sentry_rpc);
+    let sentry_addr = format!("/ip4/127.0.0.1/tcp/{}/p2p/{}", sentry_p2p, sentry.peer_id);
+
+    let (authority_p2p, authority_rpc) = (free_port(), free_port());
+    let authority_config = write_config(
+        &dir,
+        "authority",
+        &authority_key,
+        &format!(
+            "  role: \"authority\"\n  listen_addresses: [\"/ip4/127.0.0.1/tcp/{}\"]\n  sentry_nodes: [\"{}\"]",
+            authority_p2p, sentry_addr
+        ),
+        authority_rpc,
+    );
+    let authority = start(&authority_config, authority_rpc);
+
+    let (public_p2p, public_rpc) = (free_port(), free_port());
+    let public_config = write_config(
+        &dir,
+        "public",
+        &authority_key,
+        &format!(
+            "  role: \"full\"\n  listen_addresses: [\"/ip4/127.0.0.1/tcp/{}\"]\n  bootnodes: \"{}\"",
+            public_p2p, sentry_addr
+        ),
+        public_rpc,
+    );
+    let public = start(&public_config, public_rpc);
+
+    // Blocks authored behind the sentry reach the public node
+    wait_for("No blocks reached the public node", || {
+        number(&public, "best_block") >= 3
+    });
+
+    // The public node learned of the authority from the sentry, but only the sentry got through
+    wait_for("The sentry didn't connect to both nodes", || {
+        number(&sentry, "peers") == 2
+    });
+    assert_eq!(number(&authority, "peers"), 1);
+    assert_eq!(number(&sentry, "peers"), 2);
+    assert_eq!(number(&public, "peers"), 1);
+    assert_eq!(
+        health(&authority).unwrap()["is_producing"].as_bool(),
+        Some(true)
+    );
+
+    drop((public, authority, sentry));
+    let _ = fs::remove_dir_all(&dir);
+}
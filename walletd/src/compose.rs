@@ -0,0 +1,240 @@
+//! # Entry Composition
+//!
+//! Entries and new chains composed as the legacy `commit` and `reveal` requests
+//! of the legacy API of factomd, ready to send as they are: `commit-entry` and
+//! `reveal-entry`, or `commit-chain` and `reveal-chain` for a chain. A chain is
+//! started by writing its first entry.
+//!
+//! The commit message is a signed `Entries::reveal_entry` extrinsic of the entry
+//! in the legacy binary format, see `factomd_runtime::legacy_entry`, so it keeps
+//! its legacy entry hash and chain id. The runtime charges the EC account that
+//! signs it as the entry is written, per KiB of external ids and content, see
+//! `factomd_runtime::write_cost`. The reveal carries the same entry, and only
+//! succeeds once the commit is in.
+//!
+//! Extrinsics are signed with the next nonces of the account, so composed
+//! commits must be sent in the order they were composed.
+//!
+use crate::{Error, Wallet, WalletFuture};
+use factomd_address::AddressKind;
+use factomd_client::Client;
+use factomd_runtime::legacy_entry::LegacyEntry;
+use factomd_runtime::{Call, EntryCall, UncheckedExtrinsic};
+use futures::{future, Future};
+use parity_codec::Encode;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A JSON-RPC request for the legacy API of factomd
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FactomdRequest {
+    pub jsonrpc: String,
+    pub id: u32,
+    pub params: Value,
+    pub method: String,
+}
+
+impl FactomdRequest {
+    pub fn new(method: &str, params: Value) -> Self {
+        FactomdRequest {
+            jsonrpc: "2.0".to_string(),
+            id: 0,
+            params,
+            method: method.to_string(),
+        }
+    }
+
+    /// Commit to an entry with the signed extrinsic writing it
+    pub fn commit(method: &str, extrinsic: &UncheckedExtrinsic) -> Self {
+        Self::new(method, json!({ "message": hex::encode(extrinsic.encode()) }))
+    }
+
+    /// Reveal a committed entry, in the legacy binary format
+    pub fn reveal(method: &str, entry: &[u8]) -> Self {
+        Self::new(method, json!({ "entry": hex::encode(entry) }))
+    }
+
+    /// Submit the signed extrinsics of a factoid transaction, in their order
+    pub fn factoid_submit(extrinsics: &[UncheckedExtrinsic]) -> Self {
+        Self::new("factoid-submit", json!({ "transaction": hex::encode(extrinsics.encode()) }))
+    }
+}
+
+/// The requests writing an entry or chain, and what they write
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Composed {
+    pub commit: FactomdRequest,
+    pub reveal: FactomdRequest,
+    pub chainid: String,
+    pub entryhash: String,
+}
+
+/// Write an entry of an existing chain, paid for by an EC address
+pub fn compose_entry(wallet: &Wallet, node: &Client, ec_public: &str, entry: &LegacyEntry) -> WalletFuture<Composed> {
+    compose(wallet, node, ec_public, entry, ("commit-entry", "reveal-entry"))
+}
+
+/// Start a chain with its first entry, paid for by an EC address
+///
+/// The chain id is the legacy chain id of the external ids of the entry.
+pub fn compose_chain(
+    wallet: &Wallet,
+    node: &Client,
+    ec_public: &str,
+    external_ids: Vec<Vec<u8>>,
+    content: Vec<u8>,
+) -> WalletFuture<Composed> {
+    let entry = LegacyEntry {
+        chain_id: LegacyEntry::chain_id_of(&external_ids),
+        external_ids,
+        content,
+    };
+    compose(wallet, node, ec_public, &entry, ("commit-chain", "reveal-chain"))
+}
+
+/// Commit and reveal an entry with the given methods
+fn compose(
+    wallet: &Wallet,
+    node: &Client,
+    ec_public: &str,
+    entry: &LegacyEntry,
+    (commit, reveal): (&'static str, &'static str),
+) -> WalletFuture<Composed> {
+    let signer = match wallet.signer(ec_public, AddressKind::EntryCreditPublic) {
+        Ok(signer) => signer,
+        Err(e) => return Box::new(future::err(e)),
+    };
+    let data = match entry.encode() {
+        Ok(data) => data,
+        Err(e) => return Box::new(future::err(Error::InvalidEntry(e))),
+    };
+    let chainid = hex::encode(entry.chain_id);
+    let entryhash = hex::encode(LegacyEntry::hash(&data));
+
+    let reveal = FactomdRequest::reveal(reveal, &data);
+    let call = Call::Entries(EntryCall::reveal_entry(data));
+    Box::new(
+        node.compose(&signer, vec![call])
+            .map_err(Error::Node)
+            .map(move |extrinsics| Composed {
+                commit: FactomdRequest::commit(commit, &extrinsics[0]),
+                reveal,
+                chainid,
+                entryhash,
+            }),
+    )
+}
+
+/// Current time in milliseconds, as six big endian bytes
+pub(crate) fn timestamp() -> [u8; 6] {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let millis = now.as_secs() * 1000 + u64::from(now.subsec_millis());
+    let mut timestamp = [0u8; 6];
+    timestamp.copy_from_slice(&millis.to_be_bytes()[2..]);
+    timestamp
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use factomd_keystore::KeyKind;
+    use jsonrpc_core::{IoHandler, Params};
+    use jsonrpc_http_server::{Server, ServerBuilder};
+    use parity_codec::Decode;
+
+    fn temp_wallet(name: &str) -> Wallet {
+        let path = std::env::temp_dir().join(format!("factomd-compose-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        Wallet::open(path, "").unwrap()
+    }
+
+    /// A node with only a genesis block, and a client of it
+    pub(crate) fn mock_node() -> (Server, Client) {
+        let mut io = IoHandler::new();
+        io.add_method("chain_getBlockHash", |_: Params| {
+            Ok(json!(format!("0x{}", hex::encode([1u8; 32]))))
+        });
+        io.add_method("state_getStorage", |_: Params| Ok(Value::Null));
+        let server = ServerBuilder::new(io)
+            .start_http(&"127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        let url = format!("http://{}", server.address());
+        let client = Client::new(&url, &url).unwrap();
+        (server, client)
+    }
+
+    pub(crate) fn wait<T: Send + 'static>(future: WalletFuture<T>) -> Result<T, Error> {
+        tokio::runtime::Runtime::new().unwrap().block_on(future)
+    }
+
+    /// The call of a signed extrinsic, and its nonce
+    pub(crate) fn signed(extrinsic: UncheckedExtrinsic) -> (Call, u64) {
+        let (_, _, nonce, _) = extrinsic.signature.expect("composed extrinsics are signed");
+        (extrinsic.function, nonce.0)
+    }
+
+    /// The extrinsic a commit submits, and its nonce
+    fn committed(request: &FactomdRequest, method: &str) -> (Call, u64) {
+        assert_eq!(request.method, method);
+        let data = hex::decode(request.params["message"].as_str().unwrap()).unwrap();
+        signed(UncheckedExtrinsic::decode(&mut &data[..]).unwrap())
+    }
+
+    /// The entry a reveal carries
+    fn revealed(request: &FactomdRequest, method: &str) -> Vec<u8> {
+        assert_eq!(request.method, method);
+        hex::decode(request.params["entry"].as_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_compose_chain() {
+        let (_server, node) = mock_node();
+        let mut wallet = temp_wallet("chain");
+        let ec = wallet.generate(KeyKind::Ec).unwrap();
+        let external_ids = vec![b"factomd".to_vec(), b"test".to_vec()];
+        let composed = wait(compose_chain(&wallet, &node, &ec.public, external_ids.clone(), vec![7u8; 2000])).unwrap();
+
+        let chain_id = LegacyEntry::chain_id_of(&external_ids);
+        assert_eq!(composed.chainid, hex::encode(chain_id));
+        match committed(&composed.commit, "commit-chain") {
+            (Call::Entries(EntryCall::reveal_entry(data)), 0) => {
+                let entry = LegacyEntry::decode(&data).unwrap();
+                assert_eq!(entry.chain_id, chain_id);
+                assert_eq!(entry.external_ids, external_ids);
+                assert_eq!(composed.entryhash, hex::encode(LegacyEntry::hash(&data)));
+                assert_eq!(revealed(&composed.reveal, "reveal-chain"), data);
+            }
+            other => panic!("Expected a first reveal, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_compose_entry() {
+        let (_server, node) = mock_node();
+        let mut wallet = temp_wallet("entry");
+        let ec = wallet.generate(KeyKind::Ec).unwrap();
+        let fct = wallet.generate(KeyKind::Fct).unwrap();
+        let entry = LegacyEntry {
+            chain_id: [1u8; 32],
+            external_ids: vec![b"id".to_vec()],
+            content: b"hello".to_vec(),
+        };
+
+        wait(compose_entry(&wallet, &node, &ec.public, &entry)).unwrap();
+        // Entries composed one after another take the following nonces
+        let composed = wait(compose_entry(&wallet, &node, &ec.public, &entry)).unwrap();
+        assert_eq!(revealed(&composed.reveal, "reveal-entry"), entry.encode().unwrap());
+        match committed(&composed.commit, "commit-entry") {
+            (Call::Entries(EntryCall::reveal_entry(data)), 1) => assert_eq!(data, entry.encode().unwrap()),
+            other => panic!("Expected the second reveal, got {:?}", other),
+        }
+
+        match wait(compose_entry(&wallet, &node, &fct.public, &entry)) {
+            Err(Error::InvalidAddress(_)) => {}
+            other => panic!("Expected invalid address, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! # Factom Walletd
+//!
+//! A wallet of FCT and EC keys served over the JSON-RPC API of the legacy
+//! factom-walletd, so that existing clients can use it unchanged. When credentials
+//! are configured, requests need them as HTTP basic authentication.
+//!
+//! | Method                     | Result                                   |
+//! |----------------------------|------------------------------------------|
+//! | `address`                  | the key of a public address               |
+//! | `all-addresses`            | every key in the wallet                  |
+//! | `generate-factoid-address` | the next FCT key of the mnemonic         |
+//! | `generate-ec-address`      | the next EC key of the mnemonic          |
+//! | `import-addresses`         | keys imported from Fs and Es addresses   |
+//! | `wallet-backup`            | the mnemonic and every key               |
+//! | `compose-entry`            | the commit and reveal of an entry        |
+//! | `compose-chain`            | the commit and reveal starting a chain   |
+//! | `new-transaction`          | a new factoid transaction, by name       |
+//! | `add-input`                | the transaction, spending from an address |
+//! | `add-output`               | the transaction, paying an FA address    |
+//! | `add-ec-output`            | the transaction, buying entry credits    |
+//! | `sign-transaction`         | the transaction, signed by its input     |
+//! | `compose-transaction`      | the submission of the transaction        |
+//! | `delete-transaction`       | the transaction, no longer kept          |
+//! | `tmp-transactions`         | every transaction being built            |
+//!
+//! Composed entries, chains and transactions are requests to send to the legacy
+//! API of factomd, with extrinsics signed by keys of the wallet.
+//! Their nonces and the genesis hash they are signed against are read from the
+//! node at the configured URL. See `compose` and `transaction` for the calls.
+//!
+use factomd_client::Client;
+use factomd_keystore::KeyKind;
+use factomd_runtime::legacy_entry;
+use futures::Future;
+use jsonrpc_http_server::ServerBuilder;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+pub use compose::{compose_chain, compose_entry, Composed, FactomdRequest};
+pub use jsonrpc_http_server::Server;
+pub use transaction::{Transaction, TransactionView};
+pub use wallet::{AddressPair, Wallet};
+
+mod compose;
+mod rpc;
+mod transaction;
+mod wallet;
+
+/// A wallet call that waits on the node
+pub type WalletFuture<T> = Box<dyn Future<Item = T, Error = Error> + Send>;
+
+/// Walletd failures
+#[derive(Debug)]
+pub enum Error {
+    Keystore(factomd_keystore::Error),
+    /// The node failed to give nonces or the genesis hash
+    Node(factomd_client::Error),
+    /// Not an address, or not of a kind the call takes
+    InvalidAddress(String),
+    /// Wallets only hold FCT and EC keys
+    UnsupportedKind(KeyKind),
+    NotFound(String),
+    InvalidEntry(legacy_entry::Error),
+    InvalidHex(String),
+    TransactionNotFound(String),
+    TransactionExists(String),
+    /// An address used twice as an input of a transaction
+    DuplicateInput(String),
+    /// A second input of a transaction, which only one account can pay for
+    MultipleInputs(String),
+    /// A transaction without inputs
+    NoInputs(String),
+    /// Inputs and outputs differ, with no transaction fee to account for it
+    Unbalanced { inputs: u64, outputs: u64 },
+    NotSigned(String),
+    /// Amounts of a transaction add up to more than fits, or it has too many of
+    /// its inputs or outputs
+    Overflow,
+    /// Restoring a wallet created from other words
+    MnemonicMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Keystore(e) => write!(f, "{}", e),
+            Error::Node(e) => write!(f, "Node failed: {}", e),
+            Error::InvalidAddress(address) => write!(f, "Invalid address {}", address),
+            Error::UnsupportedKind(kind) => write!(f, "Wallets only hold fct and ec keys, not {}", kind),
+            Error::NotFound(address) => write!(f, "No such address {}", address),
+            Error::InvalidEntry(e) => write!(f, "Invalid entry: {}", e),
+            Error::InvalidHex(value) => write!(f, "Invalid hex {:?}", value),
+            Error::TransactionNotFound(name) => write!(f, "No transaction named {}", name),
+            Error::TransactionExists(name) => write!(f, "A transaction named {} already exists", name),
+            Error::DuplicateInput(address) => write!(f, "{} is already an input", address),
+            Error::MultipleInputs(name) => write!(f, "Transaction {} already has an input", name),
+            Error::NoInputs(name) => write!(f, "Transaction {} has no inputs", name),
+            Error::Unbalanced { inputs, outputs } => write!(
+                f,
+                "Inputs of {} don't match outputs of {}, use force to sign anyway",
+                inputs, outputs
+            ),
+            Error::NotSigned(name) => write!(f, "Transaction {} isn't signed", name),
+            Error::Overflow => write!(f, "Transaction amounts or transfers overflow"),
+            Error::MnemonicMismatch => write!(f, "The wallet was created from a different mnemonic"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<factomd_keystore::Error> for Error {
+    fn from(e: factomd_keystore::Error) -> Self {
+        Error::Keystore(e)
+    }
+}
+
+/// User and password clients authenticate with
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    pub user: String,
+    pub password: String,
+}
+
+/// Start the walletd JSON-RPC server
+///
+/// Extrinsics are composed for the node at `node_url`. Without credentials every
+/// request is served. The server stops when the returned handle is dropped.
+pub fn start_walletd_server(
+    addr: &str,
+    port: u16,
+    wallet: Wallet,
+    credentials: Option<Credentials>,
+    node_url: &str,
+) -> Result<Server, String> {
+    let socket: SocketAddr = format!("{}:{}", addr, port)
+        .parse()
+        .map_err(|e| format!("Invalid walletd address {}:{}: {}", addr, port, e))?;
+    let node = Client::new(node_url, node_url).map_err(|e| e.to_string())?;
+    ServerBuilder::new(rpc::handler(Arc::new(Mutex::new(wallet)), node))
+        .request_middleware(rpc::authentication(credentials))
+        .start_http(&socket)
+        .map_err(|e| format!("Failed to start walletd on {}: {}", socket, e))
+}
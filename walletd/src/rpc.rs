@@ -0,0 +1,363 @@
+//! # Walletd JSON-RPC
+//!
+//! Methods take and return the JSON of legacy walletd. Wallet failures are
+//! internal errors with the reason as `data`, the way legacy walletd reports them.
+//!
+//! Entries and chains are composed straight from their parameters. Factoid
+//! transactions are kept by name between calls. Composing waits on the node for
+//! nonces, so those methods answer asynchronously.
+//!
+use crate::compose::{compose_chain, compose_entry};
+use crate::{AddressPair, Credentials, Error, Transaction, Wallet, WalletFuture};
+use factomd_client::Client;
+use factomd_keystore::KeyKind;
+use factomd_runtime::legacy_entry::{self, LegacyEntry};
+use futures::{future, Future};
+use jsonrpc_core::{Error as RpcError, ErrorCode, IoHandler, Params, Value};
+use jsonrpc_http_server::hyper::{header, Body, Request, Response, StatusCode};
+use jsonrpc_http_server::RequestMiddlewareAction;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The wallet behind every method
+pub(crate) type SharedWallet = Arc<Mutex<Wallet>>;
+
+/// Transactions being built, by name
+type SharedTransactions = Arc<Mutex<BTreeMap<String, Transaction>>>;
+
+#[derive(Deserialize)]
+struct AddressParams {
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct ImportParams {
+    addresses: Vec<SecretParam>,
+}
+
+#[derive(Deserialize)]
+struct SecretParam {
+    secret: String,
+}
+
+#[derive(Serialize)]
+struct Addresses {
+    addresses: Vec<AddressPair>,
+}
+
+#[derive(Serialize)]
+struct Backup {
+    #[serde(rename = "wallet-seed")]
+    seed: String,
+    addresses: Vec<AddressPair>,
+}
+
+#[derive(Deserialize)]
+struct TransactionParams {
+    #[serde(rename = "tx-name")]
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct TransferParams {
+    #[serde(rename = "tx-name")]
+    name: String,
+    address: String,
+    amount: u64,
+}
+
+#[derive(Deserialize)]
+struct SignParams {
+    #[serde(rename = "tx-name")]
+    name: String,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Deserialize)]
+struct ComposeEntryParams {
+    entry: EntryParams,
+    ecpub: String,
+}
+
+#[derive(Deserialize)]
+struct EntryParams {
+    chainid: String,
+    #[serde(default)]
+    extids: Vec<String>,
+    #[serde(default)]
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ComposeChainParams {
+    chain: ChainParams,
+    ecpub: String,
+}
+
+#[derive(Deserialize)]
+struct ChainParams {
+    firstentry: FirstEntryParams,
+}
+
+#[derive(Deserialize)]
+struct FirstEntryParams {
+    #[serde(default)]
+    extids: Vec<String>,
+    #[serde(default)]
+    content: String,
+}
+
+/// Handler for the walletd methods
+pub(crate) fn handler(wallet: SharedWallet, node: Client) -> IoHandler {
+    let mut io = IoHandler::new();
+
+    let shared = wallet.clone();
+    io.add_method("address", move |params: Params| {
+        let params: AddressParams = params.parse()?;
+        to_value(lock(&shared)?.address(&params.address))
+    });
+
+    let shared = wallet.clone();
+    io.add_method("all-addresses", move |_: Params| {
+        let addresses = lock(&shared)?.addresses();
+        to_value(Ok(Addresses { addresses }))
+    });
+
+    let shared = wallet.clone();
+    io.add_method("generate-factoid-address", move |_: Params| {
+        to_value(lock(&shared)?.generate(KeyKind::Fct))
+    });
+
+    let shared = wallet.clone();
+    io.add_method("generate-ec-address", move |_: Params| {
+        to_value(lock(&shared)?.generate(KeyKind::Ec))
+    });
+
+    let shared = wallet.clone();
+    io.add_method("import-addresses", move |params: Params| {
+        let params: ImportParams = params.parse()?;
+        let mut wallet = lock(&shared)?;
+        let addresses = params
+            .addresses
+            .iter()
+            .map(|address| wallet.import(&address.secret))
+            .collect::<Result<Vec<_>, _>>();
+        to_value(addresses.map(|addresses| Addresses { addresses }))
+    });
+
+    let shared = wallet.clone();
+    io.add_method("wallet-backup", move |_: Params| {
+        let wallet = lock(&shared)?;
+        to_value(Ok(Backup {
+            seed: wallet.mnemonic().to_string(),
+            addresses: wallet.addresses(),
+        }))
+    });
+
+    let (shared, client) = (wallet.clone(), node.clone());
+    io.add_method("compose-entry", move |params: Params| {
+        respond(params.parse().and_then(|params: ComposeEntryParams| {
+            let wallet = lock(&shared)?;
+            Ok(match entry_of(&params.entry) {
+                Ok(entry) => compose_entry(&wallet, &client, &params.ecpub, &entry),
+                Err(e) => Box::new(future::err(e)),
+            })
+        }))
+    });
+
+    let (shared, client) = (wallet.clone(), node.clone());
+    io.add_method("compose-chain", move |params: Params| {
+        respond(params.parse().and_then(|params: ComposeChainParams| {
+            let first = &params.chain.firstentry;
+            let wallet = lock(&shared)?;
+            let decoded = decode_all(&first.extids).and_then(|external_ids| Ok((external_ids, decode(&first.content)?)));
+            Ok(match decoded {
+                Ok((external_ids, content)) => compose_chain(&wallet, &client, &params.ecpub, external_ids, content),
+                Err(e) => Box::new(future::err(e)),
+            })
+        }))
+    });
+
+    let transactions: SharedTransactions = Arc::new(Mutex::new(BTreeMap::new()));
+
+    let shared = transactions.clone();
+    io.add_method("new-transaction", move |params: Params| {
+        let params: TransactionParams = params.parse()?;
+        let mut transactions = lock(&shared)?;
+        if transactions.contains_key(&params.name) {
+            return to_value::<()>(Err(Error::TransactionExists(params.name)));
+        }
+        let transaction = Transaction::new(&params.name);
+        let view = transaction.view();
+        transactions.insert(params.name, transaction);
+        to_value(Ok(view))
+    });
+
+    let shared = transactions.clone();
+    io.add_method("delete-transaction", move |params: Params| {
+        let params: TransactionParams = params.parse()?;
+        let removed = lock(&shared)?
+            .remove(&params.name)
+            .map(|transaction| transaction.view())
+            .ok_or(Error::TransactionNotFound(params.name));
+        to_value(removed)
+    });
+
+    let shared = transactions.clone();
+    io.add_method("tmp-transactions", move |_: Params| {
+        let transactions: Vec<_> = lock(&shared)?.values().map(Transaction::view).collect();
+        to_value(Ok(json!({ "transactions": transactions })))
+    });
+
+    let (shared, shared_wallet) = (transactions.clone(), wallet.clone());
+    io.add_method("add-input", move |params: Params| {
+        let params: TransferParams = params.parse()?;
+        let wallet = lock(&shared_wallet)?;
+        update(&shared, &params.name, |transaction| {
+            transaction.add_input(&wallet, &params.address, params.amount)
+        })
+    });
+
+    let shared = transactions.clone();
+    io.add_method("add-output", move |params: Params| {
+        let params: TransferParams = params.parse()?;
+        update(&shared, &params.name, |transaction| {
+            transaction.add_output(&params.address, params.amount)
+        })
+    });
+
+    let shared = transactions.clone();
+    io.add_method("add-ec-output", move |params: Params| {
+        let params: TransferParams = params.parse()?;
+        update(&shared, &params.name, |transaction| {
+            transaction.add_ec_output(&params.address, params.amount)
+        })
+    });
+
+    let shared = transactions.clone();
+    io.add_method("sign-transaction", move |params: Params| {
+        let params: SignParams = params.parse()?;
+        let wallet = lock(&wallet)?;
+        update(&shared, &params.name, |transaction| transaction.sign(&wallet, params.force))
+    });
+
+    io.add_method("compose-transaction", move |params: Params| {
+        respond(params.parse().and_then(|params: TransactionParams| {
+            let transactions = lock(&transactions)?;
+            Ok(match transactions.get(&params.name) {
+                Some(transaction) => transaction.compose(&node),
+                None => Box::new(future::err(Error::TransactionNotFound(params.name))),
+            })
+        }))
+    });
+
+    io
+}
+
+fn lock<T>(shared: &Arc<Mutex<T>>) -> Result<MutexGuard<'_, T>, RpcError> {
+    shared.lock().map_err(|_| wallet_error("Wallet is unavailable"))
+}
+
+/// Change a transaction and return how it looks now
+fn update<F>(transactions: &SharedTransactions, name: &str, change: F) -> jsonrpc_core::Result<Value>
+where
+    F: FnOnce(&mut Transaction) -> Result<(), Error>,
+{
+    let mut transactions = lock(transactions)?;
+    let result = match transactions.get_mut(name) {
+        Some(transaction) => change(transaction).map(|_| transaction.view()),
+        None => Err(Error::TransactionNotFound(name.to_string())),
+    };
+    to_value(result)
+}
+
+fn entry_of(params: &EntryParams) -> Result<LegacyEntry, Error> {
+    let chain_id = decode(&params.chainid)?;
+    if chain_id.len() != 32 {
+        return Err(Error::InvalidEntry(legacy_entry::Error::InvalidChainId));
+    }
+    let mut entry = LegacyEntry {
+        chain_id: [0u8; 32],
+        external_ids: decode_all(&params.extids)?,
+        content: decode(&params.content)?,
+    };
+    entry.chain_id.copy_from_slice(&chain_id);
+    Ok(entry)
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(value).map_err(|_| Error::InvalidHex(value.to_string()))
+}
+
+fn decode_all(values: &[String]) -> Result<Vec<Vec<u8>>, Error> {
+    values.iter().map(|value| decode(value)).collect()
+}
+
+/// Answer once a wallet call that waits on the node is done
+fn respond<T: Serialize + Send + 'static>(
+    pending: jsonrpc_core::Result<WalletFuture<T>>,
+) -> Box<dyn Future<Item = Value, Error = RpcError> + Send> {
+    match pending {
+        Ok(pending) => Box::new(pending.then(to_value)),
+        Err(e) => Box::new(future::err(e)),
+    }
+}
+
+fn to_value<T: Serialize>(result: Result<T, crate::Error>) -> jsonrpc_core::Result<Value> {
+    let result = result.map_err(|e| wallet_error(&e.to_string()))?;
+    serde_json::to_value(result).map_err(|e| wallet_error(&e.to_string()))
+}
+
+fn wallet_error(reason: &str) -> RpcError {
+    RpcError {
+        code: ErrorCode::InternalError,
+        message: "Internal error".to_string(),
+        data: Some(Value::String(reason.to_string())),
+    }
+}
+
+/// Refuse requests without the configured credentials
+pub(crate) fn authentication(
+    credentials: Option<Credentials>,
+) -> impl Fn(Request<Body>) -> RequestMiddlewareAction {
+    let expected = credentials.map(|credentials| {
+        let user_password = format!("{}:{}", credentials.user, credentials.password);
+        format!("Basic {}", base64::encode(&user_password))
+    });
+
+    move |request: Request<Body>| {
+        let authorized = match &expected {
+            None => true,
+            Some(expected) => request
+                .headers()
+                .get(header::AUTHORIZATION)
+                .map(|value| equal_in_constant_time(value.as_bytes(), expected.as_bytes()))
+                .unwrap_or(false),
+        };
+        if authorized {
+            return RequestMiddlewareAction::Proceed {
+                should_continue_on_invalid_cors: false,
+                request,
+            };
+        }
+
+        let mut response = Response::new(Body::from("401 Unauthorized"));
+        *response.status_mut() = StatusCode::UNAUTHORIZED;
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            header::HeaderValue::from_static("Basic realm=\"factom-walletd\""),
+        );
+        RequestMiddlewareAction::Respond {
+            should_validate_hosts: true,
+            response: Box::new(futures::future::ok(response)),
+        }
+    }
+}
+
+/// Compare credentials without revealing how much of them matched
+fn equal_in_constant_time(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,316 @@
+//! # Factoid Transactions
+//!
+//! Transactions are built up under a name, an input or output at a time, then
+//! signed and composed for the node. As with legacy walletd, they are only kept
+//! in memory. Changing a signed transaction drops its signatures.
+//!
+//! A transaction is a call of the runtime per output, all paid by its one input:
+//!
+//! | Output    | Call                          |
+//! |-----------|-------------------------------|
+//! | FA output | `Factoids::transfer_factoids` |
+//! | EC output | `Factoids::buy_entry_credits` |
+//!
+//! Composing signs each call as an extrinsic with the next nonce of the input
+//! account, all in one `factoid-submit` request of the legacy API of factomd,
+//! which submits them in their order.
+//! Legacy transactions may spend from several inputs at once; here an account
+//! only signs for itself, so a transaction takes a single input.
+//!
+//! There is no transaction fee on this chain: a transaction is signed once its
+//! input matches its outputs, or regardless when forced. The transaction id is
+//! the Blake2 hash of the input account and the calls.
+//!
+use crate::compose::{timestamp, FactomdRequest};
+use crate::{Error, Wallet, WalletFuture};
+use factomd_address::{Address, AddressKind};
+use factomd_client::{Client, Signer};
+use factomd_runtime::{Call, FactoidCall};
+use futures::{future, Future};
+use parity_codec::Encode;
+use primitives::{blake2_256, sr25519};
+use serde::Serialize;
+
+/// Most outputs or EC outputs a transaction has
+const MAX_TRANSFERS: usize = 255;
+
+/// An amount sent from or to an address
+#[derive(Clone, Debug)]
+struct Transfer {
+    address: String,
+    key: [u8; 32],
+    amount: u64,
+}
+
+/// A factoid transaction being built
+#[derive(Clone)]
+pub struct Transaction {
+    name: String,
+    timestamp: [u8; 6],
+    inputs: Vec<Transfer>,
+    outputs: Vec<Transfer>,
+    ec_outputs: Vec<Transfer>,
+    /// The key of the input, once signed
+    signer: Option<Signer>,
+}
+
+/// A transaction as legacy walletd shows it
+#[derive(Clone, Debug, Serialize)]
+pub struct TransactionView {
+    pub name: String,
+    /// Seconds
+    pub timestamp: u64,
+    pub totalinputs: u64,
+    pub totaloutputs: u64,
+    pub totalecoutputs: u64,
+    pub inputs: Vec<TransferView>,
+    pub outputs: Vec<TransferView>,
+    pub ecoutputs: Vec<TransferView>,
+    pub txid: String,
+    pub feespaid: u64,
+    pub feesrequired: u64,
+    pub signed: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TransferView {
+    pub address: String,
+    pub amount: u64,
+}
+
+impl Transaction {
+    pub fn new(name: &str) -> Self {
+        Transaction {
+            name: name.to_string(),
+            timestamp: timestamp(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            ec_outputs: Vec::new(),
+            signer: None,
+        }
+    }
+
+    /// Spend from an FA address of the wallet
+    pub fn add_input(&mut self, wallet: &Wallet, address: &str, amount: u64) -> Result<(), Error> {
+        let key = account(address, AddressKind::FactoidPublic)?;
+        wallet.address(address)?;
+        if let Some(input) = self.inputs.first() {
+            return Err(if input.address == address {
+                Error::DuplicateInput(address.to_string())
+            } else {
+                Error::MultipleInputs(self.name.clone())
+            });
+        }
+        push(&mut self.inputs, address, key, amount)?;
+        self.signer = None;
+        Ok(())
+    }
+
+    /// Send factoids to an FA address
+    pub fn add_output(&mut self, address: &str, amount: u64) -> Result<(), Error> {
+        let key = account(address, AddressKind::FactoidPublic)?;
+        push(&mut self.outputs, address, key, amount)?;
+        self.signer = None;
+        Ok(())
+    }
+
+    /// Buy entry credits for an EC address with factoids
+    pub fn add_ec_output(&mut self, address: &str, amount: u64) -> Result<(), Error> {
+        let key = account(address, AddressKind::EntryCreditPublic)?;
+        push(&mut self.ec_outputs, address, key, amount)?;
+        self.signer = None;
+        Ok(())
+    }
+
+    /// Take the key of the input from the wallet to sign the calls with
+    ///
+    /// Without `force`, the input must add up to the outputs.
+    pub fn sign(&mut self, wallet: &Wallet, force: bool) -> Result<(), Error> {
+        let input = match self.inputs.first() {
+            Some(input) => input,
+            None => return Err(Error::NoInputs(self.name.clone())),
+        };
+        let outputs = total(&self.outputs)
+            .checked_add(total(&self.ec_outputs))
+            .ok_or(Error::Overflow)?;
+        if input.amount != outputs && !force {
+            return Err(Error::Unbalanced {
+                inputs: input.amount,
+                outputs,
+            });
+        }
+
+        self.signer = Some(wallet.signer(&input.address, AddressKind::FactoidPublic)?);
+        Ok(())
+    }
+
+    /// Sign the calls of the transaction as extrinsics with the next nonces of its input
+    pub fn compose(&self, node: &Client) -> WalletFuture<FactomdRequest> {
+        let signer = match &self.signer {
+            Some(signer) => signer,
+            None => return Box::new(future::err(Error::NotSigned(self.name.clone()))),
+        };
+        Box::new(
+            node.compose(signer, self.calls())
+                .map_err(Error::Node)
+                .map(|extrinsics| FactomdRequest::factoid_submit(&extrinsics)),
+        )
+    }
+
+    /// Hex encoded Blake2 hash of the input account and the calls
+    pub fn txid(&self) -> String {
+        let input = self.inputs.first().map(|input| input.key).unwrap_or_default();
+        hex::encode(blake2_256(&(input, self.calls()).encode()))
+    }
+
+    pub fn view(&self) -> TransactionView {
+        let views = |transfers: &[Transfer]| {
+            transfers
+                .iter()
+                .map(|transfer| TransferView {
+                    address: transfer.address.clone(),
+                    amount: transfer.amount,
+                })
+                .collect()
+        };
+        let mut millis = [0u8; 8];
+        millis[2..].copy_from_slice(&self.timestamp);
+        let (inputs, outputs, ec_outputs) = (total(&self.inputs), total(&self.outputs), total(&self.ec_outputs));
+
+        TransactionView {
+            name: self.name.clone(),
+            timestamp: u64::from_be_bytes(millis) / 1000,
+            totalinputs: inputs,
+            totaloutputs: outputs,
+            totalecoutputs: ec_outputs,
+            inputs: views(&self.inputs),
+            outputs: views(&self.outputs),
+            ecoutputs: views(&self.ec_outputs),
+            txid: self.txid(),
+            feespaid: inputs.saturating_sub(outputs.saturating_add(ec_outputs)),
+            feesrequired: 0,
+            signed: self.signer.is_some(),
+        }
+    }
+
+    /// A call per output, then one per EC output
+    fn calls(&self) -> Vec<Call> {
+        let transfers = self.outputs.iter().map(|output| {
+            FactoidCall::transfer_factoids(sr25519::Public(output.key), u128::from(output.amount))
+        });
+        let purchases = self.ec_outputs.iter().map(|output| {
+            FactoidCall::buy_entry_credits(sr25519::Public(output.key), u128::from(output.amount))
+        });
+        transfers.chain(purchases).map(Call::Factoids).collect()
+    }
+}
+
+/// Account key of a public address of the expected kind
+fn account(address: &str, kind: AddressKind) -> Result<[u8; 32], Error> {
+    Address::parse_kind(address, kind)
+        .and_then(|address| address.to_account())
+        .map_err(|_| Error::InvalidAddress(address.to_string()))
+}
+
+/// Add a transfer, as long as the count and total still fit
+fn push(transfers: &mut Vec<Transfer>, address: &str, key: [u8; 32], amount: u64) -> Result<(), Error> {
+    if transfers.len() == MAX_TRANSFERS || total(transfers).checked_add(amount).is_none() {
+        return Err(Error::Overflow);
+    }
+    transfers.push(Transfer {
+        address: address.to_string(),
+        key,
+        amount,
+    });
+    Ok(())
+}
+
+/// Sum of the amounts, which `push` keeps from overflowing
+fn total(transfers: &[Transfer]) -> u64 {
+    transfers.iter().map(|transfer| transfer.amount).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compose::tests::{mock_node, signed, wait};
+    use factomd_keystore::KeyKind;
+    use factomd_runtime::UncheckedExtrinsic;
+    use parity_codec::Decode;
+
+    fn temp_wallet(name: &str) -> Wallet {
+        let path = std::env::temp_dir().join(format!("factomd-transaction-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        Wallet::open(path, "").unwrap()
+    }
+
+    #[test]
+    fn test_sign_and_compose() {
+        let (_server, node) = mock_node();
+        let mut wallet = temp_wallet("sign");
+        let from = wallet.generate(KeyKind::Fct).unwrap().public;
+        let to = wallet.generate(KeyKind::Fct).unwrap().public;
+        let ec = wallet.generate(KeyKind::Ec).unwrap().public;
+
+        let mut transaction = Transaction::new("tx");
+        transaction.add_input(&wallet, &from, 1000).unwrap();
+        transaction.add_output(&to, 600).unwrap();
+        transaction.add_ec_output(&ec, 300).unwrap();
+        match transaction.sign(&wallet, false) {
+            Err(Error::Unbalanced { inputs: 1000, outputs: 900 }) => {}
+            other => panic!("Expected unbalanced transaction, got {:?}", other),
+        }
+        assert!(wait(transaction.compose(&node)).is_err());
+
+        transaction.add_output(&to, 100).unwrap();
+        transaction.sign(&wallet, false).unwrap();
+        assert!(transaction.view().signed);
+        let request = wait(transaction.compose(&node)).unwrap();
+        assert_eq!(request.method, "factoid-submit");
+        let to_key = account(&to, AddressKind::FactoidPublic).unwrap();
+        let ec_key = account(&ec, AddressKind::EntryCreditPublic).unwrap();
+        let data = hex::decode(request.params["transaction"].as_str().unwrap()).unwrap();
+        let extrinsics = Vec::<UncheckedExtrinsic>::decode(&mut &data[..]).unwrap();
+        let calls: Vec<_> = extrinsics.into_iter().map(signed).collect();
+        assert_eq!(
+            calls,
+            vec![
+                (Call::Factoids(FactoidCall::transfer_factoids(sr25519::Public(to_key), 600)), 0),
+                (Call::Factoids(FactoidCall::transfer_factoids(sr25519::Public(to_key), 100)), 1),
+                (Call::Factoids(FactoidCall::buy_entry_credits(sr25519::Public(ec_key), 300)), 2),
+            ]
+        );
+
+        let txid = transaction.txid();
+        transaction.add_output(&to, 0).unwrap();
+        assert!(!transaction.view().signed);
+        assert_ne!(transaction.txid(), txid);
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        let mut wallet = temp_wallet("invalid");
+        let fct = wallet.generate(KeyKind::Fct).unwrap().public;
+        let other = wallet.generate(KeyKind::Fct).unwrap().public;
+        let ec = wallet.generate(KeyKind::Ec).unwrap().public;
+        let stranger = Address::new(AddressKind::FactoidPublic, [9u8; 32]).to_string();
+
+        let mut transaction = Transaction::new("tx");
+        assert!(transaction.sign(&wallet, true).is_err());
+        match transaction.add_input(&wallet, &stranger, 10) {
+            Err(Error::NotFound(_)) => {}
+            other => panic!("Expected missing address, got {:?}", other),
+        }
+        transaction.add_input(&wallet, &fct, 10).unwrap();
+        match transaction.add_input(&wallet, &fct, 10) {
+            Err(Error::DuplicateInput(_)) => {}
+            other => panic!("Expected duplicate input, got {:?}", other),
+        }
+        match transaction.add_input(&wallet, &other, 10) {
+            Err(Error::MultipleInputs(_)) => {}
+            other => panic!("Expected a second input to be refused, got {:?}", other),
+        }
+        assert!(transaction.add_output(&ec, 10).is_err());
+        assert!(transaction.add_ec_output(&fct, 10).is_err());
+    }
+}
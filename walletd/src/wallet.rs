@@ -0,0 +1,293 @@
+//! # Wallet
+//!
+//! FCT and EC keys kept in a keystore, each named after its public address and
+//! encrypted with the walletd password. Every key is decrypted when the wallet
+//! opens, so that calls don't pay for key derivation.
+//!
+//! Keys are sr25519 accounts on this chain. The public address holds the account
+//! key and the secret address holds the seed, so a secret from `all-addresses`
+//! can be imported into another wallet or with `factomd key import`.
+//!
+//! Legacy wallets use the same seed as an ed25519 key. Each key also shows that
+//! legacy FA or EC address, whose balance the key can claim for its account.
+//!
+//! New keys are derived from the 12 word mnemonic of the wallet, created with it or
+//! restored from legacy wallet words, along the legacy BIP44 paths. The n-th
+//! generated FCT or EC key has the same secret as the n-th of a legacy wallet
+//! with the same words.
+//!
+use crate::Error;
+use factomd_address::{Address, AddressKind};
+use factomd_client::Signer;
+use factomd_keystore::{hd, KeyKind, Keystore};
+use primitives::{ed25519, sr25519, Pair};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Public and secret address of a key, as legacy walletd returns them
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AddressPair {
+    pub public: String,
+    pub secret: String,
+    /// FA or EC address of the ed25519 key of the seed
+    pub legacy: String,
+}
+
+/// A decrypted key
+struct Key {
+    kind: KeyKind,
+    seed: [u8; 32],
+}
+
+/// FCT and EC keys, unlocked
+pub struct Wallet {
+    keystore: Keystore,
+    password: String,
+    mnemonic: String,
+    /// Keys by public address
+    keys: BTreeMap<String, Key>,
+}
+
+impl Wallet {
+    /// Open a wallet, creating it with a new mnemonic if needed, and decrypt its keys
+    pub fn open<P: AsRef<Path>>(path: P, password: &str) -> Result<Self, Error> {
+        let keystore = Keystore::open(path)?;
+        let mut keys = BTreeMap::new();
+        for stored in keystore.list()? {
+            if stored.kind == KeyKind::Fct || stored.kind == KeyKind::Ec {
+                let seed = stored.seed(password)?;
+                keys.insert(
+                    stored.name,
+                    Key {
+                        kind: stored.kind,
+                        seed,
+                    },
+                );
+            }
+        }
+        let mnemonic = match keystore.mnemonic(password)? {
+            Some(mnemonic) => mnemonic,
+            None => {
+                let mnemonic = hd::generate_mnemonic();
+                keystore.set_mnemonic(&mnemonic, password)?;
+                mnemonic
+            }
+        };
+        Ok(Wallet {
+            keystore,
+            password: password.to_string(),
+            mnemonic,
+            keys,
+        })
+    }
+
+    /// Open a wallet restored from a mnemonic, creating it if needed
+    ///
+    /// Keys are derived again as they are generated. An existing wallet must have
+    /// been created with the same mnemonic.
+    pub fn restore<P: AsRef<Path>>(path: P, password: &str, mnemonic: &str) -> Result<Self, Error> {
+        let mnemonic = hd::normalize_mnemonic(mnemonic)?;
+        let keystore = Keystore::open(&path)?;
+        match keystore.mnemonic(password)? {
+            Some(existing) if existing != mnemonic => return Err(Error::MnemonicMismatch),
+            Some(_) => {}
+            None => keystore.set_mnemonic(&mnemonic, password)?,
+        }
+        Self::open(path, password)
+    }
+
+    /// The mnemonic keys are derived from
+    pub fn mnemonic(&self) -> &str {
+        &self.mnemonic
+    }
+
+    /// Derive and store the next FCT or EC key
+    ///
+    /// That is the key at the first index the wallet doesn't hold yet.
+    pub fn generate(&mut self, kind: KeyKind) -> Result<AddressPair, Error> {
+        let (public_kind, _) = address_kinds(kind)?;
+        let mut index = 0;
+        loop {
+            let seed = hd::derive_seed(&self.mnemonic, kind, index)?;
+            let public = Address::new(public_kind, account(&seed)).to_string();
+            if !self.keys.contains_key(&public) {
+                return self.insert(kind, seed);
+            }
+            index += 1;
+        }
+    }
+
+    /// Store the key of an Fs or Es secret address
+    ///
+    /// Importing a key the wallet already holds returns it unchanged.
+    pub fn import(&mut self, secret: &str) -> Result<AddressPair, Error> {
+        let invalid = || Error::InvalidAddress(secret.to_string());
+        let address: Address = secret.parse().map_err(|_| invalid())?;
+        let kind = match address.kind() {
+            AddressKind::FactoidSecret => KeyKind::Fct,
+            AddressKind::EntryCreditSecret => KeyKind::Ec,
+            _ => return Err(invalid()),
+        };
+        self.insert(kind, *address.key())
+    }
+
+    /// The key of a public FA or EC address
+    pub fn address(&self, public: &str) -> Result<AddressPair, Error> {
+        self.keys
+            .get(public)
+            .map(|key| pair(public, key))
+            .ok_or_else(|| Error::NotFound(public.to_string()))
+    }
+
+    /// Every key, FCT keys first, each kind ordered by address
+    pub fn addresses(&self) -> Vec<AddressPair> {
+        let of_kind = |kind: KeyKind| {
+            self.keys
+                .iter()
+                .filter(move |(_, key)| key.kind == kind)
+                .map(|(public, key)| pair(public, key))
+        };
+        of_kind(KeyKind::Fct).chain(of_kind(KeyKind::Ec)).collect()
+    }
+
+    /// Signer of the calls of a public address, which must be of the given kind
+    pub fn signer(&self, public: &str, kind: AddressKind) -> Result<Signer, Error> {
+        Address::parse_kind(public, kind).map_err(|_| Error::InvalidAddress(public.to_string()))?;
+        let key = self
+            .keys
+            .get(public)
+            .ok_or_else(|| Error::NotFound(public.to_string()))?;
+        Ok(Signer::from_seed(key.seed))
+    }
+
+    fn insert(&mut self, kind: KeyKind, seed: [u8; 32]) -> Result<AddressPair, Error> {
+        let (public_kind, _) = address_kinds(kind)?;
+        let account = account(&seed);
+        let public = Address::new(public_kind, account).to_string();
+        if !self.keys.contains_key(&public) {
+            self.keystore
+                .insert(&public, kind, &account, &seed, &self.password)?;
+            self.keys.insert(public.clone(), Key { kind, seed });
+        }
+        self.address(&public)
+    }
+}
+
+fn account(seed: &[u8; 32]) -> [u8; 32] {
+    sr25519::Pair::from_seed(seed).public().0
+}
+
+/// The address a legacy wallet shows for a seed
+fn legacy_address(kind: KeyKind, seed: &[u8; 32]) -> Address {
+    let public = ed25519::Pair::from_seed(seed).public().0;
+    match kind {
+        KeyKind::Fct => Address::factoid(&public),
+        _ => Address::entry_credit(&public),
+    }
+}
+
+/// Public and secret address kinds of a key kind
+fn address_kinds(kind: KeyKind) -> Result<(AddressKind, AddressKind), Error> {
+    match kind {
+        KeyKind::Fct => Ok((AddressKind::FactoidPublic, AddressKind::FactoidSecret)),
+        KeyKind::Ec => Ok((AddressKind::EntryCreditPublic, AddressKind::EntryCreditSecret)),
+        KeyKind::Authority | KeyKind::Network => Err(Error::UnsupportedKind(kind)),
+    }
+}
+
+fn pair(public: &str, key: &Key) -> AddressPair {
+    let (_, secret_kind) = address_kinds(key.kind).expect("only fct and ec keys are held; qed");
+    AddressPair {
+        public: public.to_string(),
+        secret: Address::new(secret_kind, key.seed).to_string(),
+        legacy: legacy_address(key.kind, &key.seed).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("factomd-wallet-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn test_generate_and_reopen() {
+        let path = temp_path("reopen");
+        let mut wallet = Wallet::open(&path, "secret").unwrap();
+        let ec = wallet.generate(KeyKind::Ec).unwrap();
+        let fct = wallet.generate(KeyKind::Fct).unwrap();
+        assert!(fct.public.starts_with("FA"));
+        assert!(fct.secret.starts_with("Fs"));
+        assert!(ec.public.starts_with("EC"));
+        assert!(ec.secret.starts_with("Es"));
+        assert!(fct.legacy.starts_with("FA"));
+        assert!(ec.legacy.starts_with("EC"));
+        assert!(wallet.generate(KeyKind::Authority).is_err());
+
+        let wallet = Wallet::open(&path, "secret").unwrap();
+        assert_eq!(wallet.addresses(), vec![fct.clone(), ec]);
+        assert_eq!(wallet.address(&fct.public).unwrap(), fct);
+        match Wallet::open(&path, "guess") {
+            Err(Error::Keystore(factomd_keystore::Error::WrongPassword)) => {}
+            Err(e) => panic!("Expected wrong password, got {:?}", e),
+            Ok(_) => panic!("Expected wrong password"),
+        }
+    }
+
+    #[test]
+    fn test_import() {
+        let mut wallet = Wallet::open(temp_path("import"), "").unwrap();
+        let secret = Address::new(AddressKind::FactoidSecret, [7u8; 32]).to_string();
+        let imported = wallet.import(&secret).unwrap();
+        assert_eq!(imported.secret, secret);
+        assert_eq!(wallet.import(&secret).unwrap(), imported);
+        assert_eq!(wallet.addresses().len(), 1);
+
+        match wallet.import(&imported.public) {
+            Err(Error::InvalidAddress(_)) => {}
+            other => panic!("Expected invalid address, got {:?}", other),
+        }
+        match wallet.address("FA2jK2HcLnRdS94dEcU27rF3meoJfpUcZPSinpb7AwQvPRY6RL1Q") {
+            Err(Error::NotFound(_)) => {}
+            other => panic!("Expected missing address, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_restore() {
+        let path = temp_path("restore");
+        let words = "yellow yellow yellow yellow yellow yellow yellow yellow yellow yellow yellow yellow";
+        let mut wallet = Wallet::restore(&path, "secret", words).unwrap();
+        assert_eq!(wallet.mnemonic(), words);
+
+        // The first keys of legacy wallets with these words
+        let fct = wallet.generate(KeyKind::Fct).unwrap();
+        assert_eq!(fct.secret, "Fs1jQGc9GJjyWNroLPq7x6LbYQHveyjWNPXSqAvCEKpETNoTU5dP");
+        assert_eq!(fct.legacy, "FA22de5NSG2FA2HmMaD4h8qSAZAJyztmmnwgLPghCQKoSekwYYct");
+        let ec = wallet.generate(KeyKind::Ec).unwrap();
+        assert_eq!(ec.secret, "Es37BZSs7jUpyn3HosZa79kENWfvj1AVUdZioWykTTqqvA2MRi9h");
+        assert_eq!(ec.legacy, "EC2KnJQN86MYq4pQyeSGTHSiVdkhRCPXS3udzD4im6BXRBjZFMmR");
+        // The account is the sr25519 key of the same seed
+        let seed = *Address::parse_kind(&fct.secret, AddressKind::FactoidSecret).unwrap().key();
+        assert_eq!(fct.public, Address::new(AddressKind::FactoidPublic, account(&seed)).to_string());
+        let next = wallet.generate(KeyKind::Fct).unwrap();
+        assert_ne!(next, fct);
+
+        let wallet = Wallet::restore(&path, "secret", words).unwrap();
+        assert_eq!(wallet.addresses().len(), 3);
+        match Wallet::restore(&path, "secret", &hd::generate_mnemonic()) {
+            Err(Error::MnemonicMismatch) => {}
+            Err(e) => panic!("Expected mismatched mnemonic, got {:?}", e),
+            Ok(_) => panic!("Expected mismatched mnemonic"),
+        }
+
+        // A new wallet generates its own words
+        let wallet = Wallet::open(temp_path("restore-new"), "").unwrap();
+        assert_eq!(wallet.mnemonic().split(' ').count(), 12);
+    }
+}